@@ -326,7 +326,8 @@ func streamScreenCaptureAsync(
             width: currentWidth,
             height: currentHeight,
             payload: nalData,
-            frameType: 1  // H264Frame
+            frameType: 1,  // H264Frame
+            isKeyframe: isKeyframe
         )
         
         client.send(frameData) { sendResult in
@@ -580,16 +581,20 @@ enum FrameType: UInt8 {
 }
 
 /// Create frame data with header
-/// Protocol: version(1) + frame_type(1) + sequence(8) + timestamp_us(8) + width(2) + height(2) + payload_size(4) = 26 bytes header
-func createFrameData(sequence: UInt64, timestampUs: UInt64, width: UInt16, height: UInt16, payload: Data, frameType: UInt8 = 0) -> Data {
-    var frameData = Data(capacity: 26 + payload.count)
-    frameData.append(1) // version
+/// Protocol v3: version(1) + frame_type(1) + sequence(8) + timestamp_us(8) + width(2) + height(2) + payload_size(4)
+///   + color_space(1) + color_range(1) + flags(1) = 29 bytes header
+func createFrameData(sequence: UInt64, timestampUs: UInt64, width: UInt16, height: UInt16, payload: Data, frameType: UInt8 = 0, isKeyframe: Bool = true) -> Data {
+    var frameData = Data(capacity: 29 + payload.count)
+    frameData.append(3) // version
     frameData.append(frameType) // frame_type
     frameData.append(contentsOf: withUnsafeBytes(of: sequence.bigEndian) { Data($0) })
     frameData.append(contentsOf: withUnsafeBytes(of: timestampUs.bigEndian) { Data($0) })
     frameData.append(contentsOf: withUnsafeBytes(of: width.bigEndian) { Data($0) })
     frameData.append(contentsOf: withUnsafeBytes(of: height.bigEndian) { Data($0) })
     frameData.append(contentsOf: withUnsafeBytes(of: UInt32(payload.count).bigEndian) { Data($0) })
+    frameData.append(1) // color_space: BT.709 (VideoToolbox default)
+    frameData.append(0) // color_range: limited
+    frameData.append(isKeyframe ? 0x01 : 0x00) // flags: bit 0 = keyframe
     frameData.append(payload)
     return frameData
 }
@@ -21,8 +21,9 @@ class QuicClient {
     func connect(host: String, port: UInt16, timeout: TimeInterval = defaultTimeout, completion: @escaping (Result<Void, Error>) -> Void) {
         let endpoint = NWEndpoint.hostPort(host: NWEndpoint.Host(host), port: NWEndpoint.Port(integerLiteral: port))
         
-        // Create QUIC options with insecure TLS for self-signed certs
-        let quicOptions = NWProtocolQUIC.Options(alpn: ["thunder-mirror"])
+        // Create QUIC options with insecure TLS for self-signed certs.
+        // Offer the versioned ALPN first; older receivers only know the bare one.
+        let quicOptions = NWProtocolQUIC.Options(alpn: ["thunder-mirror/3", "thunder-mirror"])
         
         // Configure TLS to skip certificate verification (for self-signed certs)
         sec_protocol_options_set_verify_block(
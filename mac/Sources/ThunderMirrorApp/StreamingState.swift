@@ -186,7 +186,8 @@ class StreamingState: ObservableObject {
                 width: currentWidth,
                 height: currentHeight,
                 payload: nalData,
-                frameType: 1  // H264Frame
+                frameType: 1,  // H264Frame
+                isKeyframe: isKeyframe
             )
             
             client.send(frameData) { [weak self] sendResult in
@@ -362,15 +363,18 @@ class StreamingState: ObservableObject {
     }
     
     /// Create frame data with header
-    private func createFrameData(sequence: UInt64, timestampUs: UInt64, width: UInt16, height: UInt16, payload: Data, frameType: UInt8 = 0) -> Data {
-        var frameData = Data(capacity: 26 + payload.count)
-        frameData.append(1) // version
+    private func createFrameData(sequence: UInt64, timestampUs: UInt64, width: UInt16, height: UInt16, payload: Data, frameType: UInt8 = 0, isKeyframe: Bool = true) -> Data {
+        var frameData = Data(capacity: 29 + payload.count)
+        frameData.append(3) // version
         frameData.append(frameType) // frame_type
         frameData.append(contentsOf: withUnsafeBytes(of: sequence.bigEndian) { Data($0) })
         frameData.append(contentsOf: withUnsafeBytes(of: timestampUs.bigEndian) { Data($0) })
         frameData.append(contentsOf: withUnsafeBytes(of: width.bigEndian) { Data($0) })
         frameData.append(contentsOf: withUnsafeBytes(of: height.bigEndian) { Data($0) })
         frameData.append(contentsOf: withUnsafeBytes(of: UInt32(payload.count).bigEndian) { Data($0) })
+        frameData.append(1) // color_space: BT.709 (VideoToolbox default)
+        frameData.append(0) // color_range: limited
+        frameData.append(isKeyframe ? 0x01 : 0x00) // flags: bit 0 = keyframe
         frameData.append(payload)
         return frameData
     }
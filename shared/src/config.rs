@@ -1,11 +1,19 @@
 //! Configuration management
 
+use std::net::IpAddr;
+use std::path::Path;
+
 use serde::{Deserialize, Serialize};
 
-use crate::{DEFAULT_MAC_IP, DEFAULT_PORT, DEFAULT_WIN_IP};
+use crate::protocol::{MAX_FRAME_DIMENSION, MAX_FRAME_SIZE};
+use crate::{DEFAULT_FRAME_QUEUE, DEFAULT_MAC_IP, DEFAULT_PORT, DEFAULT_WIN_IP};
 
 /// Application configuration
+///
+/// Fields missing from a config file fall back to `Config::default()`, or
+/// to the config it is loaded over with `Config::load_over`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     /// IP address to bind/connect (depends on role)
     pub bind_address: String,
@@ -24,8 +32,240 @@ pub struct Config {
 
     /// Log directory
     pub log_dir: String,
+
+    /// Whether logs are written to files in `log_dir`, rather than only to
+    /// the console
+    pub log_to_file: bool,
+
+    /// QUIC transport parameters
+    pub transport: TransportTuning,
+
+    /// Largest frame payload to accept, in bytes
+    pub max_frame_size: usize,
+
+    /// Largest frame width or height to accept, in pixels
+    pub max_frame_dimension: u16,
+
+    /// Frames the receiver queues between the network and its display
+    /// before backpressure applies
+    ///
+    /// A short queue keeps latency low but drops frames in bursts; a long
+    /// one rides out bursts but can fall further behind.
+    pub frame_queue: u16,
+}
+
+/// Smallest UDP payload every QUIC path has to carry
+pub const MIN_MTU: u16 = 1200;
+
+/// Largest UDP payload over IPv6
+pub const MAX_MTU: u16 = 65527;
+
+/// QUIC transport parameters
+///
+/// The defaults suit a Thunderbolt Bridge link; slower or higher-latency links
+/// such as Wi-Fi may want smaller windows and a longer idle timeout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransportTuning {
+    /// Bytes the peer may send across all streams before being acknowledged
+    pub receive_window: u32,
+
+    /// Bytes the peer may send on one stream before being acknowledged
+    pub stream_receive_window: u32,
+
+    /// Bytes of unread QUIC datagrams to buffer (0 disables datagrams)
+    pub datagram_buffer: usize,
+
+    /// UDP payload size to start with, in bytes (at least 1200)
+    ///
+    /// Caps the size of a QUIC datagram, and so of a frame sent as one,
+    /// until MTU discovery finds the path takes more.
+    pub initial_mtu: u16,
+
+    /// Largest UDP payload size MTU discovery probes for, in bytes (0
+    /// disables discovery, staying at `initial_mtu`)
+    pub max_mtu: u16,
+
+    /// Keep-alive ping interval in milliseconds (0 disables)
+    pub keep_alive_ms: u64,
+
+    /// Close the connection after this many milliseconds without traffic (0 disables)
+    pub idle_timeout_ms: u64,
+
+    /// ALPN identifiers a receiver accepts or a sender offers, most preferred first
+    pub alpn: Vec<String>,
 }
 
+impl Default for TransportTuning {
+    fn default() -> Self {
+        Self {
+            receive_window: 16 * 1024 * 1024,
+            stream_receive_window: 8 * 1024 * 1024,
+            datagram_buffer: 16 * 1024 * 1024,
+            initial_mtu: MIN_MTU,
+            // A 9000-byte jumbo frame less IPv6 and UDP headers
+            max_mtu: 8952,
+            keep_alive_ms: 5_000,
+            idle_timeout_ms: 60_000,
+            alpn: crate::transport::DEFAULT_ALPN
+                .iter()
+                .map(|alpn| alpn.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl TransportTuning {
+    /// Check the parameters can be applied to a QUIC connection
+    pub fn validate(&self) -> crate::Result<()> {
+        for (field, value) in [
+            ("receive_window", self.receive_window),
+            ("stream_receive_window", self.stream_receive_window),
+        ] {
+            if value == 0 {
+                return Err(crate::Error::config(format!(
+                    "transport.{} must be greater than 0",
+                    field
+                )));
+            }
+        }
+
+        if !(MIN_MTU..=MAX_MTU).contains(&self.initial_mtu) {
+            return Err(crate::Error::config(format!(
+                "transport.initial_mtu must be between {} and {}, got {}",
+                MIN_MTU, MAX_MTU, self.initial_mtu
+            )));
+        }
+        if self.max_mtu != 0 && !(self.initial_mtu..=MAX_MTU).contains(&self.max_mtu) {
+            return Err(crate::Error::config(format!(
+                "transport.max_mtu must be 0 or between initial_mtu ({}) and {}, got {}",
+                self.initial_mtu, MAX_MTU, self.max_mtu
+            )));
+        }
+
+        // QUIC encodes the idle timeout as a 62-bit integer
+        if self.idle_timeout_ms >= 1 << 62 {
+            return Err(crate::Error::config(format!(
+                "transport.idle_timeout_ms is too large, got {}",
+                self.idle_timeout_ms
+            )));
+        }
+
+        if self.alpn.is_empty() {
+            return Err(crate::Error::config("transport.alpn must not be empty"));
+        }
+        // TLS limits each protocol name to 1-255 bytes
+        if let Some(alpn) = self.alpn.iter().find(|a| a.is_empty() || a.len() > 255) {
+            return Err(crate::Error::config(format!(
+                "transport.alpn entries must be 1-255 bytes, got {:?}",
+                alpn
+            )));
+        }
+
+        if self.keep_alive_ms > 0
+            && self.idle_timeout_ms > 0
+            && self.keep_alive_ms >= self.idle_timeout_ms
+        {
+            return Err(crate::Error::config(format!(
+                "transport.keep_alive_ms ({}) must be less than transport.idle_timeout_ms ({})",
+                self.keep_alive_ms, self.idle_timeout_ms
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Chainable setters for `Config`, validated on `build`
+///
+/// `Config::builder().port(9000).mode(StreamMode::Extend).build()`
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl From<Config> for ConfigBuilder {
+    fn from(config: Config) -> Self {
+        Self { config }
+    }
+}
+
+impl ConfigBuilder {
+    /// IP address to bind/connect (depends on role)
+    pub fn bind_address(mut self, address: impl Into<String>) -> Self {
+        self.config.bind_address = address.into();
+        self
+    }
+
+    /// Target IP address for connection
+    pub fn target_address(mut self, address: impl Into<String>) -> Self {
+        self.config.target_address = address.into();
+        self
+    }
+
+    /// Port for streaming
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.port = port;
+        self
+    }
+
+    /// Streaming mode
+    pub fn mode(mut self, mode: StreamMode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    /// Log level
+    pub fn log_level(mut self, level: impl Into<String>) -> Self {
+        self.config.log_level = level.into();
+        self
+    }
+
+    /// Log directory
+    pub fn log_dir(mut self, dir: impl Into<String>) -> Self {
+        self.config.log_dir = dir.into();
+        self
+    }
+
+    /// Write log files to the log directory
+    pub fn log_to_file(mut self, enabled: bool) -> Self {
+        self.config.log_to_file = enabled;
+        self
+    }
+
+    /// QUIC transport parameters
+    pub fn transport(mut self, transport: TransportTuning) -> Self {
+        self.config.transport = transport;
+        self
+    }
+
+    /// Largest frame payload to accept, in bytes
+    pub fn max_frame_size(mut self, bytes: usize) -> Self {
+        self.config.max_frame_size = bytes;
+        self
+    }
+
+    /// Largest frame width or height to accept, in pixels
+    pub fn max_frame_dimension(mut self, pixels: u16) -> Self {
+        self.config.max_frame_dimension = pixels;
+        self
+    }
+
+    /// Frames the receiver queues for its display
+    pub fn frame_queue(mut self, frames: u16) -> Self {
+        self.config.frame_queue = frames;
+        self
+    }
+
+    /// Finish the config, failing if `Config::validate` rejects it
+    pub fn build(self) -> crate::Result<Config> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// Log levels accepted in `Config::log_level`
+const LOG_LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error"];
+
 /// Streaming mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StreamMode {
@@ -45,6 +285,11 @@ impl Default for Config {
             mode: StreamMode::Mirror,
             log_level: "info".to_string(),
             log_dir: "logs".to_string(),
+            log_to_file: false,
+            transport: TransportTuning::default(),
+            max_frame_size: MAX_FRAME_SIZE,
+            max_frame_dimension: MAX_FRAME_DIMENSION,
+            frame_queue: DEFAULT_FRAME_QUEUE,
         }
     }
 }
@@ -67,6 +312,232 @@ impl Config {
             ..Default::default()
         }
     }
+
+    /// Start building a config from the defaults
+    ///
+    /// Use `ConfigBuilder::from(Config::mac_sender())` to start from a role's
+    /// defaults instead.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Check that every field holds a usable value
+    ///
+    /// Errors name the offending field and value so a typo in a config file
+    /// is reported up front instead of failing later inside the transport.
+    pub fn validate(&self) -> crate::Result<()> {
+        for (field, value) in [
+            ("bind_address", &self.bind_address),
+            ("target_address", &self.target_address),
+        ] {
+            if value.parse::<IpAddr>().is_err() {
+                return Err(crate::Error::config(format!(
+                    "{} must be an IP address, got {:?}",
+                    field, value
+                )));
+            }
+        }
+
+        if self.port == 0 {
+            return Err(crate::Error::config(
+                "port must be between 1 and 65535, got 0",
+            ));
+        }
+
+        if !LOG_LEVELS
+            .iter()
+            .any(|level| level.eq_ignore_ascii_case(&self.log_level))
+        {
+            return Err(crate::Error::config(format!(
+                "log_level must be one of {}, got {:?}",
+                LOG_LEVELS.join(", "),
+                self.log_level
+            )));
+        }
+
+        // The frame header stores the payload size in 32 bits
+        if self.max_frame_size == 0 || self.max_frame_size as u64 > u32::MAX as u64 {
+            return Err(crate::Error::config(format!(
+                "max_frame_size must be between 1 and {}, got {}",
+                u32::MAX,
+                self.max_frame_size
+            )));
+        }
+
+        if self.max_frame_dimension == 0 {
+            return Err(crate::Error::config(
+                "max_frame_dimension must be at least 1, got 0",
+            ));
+        }
+
+        if self.frame_queue == 0 {
+            return Err(crate::Error::config(
+                "frame_queue must be at least 1, got 0",
+            ));
+        }
+
+        self.transport.validate()?;
+
+        // Nothing is written to log_dir without file logging, so a read-only
+        // working directory is fine then.
+        if !self.log_to_file {
+            return Ok(());
+        }
+        check_dir_writable(Path::new(&self.log_dir)).map_err(|reason| {
+            crate::Error::config(format!(
+                "log_dir {:?} is not writable: {}",
+                self.log_dir, reason
+            ))
+        })
+    }
+
+    /// Load config from a `.toml` or `.json` file
+    pub fn load_from_path(path: &Path) -> crate::Result<Self> {
+        Self::load_over(path, Self::default())
+    }
+
+    /// Load config from a `.toml` or `.json` file on top of `base`
+    ///
+    /// Keys the file leaves out, also within `[transport]`, keep their value
+    /// in `base`, so a partial file only changes what it names. Pass a role's
+    /// defaults, e.g. `Config::win_receiver()`.
+    pub fn load_over(path: &Path, base: Config) -> crate::Result<Self> {
+        let format = ConfigFormat::from_path(path)?;
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            crate::Error::config(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        let invalid =
+            |e: String| crate::Error::config(format!("Invalid {}: {}", path.display(), e));
+
+        let file: serde_json::Value = match format {
+            ConfigFormat::Toml => toml::from_str(&text).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(&text).map_err(|e| e.to_string()),
+        }
+        .map_err(invalid)?;
+        let mut merged = serde_json::to_value(base).map_err(|e| invalid(e.to_string()))?;
+        overlay(&mut merged, file);
+        serde_json::from_value(merged).map_err(|e| invalid(e.to_string()))
+    }
+
+    /// Save config to a `.toml` or `.json` file, chosen by extension
+    pub fn save_to_path(&self, path: &Path) -> crate::Result<()> {
+        let text = match ConfigFormat::from_path(path)? {
+            ConfigFormat::Toml => toml::to_string_pretty(self).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| crate::Error::config(format!("Failed to serialize config: {}", e)))?;
+
+        std::fs::write(path, text)
+            .map_err(|e| crate::Error::config(format!("Failed to write {}: {}", path.display(), e)))
+    }
+
+    /// Override fields from `THUNDER_*` environment variables
+    ///
+    /// Meant to sit between the config file and command-line flags, so a
+    /// variable beats the file but an explicit flag still wins. Empty
+    /// variables are ignored.
+    pub fn apply_env(&mut self) -> crate::Result<()> {
+        self.apply_vars(|name| std::env::var(name).ok())
+    }
+
+    /// `apply_env` reading variables through `lookup`
+    fn apply_vars(&mut self, lookup: impl Fn(&str) -> Option<String>) -> crate::Result<()> {
+        let var = |name: &str| lookup(name).filter(|value| !value.is_empty());
+
+        if let Some(value) = var("THUNDER_BIND_ADDRESS") {
+            self.bind_address = value;
+        }
+        if let Some(value) = var("THUNDER_TARGET_ADDRESS") {
+            self.target_address = value;
+        }
+        if let Some(value) = var("THUNDER_PORT") {
+            self.port = value.trim().parse().map_err(|_| {
+                crate::Error::config(format!(
+                    "THUNDER_PORT must be a port number, got {:?}",
+                    value
+                ))
+            })?;
+        }
+        if let Some(value) = var("THUNDER_MODE") {
+            self.mode = match value.trim().to_ascii_lowercase().as_str() {
+                "mirror" => StreamMode::Mirror,
+                "extend" => StreamMode::Extend,
+                _ => {
+                    return Err(crate::Error::config(format!(
+                        "THUNDER_MODE must be mirror or extend, got {:?}",
+                        value
+                    )))
+                }
+            };
+        }
+        if let Some(value) = var("THUNDER_LOG_LEVEL") {
+            self.log_level = value;
+        }
+        if let Some(value) = var("THUNDER_LOG_DIR") {
+            self.log_dir = value;
+        }
+        Ok(())
+    }
+}
+
+/// Replace values in `base` with those in `top`, key by key within objects
+fn overlay(base: &mut serde_json::Value, top: serde_json::Value) {
+    match (base, top) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(top)) => {
+            for (key, value) in top {
+                match base.get_mut(&key) {
+                    Some(existing) => overlay(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, top) => *base = top,
+    }
+}
+
+/// Check `dir` can hold log files without creating it
+///
+/// A missing directory is fine as long as its closest existing ancestor is a
+/// writable directory, since logging creates it on startup.
+fn check_dir_writable(dir: &Path) -> std::result::Result<(), String> {
+    let mut existing = dir;
+    while !existing.as_os_str().is_empty() && !existing.exists() {
+        existing = existing.parent().unwrap_or(Path::new(""));
+    }
+    if existing.as_os_str().is_empty() {
+        existing = Path::new(".");
+    }
+
+    if !existing.is_dir() {
+        return Err(format!("{} is not a directory", existing.display()));
+    }
+
+    let probe = existing.join(format!(".thunder_write_test_{}", std::process::id()));
+    std::fs::write(&probe, b"")
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| e.to_string())
+}
+
+/// On-disk config formats, picked by file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    fn from_path(path: &Path) -> crate::Result<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Ok(Self::Toml),
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Ok(Self::Json),
+            _ => Err(crate::Error::config(format!(
+                "Unsupported config file extension: {} (expected .toml or .json)",
+                path.display()
+            ))),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -78,6 +549,10 @@ mod tests {
         let config = Config::default();
         assert_eq!(config.port, 9999);
         assert_eq!(config.mode, StreamMode::Mirror);
+        // The protocol's limit is the single default for every role
+        assert_eq!(config.max_frame_size, MAX_FRAME_SIZE);
+        assert_eq!(Config::win_receiver().max_frame_size, MAX_FRAME_SIZE);
+        assert_eq!(config.max_frame_dimension, MAX_FRAME_DIMENSION);
     }
 
     #[test]
@@ -86,4 +561,291 @@ mod tests {
         assert_eq!(config.bind_address, "192.168.50.1");
         assert_eq!(config.target_address, "192.168.50.2");
     }
+
+    #[test]
+    fn test_builder_sets_fields() {
+        let config = Config::builder()
+            .port(9000)
+            .mode(StreamMode::Extend)
+            .log_level("debug")
+            .build()
+            .unwrap();
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.mode, StreamMode::Extend);
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.bind_address, Config::default().bind_address);
+
+        let config = ConfigBuilder::from(Config::mac_sender())
+            .target_address("10.0.0.2")
+            .build()
+            .unwrap();
+        assert_eq!(config.bind_address, "192.168.50.1");
+        assert_eq!(config.target_address, "10.0.0.2");
+    }
+
+    #[test]
+    fn test_builder_validates() {
+        let err = Config::builder().port(0).build().unwrap_err();
+        assert!(matches!(err, crate::Error::Config(_)), "got {:?}", err);
+
+        let err = Config::builder()
+            .transport(TransportTuning {
+                receive_window: 0,
+                ..Default::default()
+            })
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("receive_window"), "got {}", err);
+    }
+
+    #[test]
+    fn test_validate_defaults() {
+        Config::default().validate().unwrap();
+        Config::mac_sender().validate().unwrap();
+        Config::win_receiver().validate().unwrap();
+    }
+
+    #[test]
+    fn test_validate_names_field_and_value() {
+        let config = Config {
+            target_address: "192.168.50.300".to_string(),
+            ..Default::default()
+        };
+        let msg = config.validate().unwrap_err().to_string();
+        assert!(msg.contains("target_address"), "got {}", msg);
+        assert!(msg.contains("192.168.50.300"), "got {}", msg);
+
+        let config = Config {
+            port: 0,
+            ..Default::default()
+        };
+        assert!(config.validate().unwrap_err().to_string().contains("port"));
+
+        let config = Config {
+            log_level: "verbose".to_string(),
+            ..Default::default()
+        };
+        let msg = config.validate().unwrap_err().to_string();
+        assert!(
+            msg.contains("log_level") && msg.contains("verbose"),
+            "got {}",
+            msg
+        );
+
+        let err = Config::builder().max_frame_size(0).build().unwrap_err();
+        assert!(err.to_string().contains("max_frame_size"), "got {}", err);
+
+        let err = Config::builder()
+            .max_frame_dimension(0)
+            .build()
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("max_frame_dimension"),
+            "got {}",
+            err
+        );
+
+        let err = Config::builder().frame_queue(0).build().unwrap_err();
+        assert!(err.to_string().contains("frame_queue"), "got {}", err);
+        assert_eq!(Config::default().frame_queue, DEFAULT_FRAME_QUEUE);
+    }
+
+    #[test]
+    fn test_validate_transport_tuning() {
+        TransportTuning::default().validate().unwrap();
+
+        let tuning = TransportTuning {
+            stream_receive_window: 0,
+            ..Default::default()
+        };
+        let msg = tuning.validate().unwrap_err().to_string();
+        assert!(msg.contains("stream_receive_window"), "got {}", msg);
+
+        let tuning = TransportTuning {
+            keep_alive_ms: 10_000,
+            idle_timeout_ms: 10_000,
+            ..Default::default()
+        };
+        assert!(tuning.validate().is_err());
+
+        // Either one disabled is fine
+        let tuning = TransportTuning {
+            keep_alive_ms: 10_000,
+            idle_timeout_ms: 0,
+            ..Default::default()
+        };
+        tuning.validate().unwrap();
+
+        for (initial_mtu, max_mtu) in [(1199, 0), (1500, 1400), (1200, 65528)] {
+            let tuning = TransportTuning {
+                initial_mtu,
+                max_mtu,
+                ..Default::default()
+            };
+            let msg = tuning.validate().unwrap_err().to_string();
+            assert!(msg.contains("mtu"), "got {}", msg);
+        }
+        let tuning = TransportTuning {
+            initial_mtu: 1500,
+            max_mtu: 0,
+            ..Default::default()
+        };
+        tuning.validate().unwrap();
+
+        for alpn in [vec![], vec![String::new()], vec!["x".repeat(256)]] {
+            let tuning = TransportTuning {
+                alpn,
+                ..Default::default()
+            };
+            let msg = tuning.validate().unwrap_err().to_string();
+            assert!(msg.contains("transport.alpn"), "got {}", msg);
+        }
+    }
+
+    #[test]
+    fn test_validate_log_dir() {
+        let file = temp_path("not_a_dir");
+        std::fs::write(&file, "").unwrap();
+        let mut config = Config {
+            log_dir: file.join("logs").to_string_lossy().into_owned(),
+            log_to_file: true,
+            ..Default::default()
+        };
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("log_dir"), "got {}", err);
+
+        // Only checked when logs go to files
+        config.log_to_file = false;
+        config.validate().unwrap();
+        std::fs::remove_file(&file).ok();
+
+        // Missing directories are created by logging, so they validate
+        let config = Config {
+            log_dir: temp_path("missing")
+                .join("nested")
+                .to_string_lossy()
+                .into_owned(),
+            log_to_file: true,
+            ..Default::default()
+        };
+        config.validate().unwrap();
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("thunder_config_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        for name in ["round_trip.toml", "round_trip.json"] {
+            let path = temp_path(name);
+            let config = Config {
+                port: 4242,
+                mode: StreamMode::Extend,
+                transport: TransportTuning {
+                    idle_timeout_ms: 120_000,
+                    ..Default::default()
+                },
+                ..Config::win_receiver()
+            };
+            config.save_to_path(&path).unwrap();
+
+            let loaded = Config::load_from_path(&path).unwrap();
+            std::fs::remove_file(&path).ok();
+            assert_eq!(loaded.port, 4242);
+            assert_eq!(loaded.mode, StreamMode::Extend);
+            assert_eq!(loaded.bind_address, "192.168.50.2");
+            assert_eq!(loaded.transport, config.transport);
+        }
+    }
+
+    #[test]
+    fn test_partial_file_uses_defaults() {
+        let path = temp_path("partial.toml");
+        std::fs::write(&path, "port = 7000\n[transport]\nkeep_alive_ms = 1000\n").unwrap();
+        let loaded = Config::load_from_path(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.port, 7000);
+        assert_eq!(loaded.log_level, "info");
+        assert_eq!(loaded.transport.keep_alive_ms, 1000);
+        assert_eq!(
+            loaded.transport.receive_window,
+            TransportTuning::default().receive_window
+        );
+    }
+
+    #[test]
+    fn test_partial_file_over_role_defaults() {
+        let path = temp_path("one_key.toml");
+        std::fs::write(&path, "port = 7000\n").unwrap();
+        let loaded = Config::load_over(&path, Config::win_receiver()).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.port, 7000);
+        assert_eq!(loaded.target_address, DEFAULT_MAC_IP, "kept from the base");
+        assert_eq!(loaded.bind_address, DEFAULT_WIN_IP);
+
+        let path = temp_path("nested.json");
+        std::fs::write(&path, r#"{ "transport": { "keep_alive_ms": 1000 } }"#).unwrap();
+        let base = Config {
+            transport: TransportTuning {
+                idle_timeout_ms: 120_000,
+                ..Default::default()
+            },
+            ..Config::win_receiver()
+        };
+        let loaded = Config::load_over(&path, base).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded.transport.keep_alive_ms, 1000);
+        assert_eq!(loaded.transport.idle_timeout_ms, 120_000);
+        assert_eq!(loaded.target_address, DEFAULT_MAC_IP);
+    }
+
+    #[test]
+    fn test_invalid_files_are_config_errors() {
+        let path = temp_path("invalid.json");
+        std::fs::write(&path, "{ \"port\": \"not a number\" }").unwrap();
+        let err = Config::load_from_path(&path).unwrap_err();
+        std::fs::remove_file(&path).ok();
+        assert!(matches!(err, crate::Error::Config(_)), "got {:?}", err);
+
+        let err = Config::load_from_path(Path::new("config.yaml")).unwrap_err();
+        assert!(matches!(err, crate::Error::Config(_)), "got {:?}", err);
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = [
+            ("THUNDER_PORT", "7100"),
+            ("THUNDER_MODE", "Extend"),
+            ("THUNDER_BIND_ADDRESS", "10.0.0.2"),
+            ("THUNDER_LOG_LEVEL", ""),
+        ];
+        let lookup = |name: &str| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        };
+
+        let mut config = Config::win_receiver();
+        config.apply_vars(lookup).unwrap();
+        assert_eq!(config.port, 7100);
+        assert_eq!(config.mode, StreamMode::Extend);
+        assert_eq!(config.bind_address, "10.0.0.2");
+        assert_eq!(config.target_address, Config::win_receiver().target_address);
+        assert_eq!(config.log_level, "info");
+    }
+
+    #[test]
+    fn test_invalid_env_names_the_variable() {
+        for (name, value) in [("THUNDER_PORT", "99999"), ("THUNDER_MODE", "mirrored")] {
+            let mut config = Config::default();
+            let err = config
+                .apply_vars(|var| (var == name).then(|| value.to_string()))
+                .unwrap_err();
+            assert!(matches!(&err, crate::Error::Config(_)), "got {:?}", err);
+            assert!(err.to_string().contains(name), "got {}", err);
+        }
+    }
 }
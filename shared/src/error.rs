@@ -40,6 +40,18 @@ pub enum Error {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    /// A QUIC connection failed or was closed
+    #[error("Connection error: {0}")]
+    Connection(#[from] quinn::ConnectionError),
+
+    /// TLS setup errors
+    #[error("TLS error: {0}")]
+    Tls(#[from] rustls::Error),
+
+    /// Certificate generation errors
+    #[error("Certificate error: {0}")]
+    Certificate(#[from] rcgen::Error),
+
     /// Generic errors
     #[error("{0}")]
     Other(String),
@@ -71,4 +83,30 @@ mod tests {
         let err = Error::transport("connection failed");
         assert_eq!(err.to_string(), "Transport error: connection failed");
     }
+
+    #[test]
+    fn test_library_errors_keep_their_source() {
+        fn handshake() -> Result<()> {
+            Err(quinn::ConnectionError::TimedOut)?
+        }
+        let err = handshake().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Connection(quinn::ConnectionError::TimedOut)
+        ));
+        assert_eq!(err.to_string(), "Connection error: timed out");
+        assert!(std::error::Error::source(&err).is_some());
+
+        let err = Error::from(rustls::Error::General("no cipher suites".to_string()));
+        assert!(err.to_string().starts_with("TLS error: "), "{}", err);
+        assert!(std::error::Error::source(&err).is_some());
+
+        let err = Error::from(rcgen::Error::KeyGenerationUnavailable);
+        assert!(
+            err.to_string().starts_with("Certificate error: "),
+            "{}",
+            err
+        );
+        assert!(std::error::Error::source(&err).is_some());
+    }
 }
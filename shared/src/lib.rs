@@ -1,20 +1,25 @@
 //! ThunderMirror Shared Library
 //!
 //! This crate provides shared functionality for the ThunderMirror project:
-//! - Transport layer (QUIC/UDP) - Phase 1+
+//! - Transport layer (QUIC/UDP, with a TCP fallback) - Phase 1+
 //! - Streaming protocol definitions
 //! - Statistics and metrics
 //! - Logging utilities
+//! - Recordings and frame sources, for senders that run without capture
+//! - A standalone frame receiver, for embedding the receive side
 
 pub mod config;
 pub mod error;
 pub mod logging;
 pub mod protocol;
+pub mod receiver;
+pub mod recording;
+pub mod source;
 pub mod stats;
 pub mod test_pattern;
 pub mod transport;
 
-pub use config::Config;
+pub use config::{Config, ConfigBuilder, TransportTuning};
 pub use error::{Error, Result};
 
 /// Library version
@@ -29,6 +34,9 @@ pub const DEFAULT_WIN_IP: &str = "192.168.50.2";
 /// Default streaming port
 pub const DEFAULT_PORT: u16 = 9999;
 
+/// Default number of frames the receiver queues for its display
+pub const DEFAULT_FRAME_QUEUE: u16 = 60;
+
 #[cfg(test)]
 mod tests {
     use super::*;
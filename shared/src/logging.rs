@@ -2,64 +2,395 @@
 
 use std::fs;
 use std::path::Path;
+use std::time::{Duration, SystemTime};
 
 use chrono::Local;
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{self, RollingFileAppender};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, MakeWriter};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter, Layer};
+
+/// How log output is split across files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    /// One timestamped file per run (the original behavior)
+    #[default]
+    PerRun,
+    /// Start a new file every hour
+    Hourly,
+    /// Start a new file every day
+    Daily,
+}
+
+/// How each log line is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// tracing's default single-line format (the original behavior)
+    #[default]
+    Full,
+    /// Shorter single lines, with span fields after the message
+    Compact,
+    /// Multi-line and indented, for reading while debugging locally
+    Pretty,
+    /// One JSON object per line, for log aggregators
+    Json,
+}
+
+/// Keeps the background log file writer alive
+///
+/// Log lines are written to disk on a worker thread. Dropping the handle
+/// flushes anything still buffered, so hold it for the lifetime of the
+/// process (`let _logging = ...`, not `let _ = ...`).
+#[must_use = "dropping the handle stops file logging"]
+pub struct LoggingHandle {
+    _guard: WorkerGuard,
+}
 
 /// Initialize logging with file and console output
 ///
+/// Returns an error, instead of panicking, if a global subscriber is
+/// already set.
+///
 /// # Arguments
 /// * `log_dir` - Directory to store log files
 /// * `prefix` - Prefix for log file names (e.g., "mac_sender", "win_receiver")
 /// * `level` - Log level (debug, info, warn, error)
-pub fn init_logging(log_dir: &str, prefix: &str, level: &str) -> crate::Result<()> {
+/// * `rotation` - How output is split across files
+/// * `keep_days` - Delete this prefix's log files older than this many days on
+///   startup (0 keeps everything)
+/// * `console_format` - Layout of the lines printed to the console
+/// * `file_format` - Layout of the lines written to the log files
+pub fn init_logging(
+    log_dir: &str,
+    prefix: &str,
+    level: &str,
+    rotation: Rotation,
+    keep_days: u32,
+    console_format: LogFormat,
+    file_format: LogFormat,
+) -> crate::Result<LoggingHandle> {
     // Ensure log directory exists
     let log_path = Path::new(log_dir);
     if !log_path.exists() {
         fs::create_dir_all(log_path)?;
     }
 
-    // Create timestamped log file name
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let log_file = log_path.join(format!("{}_{}.log", prefix, timestamp));
+    let removed = remove_old_logs(log_path, prefix, keep_days)?;
 
-    // Create file appender
-    let file = fs::File::create(&log_file)?;
+    let (rotation_kind, file_prefix) = match rotation {
+        // Timestamped file name, e.g. win_receiver_20240101_120000.log
+        Rotation::PerRun => (
+            rolling::Rotation::NEVER,
+            format!("{}_{}", prefix, Local::now().format("%Y%m%d_%H%M%S")),
+        ),
+        Rotation::Hourly => (rolling::Rotation::HOURLY, prefix.to_string()),
+        Rotation::Daily => (rolling::Rotation::DAILY, prefix.to_string()),
+    };
+    let file = RollingFileAppender::builder()
+        .rotation(rotation_kind)
+        .filename_prefix(file_prefix)
+        .filename_suffix("log")
+        .build(log_path)
+        .map_err(|e| crate::Error::Other(format!("Failed to create log file: {}", e)))?;
+    let (writer, guard) = tracing_appender::non_blocking(file);
 
     // Build subscriber with both console and file output
     let subscriber = tracing_subscriber::registry()
         .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level)))
-        .with(
-            fmt::layer()
-                .with_target(true)
-                .with_thread_ids(false)
-                .with_file(false),
-        )
-        .with(
-            fmt::layer()
-                .with_writer(file)
-                .with_ansi(false)
-                .with_target(true)
-                .with_thread_ids(true),
+        .with(fmt_layer(console_format, std::io::stdout, true, false))
+        .with(fmt_layer(file_format, writer, false, true));
+
+    tracing::subscriber::set_global_default(subscriber).map_err(already_set)?;
+
+    tracing::info!(
+        "Logging initialized - dir: {:?}, rotation: {:?}",
+        log_path,
+        rotation
+    );
+    if removed > 0 {
+        tracing::info!(
+            "Removed {} log files older than {} days",
+            removed,
+            keep_days
         );
+    }
+
+    Ok(LoggingHandle { _guard: guard })
+}
+
+/// A fmt layer writing `format` lines to `writer`
+fn fmt_layer<S, W>(
+    format: LogFormat,
+    writer: W,
+    ansi: bool,
+    thread_ids: bool,
+) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_target(true)
+        .with_thread_ids(thread_ids)
+        .with_file(false);
+    match format {
+        LogFormat::Full => layer.boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Json => layer.event_format(JsonFormat).boxed(),
+    }
+}
+
+/// Writes each event as one line of JSON
+///
+/// `{"timestamp", "level", "target", "spans", "fields"}`, with the message in
+/// `fields.message`. tracing-subscriber's own JSON format needs an extra
+/// dependency for the same result.
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let mut fields = JsonFields(Map::new());
+        event.record(&mut fields);
+
+        let spans: Vec<Value> = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| span.name().into())
+            .collect();
+
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert("timestamp".into(), Local::now().to_rfc3339().into());
+        line.insert("level".into(), metadata.level().as_str().into());
+        line.insert("target".into(), metadata.target().into());
+        if !spans.is_empty() {
+            line.insert("spans".into(), spans.into());
+        }
+        line.insert("fields".into(), fields.0.into());
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+/// Collects an event's fields, keeping numbers and booleans typed
+struct JsonFields(Map<String, Value>);
+
+impl Visit for JsonFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
 
-    tracing::subscriber::set_global_default(subscriber)
-        .map_err(|e| crate::Error::Other(format!("Failed to set subscriber: {}", e)))?;
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{:?}", value).into());
+    }
+}
 
-    tracing::info!("Logging initialized - file: {:?}", log_file);
+fn already_set(e: impl std::fmt::Display) -> crate::Error {
+    crate::Error::Other(format!("Failed to set subscriber: {}", e))
+}
+
+/// Delete `prefix`'s log files last modified more than `keep_days` days ago
+///
+/// Returns how many files were removed. Files that can't be inspected or
+/// deleted are skipped so a locked file never blocks startup.
+fn remove_old_logs(log_dir: &Path, prefix: &str, keep_days: u32) -> crate::Result<usize> {
+    if keep_days == 0 {
+        return Ok(0);
+    }
+    let max_age = Duration::from_secs(u64::from(keep_days) * 24 * 60 * 60);
+    let now = SystemTime::now();
 
-    Ok(())
+    let mut removed = 0;
+    for entry in fs::read_dir(log_dir)?.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !name.starts_with(prefix) || !name.ends_with(".log") {
+            continue;
+        }
+        let Ok(modified) = entry.metadata().and_then(|m| m.modified()) else {
+            continue;
+        };
+        let expired = now.duration_since(modified).is_ok_and(|age| age > max_age);
+        if expired && fs::remove_file(entry.path()).is_ok() {
+            removed += 1;
+        }
+    }
+    Ok(removed)
 }
 
 /// Initialize simple console-only logging (for quick testing)
+///
+/// If a global subscriber is already set, it stays in place and a warning is
+/// logged through it.
 pub fn init_console_logging(level: &str) {
+    if let Err(e) = try_init_console_logging(level) {
+        tracing::warn!("Console logging not initialized: {}", e);
+    }
+}
+
+/// Like `init_console_logging`, but returns an error if a global subscriber is already set
+pub fn try_init_console_logging(level: &str) -> crate::Result<()> {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
 
-    tracing_subscriber::fmt().with_env_filter(filter).init();
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .try_init()
+        .map_err(already_set)
 }
 
 #[cfg(test)]
 mod tests {
-    // Logging tests are tricky due to global state
-    // Manual testing recommended
+    // The global subscriber can only be set once per process, so only one
+    // test may install it.
+    use super::*;
+
+    #[test]
+    fn test_init_and_flush_on_drop() {
+        let dir = std::env::temp_dir().join(format!("thunder_init_{}", std::process::id()));
+        let dir_str = dir.to_string_lossy().into_owned();
+
+        let init = || {
+            init_logging(
+                &dir_str,
+                "init_test",
+                "info",
+                Rotation::PerRun,
+                0,
+                LogFormat::Compact,
+                LogFormat::Json,
+            )
+        };
+        let handle = init().unwrap();
+        tracing::info!(frames = 3, "flushed by the guard");
+
+        // A second subscriber is refused instead of panicking
+        assert!(init().is_err());
+        assert!(try_init_console_logging("info").is_err());
+        // The infallible variant leaves the existing subscriber alone
+        init_console_logging("info");
+        tracing::info!("still logged to the first subscriber");
+
+        drop(handle);
+        let contents: String = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| fs::read_to_string(e.unwrap().path()).unwrap())
+            .collect();
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(contents.contains("still logged to the first subscriber"));
+
+        // The file layer took its own format
+        let line = contents
+            .lines()
+            .find(|line| line.contains("flushed by the guard"))
+            .unwrap();
+        let json: Value = serde_json::from_str(line).unwrap();
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["fields"]["message"], "flushed by the guard");
+        assert_eq!(json["fields"]["frames"], 3);
+    }
+
+    #[test]
+    fn test_json_format_includes_spans() {
+        #[derive(Clone, Default)]
+        struct Buffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry().with(fmt_layer(
+            LogFormat::Json,
+            move || writer.clone(),
+            false,
+            false,
+        ));
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("connection").entered();
+            tracing::warn!(peer = "10.0.0.2", ok = false, "lost");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let json: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(json["level"], "WARN");
+        assert_eq!(json["spans"], serde_json::json!(["connection"]));
+        assert_eq!(json["fields"]["peer"], "10.0.0.2");
+        assert_eq!(json["fields"]["ok"], false);
+        assert_eq!(json["target"], module_path!());
+    }
+
+    #[test]
+    fn test_remove_old_logs() {
+        let dir = std::env::temp_dir().join(format!("thunder_logs_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let old = SystemTime::now() - Duration::from_secs(10 * 24 * 60 * 60);
+        for name in [
+            "app_old.log",
+            "app.2020-01-01.log",
+            "other_old.log",
+            "app_old.txt",
+        ] {
+            let file = fs::File::create(dir.join(name)).unwrap();
+            file.set_modified(old).unwrap();
+        }
+        fs::File::create(dir.join("app_new.log")).unwrap();
+
+        assert_eq!(
+            remove_old_logs(&dir, "app", 0).unwrap(),
+            0,
+            "0 keeps everything"
+        );
+        assert_eq!(remove_old_logs(&dir, "app", 7).unwrap(), 2);
+
+        let mut left: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(left, ["app_new.log", "app_old.txt", "other_old.log"]);
+    }
 }
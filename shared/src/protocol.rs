@@ -3,17 +3,34 @@
 //! This module defines the wire format for streaming frames between
 //! the Mac sender and Windows receiver.
 
+use std::time::Duration;
+
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::stats::StatsSnapshot;
 
 /// Protocol version
-pub const PROTOCOL_VERSION: u8 = 1;
+pub const PROTOCOL_VERSION: u8 = 5;
+
+/// Oldest protocol version we can still decode
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
 
 /// Maximum frame payload size (8MB)
 pub const MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
 
+/// Largest width or height a frame header may carry by default, in pixels
+///
+/// Above any display or encoder in use (8K is 7680x4320), so a header
+/// claiming more is corrupt or hostile.
+pub const MAX_FRAME_DIMENSION: u16 = 16384;
+
+/// QUIC application close code for a peer whose protocol version we can't speak
+pub const CLOSE_VERSION_MISMATCH: u32 = 1;
+
 /// Frame types
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum FrameType {
     /// Raw RGBA pixel data
@@ -27,6 +44,15 @@ pub enum FrameType {
 
     /// Statistics/heartbeat
     Stats = 3,
+
+    /// Audio samples (payload starts with an `AudioHeader`)
+    Audio = 4,
+
+    /// Changed regions of the previous frame (payload is a list of `DeltaTile`s)
+    DeltaFrame = 5,
+
+    /// Pointer position and, when it changed, image (payload is a `CursorUpdate`)
+    Cursor = 6,
 }
 
 impl TryFrom<u8> for FrameType {
@@ -38,6 +64,9 @@ impl TryFrom<u8> for FrameType {
             1 => Ok(FrameType::H264Frame),
             2 => Ok(FrameType::Control),
             3 => Ok(FrameType::Stats),
+            4 => Ok(FrameType::Audio),
+            5 => Ok(FrameType::DeltaFrame),
+            6 => Ok(FrameType::Cursor),
             _ => Err(crate::Error::protocol(format!(
                 "Unknown frame type: {}",
                 value
@@ -46,7 +75,124 @@ impl TryFrom<u8> for FrameType {
     }
 }
 
-/// Frame header (fixed size: 26 bytes)
+impl FrameType {
+    /// Every frame type, in wire order
+    pub const ALL: [FrameType; 7] = [
+        FrameType::RawFrame,
+        FrameType::H264Frame,
+        FrameType::Control,
+        FrameType::Stats,
+        FrameType::Audio,
+        FrameType::DeltaFrame,
+        FrameType::Cursor,
+    ];
+
+    /// Whether frames of this type carry video and are sequenced at the frame rate
+    pub fn is_video(self) -> bool {
+        matches!(
+            self,
+            FrameType::H264Frame | FrameType::RawFrame | FrameType::DeltaFrame
+        )
+    }
+}
+
+/// YUV color matrix used by the encoder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum ColorSpace {
+    /// ITU-R BT.601 (SD content)
+    Bt601 = 0,
+
+    /// ITU-R BT.709 (HD content, VideoToolbox default)
+    #[default]
+    Bt709 = 1,
+
+    /// ITU-R BT.2020 non-constant luminance (wide gamut and HDR content)
+    Bt2020 = 2,
+}
+
+impl TryFrom<u8> for ColorSpace {
+    type Error = crate::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ColorSpace::Bt601),
+            1 => Ok(ColorSpace::Bt709),
+            2 => Ok(ColorSpace::Bt2020),
+            _ => Err(crate::Error::protocol(format!(
+                "Unknown color space: {}",
+                value
+            ))),
+        }
+    }
+}
+
+/// YUV quantization range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum ColorRange {
+    /// Limited/video range: Y=[16,235], UV=[16,240]
+    #[default]
+    Limited = 0,
+
+    /// Full/PC range: Y, UV = [0,255]
+    Full = 1,
+}
+
+impl TryFrom<u8> for ColorRange {
+    type Error = crate::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ColorRange::Limited),
+            1 => Ok(ColorRange::Full),
+            _ => Err(crate::Error::protocol(format!(
+                "Unknown color range: {}",
+                value
+            ))),
+        }
+    }
+}
+
+/// Transfer function (EOTF) of YUV payloads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum TransferFunction {
+    /// SDR gamma (BT.709 / BT.1886)
+    #[default]
+    Sdr = 0,
+
+    /// HDR10 perceptual quantizer (SMPTE ST 2084)
+    Pq = 1,
+
+    /// Hybrid log-gamma (ARIB STD-B67)
+    Hlg = 2,
+}
+
+impl TransferFunction {
+    /// Whether the content is HDR and needs tone mapping on an SDR display
+    pub fn is_hdr(self) -> bool {
+        self != TransferFunction::Sdr
+    }
+}
+
+impl TryFrom<u8> for TransferFunction {
+    type Error = crate::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(TransferFunction::Sdr),
+            1 => Ok(TransferFunction::Pq),
+            2 => Ok(TransferFunction::Hlg),
+            _ => Err(crate::Error::protocol(format!(
+                "Unknown transfer function: {}",
+                value
+            ))),
+        }
+    }
+}
+
+/// Frame header (39 bytes for v5, 31 for v4, 29 for v3, 28 for v2, 26 for legacy v1)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FrameHeader {
     /// Protocol version
@@ -58,7 +204,7 @@ pub struct FrameHeader {
     /// Frame sequence number
     pub sequence: u64,
 
-    /// Timestamp (microseconds since stream start)
+    /// When encoding finished (microseconds since stream start)
     pub timestamp_us: u64,
 
     /// Frame width (pixels)
@@ -69,12 +215,66 @@ pub struct FrameHeader {
 
     /// Payload size in bytes
     pub payload_size: u32,
+
+    /// Color matrix of YUV payloads (v2+, BT.709 for v1)
+    pub color_space: ColorSpace,
+
+    /// Quantization range of YUV payloads (v2+, limited for v1)
+    pub color_range: ColorRange,
+
+    /// Whether the frame is complete on its own rather than a delta on the
+    /// previous one (v3+, always set for older versions)
+    pub is_keyframe: bool,
+
+    /// Bits per YUV sample, 8 or 10 (v4+, 8 for older versions)
+    pub bit_depth: u8,
+
+    /// Transfer function of YUV payloads (v4+, SDR for older versions)
+    pub transfer: TransferFunction,
+
+    /// When the frame was captured, on the same clock as `timestamp_us`
+    /// (v5+, equal to `timestamp_us` for older versions)
+    pub capture_ts_us: u64,
 }
 
 impl FrameHeader {
-    /// Header size in bytes
+    /// Header size in bytes for the current protocol version
+    /// v4 fields(31) + capture_ts_us(8) = 39
+    pub const SIZE: usize = 39;
+
+    /// Header size in bytes for protocol v4
+    /// v3 fields(29) + bit_depth(1) + transfer(1) = 31
+    pub const V4_SIZE: usize = 31;
+
+    /// Header size in bytes for protocol v3
+    /// v2 fields(28) + flags(1) = 29
+    pub const V3_SIZE: usize = 29;
+
+    /// Header size in bytes for protocol v2
+    /// v1 fields(26) + color_space(1) + color_range(1) = 28
+    pub const V2_SIZE: usize = 28;
+
+    /// Header size in bytes for protocol v1
     /// version(1) + frame_type(1) + sequence(8) + timestamp_us(8) + width(2) + height(2) + payload_size(4) = 26
-    pub const SIZE: usize = 26;
+    pub const V1_SIZE: usize = 26;
+
+    /// Smallest header size of any supported version
+    pub const MIN_SIZE: usize = Self::V1_SIZE;
+
+    /// Flags bit: the frame is a keyframe
+    const FLAG_KEYFRAME: u8 = 0x01;
+
+    /// Header size for a given protocol version, or `None` if unsupported
+    pub fn size_for_version(version: u8) -> Option<usize> {
+        match version {
+            1 => Some(Self::V1_SIZE),
+            2 => Some(Self::V2_SIZE),
+            3 => Some(Self::V3_SIZE),
+            4 => Some(Self::V4_SIZE),
+            5 => Some(Self::SIZE),
+            _ => None,
+        }
+    }
 
     /// Create a new frame header
     pub fn new(
@@ -93,10 +293,58 @@ impl FrameHeader {
             width,
             height,
             payload_size,
+            color_space: ColorSpace::default(),
+            color_range: ColorRange::default(),
+            is_keyframe: frame_type != FrameType::DeltaFrame,
+            bit_depth: 8,
+            transfer: TransferFunction::default(),
+            capture_ts_us: timestamp_us,
         }
     }
 
+    /// Set the color space and range of the payload
+    pub fn with_color(mut self, color_space: ColorSpace, color_range: ColorRange) -> Self {
+        self.color_space = color_space;
+        self.color_range = color_range;
+        self
+    }
+
+    /// Set the sample bit depth and transfer function of the payload
+    pub fn with_hdr(mut self, bit_depth: u8, transfer: TransferFunction) -> Self {
+        self.bit_depth = bit_depth;
+        self.transfer = transfer;
+        self
+    }
+
+    /// Set when the frame was captured, `timestamp_us` being when its
+    /// encoding finished
+    pub fn with_capture_time(mut self, capture_ts_us: u64) -> Self {
+        self.capture_ts_us = capture_ts_us;
+        self
+    }
+
+    /// Time the sender spent between capture and the end of encoding
+    ///
+    /// `None` for headers older than v5, which don't carry a capture time.
+    pub fn encode_time_us(&self) -> Option<u64> {
+        (self.version >= 5).then(|| self.timestamp_us.saturating_sub(self.capture_ts_us))
+    }
+
+    /// Mark the frame as a keyframe or a delta
+    pub fn with_keyframe(mut self, is_keyframe: bool) -> Self {
+        self.is_keyframe = is_keyframe;
+        self
+    }
+
+    /// Size of this header on the wire
+    pub fn encoded_size(&self) -> usize {
+        Self::size_for_version(self.version).unwrap_or(Self::SIZE)
+    }
+
     /// Encode header to bytes
+    ///
+    /// Fields introduced after `self.version` are omitted, so a v1 header
+    /// still encodes to the legacy 26-byte layout.
     pub fn encode(&self, buf: &mut BytesMut) {
         buf.put_u8(self.version);
         buf.put_u8(self.frame_type as u8);
@@ -105,22 +353,53 @@ impl FrameHeader {
         buf.put_u16(self.width);
         buf.put_u16(self.height);
         buf.put_u32(self.payload_size);
+
+        if self.version >= 2 {
+            buf.put_u8(self.color_space as u8);
+            buf.put_u8(self.color_range as u8);
+        }
+
+        if self.version >= 3 {
+            let mut flags = 0;
+            if self.is_keyframe {
+                flags |= Self::FLAG_KEYFRAME;
+            }
+            buf.put_u8(flags);
+        }
+
+        if self.version >= 4 {
+            buf.put_u8(self.bit_depth);
+            buf.put_u8(self.transfer as u8);
+        }
+
+        if self.version >= 5 {
+            buf.put_u64(self.capture_ts_us);
+        }
     }
 
     /// Decode header from bytes
+    ///
+    /// Accepts every version from `MIN_PROTOCOL_VERSION` to `PROTOCOL_VERSION`;
+    /// fields missing from older versions take their defaults.
     pub fn decode(buf: &mut Bytes) -> crate::Result<Self> {
-        if buf.remaining() < Self::SIZE {
+        Self::decode_with_limits(buf, MAX_FRAME_DIMENSION)
+    }
+
+    /// Like `decode`, allowing widths and heights up to `max_dimension` pixels
+    pub fn decode_with_limits(buf: &mut Bytes, max_dimension: u16) -> crate::Result<Self> {
+        if buf.remaining() < Self::MIN_SIZE {
             return Err(crate::Error::protocol("Header too short"));
         }
 
-        let version = buf.get_u8();
-        if version != PROTOCOL_VERSION {
-            return Err(crate::Error::protocol(format!(
-                "Unsupported version: {}",
-                version
-            )));
+        let version = buf.chunk()[0];
+        let size = Self::size_for_version(version)
+            .ok_or_else(|| crate::Error::protocol(format!("Unsupported version: {}", version)))?;
+
+        if buf.remaining() < size {
+            return Err(crate::Error::protocol("Header too short"));
         }
 
+        buf.advance(1);
         let frame_type = FrameType::try_from(buf.get_u8())?;
         let sequence = buf.get_u64();
         let timestamp_us = buf.get_u64();
@@ -128,7 +407,41 @@ impl FrameHeader {
         let height = buf.get_u16();
         let payload_size = buf.get_u32();
 
-        Ok(Self {
+        let (color_space, color_range) = if version >= 2 {
+            (
+                ColorSpace::try_from(buf.get_u8())?,
+                ColorRange::try_from(buf.get_u8())?,
+            )
+        } else {
+            (ColorSpace::default(), ColorRange::default())
+        };
+
+        let is_keyframe = if version >= 3 {
+            buf.get_u8() & Self::FLAG_KEYFRAME != 0
+        } else {
+            true
+        };
+
+        let (bit_depth, transfer) = if version >= 4 {
+            let bit_depth = buf.get_u8();
+            if bit_depth != 8 && bit_depth != 10 {
+                return Err(crate::Error::protocol(format!(
+                    "Unsupported bit depth: {}",
+                    bit_depth
+                )));
+            }
+            (bit_depth, TransferFunction::try_from(buf.get_u8())?)
+        } else {
+            (8, TransferFunction::default())
+        };
+
+        let capture_ts_us = if version >= 5 {
+            buf.get_u64()
+        } else {
+            timestamp_us
+        };
+
+        let header = Self {
             version,
             frame_type,
             sequence,
@@ -136,7 +449,39 @@ impl FrameHeader {
             width,
             height,
             payload_size,
-        })
+            color_space,
+            color_range,
+            is_keyframe,
+            bit_depth,
+            transfer,
+            capture_ts_us,
+        };
+        header.validate_with_limits(max_dimension)?;
+        Ok(header)
+    }
+
+    /// Check the fields against each other: dimensions within
+    /// `max_dimension`, and exactly `width * height * 4` payload bytes for a
+    /// raw frame
+    ///
+    /// Catches lying headers here rather than in buffer math further down.
+    pub fn validate_with_limits(&self, max_dimension: u16) -> crate::Result<()> {
+        if self.width > max_dimension || self.height > max_dimension {
+            return Err(crate::Error::protocol(format!(
+                "Frame too large: {}x{} (limit {} pixels per side)",
+                self.width, self.height, max_dimension
+            )));
+        }
+        if self.frame_type == FrameType::RawFrame {
+            let expected = self.width as u64 * self.height as u64 * 4;
+            if expected != self.payload_size as u64 {
+                return Err(crate::Error::protocol(format!(
+                    "Raw frame size mismatch: {}x{} needs {} bytes, payload is {}",
+                    self.width, self.height, expected, self.payload_size
+                )));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -155,56 +500,2336 @@ impl Frame {
 
     /// Encode frame to bytes
     pub fn encode(&self) -> BytesMut {
-        let mut buf = BytesMut::with_capacity(FrameHeader::SIZE + self.payload.len());
+        let mut buf = BytesMut::with_capacity(self.header.encoded_size() + self.payload.len());
         self.header.encode(&mut buf);
         buf.extend_from_slice(&self.payload);
         buf
     }
+
+    /// Decode a frame from the start of `buf`, leaving any bytes after it
+    ///
+    /// Fails if the header is invalid, its payload is over `MAX_FRAME_SIZE`,
+    /// or `buf` holds less payload than the header says. The payload is
+    /// sliced out of `buf` without copying.
+    pub fn decode(buf: &mut Bytes) -> crate::Result<Self> {
+        Self::decode_with_limit(buf, MAX_FRAME_SIZE)
+    }
+
+    /// Like `decode`, allowing payloads up to `max_payload_size` bytes
+    pub fn decode_with_limit(buf: &mut Bytes, max_payload_size: usize) -> crate::Result<Self> {
+        Self::decode_with_limits(buf, max_payload_size, MAX_FRAME_DIMENSION)
+    }
+
+    /// Like `decode`, allowing payloads up to `max_payload_size` bytes and
+    /// widths and heights up to `max_dimension` pixels
+    pub fn decode_with_limits(
+        buf: &mut Bytes,
+        max_payload_size: usize,
+        max_dimension: u16,
+    ) -> crate::Result<Self> {
+        let header = FrameHeader::decode_with_limits(buf, max_dimension)?;
+        let payload_size = header.payload_size as usize;
+        if payload_size > max_payload_size {
+            return Err(crate::Error::protocol(format!(
+                "Payload too large: {} bytes (limit {})",
+                payload_size, max_payload_size
+            )));
+        }
+        if buf.remaining() < payload_size {
+            return Err(crate::Error::protocol(format!(
+                "Payload size mismatch: expected {}, got {}",
+                payload_size,
+                buf.remaining()
+            )));
+        }
+        let payload = buf.split_to(payload_size);
+        Ok(Self::new(header, payload))
+    }
 }
 
-/// Control message types
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum ControlMessage {
-    /// Start streaming
-    Start { width: u16, height: u16, fps: u8 },
+/// How a byte stream of frames ended, from `FrameDecoder::end_of_stream`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEnd {
+    /// On a frame boundary
+    Clean,
 
-    /// Stop streaming
-    Stop,
+    /// Partway through a frame
+    Truncated {
+        /// Bytes buffered towards the unfinished frame, now lost
+        pending_bytes: usize,
 
-    /// Request keyframe
-    RequestKeyframe,
+        /// The stream was in sync and the bytes began with a header, so only
+        /// the last frame is incomplete and a new connection can carry on
+        /// from the next keyframe. `false` if it ended while resyncing.
+        resumable: bool,
+    },
+}
 
-    /// Resolution change
-    ResolutionChange { width: u16, height: u16 },
+impl StreamEnd {
+    /// `Ok` for a clean end, a protocol error describing the truncation
+    /// otherwise
+    pub fn into_result(self) -> crate::Result<()> {
+        match self {
+            Self::Clean => Ok(()),
+            Self::Truncated {
+                pending_bytes,
+                resumable: true,
+            } => Err(crate::Error::protocol(format!(
+                "Stream ended mid-frame with {} bytes of the frame received",
+                pending_bytes
+            ))),
+            Self::Truncated {
+                pending_bytes,
+                resumable: false,
+            } => Err(crate::Error::protocol(format!(
+                "Stream ended while resyncing with {} unparsed bytes",
+                pending_bytes
+            ))),
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Incremental parser for frames sent back to back on a byte stream
+///
+/// Append bytes with `feed` (or read straight into `buffer_mut`) and call
+/// `decode` until it returns `Ok(None)`. At EOF, `end_of_stream` tells
+/// whether the stream stopped on a frame boundary.
+///
+/// A header whose length can't be trusted, because it has an unsupported
+/// version or a payload over the limit, means the stream is corrupt. The
+/// decoder then drops bytes until the next valid header of the stream's
+/// version instead of giving up. A frame with a usable length but an
+/// otherwise invalid header, such as an unknown frame type, is dropped whole.
+///
+/// Memory stays bounded whatever sizes the sender claims: `reserve` never
+/// lets the buffer hold more than one frame of the largest size, and the
+/// room a big frame needed is given back once it has been decoded.
+#[derive(Debug)]
+pub struct FrameDecoder {
+    buf: BytesMut,
+    max_payload_size: usize,
+    max_dimension: u16,
+    /// Protocol version of the last frame decoded
+    version: Option<u8>,
+    resyncing: bool,
+    skipped: u64,
+}
 
-    #[test]
-    fn test_frame_header_encode_decode() {
-        let header = FrameHeader::new(FrameType::RawFrame, 42, 1000000, 1920, 1080, 8294400);
+impl FrameDecoder {
+    /// Buffer capacity kept from one frame to the next
+    const RETAINED_CAPACITY: usize = 1024 * 1024;
 
-        let mut buf = BytesMut::new();
-        header.encode(&mut buf);
+    /// Create a decoder that treats payloads over `max_payload_size` as corrupt
+    pub fn new(max_payload_size: usize) -> Self {
+        Self {
+            buf: BytesMut::new(),
+            max_payload_size,
+            max_dimension: MAX_FRAME_DIMENSION,
+            version: None,
+            resyncing: false,
+            skipped: 0,
+        }
+    }
 
-        assert_eq!(buf.len(), FrameHeader::SIZE);
+    /// Treat frames wider or taller than `max_dimension` pixels as invalid
+    pub fn with_max_dimension(mut self, max_dimension: u16) -> Self {
+        self.max_dimension = max_dimension;
+        self
+    }
 
-        let mut bytes = buf.freeze();
-        let decoded = FrameHeader::decode(&mut bytes).unwrap();
+    /// Append bytes read from the stream
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
 
-        assert_eq!(decoded.version, PROTOCOL_VERSION);
-        assert_eq!(decoded.frame_type, FrameType::RawFrame);
-        assert_eq!(decoded.sequence, 42);
-        assert_eq!(decoded.width, 1920);
-        assert_eq!(decoded.height, 1080);
+    /// The unparsed bytes, for reading from the stream without a copy
+    pub fn buffer_mut(&mut self) -> &mut BytesMut {
+        &mut self.buf
     }
 
-    #[test]
-    fn test_frame_type_conversion() {
-        assert_eq!(FrameType::try_from(0).unwrap(), FrameType::RawFrame);
-        assert_eq!(FrameType::try_from(1).unwrap(), FrameType::H264Frame);
-        assert!(FrameType::try_from(255).is_err());
+    /// Read up to `chunk` more bytes from `reader`; `false` at EOF
+    ///
+    /// Reads go through `reserve`, so they stop short of `max_buffered`.
+    pub async fn read_from<R: AsyncRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+        chunk: usize,
+    ) -> std::io::Result<bool> {
+        let mut limited = reader.take(self.reserve(chunk) as u64);
+        Ok(limited.read_buf(&mut self.buf).await? > 0)
+    }
+
+    /// Most bytes the decoder ever needs to hold: one frame with the
+    /// largest header and payload
+    pub fn max_buffered(&self) -> usize {
+        FrameHeader::SIZE + self.max_payload_size
+    }
+
+    /// Make room to read up to `chunk` more bytes into `buffer_mut`
+    ///
+    /// Returns how many to read, which is less than `chunk` close to
+    /// `max_buffered` but at least 1. Bytes are only buffered as they
+    /// arrive, so a header claiming a huge payload costs nothing until the
+    /// payload is actually sent.
+    pub fn reserve(&mut self, chunk: usize) -> usize {
+        let room = self
+            .max_buffered()
+            .saturating_sub(self.buf.len())
+            .min(chunk)
+            .max(1);
+        self.buf.reserve(room);
+        room
+    }
+
+    /// Total bytes dropped while resyncing
+    pub fn skipped_bytes(&self) -> u64 {
+        self.skipped
+    }
+
+    /// How the stream ended, once it hit EOF and `decode` returned `None`
+    pub fn end_of_stream(&self) -> StreamEnd {
+        match self.buf.first() {
+            None => StreamEnd::Clean,
+            Some(&version) => StreamEnd::Truncated {
+                pending_bytes: self.buf.len(),
+                resumable: !self.resyncing && FrameHeader::size_for_version(version).is_some(),
+            },
+        }
+    }
+
+    /// The next complete frame, or `None` until more bytes are fed
+    ///
+    /// An error means a frame was dropped or a resync started; decoding can
+    /// carry on with the next call.
+    pub fn decode(&mut self) -> crate::Result<Option<Frame>> {
+        loop {
+            if self.buf.len() < FrameHeader::MIN_SIZE {
+                return Ok(None);
+            }
+            // Newer headers carry extra fields after the v1 layout.
+            let header_size =
+                FrameHeader::size_for_version(self.buf[0]).unwrap_or(FrameHeader::MIN_SIZE);
+            if self.buf.len() < header_size {
+                return Ok(None);
+            }
+
+            // While resyncing only a fully valid header ends the search.
+            let payload_size = if self.resyncing {
+                self.parse_header(&self.buf)
+                    .map(|header| header.payload_size as usize)
+            } else {
+                self.payload_size(&self.buf)
+            };
+            let Some(payload_size) = payload_size else {
+                let started = !self.resyncing;
+                let details = format!(
+                    "Frame length can't be trusted (version {}, type {}, payload {} bytes), resyncing",
+                    self.buf[0],
+                    self.buf[1],
+                    read_payload_size(&self.buf)
+                );
+                self.resyncing = true;
+                self.skip_to_next_header();
+                if started {
+                    return Err(crate::Error::protocol(details));
+                }
+                continue;
+            };
+            self.resyncing = false;
+
+            if self.buf.len() < header_size + payload_size {
+                return Ok(None);
+            }
+            let mut frame = self.buf.split_to(header_size + payload_size).freeze();
+            // The frame keeps the allocation it was read into; the rest of
+            // the stream shouldn't keep the room a big frame needed.
+            if self.buf.capacity() > Self::RETAINED_CAPACITY {
+                self.buf = BytesMut::from(&self.buf[..]);
+            }
+            let frame =
+                Frame::decode_with_limits(&mut frame, self.max_payload_size, self.max_dimension)?;
+            self.version = Some(frame.header.version);
+            return Ok(Some(frame));
+        }
+    }
+
+    /// Payload size of the frame at the start of `buf`, if it can be trusted
+    ///
+    /// payload_size sits at the same offset in every header version, so the
+    /// frame can be delimited even if the rest of the header turns out to be
+    /// invalid.
+    fn payload_size(&self, buf: &[u8]) -> Option<usize> {
+        FrameHeader::size_for_version(buf[0])?;
+        let payload_size = read_payload_size(buf) as usize;
+        (payload_size <= self.max_payload_size).then_some(payload_size)
+    }
+
+    /// The header at the start of `buf`, if `buf` holds a complete and valid
+    /// one of the stream's version
+    ///
+    /// A sender never switches versions mid-stream, so insisting on the same
+    /// version cuts down on false matches while resyncing.
+    fn parse_header(&self, buf: &[u8]) -> Option<FrameHeader> {
+        let header_size = FrameHeader::size_for_version(*buf.first()?)?;
+        if buf.len() < header_size || self.version.is_some_and(|version| version != buf[0]) {
+            return None;
+        }
+        let mut header = Bytes::copy_from_slice(&buf[..header_size]);
+        let header = FrameHeader::decode_with_limits(&mut header, self.max_dimension).ok()?;
+        (header.payload_size as usize <= self.max_payload_size).then_some(header)
+    }
+
+    /// Drop bytes up to the next plausible header
+    ///
+    /// Stops early at a candidate that hasn't fully arrived yet, so it can
+    /// be checked once the rest of it has.
+    fn skip_to_next_header(&mut self) {
+        let skip = (1..self.buf.len())
+            .find(|&offset| {
+                let candidate = &self.buf[offset..];
+                let needed =
+                    FrameHeader::size_for_version(candidate[0]).unwrap_or(FrameHeader::MIN_SIZE);
+                candidate.len() < needed || self.parse_header(candidate).is_some()
+            })
+            .unwrap_or(self.buf.len());
+        self.buf.advance(skip);
+        self.skipped += skip as u64;
+    }
+}
+
+/// The payload_size field of an encoded header (any version)
+fn read_payload_size(header: &[u8]) -> u32 {
+    u32::from_be_bytes([header[22], header[23], header[24], header[25]])
+}
+
+/// One changed region of a `FrameType::DeltaFrame`
+///
+/// A delta payload is a `u16` tile count followed by that many tiles, each a
+/// 12-byte sub-header (x, y, width, height, data length) and its pixel data.
+/// The data uses the same RGBA layout as a `RawFrame`, so it must be exactly
+/// `width * height * 4` bytes. Tiles must lie within the frame header's
+/// dimensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeltaTile {
+    /// Left edge in pixels
+    pub x: u16,
+
+    /// Top edge in pixels
+    pub y: u16,
+
+    /// Tile width in pixels
+    pub width: u16,
+
+    /// Tile height in pixels
+    pub height: u16,
+
+    /// RGBA pixel data, row by row
+    pub data: Bytes,
+}
+
+impl DeltaTile {
+    /// Tile sub-header size in bytes
+    /// x(2) + y(2) + width(2) + height(2) + data_size(4) = 12
+    pub const HEADER_SIZE: usize = 12;
+
+    /// Create a new tile
+    pub fn new(x: u16, y: u16, width: u16, height: u16, data: Bytes) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Encode a list of tiles as a `FrameType::DeltaFrame` payload
+    pub fn encode_all(tiles: &[DeltaTile]) -> crate::Result<Bytes> {
+        let count = u16::try_from(tiles.len())
+            .map_err(|_| crate::Error::protocol(format!("Too many tiles: {}", tiles.len())))?;
+
+        let size: usize = tiles.iter().map(|t| Self::HEADER_SIZE + t.data.len()).sum();
+        let mut buf = BytesMut::with_capacity(2 + size);
+        buf.put_u16(count);
+        for tile in tiles {
+            tile.validate()?;
+            buf.put_u16(tile.x);
+            buf.put_u16(tile.y);
+            buf.put_u16(tile.width);
+            buf.put_u16(tile.height);
+            buf.put_u32(tile.data.len() as u32);
+            buf.extend_from_slice(&tile.data);
+        }
+        Ok(buf.freeze())
+    }
+
+    /// Decode the tiles of a `FrameType::DeltaFrame` payload
+    ///
+    /// Tile data is sliced out of `payload` without copying.
+    pub fn decode_all(payload: &Bytes) -> crate::Result<Vec<DeltaTile>> {
+        let mut buf = payload.clone();
+        if buf.remaining() < 2 {
+            return Err(crate::Error::protocol("Delta frame too short"));
+        }
+
+        let count = buf.get_u16() as usize;
+        let mut tiles = Vec::with_capacity(count.min(buf.remaining() / Self::HEADER_SIZE));
+        for _ in 0..count {
+            if buf.remaining() < Self::HEADER_SIZE {
+                return Err(crate::Error::protocol("Tile header too short"));
+            }
+
+            let x = buf.get_u16();
+            let y = buf.get_u16();
+            let width = buf.get_u16();
+            let height = buf.get_u16();
+            let data_size = buf.get_u32() as usize;
+
+            if buf.remaining() < data_size {
+                return Err(crate::Error::protocol(format!(
+                    "Tile data truncated: expected {}, got {}",
+                    data_size,
+                    buf.remaining()
+                )));
+            }
+
+            let tile = Self::new(x, y, width, height, buf.split_to(data_size));
+            tile.validate()?;
+            tiles.push(tile);
+        }
+
+        if buf.has_remaining() {
+            return Err(crate::Error::protocol(format!(
+                "{} trailing bytes after delta tiles",
+                buf.remaining()
+            )));
+        }
+
+        Ok(tiles)
+    }
+
+    /// Whether the tile lies entirely within a `width` x `height` frame
+    pub fn fits_within(&self, width: u16, height: u16) -> bool {
+        self.x as u32 + self.width as u32 <= width as u32
+            && self.y as u32 + self.height as u32 <= height as u32
+    }
+
+    fn validate(&self) -> crate::Result<()> {
+        let expected = self.width as usize * self.height as usize * 4;
+        if self.data.len() != expected {
+            return Err(crate::Error::protocol(format!(
+                "Tile {}x{} needs {} bytes, got {}",
+                self.width,
+                self.height,
+                expected,
+                self.data.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Payload of a `FrameType::Cursor` frame
+///
+/// Sent when the sender captures without the hardware cursor, so the
+/// receiver can draw it on top of the video. The payload is a 16-byte
+/// sub-header (x, y, hotspot x, hotspot y, width, height, data length)
+/// followed by the cursor image in the same RGBA layout as a `RawFrame`,
+/// with straight (not premultiplied) alpha.
+///
+/// An update that only moves the pointer has no image: width, height and
+/// data length are 0 and the receiver keeps the last image it got.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorUpdate {
+    /// Pointer position in frame pixels; negative or past the edge while
+    /// the pointer is leaving the frame
+    pub x: i16,
+    pub y: i16,
+
+    /// Point of the image that sits at (`x`, `y`)
+    pub hotspot_x: u16,
+    pub hotspot_y: u16,
+
+    /// Image size in pixels (0 x 0 to keep the current image)
+    pub width: u16,
+    pub height: u16,
+
+    /// RGBA pixel data, row by row
+    pub data: Bytes,
+}
+
+impl CursorUpdate {
+    /// Sub-header size in bytes
+    /// x(2) + y(2) + hotspot_x(2) + hotspot_y(2) + width(2) + height(2) + data_size(4) = 16
+    pub const HEADER_SIZE: usize = 16;
+
+    /// An update that only moves the pointer
+    pub fn moved(x: i16, y: i16) -> Self {
+        Self {
+            x,
+            y,
+            hotspot_x: 0,
+            hotspot_y: 0,
+            width: 0,
+            height: 0,
+            data: Bytes::new(),
+        }
+    }
+
+    /// Whether the update carries a new cursor image
+    pub fn has_image(&self) -> bool {
+        self.width > 0 && self.height > 0
+    }
+
+    /// Encode as a `FrameType::Cursor` payload
+    pub fn encode(&self) -> crate::Result<Bytes> {
+        self.validate()?;
+        let mut buf = BytesMut::with_capacity(Self::HEADER_SIZE + self.data.len());
+        buf.put_i16(self.x);
+        buf.put_i16(self.y);
+        buf.put_u16(self.hotspot_x);
+        buf.put_u16(self.hotspot_y);
+        buf.put_u16(self.width);
+        buf.put_u16(self.height);
+        buf.put_u32(self.data.len() as u32);
+        buf.extend_from_slice(&self.data);
+        Ok(buf.freeze())
+    }
+
+    /// Decode a `FrameType::Cursor` payload
+    ///
+    /// The image is sliced out of `payload` without copying.
+    pub fn decode(payload: &Bytes) -> crate::Result<Self> {
+        let mut buf = payload.clone();
+        if buf.remaining() < Self::HEADER_SIZE {
+            return Err(crate::Error::protocol("Cursor header too short"));
+        }
+
+        let x = buf.get_i16();
+        let y = buf.get_i16();
+        let hotspot_x = buf.get_u16();
+        let hotspot_y = buf.get_u16();
+        let width = buf.get_u16();
+        let height = buf.get_u16();
+        let data_size = buf.get_u32() as usize;
+
+        if buf.remaining() != data_size {
+            return Err(crate::Error::protocol(format!(
+                "Cursor data size mismatch: expected {}, got {}",
+                data_size,
+                buf.remaining()
+            )));
+        }
+
+        let update = Self {
+            x,
+            y,
+            hotspot_x,
+            hotspot_y,
+            width,
+            height,
+            data: buf,
+        };
+        update.validate()?;
+        Ok(update)
+    }
+
+    /// Wrap this update in a complete cursor frame
+    pub fn to_frame(&self, sequence: u64, timestamp_us: u64) -> crate::Result<Frame> {
+        let payload = self.encode()?;
+        let header = FrameHeader::new(
+            FrameType::Cursor,
+            sequence,
+            timestamp_us,
+            0,
+            0,
+            payload.len() as u32,
+        );
+        Ok(Frame::new(header, payload))
+    }
+
+    fn validate(&self) -> crate::Result<()> {
+        let expected = self.width as usize * self.height as usize * 4;
+        if self.data.len() != expected {
+            return Err(crate::Error::protocol(format!(
+                "Cursor {}x{} needs {} bytes, got {}",
+                self.width,
+                self.height,
+                expected,
+                self.data.len()
+            )));
+        }
+        if self.has_image() && (self.hotspot_x >= self.width || self.hotspot_y >= self.height) {
+            return Err(crate::Error::protocol(format!(
+                "Cursor hotspot ({}, {}) outside the {}x{} image",
+                self.hotspot_x, self.hotspot_y, self.width, self.height
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Audio codecs carried in `FrameType::Audio` payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum AudioCodec {
+    /// Interleaved signed 16-bit little-endian PCM (CoreAudio native)
+    Pcm = 0,
+
+    /// Opus packets
+    Opus = 1,
+}
+
+impl AudioCodec {
+    /// Sample rates an Opus stream can be encoded at
+    pub const OPUS_SAMPLE_RATES: [u32; 5] = [8000, 12000, 16000, 24000, 48000];
+
+    /// The codec a sender should encode with
+    ///
+    /// The receiver's preference wins: the first codec it can decode that
+    /// the sender can encode. Every peer speaks PCM, so that's the fallback,
+    /// including for peers from before codecs were negotiated.
+    pub fn negotiate(receiver: &[AudioCodec], sender: &[AudioCodec]) -> AudioCodec {
+        receiver
+            .iter()
+            .copied()
+            .find(|codec| sender.contains(codec))
+            .unwrap_or(AudioCodec::Pcm)
+    }
+}
+
+impl TryFrom<u8> for AudioCodec {
+    type Error = crate::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AudioCodec::Pcm),
+            1 => Ok(AudioCodec::Opus),
+            _ => Err(crate::Error::protocol(format!(
+                "Unknown audio codec: {}",
+                value
+            ))),
+        }
+    }
+}
+
+/// Audio sub-header at the start of every `FrameType::Audio` payload (fixed size: 6 bytes)
+///
+/// The encoded audio data follows immediately. The frame header's
+/// `timestamp_us` is the presentation time of the first sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioHeader {
+    /// Sample rate in Hz
+    pub sample_rate: u32,
+
+    /// Number of interleaved channels
+    pub channels: u8,
+
+    /// Codec of the audio data
+    pub codec: AudioCodec,
+}
+
+impl AudioHeader {
+    /// Sub-header size in bytes
+    /// sample_rate(4) + channels(1) + codec(1) = 6
+    pub const SIZE: usize = 6;
+
+    /// Create a new audio sub-header
+    pub fn new(sample_rate: u32, channels: u8, codec: AudioCodec) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            codec,
+        }
+    }
+
+    /// Encode sub-header to bytes
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u32(self.sample_rate);
+        buf.put_u8(self.channels);
+        buf.put_u8(self.codec as u8);
+    }
+
+    /// Decode sub-header from the start of an audio payload
+    pub fn decode(buf: &mut Bytes) -> crate::Result<Self> {
+        if buf.remaining() < Self::SIZE {
+            return Err(crate::Error::protocol("Audio header too short"));
+        }
+
+        let sample_rate = buf.get_u32();
+        let channels = buf.get_u8();
+        let codec = AudioCodec::try_from(buf.get_u8())?;
+
+        if sample_rate == 0 || channels == 0 {
+            return Err(crate::Error::protocol(format!(
+                "Invalid audio format: {} Hz, {} channels",
+                sample_rate, channels
+            )));
+        }
+        if codec == AudioCodec::Opus
+            && (!AudioCodec::OPUS_SAMPLE_RATES.contains(&sample_rate) || channels > 2)
+        {
+            return Err(crate::Error::protocol(format!(
+                "Opus can't carry {} Hz with {} channels",
+                sample_rate, channels
+            )));
+        }
+
+        Ok(Self {
+            sample_rate,
+            channels,
+            codec,
+        })
+    }
+}
+
+/// Magic bytes at the start of a recording file
+pub const RECORDING_MAGIC: &[u8; 6] = b"THMREC";
+
+/// Recording format version
+///
+/// Version 1 files start with `THMREC01` and have neither header fields nor
+/// an index.
+pub const RECORDING_VERSION: u16 = 2;
+
+/// Magic bytes at the very end of a recording with a keyframe index
+pub const RECORDING_INDEX_MAGIC: &[u8; 4] = b"TIDX";
+
+/// Header at the start of a recording file
+///
+/// A recording is this header, then entries, then a `RecordingIndex`. The
+/// header is written with no resolution or codec and filled in when the
+/// recording is finished, so a file cut short by a crash keeps its entries
+/// but has neither.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordingHeader {
+    /// Format version of the file
+    pub version: u16,
+
+    /// Resolution of the first recorded keyframe (0 if none)
+    pub width: u16,
+    pub height: u16,
+
+    /// Video codec of the first recorded keyframe
+    pub codec: Option<FrameType>,
+}
+
+impl Default for RecordingHeader {
+    fn default() -> Self {
+        Self {
+            version: RECORDING_VERSION,
+            width: 0,
+            height: 0,
+            codec: None,
+        }
+    }
+}
+
+impl RecordingHeader {
+    /// Header size in bytes
+    /// magic(6) + version(2) + width(2) + height(2) + codec(1) = 13
+    pub const SIZE: usize = 13;
+
+    /// `codec` byte when no video was recorded
+    const NO_CODEC: u8 = 0xFF;
+
+    /// Encode header to bytes
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.extend_from_slice(RECORDING_MAGIC);
+        buf.put_u16(self.version);
+        buf.put_u16(self.width);
+        buf.put_u16(self.height);
+        buf.put_u8(self.codec.map_or(Self::NO_CODEC, |codec| codec as u8));
+    }
+
+    /// Decode header from bytes
+    pub fn decode(buf: &mut Bytes) -> crate::Result<Self> {
+        if buf.remaining() < Self::SIZE {
+            return Err(crate::Error::protocol("Recording header too short"));
+        }
+        if &buf[..RECORDING_MAGIC.len()] != RECORDING_MAGIC {
+            return Err(crate::Error::protocol("Not a ThunderMirror recording"));
+        }
+        buf.advance(RECORDING_MAGIC.len());
+
+        let version = buf.get_u16();
+        if version != RECORDING_VERSION {
+            return Err(crate::Error::protocol(format!(
+                "Unsupported recording version: {}",
+                version
+            )));
+        }
+        let width = buf.get_u16();
+        let height = buf.get_u16();
+        let codec = match buf.get_u8() {
+            Self::NO_CODEC => None,
+            codec => Some(FrameType::try_from(codec)?),
+        };
+
+        Ok(Self {
+            version,
+            width,
+            height,
+            codec,
+        })
+    }
+}
+
+/// Where a keyframe starts in a recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordingKeyframe {
+    /// Sequence number from the frame header
+    pub sequence: u64,
+
+    /// Arrival time in microseconds since recording started
+    pub offset_us: u64,
+
+    /// Byte offset of the keyframe's entry from the start of the file
+    pub position: u64,
+}
+
+/// Keyframe index at the end of a recording
+///
+/// Stored as the keyframes in recording order, then their count (u32) and
+/// `RECORDING_INDEX_MAGIC`, so a reader finds it from the end of the file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordingIndex {
+    pub keyframes: Vec<RecordingKeyframe>,
+}
+
+impl RecordingIndex {
+    /// Size of one keyframe in bytes
+    /// sequence(8) + offset_us(8) + position(8) = 24
+    pub const KEYFRAME_SIZE: usize = 24;
+
+    /// Trailer size in bytes
+    /// count(4) + magic(4) = 8
+    pub const TRAILER_SIZE: usize = 8;
+
+    /// Size of the whole index on disk
+    pub fn encoded_size(&self) -> usize {
+        self.keyframes.len() * Self::KEYFRAME_SIZE + Self::TRAILER_SIZE
+    }
+
+    /// Encode the index and trailer to bytes
+    pub fn encode(&self, buf: &mut BytesMut) {
+        for keyframe in &self.keyframes {
+            buf.put_u64(keyframe.sequence);
+            buf.put_u64(keyframe.offset_us);
+            buf.put_u64(keyframe.position);
+        }
+        buf.put_u32(self.keyframes.len() as u32);
+        buf.extend_from_slice(RECORDING_INDEX_MAGIC);
+    }
+
+    /// Number of keyframes in the index ending with `trailer`
+    ///
+    /// Returns `None` if the file has no index, e.g. because recording
+    /// was interrupted.
+    pub fn decode_trailer(trailer: &[u8]) -> Option<usize> {
+        let mut buf = trailer;
+        if buf.remaining() < Self::TRAILER_SIZE || &trailer[4..8] != RECORDING_INDEX_MAGIC {
+            return None;
+        }
+        Some(buf.get_u32() as usize)
+    }
+
+    /// Decode a complete index, trailer included
+    pub fn decode(buf: &mut Bytes) -> crate::Result<Self> {
+        let count = buf
+            .len()
+            .checked_sub(Self::TRAILER_SIZE)
+            .and_then(|start| Self::decode_trailer(&buf[start..]))
+            .ok_or_else(|| crate::Error::protocol("Recording index trailer missing"))?;
+        if buf.remaining() != count * Self::KEYFRAME_SIZE + Self::TRAILER_SIZE {
+            return Err(crate::Error::protocol(format!(
+                "Recording index has {} bytes for {} keyframes",
+                buf.remaining(),
+                count
+            )));
+        }
+
+        let keyframes = (0..count)
+            .map(|_| RecordingKeyframe {
+                sequence: buf.get_u64(),
+                offset_us: buf.get_u64(),
+                position: buf.get_u64(),
+            })
+            .collect();
+        buf.advance(Self::TRAILER_SIZE);
+        Ok(Self { keyframes })
+    }
+
+    /// The last keyframe at or before `sequence`
+    pub fn find(&self, sequence: u64) -> Option<&RecordingKeyframe> {
+        self.keyframes
+            .iter()
+            .rev()
+            .find(|keyframe| keyframe.sequence <= sequence)
+    }
+}
+
+/// One received frame in a recording file
+///
+/// Each entry is a 12-byte prefix (offset, frame length) and the frame
+/// exactly as it arrived on the wire, header included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingEntry {
+    /// Arrival time in microseconds since recording started
+    pub offset_us: u64,
+
+    /// Encoded frame (header + payload)
+    pub frame: Bytes,
+}
+
+impl RecordingEntry {
+    /// Entry prefix size in bytes
+    /// offset_us(8) + frame_len(4) = 12
+    pub const PREFIX_SIZE: usize = 12;
+
+    /// Create a new entry
+    pub fn new(offset_us: u64, frame: Bytes) -> Self {
+        Self { offset_us, frame }
+    }
+
+    /// Encode entry to bytes
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u64(self.offset_us);
+        buf.put_u32(self.frame.len() as u32);
+        buf.extend_from_slice(&self.frame);
+    }
+
+    /// Decode the offset and frame length from an entry prefix
+    ///
+    /// Lets a reader size its next read before the frame itself is available.
+    pub fn decode_prefix(prefix: &[u8]) -> crate::Result<(u64, usize)> {
+        let mut buf = prefix;
+        if buf.remaining() < Self::PREFIX_SIZE {
+            return Err(crate::Error::protocol("Recording entry prefix too short"));
+        }
+        Ok((buf.get_u64(), buf.get_u32() as usize))
+    }
+
+    /// Decode a complete entry
+    pub fn decode(buf: &mut Bytes) -> crate::Result<Self> {
+        let (offset_us, len) = Self::decode_prefix(buf)?;
+        if buf.remaining() < Self::PREFIX_SIZE + len {
+            return Err(crate::Error::protocol(format!(
+                "Recording entry truncated: expected {} frame bytes, got {}",
+                len,
+                buf.remaining() - Self::PREFIX_SIZE
+            )));
+        }
+        buf.advance(Self::PREFIX_SIZE);
+        Ok(Self::new(offset_us, buf.split_to(len)))
+    }
+}
+
+/// Video codecs a receiver can ask the sender to encode with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VideoCodec {
+    /// Uncompressed `FrameType::RawFrame` pixels, and delta tiles
+    Raw,
+
+    /// `FrameType::H264Frame` access units
+    H264,
+
+    /// A codec added after this build; the sender keeps its own choice
+    #[serde(other)]
+    Unknown,
+}
+
+/// H.264 profiles a receiver can ask for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum H264Profile {
+    Baseline,
+    Main,
+    High,
+
+    /// A profile added after this build; the sender keeps its own choice
+    #[serde(other)]
+    Unknown,
+}
+
+/// Control message types
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ControlMessage {
+    /// First frame a sender sends on its control stream: the protocol
+    /// version it speaks and the `CAP_*` features it supports
+    Hello {
+        protocol_version: u8,
+        capabilities: u32,
+        /// Audio codecs the sender can encode; empty (older senders) means PCM only
+        #[serde(default)]
+        audio_codecs: Vec<AudioCodec>,
+    },
+
+    /// The receiver's own version and features, sent first on every control
+    /// stream; a receiver that can't speak the sender's version closes the
+    /// connection with `CLOSE_VERSION_MISMATCH` instead of answering
+    HelloAck {
+        protocol_version: u8,
+        capabilities: u32,
+        /// Audio codecs the receiver can decode, most preferred first; the
+        /// sender picks with `AudioCodec::negotiate`
+        #[serde(default)]
+        audio_codecs: Vec<AudioCodec>,
+    },
+
+    /// Start streaming, encoded the way the receiver prefers
+    ///
+    /// A `width` and `height` of 0 and `fps` 0 leave the size and rate to
+    /// the sender, as `None` does for the rest. Messages from peers that
+    /// predate a field decode it as `None`.
+    Start {
+        width: u16,
+        height: u16,
+        fps: u8,
+        /// Target bitrate in kilobits per second, until a `SetBitrate`
+        #[serde(default)]
+        bitrate_kbps: Option<u32>,
+        #[serde(default)]
+        codec: Option<VideoCodec>,
+        /// Only meaningful with `VideoCodec::H264`
+        #[serde(default)]
+        profile: Option<H264Profile>,
+    },
+
+    /// Stop streaming
+    Stop,
+
+    /// Request keyframe
+    RequestKeyframe,
+
+    /// Resolution change
+    ResolutionChange { width: u16, height: u16 },
+
+    /// Sender wall-clock time (microseconds since the UNIX epoch) at which
+    /// `timestamp_us` was zero, so the receiver can compute one-way latency
+    ClockSync { sender_epoch_us: u64 },
+
+    /// Sender clipboard contents (receivers currently apply `text/plain` only)
+    Clipboard { mime: String, data: Vec<u8> },
+
+    /// Ask the sender to capture at most `fps` frames per second
+    /// (0 = as fast as the display produces them)
+    SetFps { fps: u8 },
+
+    /// Ask the sender to encode at about `kbps` kilobits per second,
+    /// suggested by the receiver from the loss and round-trip time it sees
+    SetBitrate { kbps: u32 },
+
+    /// Keyboard or mouse event from the receiver for the sender to synthesize
+    Input(InputEvent),
+
+    /// The sender's pointer was hidden (`true`, e.g. while typing) or shown
+    /// again; the receiver stops drawing `FrameType::Cursor` images meanwhile
+    CursorHidden { hidden: bool },
+}
+
+/// Remote-control input captured by the receiver
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    /// Pointer position, normalized to the frame (0.0-1.0 from the top left)
+    MouseMove { x: f32, y: f32 },
+
+    /// Mouse button pressed or released
+    MouseButton { button: MouseButton, down: bool },
+
+    /// Key pressed or released, with `modifiers` the `MOD_*` bits held at
+    /// the time
+    ///
+    /// `repeat` marks the key-downs sent while a key is held, after the
+    /// first one. Older receivers never set it.
+    KeyEvent {
+        keycode: KeyCode,
+        down: bool,
+        modifiers: u8,
+        #[serde(default)]
+        repeat: bool,
+    },
+}
+
+impl InputEvent {
+    pub const MOD_SHIFT: u8 = 0x01;
+    pub const MOD_CONTROL: u8 = 0x02;
+    pub const MOD_ALT: u8 = 0x04;
+    /// Windows key on the receiver, Command on the Mac
+    pub const MOD_META: u8 = 0x08;
+}
+
+/// Keyboard key, sent as its USB HID keyboard usage ID (page 0x07)
+///
+/// Usage IDs name physical key positions the same way on every platform, so
+/// neither side needs the other's key codes. Keys are serialized as the
+/// plain number; new ones may be added but existing IDs never change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "u16", try_from = "u16")]
+#[repr(u16)]
+pub enum KeyCode {
+    A = 0x04,
+    B = 0x05,
+    C = 0x06,
+    D = 0x07,
+    E = 0x08,
+    F = 0x09,
+    G = 0x0a,
+    H = 0x0b,
+    I = 0x0c,
+    J = 0x0d,
+    K = 0x0e,
+    L = 0x0f,
+    M = 0x10,
+    N = 0x11,
+    O = 0x12,
+    P = 0x13,
+    Q = 0x14,
+    R = 0x15,
+    S = 0x16,
+    T = 0x17,
+    U = 0x18,
+    V = 0x19,
+    W = 0x1a,
+    X = 0x1b,
+    Y = 0x1c,
+    Z = 0x1d,
+    Digit1 = 0x1e,
+    Digit2 = 0x1f,
+    Digit3 = 0x20,
+    Digit4 = 0x21,
+    Digit5 = 0x22,
+    Digit6 = 0x23,
+    Digit7 = 0x24,
+    Digit8 = 0x25,
+    Digit9 = 0x26,
+    Digit0 = 0x27,
+    Enter = 0x28,
+    Escape = 0x29,
+    Backspace = 0x2a,
+    Tab = 0x2b,
+    Space = 0x2c,
+    Minus = 0x2d,
+    Equal = 0x2e,
+    LeftBracket = 0x2f,
+    RightBracket = 0x30,
+    Backslash = 0x31,
+    Semicolon = 0x33,
+    Apostrophe = 0x34,
+    Backquote = 0x35,
+    Comma = 0x36,
+    Period = 0x37,
+    Slash = 0x38,
+    CapsLock = 0x39,
+    F1 = 0x3a,
+    F2 = 0x3b,
+    F3 = 0x3c,
+    F4 = 0x3d,
+    F5 = 0x3e,
+    F6 = 0x3f,
+    F7 = 0x40,
+    F8 = 0x41,
+    F9 = 0x42,
+    F10 = 0x43,
+    F11 = 0x44,
+    F12 = 0x45,
+    ScrollLock = 0x47,
+    Pause = 0x48,
+    Insert = 0x49,
+    Home = 0x4a,
+    PageUp = 0x4b,
+    Delete = 0x4c,
+    End = 0x4d,
+    PageDown = 0x4e,
+    Right = 0x4f,
+    Left = 0x50,
+    Down = 0x51,
+    Up = 0x52,
+    NumLock = 0x53,
+    NumPadSlash = 0x54,
+    NumPadAsterisk = 0x55,
+    NumPadMinus = 0x56,
+    NumPadPlus = 0x57,
+    NumPadEnter = 0x58,
+    NumPad1 = 0x59,
+    NumPad2 = 0x5a,
+    NumPad3 = 0x5b,
+    NumPad4 = 0x5c,
+    NumPad5 = 0x5d,
+    NumPad6 = 0x5e,
+    NumPad7 = 0x5f,
+    NumPad8 = 0x60,
+    NumPad9 = 0x61,
+    NumPad0 = 0x62,
+    NumPadDot = 0x63,
+    Menu = 0x65,
+    F13 = 0x68,
+    F14 = 0x69,
+    F15 = 0x6a,
+    LeftCtrl = 0xe0,
+    LeftShift = 0xe1,
+    LeftAlt = 0xe2,
+    LeftSuper = 0xe3,
+    RightCtrl = 0xe4,
+    RightShift = 0xe5,
+    RightAlt = 0xe6,
+    RightSuper = 0xe7,
+}
+
+impl KeyCode {
+    /// Every key, in usage ID order
+    pub const ALL: [KeyCode; 106] = [
+        KeyCode::A,
+        KeyCode::B,
+        KeyCode::C,
+        KeyCode::D,
+        KeyCode::E,
+        KeyCode::F,
+        KeyCode::G,
+        KeyCode::H,
+        KeyCode::I,
+        KeyCode::J,
+        KeyCode::K,
+        KeyCode::L,
+        KeyCode::M,
+        KeyCode::N,
+        KeyCode::O,
+        KeyCode::P,
+        KeyCode::Q,
+        KeyCode::R,
+        KeyCode::S,
+        KeyCode::T,
+        KeyCode::U,
+        KeyCode::V,
+        KeyCode::W,
+        KeyCode::X,
+        KeyCode::Y,
+        KeyCode::Z,
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+        KeyCode::Digit0,
+        KeyCode::Enter,
+        KeyCode::Escape,
+        KeyCode::Backspace,
+        KeyCode::Tab,
+        KeyCode::Space,
+        KeyCode::Minus,
+        KeyCode::Equal,
+        KeyCode::LeftBracket,
+        KeyCode::RightBracket,
+        KeyCode::Backslash,
+        KeyCode::Semicolon,
+        KeyCode::Apostrophe,
+        KeyCode::Backquote,
+        KeyCode::Comma,
+        KeyCode::Period,
+        KeyCode::Slash,
+        KeyCode::CapsLock,
+        KeyCode::F1,
+        KeyCode::F2,
+        KeyCode::F3,
+        KeyCode::F4,
+        KeyCode::F5,
+        KeyCode::F6,
+        KeyCode::F7,
+        KeyCode::F8,
+        KeyCode::F9,
+        KeyCode::F10,
+        KeyCode::F11,
+        KeyCode::F12,
+        KeyCode::ScrollLock,
+        KeyCode::Pause,
+        KeyCode::Insert,
+        KeyCode::Home,
+        KeyCode::PageUp,
+        KeyCode::Delete,
+        KeyCode::End,
+        KeyCode::PageDown,
+        KeyCode::Right,
+        KeyCode::Left,
+        KeyCode::Down,
+        KeyCode::Up,
+        KeyCode::NumLock,
+        KeyCode::NumPadSlash,
+        KeyCode::NumPadAsterisk,
+        KeyCode::NumPadMinus,
+        KeyCode::NumPadPlus,
+        KeyCode::NumPadEnter,
+        KeyCode::NumPad1,
+        KeyCode::NumPad2,
+        KeyCode::NumPad3,
+        KeyCode::NumPad4,
+        KeyCode::NumPad5,
+        KeyCode::NumPad6,
+        KeyCode::NumPad7,
+        KeyCode::NumPad8,
+        KeyCode::NumPad9,
+        KeyCode::NumPad0,
+        KeyCode::NumPadDot,
+        KeyCode::Menu,
+        KeyCode::F13,
+        KeyCode::F14,
+        KeyCode::F15,
+        KeyCode::LeftCtrl,
+        KeyCode::LeftShift,
+        KeyCode::LeftAlt,
+        KeyCode::LeftSuper,
+        KeyCode::RightCtrl,
+        KeyCode::RightShift,
+        KeyCode::RightAlt,
+        KeyCode::RightSuper,
+    ];
+
+    /// Shift, Control, Alt and Super, which don't auto-repeat
+    pub fn is_modifier(self) -> bool {
+        matches!(
+            self,
+            KeyCode::LeftCtrl
+                | KeyCode::LeftShift
+                | KeyCode::LeftAlt
+                | KeyCode::LeftSuper
+                | KeyCode::RightCtrl
+                | KeyCode::RightShift
+                | KeyCode::RightAlt
+                | KeyCode::RightSuper
+        )
+    }
+}
+
+impl From<KeyCode> for u16 {
+    fn from(key: KeyCode) -> u16 {
+        key as u16
+    }
+}
+
+impl TryFrom<u16> for KeyCode {
+    type Error = crate::Error;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        KeyCode::ALL
+            .iter()
+            .copied()
+            .find(|&key| key as u16 == value)
+            .ok_or_else(|| crate::Error::protocol(format!("Unknown key code: {:#04x}", value)))
+    }
+}
+
+/// Mouse buttons that can be forwarded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+impl ControlMessage {
+    pub const CAP_AUDIO: u32 = 1 << 0;
+    pub const CAP_CURSOR: u32 = 1 << 1;
+    pub const CAP_DELTA_FRAMES: u32 = 1 << 2;
+    pub const CAP_INPUT: u32 = 1 << 3;
+    pub const CAP_CLIPBOARD: u32 = 1 << 4;
+    /// 10-bit video with PQ or HLG transfer
+    pub const CAP_HDR: u32 = 1 << 5;
+
+    /// Encode as a `FrameType::Control` payload (JSON)
+    pub fn encode(&self) -> crate::Result<Bytes> {
+        serde_json::to_vec(self)
+            .map(Bytes::from)
+            .map_err(|e| crate::Error::protocol(format!("Failed to encode control message: {}", e)))
+    }
+
+    /// Decode from a `FrameType::Control` payload
+    pub fn decode(payload: &[u8]) -> crate::Result<Self> {
+        serde_json::from_slice(payload)
+            .map_err(|e| crate::Error::protocol(format!("Invalid control message: {}", e)))
+    }
+
+    /// Wrap this message in a complete control frame
+    pub fn to_frame(&self, sequence: u64, timestamp_us: u64) -> crate::Result<Frame> {
+        let payload = self.encode()?;
+        let header = FrameHeader::new(
+            FrameType::Control,
+            sequence,
+            timestamp_us,
+            0,
+            0,
+            payload.len() as u32,
+        );
+        Ok(Frame::new(header, payload))
+    }
+}
+
+/// How often each side sends a `StatsFrame`
+pub const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A peer that has sent stats before but none for this long is gone
+pub const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `FrameType::Stats` payload: the sending side's own measurements
+///
+/// Both sides send one every `STATS_INTERVAL`, so a link with no video
+/// changes still proves the peer is alive, and each end learns the other's
+/// fps and loss. Encoded as JSON like control messages.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StatsFrame(pub StatsSnapshot);
+
+impl StatsFrame {
+    /// Encode as a `FrameType::Stats` payload (JSON)
+    pub fn encode(&self) -> crate::Result<Bytes> {
+        serde_json::to_vec(self)
+            .map(Bytes::from)
+            .map_err(|e| crate::Error::protocol(format!("Failed to encode stats frame: {}", e)))
+    }
+
+    /// Decode from a `FrameType::Stats` payload
+    pub fn decode(payload: &[u8]) -> crate::Result<Self> {
+        serde_json::from_slice(payload)
+            .map_err(|e| crate::Error::protocol(format!("Invalid stats frame: {}", e)))
+    }
+
+    /// Wrap these stats in a complete stats frame
+    pub fn to_frame(&self, sequence: u64, timestamp_us: u64) -> crate::Result<Frame> {
+        let payload = self.encode()?;
+        let header = FrameHeader::new(
+            FrameType::Stats,
+            sequence,
+            timestamp_us,
+            0,
+            0,
+            payload.len() as u32,
+        );
+        Ok(Frame::new(header, payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_header_encode_decode() {
+        let header = FrameHeader::new(FrameType::RawFrame, 42, 1000000, 1920, 1080, 8294400);
+
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf);
+
+        assert_eq!(buf.len(), FrameHeader::SIZE);
+
+        let mut bytes = buf.freeze();
+        let decoded = FrameHeader::decode(&mut bytes).unwrap();
+
+        assert_eq!(decoded.version, PROTOCOL_VERSION);
+        assert_eq!(decoded.frame_type, FrameType::RawFrame);
+        assert_eq!(decoded.sequence, 42);
+        assert_eq!(decoded.width, 1920);
+        assert_eq!(decoded.height, 1080);
+    }
+
+    #[test]
+    fn test_frame_header_color_round_trip() {
+        let header = FrameHeader::new(FrameType::H264Frame, 7, 0, 640, 480, 1234)
+            .with_color(ColorSpace::Bt601, ColorRange::Full);
+
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf);
+
+        let decoded = FrameHeader::decode(&mut buf.freeze()).unwrap();
+        assert_eq!(decoded.color_space, ColorSpace::Bt601);
+        assert_eq!(decoded.color_range, ColorRange::Full);
+    }
+
+    #[test]
+    fn test_frame_header_v1_decodes_with_default_color() {
+        let mut header = FrameHeader::new(FrameType::H264Frame, 1, 0, 1920, 1080, 100);
+        header.version = 1;
+
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf);
+        assert_eq!(buf.len(), FrameHeader::V1_SIZE);
+
+        let decoded = FrameHeader::decode(&mut buf.freeze()).unwrap();
+        assert_eq!(decoded.version, 1);
+        assert_eq!(decoded.color_space, ColorSpace::Bt709);
+        assert_eq!(decoded.color_range, ColorRange::Limited);
+    }
+
+    #[test]
+    fn test_frame_header_v2_decodes_as_keyframe() {
+        let mut header = FrameHeader::new(FrameType::H264Frame, 1, 0, 1920, 1080, 100)
+            .with_color(ColorSpace::Bt601, ColorRange::Full);
+        header.version = 2;
+
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf);
+        assert_eq!(buf.len(), FrameHeader::V2_SIZE);
+
+        let decoded = FrameHeader::decode(&mut buf.freeze()).unwrap();
+        assert_eq!(decoded.color_space, ColorSpace::Bt601);
+        assert!(decoded.is_keyframe);
+    }
+
+    #[test]
+    fn test_frame_header_v3_decodes_as_sdr() {
+        let mut header = FrameHeader::new(FrameType::H264Frame, 1, 0, 1920, 1080, 100)
+            .with_hdr(10, TransferFunction::Pq);
+        header.version = 3;
+
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf);
+        assert_eq!(buf.len(), FrameHeader::V3_SIZE);
+
+        let decoded = FrameHeader::decode(&mut buf.freeze()).unwrap();
+        assert_eq!(decoded.bit_depth, 8);
+        assert_eq!(decoded.transfer, TransferFunction::Sdr);
+    }
+
+    #[test]
+    fn test_frame_header_capture_time_round_trip() {
+        let header = FrameHeader::new(FrameType::H264Frame, 1, 48_000, 1920, 1080, 100)
+            .with_capture_time(40_000);
+
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf);
+        let decoded = FrameHeader::decode(&mut buf.freeze()).unwrap();
+        assert_eq!(decoded.capture_ts_us, 40_000);
+        assert_eq!(decoded.encode_time_us(), Some(8_000));
+
+        // A capture time after the encode time is clamped rather than wrapping
+        let skewed = header.with_capture_time(50_000);
+        assert_eq!(skewed.encode_time_us(), Some(0));
+    }
+
+    #[test]
+    fn test_frame_header_v4_has_no_capture_time() {
+        let mut header = FrameHeader::new(FrameType::H264Frame, 1, 48_000, 1920, 1080, 100)
+            .with_hdr(10, TransferFunction::Pq)
+            .with_capture_time(40_000);
+        header.version = 4;
+
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf);
+        assert_eq!(buf.len(), FrameHeader::V4_SIZE);
+
+        let decoded = FrameHeader::decode(&mut buf.freeze()).unwrap();
+        assert_eq!(decoded.transfer, TransferFunction::Pq);
+        assert_eq!(decoded.capture_ts_us, 48_000);
+        assert_eq!(decoded.encode_time_us(), None);
+    }
+
+    #[test]
+    fn test_frame_header_hdr_round_trip() {
+        for transfer in [
+            TransferFunction::Sdr,
+            TransferFunction::Pq,
+            TransferFunction::Hlg,
+        ] {
+            let header = FrameHeader::new(FrameType::H264Frame, 3, 0, 3840, 2160, 100)
+                .with_color(ColorSpace::Bt2020, ColorRange::Limited)
+                .with_hdr(10, transfer);
+
+            let mut buf = BytesMut::new();
+            header.encode(&mut buf);
+            let decoded = FrameHeader::decode(&mut buf.freeze()).unwrap();
+            assert_eq!(decoded.color_space, ColorSpace::Bt2020);
+            assert_eq!(decoded.bit_depth, 10);
+            assert_eq!(decoded.transfer, transfer);
+        }
+    }
+
+    #[test]
+    fn test_frame_header_rejects_invalid_hdr_fields() {
+        let header = FrameHeader::new(FrameType::H264Frame, 1, 0, 64, 64, 0);
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf);
+
+        let mut bad_depth = buf.clone();
+        bad_depth[FrameHeader::V3_SIZE] = 12;
+        assert!(FrameHeader::decode(&mut bad_depth.freeze()).is_err());
+
+        let mut bad_transfer = buf;
+        bad_transfer[FrameHeader::V3_SIZE + 1] = 9;
+        assert!(FrameHeader::decode(&mut bad_transfer.freeze()).is_err());
+    }
+
+    #[test]
+    fn test_color_enum_conversion() {
+        for space in [ColorSpace::Bt601, ColorSpace::Bt709, ColorSpace::Bt2020] {
+            assert_eq!(ColorSpace::try_from(space as u8).unwrap(), space);
+        }
+        for transfer in [
+            TransferFunction::Sdr,
+            TransferFunction::Pq,
+            TransferFunction::Hlg,
+        ] {
+            assert_eq!(
+                TransferFunction::try_from(transfer as u8).unwrap(),
+                transfer
+            );
+        }
+        assert!(ColorSpace::try_from(3).is_err());
+        assert!(TransferFunction::try_from(3).is_err());
+        assert!(TransferFunction::Hlg.is_hdr());
+        assert!(!TransferFunction::Sdr.is_hdr());
+    }
+
+    #[test]
+    fn test_frame_header_keyframe_flag_round_trip() {
+        let delta = FrameHeader::new(FrameType::DeltaFrame, 1, 0, 64, 64, 0);
+        assert!(!delta.is_keyframe);
+
+        for header in [delta.clone(), delta.with_keyframe(true)] {
+            let mut buf = BytesMut::new();
+            header.encode(&mut buf);
+            let decoded = FrameHeader::decode(&mut buf.freeze()).unwrap();
+            assert_eq!(decoded.frame_type, FrameType::DeltaFrame);
+            assert_eq!(decoded.is_keyframe, header.is_keyframe);
+        }
+    }
+
+    #[test]
+    fn test_frame_header_rejects_unknown_version() {
+        let mut header = FrameHeader::new(FrameType::RawFrame, 1, 0, 16, 16, 0);
+        header.version = PROTOCOL_VERSION + 1;
+
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf);
+
+        assert!(FrameHeader::decode(&mut buf.freeze()).is_err());
+    }
+
+    fn test_frame(sequence: u64, payload: &'static [u8]) -> BytesMut {
+        let header = FrameHeader::new(
+            FrameType::H264Frame,
+            sequence,
+            0,
+            1,
+            1,
+            payload.len() as u32,
+        );
+        Frame::new(header, Bytes::from_static(payload)).encode()
+    }
+
+    #[test]
+    fn test_frame_decode_leaves_following_bytes() {
+        let mut stream = test_frame(1, &[1, 2, 3, 4]);
+        stream.extend_from_slice(&test_frame(2, &[5]));
+        let mut buf = stream.freeze();
+
+        let frame = Frame::decode(&mut buf).unwrap();
+        assert_eq!(frame.header.sequence, 1);
+        assert_eq!(&frame.payload[..], &[1, 2, 3, 4]);
+        let frame = Frame::decode(&mut buf).unwrap();
+        assert_eq!(&frame.payload[..], &[5]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_frame_decode_rejects_truncated_buffers() {
+        let frame = test_frame(1, &[1, 2, 3, 4]).freeze();
+        assert!(Frame::decode(&mut frame.slice(..frame.len() - 1)).is_err());
+        assert!(Frame::decode(&mut frame.slice(..FrameHeader::SIZE - 1)).is_err());
+        assert!(Frame::decode(&mut Bytes::new()).is_err());
+    }
+
+    #[test]
+    fn test_frame_header_rejects_inconsistent_fields() {
+        let decode = |header: FrameHeader| {
+            let mut buf = BytesMut::new();
+            header.encode(&mut buf);
+            FrameHeader::decode(&mut buf.freeze())
+        };
+        let raw = |width, height, payload_size| {
+            FrameHeader::new(FrameType::RawFrame, 1, 0, width, height, payload_size)
+        };
+
+        assert!(decode(raw(1920, 1080, 1920 * 1080 * 4)).is_ok());
+        assert!(decode(raw(0, 0, 0)).is_ok());
+        for (width, height, payload_size) in [
+            (10000, 10000, 0),
+            (10000, 10000, 16),
+            (1920, 1080, 1920 * 1080 * 3),
+            (u16::MAX, u16::MAX, u32::MAX),
+            (0, 1080, 4),
+        ] {
+            let err = decode(raw(width, height, payload_size)).unwrap_err();
+            assert!(
+                matches!(err, crate::Error::Protocol(_)),
+                "{}x{}: {}",
+                width,
+                height,
+                err
+            );
+        }
+
+        // Only raw frames have a known payload size, but every type has a
+        // size limit.
+        let h264 = |width, height| FrameHeader::new(FrameType::H264Frame, 1, 0, width, height, 100);
+        assert!(decode(h264(3840, 2160)).is_ok());
+        let err = decode(h264(MAX_FRAME_DIMENSION + 1, 2160)).unwrap_err();
+        assert!(err.to_string().contains("too large"), "{}", err);
+
+        // Frame decoding goes through the same checks
+        let mut buf = BytesMut::new();
+        raw(100, 100, 4).encode(&mut buf);
+        buf.extend_from_slice(&[0; 4]);
+        assert!(Frame::decode(&mut buf.freeze()).is_err());
+    }
+
+    #[test]
+    fn test_frame_decode_rejects_oversized_payload() {
+        let size = MAX_FRAME_SIZE as u32 + 1;
+        let mut buf = BytesMut::new();
+        FrameHeader::new(FrameType::H264Frame, 1, 0, 1, 1, size).encode(&mut buf);
+        buf.resize(buf.len() + size as usize, 0);
+        let buf = buf.freeze();
+
+        let err = Frame::decode(&mut buf.clone()).unwrap_err();
+        assert!(err.to_string().contains("too large"), "{}", err);
+        let frame = Frame::decode_with_limit(&mut buf.clone(), MAX_FRAME_SIZE * 2).unwrap();
+        assert_eq!(frame.payload.len(), size as usize);
+    }
+
+    #[test]
+    fn test_frame_decode_with_dimension_limit() {
+        let header = FrameHeader::new(FrameType::H264Frame, 1, 0, 3840, 2160, 1);
+        let buf = Frame::new(header, Bytes::from_static(&[0]))
+            .encode()
+            .freeze();
+
+        assert!(Frame::decode(&mut buf.clone()).is_ok());
+        let err = Frame::decode_with_limits(&mut buf.clone(), MAX_FRAME_SIZE, 1920).unwrap_err();
+        assert!(err.to_string().contains("limit 1920 pixels"), "{}", err);
+
+        let mut decoder = FrameDecoder::new(MAX_FRAME_SIZE).with_max_dimension(1920);
+        decoder.feed(&buf);
+        assert!(decoder.decode().is_err());
+        let mut decoder = FrameDecoder::new(MAX_FRAME_SIZE).with_max_dimension(3840);
+        decoder.feed(&buf);
+        assert_eq!(decoder.decode().unwrap().unwrap().header.width, 3840);
+    }
+
+    #[test]
+    fn test_frame_decoder_handles_partial_chunks() {
+        let mut stream = test_frame(1, &[1, 2, 3, 4]);
+        stream.extend_from_slice(&test_frame(2, &[]));
+        stream.extend_from_slice(&test_frame(3, &[5; 40]));
+
+        for chunk in [1, 7, FrameHeader::SIZE, 1000] {
+            let mut decoder = FrameDecoder::new(1024);
+            let mut frames = Vec::new();
+            for part in stream.chunks(chunk) {
+                decoder.feed(part);
+                while let Some(frame) = decoder.decode().unwrap() {
+                    frames.push((frame.header.sequence, frame.payload));
+                }
+            }
+            assert_eq!(
+                frames,
+                [
+                    (1, Bytes::from_static(&[1, 2, 3, 4])),
+                    (2, Bytes::new()),
+                    (3, Bytes::from_static(&[5; 40])),
+                ],
+                "chunk size {}",
+                chunk
+            );
+            assert_eq!(decoder.skipped_bytes(), 0);
+        }
+    }
+
+    #[test]
+    fn test_frame_decoder_resyncs_after_bad_length() {
+        let mut stream = test_frame(6, &[1, 2, 3, 4]);
+        FrameHeader::new(FrameType::RawFrame, 0, 0, 1, 1, u32::MAX).encode(&mut stream);
+        stream.extend_from_slice(&[0xff; 16]);
+        stream.extend_from_slice(&test_frame(7, &[1, 2, 3, 4]));
+
+        let mut decoder = FrameDecoder::new(1024);
+        decoder.feed(&stream);
+        assert_eq!(decoder.decode().unwrap().unwrap().header.sequence, 6);
+        assert!(decoder.decode().is_err());
+        let frame = decoder
+            .decode()
+            .unwrap()
+            .expect("valid frame after the bad one");
+        assert_eq!(frame.header.sequence, 7);
+        assert_eq!(&frame.payload[..], [1, 2, 3, 4]);
+        assert!(decoder.decode().unwrap().is_none());
+        assert_eq!(decoder.skipped_bytes(), FrameHeader::SIZE as u64 + 16);
+        assert_eq!(decoder.end_of_stream(), StreamEnd::Clean);
+    }
+
+    #[test]
+    fn test_frame_decoder_reports_truncated_streams() {
+        let stream = test_frame(1, &[1, 2, 3, 4]);
+        let mut decoder = FrameDecoder::new(1024);
+        decoder.feed(&stream[..stream.len() - 1]);
+        assert!(decoder.decode().unwrap().is_none());
+        assert_eq!(
+            decoder.end_of_stream(),
+            StreamEnd::Truncated {
+                pending_bytes: stream.len() - 1,
+                resumable: true
+            }
+        );
+        let err = decoder.end_of_stream().into_result().unwrap_err();
+        assert!(err.to_string().contains("mid-frame"), "{}", err);
+
+        // Cut off while skipping garbage
+        let mut decoder = FrameDecoder::new(1024);
+        decoder.feed(&[0xff; 64]);
+        assert!(decoder.decode().is_err());
+        while decoder.decode().is_err() {}
+        assert!(matches!(
+            decoder.end_of_stream(),
+            StreamEnd::Truncated {
+                resumable: false,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_frame_decoder_drops_invalid_frames_whole() {
+        let mut stream = test_frame(1, &[9; 8]);
+        stream[1] = 42; // unknown frame type
+        stream.extend_from_slice(&test_frame(2, &[]));
+
+        let mut decoder = FrameDecoder::new(1024);
+        decoder.feed(&stream);
+        assert!(decoder.decode().is_err());
+        assert_eq!(decoder.decode().unwrap().unwrap().header.sequence, 2);
+        assert_eq!(decoder.skipped_bytes(), 0);
+    }
+
+    #[test]
+    fn test_frame_decoder_memory_stays_bounded() {
+        let frame = |sequence, size: usize| {
+            let mut buf = BytesMut::new();
+            FrameHeader::new(FrameType::H264Frame, sequence, 0, 1, 1, size as u32).encode(&mut buf);
+            buf.resize(buf.len() + size, sequence as u8);
+            buf
+        };
+        // Mostly small frames with the odd keyframe near the limit
+        let mut stream = BytesMut::new();
+        for sequence in 0..200 {
+            let size = if sequence % 50 == 0 {
+                4 << 20
+            } else {
+                1000 + sequence as usize
+            };
+            stream.extend_from_slice(&frame(sequence, size));
+        }
+
+        let chunk = 64 * 1024;
+        let mut decoder = FrameDecoder::new(4 << 20);
+        let (mut read, mut decoded) = (0, 0);
+        while read < stream.len() {
+            let n = decoder.reserve(chunk).min(stream.len() - read);
+            decoder
+                .buffer_mut()
+                .extend_from_slice(&stream[read..read + n]);
+            read += n;
+            assert!(decoder.buffer_mut().len() <= decoder.max_buffered());
+            assert!(decoder.buffer_mut().capacity() <= 2 * decoder.max_buffered());
+            while let Some(frame) = decoder.decode().unwrap() {
+                assert_eq!(frame.header.sequence, decoded);
+                decoded += 1;
+                let capacity = decoder.buffer_mut().capacity();
+                assert!(capacity <= FrameDecoder::RETAINED_CAPACITY, "{}", capacity);
+            }
+        }
+        assert_eq!(decoded, 200);
+
+        // A header claiming the largest payload doesn't allocate for it
+        let mut decoder = FrameDecoder::new(4 << 20);
+        decoder.feed(&frame(0, 4 << 20)[..FrameHeader::SIZE]);
+        assert!(decoder.decode().unwrap().is_none());
+        assert_eq!(decoder.reserve(chunk), chunk);
+        assert!(decoder.buffer_mut().capacity() < 2 * chunk);
+    }
+
+    #[test]
+    fn test_frame_type_conversion() {
+        assert_eq!(FrameType::try_from(0).unwrap(), FrameType::RawFrame);
+        assert_eq!(FrameType::try_from(1).unwrap(), FrameType::H264Frame);
+        assert_eq!(FrameType::try_from(4).unwrap(), FrameType::Audio);
+        assert_eq!(FrameType::try_from(5).unwrap(), FrameType::DeltaFrame);
+        assert_eq!(FrameType::try_from(6).unwrap(), FrameType::Cursor);
+        assert!(FrameType::try_from(255).is_err());
+    }
+
+    #[test]
+    fn test_control_message_round_trip() {
+        let msg = ControlMessage::ClockSync {
+            sender_epoch_us: 1_700_000_000_000_000,
+        };
+
+        let frame = msg.to_frame(3, 42).unwrap();
+        assert_eq!(frame.header.frame_type, FrameType::Control);
+        assert_eq!(frame.header.payload_size as usize, frame.payload.len());
+
+        match ControlMessage::decode(&frame.payload).unwrap() {
+            ControlMessage::ClockSync { sender_epoch_us } => {
+                assert_eq!(sender_epoch_us, 1_700_000_000_000_000)
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        assert!(ControlMessage::decode(b"not json").is_err());
+    }
+
+    #[test]
+    fn test_stats_frame_round_trip() {
+        let stats = StatsFrame(StatsSnapshot {
+            fps: 59.8,
+            total_frames: 1200,
+            loss_percent: 0.5,
+            ..Default::default()
+        });
+        let mut encoded = stats.to_frame(9, 5000).unwrap().encode().freeze();
+        let frame = Frame::decode(&mut encoded).unwrap();
+        assert_eq!(frame.header.frame_type, FrameType::Stats);
+        assert_eq!(frame.header.sequence, 9);
+
+        let decoded = StatsFrame::decode(&frame.payload).unwrap();
+        assert_eq!(decoded.0.fps, 59.8);
+        assert_eq!(decoded.0.total_frames, 1200);
+        assert_eq!(decoded.0.loss_percent, 0.5);
+        assert!(StatsFrame::decode(b"{}").is_err());
+    }
+
+    #[test]
+    fn test_delta_tiles_round_trip() {
+        let tiles = vec![
+            DeltaTile::new(0, 0, 2, 1, Bytes::from_static(&[1, 2, 3, 4, 5, 6, 7, 8])),
+            DeltaTile::new(10, 20, 1, 1, Bytes::from_static(&[9, 9, 9, 9])),
+            DeltaTile::new(5, 5, 0, 0, Bytes::new()),
+        ];
+
+        let payload = DeltaTile::encode_all(&tiles).unwrap();
+        assert_eq!(payload.len(), 2 + 3 * DeltaTile::HEADER_SIZE + 12);
+        assert_eq!(DeltaTile::decode_all(&payload).unwrap(), tiles);
+
+        let empty = DeltaTile::encode_all(&[]).unwrap();
+        assert!(DeltaTile::decode_all(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_delta_tiles_reject_malformed_payloads() {
+        // Data length doesn't match the tile size
+        let bad = DeltaTile::new(0, 0, 2, 2, Bytes::from_static(&[0; 4]));
+        assert!(DeltaTile::encode_all(&[bad]).is_err());
+
+        let tile = DeltaTile::new(0, 0, 1, 1, Bytes::from_static(&[1, 2, 3, 4]));
+        let payload = DeltaTile::encode_all(&[tile]).unwrap();
+
+        // Truncated data, missing tile header, trailing bytes
+        assert!(DeltaTile::decode_all(&payload.slice(..payload.len() - 1)).is_err());
+        assert!(DeltaTile::decode_all(&payload.slice(..4)).is_err());
+        assert!(DeltaTile::decode_all(&Bytes::from_static(&[0])).is_err());
+        let mut trailing = BytesMut::from(&payload[..]);
+        trailing.put_u8(0);
+        assert!(DeltaTile::decode_all(&trailing.freeze()).is_err());
+    }
+
+    #[test]
+    fn test_delta_tile_bounds() {
+        let tile = DeltaTile::new(60, 0, 4, 64, Bytes::from(vec![0; 4 * 64 * 4]));
+        assert!(tile.fits_within(64, 64));
+        assert!(!tile.fits_within(63, 64));
+        assert!(
+            !DeltaTile::new(u16::MAX, 0, 1, 1, Bytes::from_static(&[0; 4])).fits_within(64, 64)
+        );
+    }
+
+    #[test]
+    fn test_cursor_update_round_trip() {
+        let update = CursorUpdate {
+            x: -3,
+            y: 40,
+            hotspot_x: 1,
+            hotspot_y: 0,
+            width: 2,
+            height: 1,
+            data: Bytes::from_static(&[255, 255, 255, 255, 0, 0, 0, 128]),
+        };
+        let frame = update.to_frame(9, 0).unwrap();
+        assert_eq!(frame.header.frame_type, FrameType::Cursor);
+        assert_eq!(frame.payload.len(), CursorUpdate::HEADER_SIZE + 8);
+        assert_eq!(CursorUpdate::decode(&frame.payload).unwrap(), update);
+
+        // Position-only updates keep the current image
+        let moved = CursorUpdate::moved(100, 200);
+        let decoded = CursorUpdate::decode(&moved.encode().unwrap()).unwrap();
+        assert!(!decoded.has_image());
+        assert_eq!((decoded.x, decoded.y), (100, 200));
+    }
+
+    #[test]
+    fn test_cursor_update_rejects_malformed_payloads() {
+        let mut bad = CursorUpdate::moved(0, 0);
+        bad.width = 1;
+        bad.height = 1;
+        assert!(bad.encode().is_err());
+        bad.data = Bytes::from_static(&[0; 4]);
+        bad.hotspot_x = 1;
+        assert!(bad.encode().is_err());
+
+        let payload = CursorUpdate {
+            hotspot_x: 0,
+            ..bad
+        }
+        .encode()
+        .unwrap();
+        assert!(CursorUpdate::decode(&payload.slice(..payload.len() - 1)).is_err());
+        assert!(CursorUpdate::decode(&payload.slice(..4)).is_err());
+        let mut trailing = BytesMut::from(&payload[..]);
+        trailing.put_u8(0);
+        assert!(CursorUpdate::decode(&trailing.freeze()).is_err());
+    }
+
+    #[test]
+    fn test_clipboard_message_round_trip() {
+        let msg = ControlMessage::Clipboard {
+            mime: "text/plain".to_string(),
+            data: b"copied".to_vec(),
+        };
+
+        let frame = msg.to_frame(1, 0).unwrap();
+        match ControlMessage::decode(&frame.payload).unwrap() {
+            ControlMessage::Clipboard { mime, data } => {
+                assert_eq!(mime, "text/plain");
+                assert_eq!(data, b"copied");
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_set_fps_round_trip() {
+        let frame = ControlMessage::SetFps { fps: 144 }.to_frame(2, 0).unwrap();
+        match ControlMessage::decode(&frame.payload).unwrap() {
+            ControlMessage::SetFps { fps } => assert_eq!(fps, 144),
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_hello_round_trip() {
+        let capabilities = ControlMessage::CAP_AUDIO | ControlMessage::CAP_CURSOR;
+        let frame = ControlMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities,
+            audio_codecs: vec![AudioCodec::Opus, AudioCodec::Pcm],
+        }
+        .to_frame(0, 0)
+        .unwrap();
+        match ControlMessage::decode(&frame.payload).unwrap() {
+            ControlMessage::Hello {
+                protocol_version,
+                capabilities: decoded,
+                audio_codecs,
+            } => {
+                assert_eq!(
+                    (protocol_version, decoded),
+                    (PROTOCOL_VERSION, capabilities)
+                );
+                assert_eq!(audio_codecs, [AudioCodec::Opus, AudioCodec::Pcm]);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        let frame = ControlMessage::HelloAck {
+            protocol_version: 2,
+            capabilities: 0,
+            audio_codecs: Vec::new(),
+        }
+        .to_frame(1, 0)
+        .unwrap();
+        assert!(matches!(
+            ControlMessage::decode(&frame.payload).unwrap(),
+            ControlMessage::HelloAck {
+                protocol_version: 2,
+                capabilities: 0,
+                ..
+            }
+        ));
+
+        // Older peers don't list codecs
+        let old = br#"{"Hello":{"protocol_version":4,"capabilities":1}}"#;
+        match ControlMessage::decode(old).unwrap() {
+            ControlMessage::Hello { audio_codecs, .. } => assert!(audio_codecs.is_empty()),
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_audio_codec_negotiation() {
+        use AudioCodec::{Opus, Pcm};
+
+        assert_eq!(AudioCodec::negotiate(&[Opus, Pcm], &[Pcm, Opus]), Opus);
+        assert_eq!(AudioCodec::negotiate(&[Pcm, Opus], &[Opus, Pcm]), Pcm);
+        assert_eq!(AudioCodec::negotiate(&[Opus, Pcm], &[Pcm]), Pcm);
+        assert_eq!(AudioCodec::negotiate(&[Opus], &[]), Pcm);
+        assert_eq!(AudioCodec::negotiate(&[], &[Opus]), Pcm);
+    }
+
+    #[test]
+    fn test_start_round_trip() {
+        let frame = ControlMessage::Start {
+            width: 3840,
+            height: 2160,
+            fps: 120,
+            bitrate_kbps: Some(40_000),
+            codec: Some(VideoCodec::H264),
+            profile: Some(H264Profile::High),
+        }
+        .to_frame(1, 0)
+        .unwrap();
+        assert!(matches!(
+            ControlMessage::decode(&frame.payload).unwrap(),
+            ControlMessage::Start {
+                width: 3840,
+                height: 2160,
+                fps: 120,
+                bitrate_kbps: Some(40_000),
+                codec: Some(VideoCodec::H264),
+                profile: Some(H264Profile::High),
+            }
+        ));
+
+        // Older peers only send the size and rate
+        let old = br#"{"Start":{"width":1920,"height":1080,"fps":60}}"#;
+        assert!(matches!(
+            ControlMessage::decode(old).unwrap(),
+            ControlMessage::Start {
+                width: 1920,
+                bitrate_kbps: None,
+                codec: None,
+                profile: None,
+                ..
+            }
+        ));
+
+        // Newer ones may name codecs and profiles we don't know
+        let new = br#"{"Start":{"width":0,"height":0,"fps":0,"codec":"Av1","profile":"High10"}}"#;
+        assert!(matches!(
+            ControlMessage::decode(new).unwrap(),
+            ControlMessage::Start {
+                codec: Some(VideoCodec::Unknown),
+                profile: Some(H264Profile::Unknown),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_set_bitrate_round_trip() {
+        let frame = ControlMessage::SetBitrate { kbps: 12_500 }
+            .to_frame(4, 0)
+            .unwrap();
+        match ControlMessage::decode(&frame.payload).unwrap() {
+            ControlMessage::SetBitrate { kbps } => assert_eq!(kbps, 12_500),
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_input_message_round_trip() {
+        let events = [
+            InputEvent::MouseMove { x: 0.25, y: 1.0 },
+            InputEvent::MouseButton {
+                button: MouseButton::Right,
+                down: true,
+            },
+            InputEvent::KeyEvent {
+                keycode: KeyCode::A,
+                down: false,
+                modifiers: InputEvent::MOD_SHIFT | InputEvent::MOD_META,
+                repeat: false,
+            },
+            InputEvent::KeyEvent {
+                keycode: KeyCode::Down,
+                down: true,
+                modifiers: 0,
+                repeat: true,
+            },
+        ];
+        for event in events {
+            let frame = ControlMessage::Input(event).to_frame(3, 0).unwrap();
+            match ControlMessage::decode(&frame.payload).unwrap() {
+                ControlMessage::Input(decoded) => assert_eq!(decoded, event),
+                other => panic!("unexpected message: {:?}", other),
+            }
+        }
+
+        // Keys go out as the bare usage ID, and `repeat` may be missing
+        let json = br#"{"Input":{"KeyEvent":{"keycode":4,"down":true,"modifiers":0}}}"#;
+        match ControlMessage::decode(json).unwrap() {
+            ControlMessage::Input(InputEvent::KeyEvent {
+                keycode, repeat, ..
+            }) => assert_eq!((keycode, repeat), (KeyCode::A, false)),
+            other => panic!("unexpected message: {:?}", other),
+        }
+        let json = br#"{"Input":{"KeyEvent":{"keycode":1,"down":true,"modifiers":0}}}"#;
+        assert!(ControlMessage::decode(json).is_err());
+    }
+
+    #[test]
+    fn test_key_codes_are_hid_usage_ids() {
+        assert_eq!(u16::from(KeyCode::A), 0x04);
+        assert_eq!(u16::from(KeyCode::Digit0), 0x27);
+        assert_eq!(u16::from(KeyCode::Enter), 0x28);
+        assert_eq!(u16::from(KeyCode::Up), 0x52);
+        assert_eq!(u16::from(KeyCode::RightSuper), 0xe7);
+
+        // Sorted and unique, and every ID maps back to its key
+        assert!(KeyCode::ALL
+            .windows(2)
+            .all(|pair| (pair[0] as u16) < pair[1] as u16));
+        for key in KeyCode::ALL {
+            assert_eq!(KeyCode::try_from(key as u16).unwrap(), key);
+        }
+        assert!(KeyCode::try_from(0x00).is_err());
+        assert!(
+            KeyCode::try_from(0x32).is_err(),
+            "non-US backslash isn't mapped"
+        );
+
+        assert!(KeyCode::LeftShift.is_modifier());
+        assert!(!KeyCode::CapsLock.is_modifier());
+    }
+
+    #[test]
+    fn test_recording_entry_round_trip() {
+        let frame = FrameHeader::new(FrameType::RawFrame, 9, 100, 1, 1, 4);
+        let frame = Frame::new(frame, Bytes::from_static(&[1, 2, 3, 4]))
+            .encode()
+            .freeze();
+        let entries = [
+            RecordingEntry::new(0, frame.clone()),
+            RecordingEntry::new(16_667, frame),
+        ];
+
+        let mut buf = BytesMut::new();
+        for entry in &entries {
+            entry.encode(&mut buf);
+        }
+        assert_eq!(
+            RecordingEntry::decode_prefix(&buf).unwrap(),
+            (0, FrameHeader::SIZE + 4)
+        );
+
+        let mut bytes = buf.freeze();
+        for entry in &entries {
+            assert_eq!(&RecordingEntry::decode(&mut bytes).unwrap(), entry);
+        }
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn test_recording_entry_rejects_truncation() {
+        let mut buf = BytesMut::new();
+        RecordingEntry::new(5, Bytes::from_static(&[0; 10])).encode(&mut buf);
+
+        assert!(RecordingEntry::decode_prefix(&buf[..11]).is_err());
+        assert!(RecordingEntry::decode(&mut buf.freeze().slice(..21)).is_err());
+    }
+
+    #[test]
+    fn test_recording_header_round_trip() {
+        let headers = [
+            RecordingHeader::default(),
+            RecordingHeader {
+                width: 1920,
+                height: 1080,
+                codec: Some(FrameType::H264Frame),
+                ..Default::default()
+            },
+        ];
+        for header in headers {
+            let mut buf = BytesMut::new();
+            header.encode(&mut buf);
+            assert_eq!(buf.len(), RecordingHeader::SIZE);
+            assert_eq!(RecordingHeader::decode(&mut buf.freeze()).unwrap(), header);
+        }
+
+        let mut legacy = Bytes::from_static(b"THMREC01\0\0\0\0\0");
+        assert!(RecordingHeader::decode(&mut legacy).is_err());
+        let mut short = Bytes::from_static(b"THMREC");
+        assert!(RecordingHeader::decode(&mut short).is_err());
+    }
+
+    #[test]
+    fn test_recording_index_round_trip_and_find() {
+        let keyframe = |sequence, position| RecordingKeyframe {
+            sequence,
+            offset_us: sequence * 16_667,
+            position,
+        };
+        let index = RecordingIndex {
+            keyframes: vec![keyframe(0, 13), keyframe(60, 9000), keyframe(120, 20000)],
+        };
+
+        let mut buf = BytesMut::new();
+        index.encode(&mut buf);
+        assert_eq!(buf.len(), index.encoded_size());
+        let trailer = &buf[buf.len() - RecordingIndex::TRAILER_SIZE..];
+        assert_eq!(RecordingIndex::decode_trailer(trailer), Some(3));
+        assert_eq!(
+            RecordingIndex::decode(&mut buf.clone().freeze()).unwrap(),
+            index
+        );
+
+        assert_eq!(index.find(0).unwrap().position, 13);
+        assert_eq!(index.find(119).unwrap().position, 9000);
+        assert_eq!(index.find(500).unwrap().position, 20000);
+        assert!(RecordingIndex::default().find(5).is_none());
+
+        // A file cut short has no trailer, and a damaged one doesn't add up
+        assert_eq!(RecordingIndex::decode_trailer(&buf[..7]), None);
+        assert!(RecordingIndex::decode(&mut buf.freeze().slice(1..)).is_err());
+    }
+
+    #[test]
+    fn test_audio_header_encode_decode() {
+        let header = AudioHeader::new(48000, 2, AudioCodec::Pcm);
+
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf);
+        buf.extend_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(buf.len(), AudioHeader::SIZE + 4);
+
+        let mut bytes = buf.freeze();
+        let decoded = AudioHeader::decode(&mut bytes).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(&bytes[..], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_audio_header_rejects_invalid_format() {
+        let mut buf = BytesMut::new();
+        AudioHeader::new(0, 2, AudioCodec::Pcm).encode(&mut buf);
+        assert!(AudioHeader::decode(&mut buf.freeze()).is_err());
+
+        let mut bytes = Bytes::from_static(&[0, 0, 0xBB, 0x80, 2, 9]);
+        assert!(AudioHeader::decode(&mut bytes).is_err());
+
+        // Opus runs at a fixed set of rates, mono or stereo here
+        for (sample_rate, channels, valid) in
+            [(48000, 2, true), (44100, 2, false), (48000, 6, false)]
+        {
+            let mut buf = BytesMut::new();
+            AudioHeader::new(sample_rate, channels, AudioCodec::Opus).encode(&mut buf);
+            assert_eq!(AudioHeader::decode(&mut buf.freeze()).is_ok(), valid);
+        }
     }
 }
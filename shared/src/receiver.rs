@@ -0,0 +1,594 @@
+//! Receiving frames, without the rest of a receiver
+//!
+//! `FrameReceiver` is the receive side of the protocol on its own: it
+//! accepts senders over QUIC (or dials one that listens), parses what
+//! arrives on their bidirectional streams (with `FrameDecoder`),
+//! unidirectional streams and datagrams, and hands the frames out as one
+//! `Stream`. Embedders and tests get frames without windows, decoders or a
+//! control channel. A `ConnectionHook` sees each connection on the way:
+//! the Windows receiver uses one to write control messages back to the
+//! sender, keep per-sender stats and record frames.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tracing::{debug, warn};
+
+use crate::config::TransportTuning;
+use crate::protocol::{Frame, FrameDecoder, FrameHeader, MAX_FRAME_DIMENSION, MAX_FRAME_SIZE};
+use crate::transport::{negotiated_alpn, ConnectionEvent, QuicClient, QuicServer};
+use crate::{Result, DEFAULT_FRAME_QUEUE};
+
+/// Bytes read from a stream at a time
+const READ_CHUNK: usize = 256 * 1024;
+
+/// Per-connection extension point of a `FrameReceiver`
+pub trait ConnectionHook: Send + Sync + 'static {
+    /// A sender connected; the tap sees its streams and frames until it
+    /// disconnects
+    fn connected(&self, conn: &quinn::Connection) -> Arc<dyn ConnectionTap>;
+}
+
+/// What a `ConnectionHook` sees of one connection
+///
+/// The defaults do what a `FrameReceiver` without a hook does.
+pub trait ConnectionTap: Send + Sync {
+    /// The sender opened a bidirectional stream; `send` is the half back to it
+    fn stream_opened(&self, send: quinn::SendStream) {
+        drop(send);
+    }
+
+    /// A bidirectional stream starts with a header of protocol `version`
+    ///
+    /// Called before the stream's first frame is parsed, so a sender
+    /// speaking an unknown version can be turned away instead of having its
+    /// frames skipped as garbage. An error ends the stream.
+    fn check_version(&self, _version: u8) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether `frame` goes on to the `FrameStream`
+    fn frame(&self, _frame: &Frame) -> bool {
+        true
+    }
+
+    /// The connection is over
+    fn closed(&self) {}
+}
+
+/// The tap of connections when there is no hook
+struct PassThrough;
+
+impl ConnectionTap for PassThrough {}
+
+/// Accepts senders and merges their frames into one stream
+pub struct FrameReceiver {
+    tuning: TransportTuning,
+    max_payload_size: usize,
+    max_dimension: u16,
+    queue: usize,
+    events: Option<mpsc::UnboundedSender<ConnectionEvent>>,
+    hook: Option<Arc<dyn ConnectionHook>>,
+}
+
+impl FrameReceiver {
+    /// A receiver with the transport parameters of `tuning`
+    pub fn new(tuning: TransportTuning) -> Self {
+        Self {
+            tuning,
+            max_payload_size: MAX_FRAME_SIZE,
+            max_dimension: MAX_FRAME_DIMENSION,
+            queue: DEFAULT_FRAME_QUEUE.into(),
+            events: None,
+            hook: None,
+        }
+    }
+
+    /// Treat payloads over `max_payload_size` bytes as corrupt
+    pub fn with_max_payload_size(mut self, max_payload_size: usize) -> Self {
+        self.max_payload_size = max_payload_size;
+        self
+    }
+
+    /// Treat frames wider or taller than `max_dimension` pixels as invalid
+    pub fn with_max_dimension(mut self, max_dimension: u16) -> Self {
+        self.max_dimension = max_dimension;
+        self
+    }
+
+    /// Hold up to `frames` frames for a slow consumer; after that senders
+    /// wait, as QUIC flow control pushes back on them
+    pub fn with_queue(mut self, frames: usize) -> Self {
+        self.queue = frames.max(1);
+        self
+    }
+
+    /// Report the lifecycle of the server and its connections to `events`
+    pub fn with_events(mut self, events: mpsc::UnboundedSender<ConnectionEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Let `hook` see every connection, its streams and its frames
+    pub fn with_hook(mut self, hook: Arc<dyn ConnectionHook>) -> Self {
+        self.hook = Some(hook);
+        self
+    }
+
+    /// Listen on `addr` with a self-signed certificate and receive from
+    /// every sender that connects
+    pub async fn run(self, addr: SocketAddr) -> Result<FrameStream> {
+        let server = QuicServer::new_self_signed(addr, &self.tuning).await?;
+        Ok(self.serve(server))
+    }
+
+    /// Receive from every sender that connects to `server`
+    ///
+    /// Frames of all connections are merged in the order they arrive.
+    /// Dropping the stream closes the server.
+    pub fn serve(self, server: impl Into<Arc<QuicServer>>) -> FrameStream {
+        let server = server.into();
+        let local_addr = server.local_addr();
+        let (receiving, rx) = self.receiving();
+        report(
+            &receiving.events,
+            ConnectionEvent::Listening { addr: local_addr },
+        );
+
+        tokio::spawn(async move {
+            loop {
+                let handshake = tokio::select! {
+                    handshake = server.accept_handshake() => handshake,
+                    () = receiving.frames.closed() => break,
+                };
+                let handshake = match handshake {
+                    Ok(handshake) => handshake,
+                    Err(_) if server.is_closed() => break,
+                    Err(e) => {
+                        report(
+                            &receiving.events,
+                            ConnectionEvent::Error {
+                                addr: None,
+                                msg: e.to_string(),
+                            },
+                        );
+                        continue;
+                    }
+                };
+                // A slow handshake doesn't hold up the next sender.
+                let receiving = receiving.clone();
+                tokio::spawn(async move {
+                    let addr = handshake.remote_address();
+                    match handshake.finish().await {
+                        Ok(conn) => receiving.connection(conn).await,
+                        Err(e) => {
+                            let msg = e.to_string();
+                            report(
+                                &receiving.events,
+                                ConnectionEvent::Error {
+                                    addr: Some(addr),
+                                    msg,
+                                },
+                            );
+                        }
+                    }
+                });
+            }
+            server.shutdown_handle().close(0, b"receiver closed");
+        });
+
+        FrameStream {
+            local_addr,
+            frames: ReceiverStream::new(rx),
+        }
+    }
+
+    /// Dial the sender at `sender` with `client` and receive from it
+    ///
+    /// For senders that listen instead of connecting. The stream ends with
+    /// the connection, and dropping it closes the connection.
+    pub async fn connect(self, client: &QuicClient, sender: SocketAddr) -> Result<FrameStream> {
+        // Certificates aren't verified, so the name only has to be valid.
+        let conn = client.connect(sender, &sender.ip().to_string()).await?;
+        let local_addr = client.local_addr()?;
+        let (receiving, rx) = self.receiving();
+        tokio::spawn(receiving.connection(conn));
+
+        Ok(FrameStream {
+            local_addr,
+            frames: ReceiverStream::new(rx),
+        })
+    }
+
+    fn receiving(self) -> (Receiving, mpsc::Receiver<Frame>) {
+        let (frames, rx) = mpsc::channel(self.queue);
+        let receiving = Receiving {
+            frames,
+            max_payload_size: self.max_payload_size,
+            max_dimension: self.max_dimension,
+            events: self.events,
+            hook: self.hook,
+        };
+        (receiving, rx)
+    }
+}
+
+/// Frames from a `FrameReceiver`, until the stream is dropped
+pub struct FrameStream {
+    local_addr: SocketAddr,
+    frames: ReceiverStream<Frame>,
+}
+
+impl FrameStream {
+    /// The address the server is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Stream for FrameStream {
+    type Item = Frame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Frame>> {
+        Pin::new(&mut self.frames).poll_next(cx)
+    }
+}
+
+fn report(events: &Option<mpsc::UnboundedSender<ConnectionEvent>>, event: ConnectionEvent) {
+    if let Some(events) = events {
+        let _ = events.send(event);
+    }
+}
+
+/// What every connection of one `FrameReceiver` is received with
+#[derive(Clone)]
+struct Receiving {
+    frames: mpsc::Sender<Frame>,
+    max_payload_size: usize,
+    max_dimension: u16,
+    events: Option<mpsc::UnboundedSender<ConnectionEvent>>,
+    hook: Option<Arc<dyn ConnectionHook>>,
+}
+
+impl Receiving {
+    /// Receive frames from every stream and datagram of `conn` until it
+    /// closes or nobody takes the frames any more
+    async fn connection(self, conn: quinn::Connection) {
+        let Self {
+            frames,
+            max_payload_size,
+            max_dimension,
+            events,
+            hook,
+        } = self;
+        let addr = conn.remote_address();
+        let alpn = negotiated_alpn(&conn).map(|alpn| String::from_utf8_lossy(&alpn).into_owned());
+        report(&events, ConnectionEvent::Accepted { addr, alpn });
+        let tap = match &hook {
+            Some(hook) => hook.connected(&conn),
+            None => Arc::new(PassThrough),
+        };
+
+        let bi = async {
+            while let Ok((send, mut recv)) = conn.accept_bi().await {
+                report(&events, ConnectionEvent::StreamOpened { addr });
+                tap.stream_opened(send);
+                let (frames, tap) = (frames.clone(), tap.clone());
+                tokio::spawn(async move {
+                    let result = receive_byte_stream(
+                        &mut recv,
+                        &frames,
+                        &*tap,
+                        max_payload_size,
+                        max_dimension,
+                    )
+                    .await;
+                    if let Err(e) = result {
+                        warn!("Frame stream from {} failed: {}", addr, e);
+                    }
+                });
+            }
+        };
+
+        // Legacy senders send one frame per unidirectional stream.
+        let uni = async {
+            while let Ok(mut recv) = conn.accept_uni().await {
+                let (frames, tap) = (frames.clone(), tap.clone());
+                tokio::spawn(async move {
+                    let data = match recv.read_to_end(FrameHeader::SIZE + max_payload_size).await {
+                        Ok(data) => Bytes::from(data),
+                        Err(e) => {
+                            debug!("Unidirectional stream from {} failed: {}", addr, e);
+                            return;
+                        }
+                    };
+                    if let Err(e) =
+                        forward(&frames, &*tap, data, max_payload_size, max_dimension).await
+                    {
+                        debug!("Invalid frame from {}: {}", addr, e);
+                    }
+                });
+            }
+        };
+
+        // A datagram holds one whole frame, so frames over the path's
+        // datagram limit (keyframes, mostly) have to come on a stream.
+        let datagrams = async {
+            let mut warned = false;
+            while let Ok(datagram) = conn.read_datagram().await {
+                let Err(e) =
+                    forward(&frames, &*tap, datagram, max_payload_size, max_dimension).await
+                else {
+                    continue;
+                };
+                if warned {
+                    debug!("Invalid datagram frame from {}: {}", addr, e);
+                } else {
+                    // Most likely a frame cut short by a sender that ignores the limit.
+                    let limit = conn.max_datagram_size().unwrap_or(0);
+                    warn!(
+                        "Invalid datagram frame from {}: {} (datagrams hold at most {} bytes; larger frames must use a stream)",
+                        addr, e, limit
+                    );
+                    warned = true;
+                }
+            }
+        };
+
+        tokio::select! {
+            () = async { tokio::join!(bi, uni, datagrams); } => {}
+            () = frames.closed() => conn.close(0u32.into(), b"receiver closed"),
+        }
+        tap.closed();
+
+        let reason = conn
+            .close_reason()
+            .map_or_else(|| "closed".to_string(), |e| e.to_string());
+        report(&events, ConnectionEvent::Closed { addr, reason });
+    }
+}
+
+/// Parse frames sent back to back on `recv` until it ends
+async fn receive_byte_stream(
+    recv: &mut quinn::RecvStream,
+    frames: &mpsc::Sender<Frame>,
+    tap: &dyn ConnectionTap,
+    max_payload_size: usize,
+    max_dimension: u16,
+) -> Result<()> {
+    let mut decoder = FrameDecoder::new(max_payload_size).with_max_dimension(max_dimension);
+    let mut skipped = 0;
+    // Senders start on a frame boundary, so the first byte is a header version.
+    let mut version_checked = false;
+    loop {
+        if !version_checked {
+            if let Some(&version) = decoder.buffer_mut().first() {
+                tap.check_version(version)?;
+                version_checked = true;
+            }
+        }
+        match decoder.decode() {
+            Ok(Some(frame)) => {
+                if decoder.skipped_bytes() > skipped {
+                    warn!(
+                        "Resynced frame stream after skipping {} bytes",
+                        decoder.skipped_bytes() - skipped
+                    );
+                    skipped = decoder.skipped_bytes();
+                }
+                if tap.frame(&frame) && frames.send(frame).await.is_err() {
+                    return Ok(());
+                }
+            }
+            Ok(None) => {
+                if !decoder.read_from(recv, READ_CHUNK).await? {
+                    return decoder.end_of_stream().into_result();
+                }
+            }
+            Err(e) => warn!("Invalid frame in stream: {}", e),
+        }
+    }
+}
+
+/// Send on the frame that makes up all of `data`, if `tap` lets it through
+async fn forward(
+    frames: &mpsc::Sender<Frame>,
+    tap: &dyn ConnectionTap,
+    mut data: Bytes,
+    max_payload_size: usize,
+    max_dimension: u16,
+) -> Result<()> {
+    let frame = Frame::decode_with_limits(&mut data, max_payload_size, max_dimension)?;
+    if tap.frame(&frame) {
+        let _ = frames.send(frame).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::protocol::FrameType;
+    use crate::transport::{send_frame_datagram, QuicClient};
+
+    fn frame(sequence: u64) -> Frame {
+        let header = FrameHeader::new(FrameType::RawFrame, sequence, 0, 1, 1, 4);
+        Frame::new(header, Bytes::from_static(&[1, 2, 3, 4]))
+    }
+
+    #[tokio::test]
+    async fn test_frames_from_streams_and_datagrams() {
+        let (events, mut event_rx) = mpsc::unbounded_channel();
+        let mut stream = FrameReceiver::new(TransportTuning::default())
+            .with_events(events)
+            .run("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let conn = client
+            .connect(stream.local_addr(), "localhost")
+            .await
+            .unwrap();
+
+        // Two frames on a bidirectional stream, one on a unidirectional
+        // stream and one as a datagram
+        let (mut send, _recv) = conn.open_bi().await.unwrap();
+        let mut bytes = frame(1).encode();
+        bytes.extend_from_slice(&frame(2).encode());
+        send.write_all(&bytes).await.unwrap();
+        let mut uni = conn.open_uni().await.unwrap();
+        uni.write_all(&frame(3).encode()).await.unwrap();
+        uni.finish().await.unwrap();
+        send_frame_datagram(&conn, &frame(4)).unwrap();
+
+        let mut sequences = Vec::new();
+        for _ in 0..4 {
+            let frame = timeout(Duration::from_secs(5), stream.next())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&frame.payload[..], [1, 2, 3, 4]);
+            sequences.push(frame.header.sequence);
+        }
+        sequences.sort_unstable();
+        assert_eq!(sequences, [1, 2, 3, 4]);
+
+        assert!(matches!(
+            event_rx.recv().await.unwrap(),
+            ConnectionEvent::Listening { .. }
+        ));
+        assert!(matches!(
+            event_rx.recv().await.unwrap(),
+            ConnectionEvent::Accepted { alpn: Some(_), .. }
+        ));
+        assert!(matches!(
+            event_rx.recv().await.unwrap(),
+            ConnectionEvent::StreamOpened { .. }
+        ));
+
+        // Dropping the stream closes the connection
+        drop(stream);
+        timeout(Duration::from_secs(5), conn.closed())
+            .await
+            .expect("the receiver should close the connection");
+        assert!(matches!(
+            timeout(Duration::from_secs(5), event_rx.recv())
+                .await
+                .unwrap(),
+            Some(ConnectionEvent::Closed { .. })
+        ));
+    }
+
+    /// Writes a greeting on every stream, drops frame 2 and notes the close
+    #[derive(Default)]
+    struct Greeter {
+        closed: Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl ConnectionHook for Greeter {
+        fn connected(&self, _conn: &quinn::Connection) -> Arc<dyn ConnectionTap> {
+            Arc::new(Greeter {
+                closed: self.closed.clone(),
+            })
+        }
+    }
+
+    impl ConnectionTap for Greeter {
+        fn stream_opened(&self, mut send: quinn::SendStream) {
+            tokio::spawn(async move { send.write_all(b"hi").await });
+        }
+
+        fn frame(&self, frame: &Frame) -> bool {
+            frame.header.sequence != 2
+        }
+
+        fn closed(&self) {
+            self.closed.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hook_sees_streams_and_frames() {
+        let hook = Arc::new(Greeter::default());
+        let closed = hook.closed.clone();
+        let mut stream = FrameReceiver::new(TransportTuning::default())
+            .with_hook(hook)
+            .run("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let conn = client
+            .connect(stream.local_addr(), "localhost")
+            .await
+            .unwrap();
+
+        let (mut send, mut recv) = conn.open_bi().await.unwrap();
+        for sequence in 1..=3 {
+            send.write_all(&frame(sequence).encode()).await.unwrap();
+        }
+        let mut greeting = [0; 2];
+        recv.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(&greeting, b"hi");
+
+        // The hook kept frame 2 out of the stream
+        for sequence in [1, 3] {
+            let frame = timeout(Duration::from_secs(5), stream.next())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(frame.header.sequence, sequence);
+        }
+
+        assert!(!closed.load(std::sync::atomic::Ordering::SeqCst));
+        drop(stream);
+        timeout(Duration::from_secs(5), conn.closed())
+            .await
+            .unwrap();
+        timeout(Duration::from_secs(5), async {
+            while !closed.load(std::sync::atomic::Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the tap should hear about the close");
+    }
+
+    #[tokio::test]
+    async fn test_connect_to_listening_sender() {
+        let sender = QuicServer::new("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let (stream, conn) = tokio::join!(
+            FrameReceiver::new(TransportTuning::default()).connect(&client, sender.local_addr()),
+            sender.accept()
+        );
+        let (mut stream, conn) = (stream.unwrap(), conn.unwrap());
+        assert_eq!(stream.local_addr(), client.local_addr().unwrap());
+
+        send_frame_datagram(&conn, &frame(5)).unwrap();
+        let frame = timeout(Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.header.sequence, 5);
+
+        // The stream ends with the connection
+        conn.close(0u32.into(), b"bye");
+        assert!(timeout(Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
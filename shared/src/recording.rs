@@ -0,0 +1,361 @@
+//! Recording frames to a file and reading them back
+//!
+//! Frames are stored exactly as they arrived, in the container format defined
+//! by `protocol::RecordingHeader`, so a replay goes through the same parsing
+//! and decoding as a live stream. The keyframe index at the end of the file
+//! lets a reader start at any keyframe instead of the beginning.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use tracing::warn;
+
+use crate::protocol::{
+    FrameHeader, FrameType, RecordingEntry, RecordingHeader, RecordingIndex, RecordingKeyframe,
+};
+use crate::{Error, Result};
+
+/// Appends received frames to a recording file
+///
+/// Shared by every stream task of every connection; writes are buffered, and
+/// the index and header are written when the writer is dropped.
+pub struct RecordingWriter {
+    start: Instant,
+    state: Mutex<WriterState>,
+}
+
+struct WriterState {
+    writer: BufWriter<File>,
+    // Byte offset the next entry is written at
+    position: u64,
+    header: RecordingHeader,
+    index: RecordingIndex,
+}
+
+impl RecordingWriter {
+    /// Create (or truncate) a recording at `path`
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let header = RecordingHeader::default();
+        let mut buf = BytesMut::with_capacity(RecordingHeader::SIZE);
+        header.encode(&mut buf);
+        writer.write_all(&buf)?;
+        Ok(Self {
+            start: Instant::now(),
+            state: Mutex::new(WriterState {
+                writer,
+                position: RecordingHeader::SIZE as u64,
+                header,
+                index: RecordingIndex::default(),
+            }),
+        })
+    }
+
+    /// Append one frame (header + payload as received)
+    ///
+    /// Raw frames and H.264 keyframes are added to the index.
+    pub fn write_frame(&self, frame: &[u8]) -> Result<()> {
+        let offset_us = self.start.elapsed().as_micros() as u64;
+        let entry = RecordingEntry::new(offset_us, Bytes::copy_from_slice(frame));
+        let mut buf = BytesMut::with_capacity(RecordingEntry::PREFIX_SIZE + frame.len());
+        entry.encode(&mut buf);
+
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| Error::Other("Recording writer lock poisoned".to_string()))?;
+        if let Some(header) = keyframe_header(&entry.frame) {
+            if state.header.codec.is_none() {
+                state.header.width = header.width;
+                state.header.height = header.height;
+                state.header.codec = Some(header.frame_type);
+            }
+            let position = state.position;
+            state.index.keyframes.push(RecordingKeyframe {
+                sequence: header.sequence,
+                offset_us,
+                position,
+            });
+        }
+        state.writer.write_all(&buf)?;
+        state.position += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Write the index after the entries and fill in the header
+    fn finish(&self) -> Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| Error::Other("Recording writer lock poisoned".to_string()))?;
+        let mut buf = BytesMut::with_capacity(state.index.encoded_size());
+        state.index.encode(&mut buf);
+        state.writer.write_all(&buf)?;
+
+        let mut buf = BytesMut::with_capacity(RecordingHeader::SIZE);
+        state.header.encode(&mut buf);
+        state.writer.seek(SeekFrom::Start(0))?;
+        state.writer.write_all(&buf)?;
+        state.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for RecordingWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            warn!("Failed to finish recording: {}", e);
+        }
+    }
+}
+
+/// Header of `frame` if it can be shown without the frames before it
+fn keyframe_header(frame: &Bytes) -> Option<FrameHeader> {
+    let header = FrameHeader::decode(&mut frame.clone()).ok()?;
+    let independent = match header.frame_type {
+        FrameType::RawFrame => true,
+        FrameType::H264Frame => header.is_keyframe,
+        _ => false,
+    };
+    independent.then_some(header)
+}
+
+/// Reads frames back from a recording
+pub struct RecordingReader<R> {
+    reader: R,
+    header: RecordingHeader,
+    index: RecordingIndex,
+    max_frame_size: usize,
+    // Where the entries stop and the index starts
+    entries_end: u64,
+    position: u64,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> RecordingReader<R> {
+    /// Read the header and index and prepare to read frames
+    ///
+    /// Frames larger than `max_frame_size` are rejected rather than
+    /// allocated. A recording without an index can still be read from the
+    /// start, but not seeked in.
+    pub async fn new(mut reader: R, max_frame_size: usize) -> Result<Self> {
+        let mut header = [0u8; RecordingHeader::SIZE];
+        reader.read_exact(&mut header).await?;
+        let header = RecordingHeader::decode(&mut Bytes::copy_from_slice(&header))?;
+
+        let len = reader.seek(SeekFrom::End(0)).await?;
+        let (index, entries_end) = read_index(&mut reader, len).await?;
+        reader
+            .seek(SeekFrom::Start(RecordingHeader::SIZE as u64))
+            .await?;
+
+        Ok(Self {
+            reader,
+            header,
+            index,
+            max_frame_size,
+            entries_end,
+            position: RecordingHeader::SIZE as u64,
+        })
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+
+    /// Keyframes that can be seeked to, in recording order
+    pub fn keyframes(&self) -> &[RecordingKeyframe] {
+        &self.index.keyframes
+    }
+
+    /// Next frame, or `None` at the end of the recording
+    pub async fn read_frame(&mut self) -> Result<Option<RecordingEntry>> {
+        if self.position >= self.entries_end {
+            return Ok(None);
+        }
+        let mut prefix = [0u8; RecordingEntry::PREFIX_SIZE];
+        match self.reader.read_exact(&mut prefix).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let (offset_us, len) = RecordingEntry::decode_prefix(&prefix)?;
+        if len > self.max_frame_size {
+            return Err(Error::protocol(format!(
+                "Recorded frame too large: {} bytes",
+                len
+            )));
+        }
+
+        let mut frame = vec![0u8; len];
+        self.reader.read_exact(&mut frame).await?;
+        self.position += (RecordingEntry::PREFIX_SIZE + len) as u64;
+        Ok(Some(RecordingEntry::new(offset_us, Bytes::from(frame))))
+    }
+
+    /// Continue reading at the last keyframe at or before `sequence`
+    ///
+    /// Returns the keyframe, or `None` (without moving) if the index has
+    /// none that early.
+    pub async fn seek_to_keyframe(&mut self, sequence: u64) -> Result<Option<RecordingKeyframe>> {
+        let Some(&keyframe) = self.index.find(sequence) else {
+            return Ok(None);
+        };
+        self.reader.seek(SeekFrom::Start(keyframe.position)).await?;
+        self.position = keyframe.position;
+        Ok(Some(keyframe))
+    }
+}
+
+/// Index at the end of a `len`-byte recording and where it starts
+///
+/// A recording without one has an empty index and entries up to the end.
+async fn read_index<R: AsyncRead + AsyncSeek + Unpin>(
+    reader: &mut R,
+    len: u64,
+) -> Result<(RecordingIndex, u64)> {
+    let no_index = Ok((RecordingIndex::default(), len));
+    let Some(trailer_start) = len.checked_sub(RecordingIndex::TRAILER_SIZE as u64) else {
+        return no_index;
+    };
+    if trailer_start < RecordingHeader::SIZE as u64 {
+        return no_index;
+    }
+
+    let mut trailer = [0u8; RecordingIndex::TRAILER_SIZE];
+    reader.seek(SeekFrom::Start(trailer_start)).await?;
+    reader.read_exact(&mut trailer).await?;
+    let Some(count) = RecordingIndex::decode_trailer(&trailer) else {
+        return no_index;
+    };
+
+    let size = (count * RecordingIndex::KEYFRAME_SIZE + RecordingIndex::TRAILER_SIZE) as u64;
+    let Some(start) = len
+        .checked_sub(size)
+        .filter(|&start| start >= RecordingHeader::SIZE as u64)
+    else {
+        return Err(Error::protocol("Recording index larger than the file"));
+    };
+    let mut index = vec![0u8; size as usize];
+    reader.seek(SeekFrom::Start(start)).await?;
+    reader.read_exact(&mut index).await?;
+    Ok((RecordingIndex::decode(&mut Bytes::from(index))?, start))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::protocol::{Frame, RECORDING_VERSION};
+
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("thunder_rec_{}_{}.bin", name, std::process::id()))
+    }
+
+    fn encoded_frame(frame_type: FrameType, sequence: u64, is_keyframe: bool) -> Bytes {
+        let header =
+            FrameHeader::new(frame_type, sequence, 0, 64, 32, 4).with_keyframe(is_keyframe);
+        Frame::new(header, Bytes::from_static(&[1, 2, 3, 4]))
+            .encode()
+            .freeze()
+    }
+
+    #[tokio::test]
+    async fn test_record_and_read_back() {
+        let path = temp_path("read");
+
+        let writer = RecordingWriter::create(&path).unwrap();
+        writer.write_frame(b"first frame").unwrap();
+        writer.write_frame(b"second").unwrap();
+        drop(writer);
+
+        let file = tokio::fs::File::open(&path).await.unwrap();
+        let mut reader = RecordingReader::new(file, 1024).await.unwrap();
+        let first = reader.read_frame().await.unwrap().unwrap();
+        let second = reader.read_frame().await.unwrap().unwrap();
+        assert!(reader.read_frame().await.unwrap().is_none());
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(&first.frame[..], b"first frame");
+        assert_eq!(&second.frame[..], b"second");
+        assert!(second.offset_us >= first.offset_us);
+        assert_eq!(reader.header().codec, None);
+        assert!(reader.keyframes().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_seek_to_keyframe() {
+        let path = temp_path("seek");
+        let frames = [
+            encoded_frame(FrameType::H264Frame, 0, true),
+            encoded_frame(FrameType::H264Frame, 1, false),
+            encoded_frame(FrameType::Control, 2, true),
+            encoded_frame(FrameType::H264Frame, 3, true),
+            encoded_frame(FrameType::H264Frame, 4, false),
+        ];
+
+        let writer = RecordingWriter::create(&path).unwrap();
+        for frame in &frames {
+            writer.write_frame(frame).unwrap();
+        }
+        drop(writer);
+
+        let file = tokio::fs::File::open(&path).await.unwrap();
+        let mut reader = RecordingReader::new(file, 1024).await.unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let header = reader.header();
+        assert_eq!(header.version, RECORDING_VERSION);
+        assert_eq!((header.width, header.height), (64, 32));
+        assert_eq!(header.codec, Some(FrameType::H264Frame));
+        let sequences: Vec<u64> = reader.keyframes().iter().map(|k| k.sequence).collect();
+        assert_eq!(sequences, [0, 3]);
+
+        let keyframe = reader.seek_to_keyframe(4).await.unwrap().unwrap();
+        assert_eq!(keyframe.sequence, 3);
+        assert_eq!(reader.read_frame().await.unwrap().unwrap().frame, frames[3]);
+        assert_eq!(reader.read_frame().await.unwrap().unwrap().frame, frames[4]);
+        assert!(
+            reader.read_frame().await.unwrap().is_none(),
+            "index is not a frame"
+        );
+
+        reader.seek_to_keyframe(2).await.unwrap().unwrap();
+        assert_eq!(reader.read_frame().await.unwrap().unwrap().frame, frames[0]);
+    }
+
+    #[tokio::test]
+    async fn test_reader_rejects_bad_input() {
+        let not_a_recording = Cursor::new(b"NOTAREC!ABCDEFGH".to_vec());
+        assert!(RecordingReader::new(not_a_recording, 1024).await.is_err());
+
+        // Header and one entry but no index, as left by a crash
+        let mut buf = BytesMut::new();
+        RecordingHeader::default().encode(&mut buf);
+        RecordingEntry::new(0, Bytes::from_static(&[0; 64])).encode(&mut buf);
+        let data = buf.to_vec();
+
+        let mut reader = RecordingReader::new(Cursor::new(data.clone()), 32)
+            .await
+            .unwrap();
+        assert!(reader.read_frame().await.is_err(), "oversized frame");
+
+        let mut reader = RecordingReader::new(Cursor::new(data.clone()), 1024)
+            .await
+            .unwrap();
+        assert!(reader.read_frame().await.unwrap().is_some());
+        assert!(reader.seek_to_keyframe(0).await.unwrap().is_none());
+
+        // Truncated mid-frame
+        let truncated = Cursor::new(data[..data.len() - 1].to_vec());
+        let mut reader = RecordingReader::new(truncated, 1024).await.unwrap();
+        assert!(reader.read_frame().await.is_err());
+    }
+}
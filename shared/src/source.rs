@@ -0,0 +1,350 @@
+//! Where a sender's frames come from
+//!
+//! A sender is a loop that takes frames from a `FrameSource` and writes them
+//! to a stream, so the transport and protocol don't care what produces them.
+//! Screen capture is one source. The two here need no capture hardware:
+//! `TestPatternSource` generates patterns at a fixed rate, and
+//! `FileFrameSource` plays a recording back at the pace it was recorded.
+//! Senders for other platforms, and tests, can swap one for another.
+
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::fs::File;
+use tokio::io::BufReader;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+use crate::protocol::{
+    Frame, FrameHeader, FrameType, RecordingEntry, RecordingHeader, RecordingKeyframe,
+    MAX_FRAME_DIMENSION,
+};
+use crate::recording::RecordingReader;
+use crate::test_pattern::{self, GradientAxis};
+use crate::Result;
+
+/// Something that produces frames to send
+pub trait FrameSource: Send {
+    /// Wait for the next frame, or `None` once the source has no more
+    ///
+    /// A frame that can't be produced is an error; whether to carry on with
+    /// the next one is up to the caller.
+    fn next_frame(&mut self) -> impl Future<Output = Result<Option<Frame>>> + Send;
+}
+
+/// Which `test_pattern` a `TestPatternSource` generates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestPattern {
+    ColorBars,
+    Gradient(GradientAxis),
+    Checkerboard { cell_size: u16 },
+    MovingBar,
+    FrameCounter,
+}
+
+impl TestPattern {
+    /// Whether frames differ from one to the next
+    fn is_animated(self) -> bool {
+        matches!(self, Self::MovingBar | Self::FrameCounter)
+    }
+
+    /// RGBA pixels of frame `index`
+    fn generate(self, width: u16, height: u16, index: u64) -> Bytes {
+        match self {
+            Self::ColorBars => test_pattern::generate_color_bars(width, height),
+            Self::Gradient(axis) => test_pattern::generate_gradient(width, height, axis),
+            Self::Checkerboard { cell_size } => {
+                test_pattern::generate_checkerboard(width, height, cell_size)
+            }
+            Self::MovingBar => test_pattern::generate_moving_bar(width, height, index),
+            Self::FrameCounter => test_pattern::generate_frame_counter(width, height, index),
+        }
+    }
+}
+
+/// Raw frames of a test pattern at a fixed frame rate
+///
+/// Timestamps count from the first frame. A caller that falls behind gets
+/// the next frame right away, and missed frames are skipped rather than
+/// sent in a burst.
+pub struct TestPatternSource {
+    pattern: TestPattern,
+    width: u16,
+    height: u16,
+    period: Option<Duration>,
+    ticker: Option<Interval>,
+    start: Option<Instant>,
+    sequence: u64,
+    limit: Option<u64>,
+    // Pixels of a pattern that doesn't move, generated once
+    still: Option<Bytes>,
+}
+
+impl TestPatternSource {
+    /// `width`x`height` frames of `pattern`, `fps` a second (0 for as fast
+    /// as they are taken)
+    pub fn new(pattern: TestPattern, width: u16, height: u16, fps: u32) -> Self {
+        Self {
+            pattern,
+            width,
+            height,
+            period: (fps > 0).then(|| Duration::from_secs(1) / fps),
+            ticker: None,
+            start: None,
+            sequence: 0,
+            limit: None,
+            still: None,
+        }
+    }
+
+    /// End the source after `frames` frames
+    pub fn with_frame_limit(mut self, frames: u64) -> Self {
+        self.limit = Some(frames);
+        self
+    }
+
+    fn pixels(&mut self) -> Bytes {
+        if self.pattern.is_animated() {
+            return self
+                .pattern
+                .generate(self.width, self.height, self.sequence);
+        }
+        let (pattern, width, height) = (self.pattern, self.width, self.height);
+        self.still
+            .get_or_insert_with(|| pattern.generate(width, height, 0))
+            .clone()
+    }
+}
+
+impl FrameSource for TestPatternSource {
+    async fn next_frame(&mut self) -> Result<Option<Frame>> {
+        if self.limit.is_some_and(|limit| self.sequence >= limit) {
+            return Ok(None);
+        }
+        if let Some(period) = self.period {
+            // Created on first use, as intervals need a runtime.
+            let ticker = self.ticker.get_or_insert_with(|| {
+                let mut ticker = tokio::time::interval(period);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                ticker
+            });
+            ticker.tick().await;
+        }
+
+        let timestamp_us = self.start.get_or_insert_with(Instant::now).elapsed();
+        let payload = self.pixels();
+        let header = FrameHeader::new(
+            FrameType::RawFrame,
+            self.sequence,
+            timestamp_us.as_micros() as u64,
+            self.width,
+            self.height,
+            payload.len() as u32,
+        );
+        self.sequence += 1;
+        Ok(Some(Frame::new(header, payload)))
+    }
+}
+
+/// Frames of a recording, released at the times they were recorded
+///
+/// Playback time starts with the first frame taken, or at the keyframe
+/// seeked to.
+pub struct FileFrameSource {
+    reader: RecordingReader<BufReader<File>>,
+    max_payload_size: usize,
+    max_dimension: u16,
+    start: Option<Instant>,
+    skipped_us: u64,
+}
+
+impl FileFrameSource {
+    /// Open the recording at `path`, rejecting frames with payloads over
+    /// `max_payload_size` bytes
+    pub async fn open(path: impl AsRef<Path>, max_payload_size: usize) -> Result<Self> {
+        let file = File::open(path).await?;
+        let reader =
+            RecordingReader::new(BufReader::new(file), max_payload_size + FrameHeader::SIZE)
+                .await?;
+        Ok(Self {
+            reader,
+            max_payload_size,
+            max_dimension: MAX_FRAME_DIMENSION,
+            start: None,
+            skipped_us: 0,
+        })
+    }
+
+    /// Reject frames wider or taller than `max_dimension` pixels
+    pub fn with_max_dimension(mut self, max_dimension: u16) -> Self {
+        self.max_dimension = max_dimension;
+        self
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        self.reader.header()
+    }
+
+    /// Keyframes that can be seeked to, in recording order
+    pub fn keyframes(&self) -> &[RecordingKeyframe] {
+        self.reader.keyframes()
+    }
+
+    /// Continue at the last keyframe at or before `sequence`
+    ///
+    /// Returns the keyframe, or `None` (without moving) if the index has
+    /// none that early.
+    pub async fn seek_to_keyframe(&mut self, sequence: u64) -> Result<Option<RecordingKeyframe>> {
+        let keyframe = self.reader.seek_to_keyframe(sequence).await?;
+        if let Some(keyframe) = keyframe {
+            self.skipped_us = keyframe.offset_us;
+            self.start = None;
+        }
+        Ok(keyframe)
+    }
+
+    /// The next frame as it was recorded, header and payload undecoded,
+    /// once its time has come
+    ///
+    /// For replaying through the same parsing as a live stream.
+    pub async fn next_entry(&mut self) -> Result<Option<RecordingEntry>> {
+        let Some(entry) = self.reader.read_frame().await? else {
+            return Ok(None);
+        };
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let offset = Duration::from_micros(entry.offset_us.saturating_sub(self.skipped_us));
+        tokio::time::sleep_until(start + offset).await;
+        Ok(Some(entry))
+    }
+}
+
+impl FrameSource for FileFrameSource {
+    /// A recorded frame that doesn't decode is an error; the next call
+    /// reads on after it.
+    async fn next_frame(&mut self) -> Result<Option<Frame>> {
+        let Some(entry) = self.next_entry().await? else {
+            return Ok(None);
+        };
+        let mut frame = entry.frame;
+        Frame::decode_with_limits(&mut frame, self.max_payload_size, self.max_dimension).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::protocol::{FrameDecoder, MAX_FRAME_SIZE};
+    use crate::recording::RecordingWriter;
+    use crate::transport::{QuicClient, QuicServer};
+    use crate::TransportTuning;
+
+    /// Send every frame of `source` over one QUIC stream and decode what
+    /// the server got
+    async fn send_over_quic(mut source: impl FrameSource) -> Vec<Frame> {
+        let server = QuicServer::new_self_signed(
+            "127.0.0.1:0".parse().unwrap(),
+            &TransportTuning::default(),
+        )
+        .await
+        .unwrap();
+        let server_addr = server.local_addr();
+        let server = Arc::new(server);
+        let accept = tokio::spawn({
+            let server = server.clone();
+            async move {
+                let conn = server.accept().await.unwrap();
+                let mut recv = conn.accept_uni().await.unwrap();
+                recv.read_to_end(64 << 20).await.unwrap()
+            }
+        });
+
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let conn = client.connect(server_addr, "localhost").await.unwrap();
+        let mut send = conn.open_uni().await.unwrap();
+        while let Some(frame) = source.next_frame().await.unwrap() {
+            send.write_all(&frame.encode()).await.unwrap();
+        }
+        send.finish().await.unwrap();
+
+        let mut decoder = FrameDecoder::new(MAX_FRAME_SIZE);
+        decoder.feed(&accept.await.unwrap());
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.decode().unwrap() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    #[tokio::test]
+    async fn test_pattern_source_over_quic() {
+        let source =
+            TestPatternSource::new(TestPattern::FrameCounter, 64, 48, 0).with_frame_limit(10);
+        let frames = send_over_quic(source).await;
+
+        assert_eq!(frames.len(), 10);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.header.frame_type, FrameType::RawFrame);
+            assert_eq!(frame.header.sequence, i as u64);
+            assert_eq!((frame.header.width, frame.header.height), (64, 48));
+            assert_eq!(
+                frame.payload,
+                test_pattern::generate_frame_counter(64, 48, i as u64)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pattern_source_paces_frames() {
+        let start = std::time::Instant::now();
+        let source =
+            TestPatternSource::new(TestPattern::ColorBars, 32, 16, 100).with_frame_limit(6);
+        let frames = send_over_quic(source).await;
+        assert_eq!(frames.len(), 6);
+        assert!(start.elapsed() >= Duration::from_millis(50), "5 periods");
+
+        let timestamps: Vec<u64> = frames.iter().map(|f| f.header.timestamp_us).collect();
+        assert_eq!(timestamps[0], 0);
+        assert!(timestamps.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(frames.iter().all(|f| f.payload == frames[0].payload));
+    }
+
+    fn encoded(sequence: u64, is_keyframe: bool) -> Bytes {
+        let payload = test_pattern::generate_moving_bar(8, 4, sequence);
+        let header = FrameHeader::new(FrameType::RawFrame, sequence, 0, 8, 4, 128)
+            .with_keyframe(is_keyframe);
+        Frame::new(header, payload).encode().freeze()
+    }
+
+    #[tokio::test]
+    async fn test_file_source_plays_recording() {
+        let path = std::env::temp_dir().join(format!("thunder_source_{}.bin", std::process::id()));
+        let writer = RecordingWriter::create(&path).unwrap();
+        writer.write_frame(&encoded(0, true)).unwrap();
+        writer.write_frame(b"not a frame").unwrap();
+        writer.write_frame(&encoded(1, true)).unwrap();
+        drop(writer);
+
+        let mut source = FileFrameSource::open(&path, 1024).await.unwrap();
+        assert_eq!((source.header().width, source.header().height), (8, 4));
+        assert_eq!(source.keyframes().len(), 2);
+
+        let first = source.next_frame().await.unwrap().unwrap();
+        assert_eq!(first.header.sequence, 0);
+        assert!(source.next_frame().await.is_err(), "garbage entry");
+        let second = source.next_frame().await.unwrap().unwrap();
+        assert_eq!(second.payload, test_pattern::generate_moving_bar(8, 4, 1));
+        assert!(source.next_frame().await.unwrap().is_none());
+
+        source.seek_to_keyframe(1).await.unwrap().unwrap();
+        let again = source.next_frame().await.unwrap().unwrap();
+        assert_eq!(again.header.sequence, 1);
+
+        // Frames over the payload limit are rejected
+        let mut small = FileFrameSource::open(&path, 64).await.unwrap();
+        assert!(small.next_frame().await.is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -1,11 +1,21 @@
 //! Statistics and metrics collection
 
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 
+use crate::protocol::FrameType;
+
+/// Frame and byte totals for one frame type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameTypeCounts {
+    pub frames: u64,
+    pub bytes: u64,
+}
+
 /// Statistics snapshot
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct StatsSnapshot {
@@ -18,6 +28,15 @@ pub struct StatsSnapshot {
     /// Bitrate in Mbps
     pub bitrate_mbps: f64,
 
+    /// `fps` smoothed with an exponential moving average, for a readout that
+    /// doesn't flicker
+    #[serde(default)]
+    pub smoothed_fps: f64,
+
+    /// `bitrate_mbps` smoothed the same way
+    #[serde(default)]
+    pub smoothed_bitrate_mbps: f64,
+
     /// Total frames sent/received
     pub total_frames: u64,
 
@@ -27,47 +46,649 @@ pub struct StatsSnapshot {
     /// Dropped frames
     pub dropped_frames: u64,
 
-    /// Estimated latency in milliseconds (if available)
+    /// Times the sender's timestamps jumped back and latency started over
+    #[serde(default)]
+    pub clock_reset_count: u64,
+
+    /// Estimated one-way latency in milliseconds (if available)
+    ///
+    /// Measured from the end of encoding to display once the sender's clock
+    /// is known, otherwise half the transport round-trip time.
     pub latency_ms: Option<f64>,
 
+    /// Time the sender spent between capture and the end of encoding, in
+    /// milliseconds, from the two timestamps in v5+ frame headers
+    #[serde(default)]
+    pub encode_latency_ms: Option<f64>,
+
+    /// Transport round-trip time in milliseconds, from QUIC path stats
+    #[serde(default)]
+    pub rtt_ms: Option<f64>,
+
+    /// Inter-arrival jitter in milliseconds (mean absolute deviation of frame intervals)
+    pub jitter_ms: f64,
+
+    /// Estimated loss in percent: sequence gaps in expected frames, or
+    /// transport packet loss if that is worse
+    pub loss_percent: f64,
+
+    /// Video frames expected from the sequence numbers seen so far
+    #[serde(default)]
+    pub expected_frames: u64,
+
+    /// Expected video frames that never arrived
+    #[serde(default)]
+    pub missing_frames: u64,
+
+    /// Video frames that arrived after a newer one
+    #[serde(default)]
+    pub out_of_order_frames: u64,
+
+    /// Video frames whose sequence number had already been seen
+    #[serde(default)]
+    pub duplicate_frames: u64,
+
     /// Uptime in seconds
     pub uptime_secs: f64,
+
+    /// Seconds the totals cover: since the collector was created or last reset
+    #[serde(default)]
+    pub period_secs: f64,
+
+    /// Totals per frame type, for types seen at least once
+    #[serde(default)]
+    pub frame_types: BTreeMap<FrameType, FrameTypeCounts>,
+
+    /// Frame sizes as (upper bound in bytes, count) per power-of-two bucket
+    #[serde(default)]
+    pub size_histogram: Vec<(u64, u64)>,
+}
+
+impl StatsSnapshot {
+    /// Render in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        use std::fmt::Write;
+
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+            let _ = writeln!(out, "# HELP thundermirror_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE thundermirror_{} {}", name, kind);
+            let _ = writeln!(out, "thundermirror_{} {}", name, value);
+        };
+
+        metric("fps", "gauge", "Frames per second", self.fps);
+        metric(
+            "bytes_per_second",
+            "gauge",
+            "Bytes per second",
+            self.bytes_per_sec as f64,
+        );
+        metric(
+            "bitrate_mbps",
+            "gauge",
+            "Bitrate in Mbps",
+            self.bitrate_mbps,
+        );
+        metric(
+            "frames_total",
+            "counter",
+            "Total frames",
+            self.total_frames as f64,
+        );
+        metric(
+            "bytes_total",
+            "counter",
+            "Total bytes",
+            self.total_bytes as f64,
+        );
+        metric(
+            "dropped_frames_total",
+            "counter",
+            "Dropped frames",
+            self.dropped_frames as f64,
+        );
+        metric(
+            "clock_resets_total",
+            "counter",
+            "Sender timestamp regressions",
+            self.clock_reset_count as f64,
+        );
+        metric(
+            "out_of_order_frames_total",
+            "counter",
+            "Video frames that arrived after a newer one",
+            self.out_of_order_frames as f64,
+        );
+        metric(
+            "duplicate_frames_total",
+            "counter",
+            "Video frames with an already seen sequence number",
+            self.duplicate_frames as f64,
+        );
+        if let Some(latency) = self.latency_ms {
+            metric("latency_ms", "gauge", "Smoothed one-way latency", latency);
+        }
+        if let Some(encode) = self.encode_latency_ms {
+            metric(
+                "encode_latency_ms",
+                "gauge",
+                "Smoothed sender capture-to-encode time",
+                encode,
+            );
+        }
+        if let Some(rtt) = self.rtt_ms {
+            metric("rtt_ms", "gauge", "Transport round-trip time", rtt);
+        }
+        metric("jitter_ms", "gauge", "Inter-arrival jitter", self.jitter_ms);
+        metric(
+            "loss_percent",
+            "gauge",
+            "Estimated loss from sequence gaps or packet loss",
+            self.loss_percent,
+        );
+        metric("uptime_seconds", "gauge", "Uptime", self.uptime_secs);
+
+        if !self.size_histogram.is_empty() {
+            self.write_size_histogram(&mut out);
+        }
+
+        if !self.frame_types.is_empty() {
+            self.write_per_type(&mut out, "type_frames_total", "Frames by type", |c| {
+                c.frames
+            });
+            self.write_per_type(&mut out, "type_bytes_total", "Bytes by type", |c| c.bytes);
+        }
+
+        out
+    }
+
+    /// `size_histogram` as a Prometheus histogram (cumulative buckets)
+    fn write_size_histogram(&self, out: &mut String) {
+        use std::fmt::Write;
+
+        let name = "thundermirror_frame_size_bytes";
+        let _ = writeln!(out, "# HELP {} Video frame payload sizes", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut cumulative = 0;
+        for &(upper_bound, count) in &self.size_histogram {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name, upper_bound, cumulative
+            );
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, cumulative);
+        let _ = writeln!(out, "{}_sum {}", name, self.total_bytes);
+        let _ = writeln!(out, "{}_count {}", name, cumulative);
+    }
+
+    /// One counter with a sample per frame type
+    fn write_per_type(
+        &self,
+        out: &mut String,
+        name: &str,
+        help: &str,
+        value: fn(&FrameTypeCounts) -> u64,
+    ) {
+        use std::fmt::Write;
+
+        let _ = writeln!(out, "# HELP thundermirror_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE thundermirror_{} counter", name);
+        for (frame_type, counts) in &self.frame_types {
+            let label = frame_type_label(*frame_type);
+            let _ = writeln!(
+                out,
+                "thundermirror_{}{{type=\"{}\"}} {}",
+                name,
+                label,
+                value(counts)
+            );
+        }
+    }
+
+    /// Totals for `frame_type` (zero if none were seen)
+    pub fn frame_type(&self, frame_type: FrameType) -> FrameTypeCounts {
+        self.frame_types
+            .get(&frame_type)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Serialize as JSON
+    pub fn to_json(&self) -> crate::Result<String> {
+        serde_json::to_string(self)
+            .map_err(|e| crate::Error::Other(format!("Failed to serialize stats: {}", e)))
+    }
+}
+
+/// Machine-readable receiver status, one JSON object per line
+///
+/// Front ends (the Windows UI) follow the receiver through these instead of
+/// matching log messages. Serialized with an `event` tag, e.g.
+/// `{"event":"connected","remote":"192.168.50.1:50000"}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum StatusEvent {
+    /// Waiting for a sender on `port`
+    Listening {
+        port: u16,
+
+        /// The bound address, e.g. `0.0.0.0:9999` (missing from older receivers)
+        #[serde(default)]
+        address: Option<String>,
+    },
+
+    /// A sender connected
+    Connected { remote: String },
+
+    /// A sender's connection ended
+    Disconnected { remote: String },
+
+    /// Stream statistics, reported once a second
+    Stats {
+        fps: f64,
+        mbps: f64,
+        width: u32,
+        height: u32,
+        codec: String,
+        latency_ms: Option<f64>,
+
+        /// `fps` and `mbps` smoothed for display (missing from older receivers)
+        #[serde(default)]
+        smoothed_fps: Option<f64>,
+        #[serde(default)]
+        smoothed_mbps: Option<f64>,
+    },
+
+    /// Measurements the sender reported in its last stats frame
+    SenderStats { fps: f64, loss_percent: f64 },
+
+    /// No H.264 decoder could be created; only raw frames are shown
+    DecoderUnavailable { message: String },
+
+    /// Something failed (the server, or a connection)
+    Error { message: String },
+}
+
+impl StatusEvent {
+    /// Serialize as a single line of JSON (without the newline)
+    pub fn to_json(&self) -> crate::Result<String> {
+        serde_json::to_string(self)
+            .map_err(|e| crate::Error::Other(format!("Failed to serialize status: {}", e)))
+    }
+
+    /// Parse one line of `--status-json` output
+    pub fn from_json(line: &str) -> crate::Result<Self> {
+        serde_json::from_str(line.trim())
+            .map_err(|e| crate::Error::protocol(format!("Invalid status line: {}", e)))
+    }
+}
+
+/// Prometheus label value for a frame type
+fn frame_type_label(frame_type: FrameType) -> &'static str {
+    match frame_type {
+        FrameType::RawFrame => "raw",
+        FrameType::H264Frame => "h264",
+        FrameType::Control => "control",
+        FrameType::Stats => "stats",
+        FrameType::Audio => "audio",
+        FrameType::DeltaFrame => "delta",
+        FrameType::Cursor => "cursor",
+    }
+}
+
+/// Upper bound of the smallest frame-size bucket (1 KiB)
+const SIZE_BUCKET_MIN_SHIFT: u32 = 10;
+
+/// Number of frame-size buckets: 1 KiB up to `MAX_FRAME_SIZE` (8 MiB)
+const SIZE_BUCKETS: usize = 14;
+
+/// Histogram bucket for a frame of `bytes`
+///
+/// Bucket `i` holds sizes up to `1 KiB << i`; anything larger than the last
+/// bound lands in the last bucket.
+fn size_bucket(bytes: u64) -> usize {
+    let ceil_log2 = if bytes <= 1 {
+        0
+    } else {
+        u64::BITS - (bytes - 1).leading_zeros()
+    };
+    (ceil_log2.saturating_sub(SIZE_BUCKET_MIN_SHIFT) as usize).min(SIZE_BUCKETS - 1)
+}
+
+/// Per-frame-type counters, indexed by the `FrameType` discriminant
+#[derive(Debug, Default)]
+struct TypeCounters {
+    frames: AtomicU64,
+    bytes: AtomicU64,
 }
 
 /// Thread-safe statistics collector
 #[derive(Debug)]
 pub struct Stats {
     start_time: Instant,
-    last_snapshot_time: std::sync::Mutex<Instant>,
 
     // Atomic counters for thread-safe updates
     frames: AtomicU64,
     bytes: AtomicU64,
     dropped: AtomicU64,
+    clock_resets: AtomicU64,
+    by_type: [TypeCounters; FrameType::ALL.len()],
+    size_buckets: [AtomicU64; SIZE_BUCKETS],
+
+    // (arrival time, bytes) per frame within the trailing rate window
+    window: Duration,
+    recent: std::sync::Mutex<VecDeque<(Instant, u64)>>,
+
+    // Encode-to-display and capture-to-encode latency
+    latency: SmoothedMs,
+    encode_latency: SmoothedMs,
+
+    // Sequence/arrival tracking for jitter and loss
+    arrivals: std::sync::Mutex<ArrivalTracker>,
+
+    // Latest transport path sample, while a connection reports one
+    path: std::sync::Mutex<Option<PathSample>>,
+
+    // Moving averages of the windowed rates, and the weight of each update
+    smoothed: std::sync::Mutex<Option<SmoothedRates>>,
+    rate_smoothing: AtomicU64,
+
+    // Microseconds after `start_time` at which the totals were last reset
+    period_start_us: AtomicU64,
+
+    // Named sub-meters, created on first use
+    meters: std::sync::Mutex<BTreeMap<String, Arc<Stats>>>,
+}
+
+/// FPS and bitrate moving averages, updated once per rate window
+#[derive(Debug, Clone, Copy)]
+struct SmoothedRates {
+    fps: f64,
+    bitrate_mbps: f64,
+    updated: Instant,
+}
+
+/// Round-trip time and packet loss reported by the transport
+#[derive(Debug, Clone, Copy)]
+struct PathSample {
+    rtt_ms: f64,
+    loss_percent: f64,
+}
+
+/// Trailing window used for FPS/bitrate by `Stats::new`
+const DEFAULT_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Weight of each new latency sample in the exponential moving average
+const LATENCY_SMOOTHING: f64 = 0.1;
+
+/// Default weight of each rate window's FPS and bitrate in their moving averages
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// Weight of each new interval in the jitter estimate (RFC 3550 uses 1/16)
+const JITTER_SMOOTHING: f64 = 1.0 / 16.0;
+
+/// Exponential moving average of a latency, safe to update from any thread
+#[derive(Debug, Default)]
+struct SmoothedMs {
+    // Smoothed value (f64 bits) and number of samples folded into it
+    bits: AtomicU64,
+    samples: AtomicU64,
+}
 
-    // Last snapshot values for rate calculation
-    last_frames: AtomicU64,
-    last_bytes: AtomicU64,
+impl SmoothedMs {
+    fn record(&self, ms: f64) {
+        let first = self.samples.fetch_add(1, Ordering::Relaxed) == 0;
+        let _ = self
+            .bits
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                let smoothed = if first {
+                    ms
+                } else {
+                    let prev = f64::from_bits(bits);
+                    prev + LATENCY_SMOOTHING * (ms - prev)
+                };
+                Some(smoothed.to_bits())
+            });
+    }
+
+    /// The smoothed value, or `None` before the first sample
+    fn get(&self) -> Option<f64> {
+        (self.samples.load(Ordering::Relaxed) > 0)
+            .then(|| f64::from_bits(self.bits.load(Ordering::Relaxed)))
+    }
+
+    fn reset(&self) {
+        self.bits.store(0, Ordering::Relaxed);
+        self.samples.store(0, Ordering::Relaxed);
+    }
+
+    /// The smoothed value, starting over with the next sample
+    fn take(&self) -> Option<f64> {
+        let samples = self.samples.swap(0, Ordering::Relaxed);
+        let bits = self.bits.swap(0, Ordering::Relaxed);
+        (samples > 0).then(|| f64::from_bits(bits))
+    }
+}
+
+/// How a video frame's sequence number relates to the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceArrival {
+    /// Newer than every frame so far
+    InOrder,
+    /// Older than the newest frame, filling a gap
+    OutOfOrder,
+    /// Already seen; showing it again would step the picture back
+    Duplicate,
+}
+
+/// How far behind the newest sequence duplicates can still be told apart
+/// from late frames
+const SEEN_WINDOW: u64 = 64;
+
+/// Sequence gap and inter-arrival interval state
+#[derive(Debug, Default)]
+struct ArrivalTracker {
+    first_seq: Option<u64>,
+    highest_seq: u64,
+    /// Bit `n` is set if `highest_seq - n` was seen
+    seen: u64,
+    /// Sequences skipped over and not (yet) seen late
+    missing: u64,
+    out_of_order: u64,
+    duplicates: u64,
+    last_arrival: Option<Instant>,
+    mean_interval_ms: Option<f64>,
+    jitter_ms: f64,
+}
+
+impl ArrivalTracker {
+    fn record(&mut self, seq: u64, now: Instant) -> SequenceArrival {
+        if let Some(last) = self.last_arrival {
+            let interval = now.duration_since(last).as_secs_f64() * 1000.0;
+            let mean = match self.mean_interval_ms {
+                Some(mean) => mean + JITTER_SMOOTHING * (interval - mean),
+                None => interval,
+            };
+            self.jitter_ms += JITTER_SMOOTHING * ((interval - mean).abs() - self.jitter_ms);
+            self.mean_interval_ms = Some(mean);
+        }
+        self.last_arrival = Some(now);
+
+        match self.first_seq {
+            None => {
+                self.first_seq = Some(seq);
+                self.highest_seq = seq;
+                self.seen = 1;
+                SequenceArrival::InOrder
+            }
+            Some(_) if seq > self.highest_seq => {
+                let ahead = seq - self.highest_seq;
+                self.missing += ahead - 1;
+                self.highest_seq = seq;
+                self.seen = if ahead < SEEN_WINDOW {
+                    self.seen << ahead | 1
+                } else {
+                    1
+                };
+                SequenceArrival::InOrder
+            }
+            Some(first) => {
+                let behind = self.highest_seq - seq;
+                self.out_of_order += 1;
+                if behind >= SEEN_WINDOW {
+                    // Too old to tell a late frame from a repeat, so it
+                    // can't be trusted to fill a gap: the gap stays lost.
+                    return SequenceArrival::OutOfOrder;
+                }
+                if self.seen & 1 << behind != 0 {
+                    self.out_of_order -= 1;
+                    self.duplicates += 1;
+                    return SequenceArrival::Duplicate;
+                }
+                self.seen |= 1 << behind;
+                // A late arrival fills a gap counted as missing; frames from
+                // before the first one were never counted.
+                if seq > first {
+                    self.missing = self.missing.saturating_sub(1);
+                }
+                SequenceArrival::OutOfOrder
+            }
+        }
+    }
+
+    fn expected(&self) -> u64 {
+        self.first_seq
+            .map_or(0, |first| self.highest_seq - first + 1)
+    }
+
+    fn loss_percent(&self) -> f64 {
+        match self.expected() {
+            0 => 0.0,
+            expected => (self.missing as f64 / expected as f64) * 100.0,
+        }
+    }
 }
 
 impl Stats {
-    /// Create new stats collector
+    /// Create new stats collector with a 1-second rate window
     pub fn new() -> Arc<Self> {
-        Arc::new(Self {
+        Self::with_window(DEFAULT_RATE_WINDOW)
+    }
+
+    /// Create a stats collector computing FPS/bitrate over a trailing `window`
+    pub fn with_window(window: Duration) -> Arc<Self> {
+        Arc::new(Self::build(window))
+    }
+
+    fn build(window: Duration) -> Self {
+        Self {
             start_time: Instant::now(),
-            last_snapshot_time: std::sync::Mutex::new(Instant::now()),
             frames: AtomicU64::new(0),
             bytes: AtomicU64::new(0),
             dropped: AtomicU64::new(0),
-            last_frames: AtomicU64::new(0),
-            last_bytes: AtomicU64::new(0),
-        })
+            clock_resets: AtomicU64::new(0),
+            by_type: Default::default(),
+            size_buckets: Default::default(),
+            window: window.max(Duration::from_millis(1)),
+            recent: std::sync::Mutex::new(VecDeque::new()),
+            latency: SmoothedMs::default(),
+            encode_latency: SmoothedMs::default(),
+            arrivals: std::sync::Mutex::new(ArrivalTracker::default()),
+            path: std::sync::Mutex::new(None),
+            smoothed: std::sync::Mutex::new(None),
+            rate_smoothing: AtomicU64::new(RATE_SMOOTHING.to_bits()),
+            period_start_us: AtomicU64::new(0),
+            meters: std::sync::Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Set how much each rate window moves `smoothed_fps` and
+    /// `smoothed_bitrate_mbps`, from just above 0 (barely) to 1 (no smoothing)
+    ///
+    /// Defaults to 0.3.
+    pub fn set_rate_smoothing(&self, alpha: f64) {
+        let alpha = if alpha.is_nan() {
+            RATE_SMOOTHING
+        } else {
+            alpha.clamp(0.01, 1.0)
+        };
+        self.rate_smoothing
+            .store(alpha.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Sub-meter `name`, created the first time it's asked for
+    ///
+    /// Sub-meters count separately from this collector and from each other,
+    /// so e.g. the decode path and the network path can be measured apart
+    /// without touching the global totals. They use the same rate window.
+    pub fn meter(&self, name: &str) -> Arc<Stats> {
+        let mut meters = self
+            .meters
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        meters
+            .entry(name.to_string())
+            .or_insert_with(|| Self::with_window(self.window))
+            .clone()
+    }
+
+    /// Snapshots of the sub-meters, by name
+    pub fn meter_snapshots(&self) -> BTreeMap<String, StatsSnapshot> {
+        self.meters
+            .lock()
+            .map(|meters| {
+                meters
+                    .iter()
+                    .map(|(name, meter)| (name.clone(), meter.snapshot()))
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 
     /// Record a frame
     pub fn record_frame(&self, bytes: u64) {
+        self.record_frame_at(bytes, Instant::now());
+    }
+
+    /// Record a frame of a given type
+    ///
+    /// Every type gets its own frame and byte totals. Only video frames
+    /// also count towards the overall totals, FPS and bitrate, so audio and
+    /// control traffic doesn't inflate the frame rate.
+    pub fn record_frame_typed(&self, frame_type: FrameType, bytes: u64) {
+        self.record_frame_typed_at(frame_type, bytes, Instant::now());
+    }
+
+    fn record_frame_typed_at(&self, frame_type: FrameType, bytes: u64, now: Instant) {
+        let counters = &self.by_type[frame_type as usize];
+        counters.frames.fetch_add(1, Ordering::Relaxed);
+        counters.bytes.fetch_add(bytes, Ordering::Relaxed);
+        if frame_type.is_video() {
+            self.record_frame_at(bytes, now);
+        }
+    }
+
+    fn record_frame_at(&self, bytes: u64, now: Instant) {
         self.frames.fetch_add(1, Ordering::Relaxed);
         self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.size_buckets[size_bucket(bytes)].fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut recent) = self.recent.lock() {
+            recent.push_back((now, bytes));
+            self.prune(&mut recent, now);
+        }
+    }
+
+    /// Drop samples that fell out of the trailing window
+    fn prune(&self, recent: &mut VecDeque<(Instant, u64)>, now: Instant) {
+        while let Some(&(t, _)) = recent.front() {
+            if now.duration_since(t) > self.window {
+                recent.pop_front();
+            } else {
+                break;
+            }
+        }
     }
 
     /// Record a dropped frame
@@ -75,73 +696,334 @@ impl Stats {
         self.dropped.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Record a one-way latency measurement
+    ///
+    /// Samples are smoothed with an exponential moving average so a single
+    /// late frame doesn't dominate the reported value.
+    pub fn record_latency(&self, ms: f64) {
+        self.latency.record(ms);
+    }
+
+    /// Record that the sender's timestamps jumped back
+    ///
+    /// Latency samples from before the jump were against a different
+    /// baseline, so the smoothed latency starts over.
+    pub fn record_clock_reset(&self) {
+        self.clock_resets.fetch_add(1, Ordering::Relaxed);
+        self.latency.reset();
+    }
+
+    /// Record how long the sender took from capturing a frame to finishing
+    /// its encoding, smoothed like `record_latency`
+    pub fn record_encode_latency(&self, ms: f64) {
+        self.encode_latency.record(ms);
+    }
+
+    /// Record the arrival of a frame with the given sequence number
+    ///
+    /// Gaps in the sequence count towards the loss estimate until the
+    /// missing frame shows up late, within `SEEN_WINDOW` sequences of the
+    /// newest; arrival times feed the jitter estimate.
+    /// Late and repeated sequences are counted, and the result says which
+    /// one this was so duplicates can be dropped.
+    pub fn record_sequence(&self, seq: u64) -> SequenceArrival {
+        self.record_sequence_at(seq, Instant::now())
+    }
+
+    fn record_sequence_at(&self, seq: u64, now: Instant) -> SequenceArrival {
+        match self.arrivals.lock() {
+            Ok(mut arrivals) => arrivals.record(seq, now),
+            Err(_) => SequenceArrival::InOrder,
+        }
+    }
+
+    /// Record transport path stats (e.g. from `quinn::Connection::stats`)
+    ///
+    /// `lost_packets` out of `sent_packets` gives the packet loss. The latest
+    /// sample replaces the previous one.
+    pub fn record_path(&self, rtt: Duration, lost_packets: u64, sent_packets: u64) {
+        let loss_percent = if sent_packets > 0 {
+            lost_packets as f64 / sent_packets as f64 * 100.0
+        } else {
+            0.0
+        };
+        if let Ok(mut path) = self.path.lock() {
+            *path = Some(PathSample {
+                rtt_ms: rtt.as_secs_f64() * 1000.0,
+                loss_percent,
+            });
+        }
+    }
+
+    /// Forget the path stats, e.g. once the connection that reported them closed
+    pub fn clear_path(&self) {
+        if let Ok(mut path) = self.path.lock() {
+            *path = None;
+        }
+    }
+
     /// Get current statistics snapshot
+    ///
+    /// FPS and bitrate cover the trailing window, so the result doesn't
+    /// depend on how often this is called.
     pub fn snapshot(&self) -> StatsSnapshot {
-        let now = Instant::now();
-        let uptime = now.duration_since(self.start_time);
-
-        let current_frames = self.frames.load(Ordering::Relaxed);
-        let current_bytes = self.bytes.load(Ordering::Relaxed);
-        let dropped = self.dropped.load(Ordering::Relaxed);
+        self.snapshot_at(Instant::now())
+    }
 
-        // Calculate rates
-        let mut last_time = self.last_snapshot_time.lock().unwrap();
-        let elapsed = now.duration_since(*last_time);
+    /// Get a snapshot and start over from zero, as `reset` does
+    ///
+    /// Each counter is read and cleared in one atomic step, so frames
+    /// recorded concurrently count towards either this snapshot or the next
+    /// one, never both or neither. Calling this at the end of each phase
+    /// (e.g. each replayed file) gives per-phase totals; `period_secs` is
+    /// how long the phase lasted.
+    pub fn snapshot_and_reset(&self) -> StatsSnapshot {
+        self.collect_at(Instant::now(), true)
+    }
 
-        let (fps, bytes_per_sec) = if elapsed >= Duration::from_millis(100) {
-            let last_frames = self.last_frames.swap(current_frames, Ordering::Relaxed);
-            let last_bytes = self.last_bytes.swap(current_bytes, Ordering::Relaxed);
+    fn snapshot_at(&self, now: Instant) -> StatsSnapshot {
+        self.collect_at(now, false)
+    }
 
-            let frame_delta = current_frames.saturating_sub(last_frames);
-            let byte_delta = current_bytes.saturating_sub(last_bytes);
+    fn collect_at(&self, now: Instant, reset: bool) -> StatsSnapshot {
+        let uptime = now.duration_since(self.start_time);
+        let read = |counter: &AtomicU64| {
+            if reset {
+                counter.swap(0, Ordering::Relaxed)
+            } else {
+                counter.load(Ordering::Relaxed)
+            }
+        };
 
-            let secs = elapsed.as_secs_f64();
-            *last_time = now;
+        let current_frames = read(&self.frames);
+        let current_bytes = read(&self.bytes);
+        let dropped = read(&self.dropped);
+        let clock_resets = read(&self.clock_resets);
 
-            (frame_delta as f64 / secs, (byte_delta as f64 / secs) as u64)
+        let uptime_us = uptime.as_micros() as u64;
+        let period_start_us = if reset {
+            self.period_start_us.swap(uptime_us, Ordering::Relaxed)
         } else {
-            // Not enough time has passed, return previous rates
-            (0.0, 0)
+            self.period_start_us.load(Ordering::Relaxed)
+        };
+        let period_secs = uptime_us.saturating_sub(period_start_us) as f64 / 1_000_000.0;
+
+        // Calculate rates over the trailing window
+        let (window_frames, window_bytes) = match self.recent.lock() {
+            Ok(mut recent) => {
+                self.prune(&mut recent, now);
+                let totals = (recent.len(), recent.iter().map(|&(_, b)| b).sum::<u64>());
+                if reset {
+                    recent.clear();
+                }
+                totals
+            }
+            Err(_) => (0, 0),
         };
+        let secs = self.window.as_secs_f64();
+        let fps = window_frames as f64 / secs;
+        let bytes_per_sec = (window_bytes as f64 / secs) as u64;
 
         let bitrate_mbps = (bytes_per_sec as f64 * 8.0) / 1_000_000.0;
+        let (smoothed_fps, smoothed_bitrate_mbps) = self.smooth_rates(fps, bitrate_mbps, now);
+        if reset {
+            if let Ok(mut smoothed) = self.smoothed.lock() {
+                *smoothed = None;
+            }
+        }
+
+        let (arrival, (out_of_order_frames, duplicate_frames)) = self
+            .arrivals
+            .lock()
+            .map(|mut a| {
+                let values = (a.jitter_ms, a.loss_percent(), a.expected(), a.missing);
+                let reordering = (a.out_of_order, a.duplicates);
+                if reset {
+                    *a = ArrivalTracker::default();
+                }
+                (values, reordering)
+            })
+            .unwrap_or_default();
+        let (jitter_ms, gap_loss_percent, expected_frames, missing_frames) = arrival;
+        let path = match self.path.lock() {
+            Ok(mut path) if reset => path.take(),
+            Ok(path) => *path,
+            Err(_) => None,
+        };
+        let loss_percent = path.map_or(gap_loss_percent, |p| p.loss_percent.max(gap_loss_percent));
+
+        let frame_types = FrameType::ALL
+            .into_iter()
+            .zip(&self.by_type)
+            .map(|(frame_type, counters)| {
+                let counts = FrameTypeCounts {
+                    frames: read(&counters.frames),
+                    bytes: read(&counters.bytes),
+                };
+                (frame_type, counts)
+            })
+            .filter(|(_, counts)| counts.frames > 0)
+            .collect();
+
+        let size_histogram = self
+            .size_buckets
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                let upper_bound = 1u64 << (SIZE_BUCKET_MIN_SHIFT + i as u32);
+                (upper_bound, read(count))
+            })
+            .collect();
+
+        let (latency, encode_latency) = if reset {
+            (self.latency.take(), self.encode_latency.take())
+        } else {
+            (self.latency.get(), self.encode_latency.get())
+        };
 
         StatsSnapshot {
             fps,
             bytes_per_sec,
             bitrate_mbps,
+            smoothed_fps,
+            smoothed_bitrate_mbps,
             total_frames: current_frames,
             total_bytes: current_bytes,
             dropped_frames: dropped,
-            latency_ms: None, // Set by transport layer
+            clock_reset_count: clock_resets,
+            latency_ms: latency.or(path.map(|p| p.rtt_ms / 2.0)),
+            encode_latency_ms: encode_latency,
+            rtt_ms: path.map(|p| p.rtt_ms),
+            jitter_ms,
+            loss_percent,
+            expected_frames,
+            missing_frames,
+            out_of_order_frames,
+            duplicate_frames,
             uptime_secs: uptime.as_secs_f64(),
+            period_secs,
+            frame_types,
+            size_histogram,
+        }
+    }
+
+    /// Fold the current rates into their moving averages and return those
+    ///
+    /// Averages move at most once per rate window, so they don't depend on
+    /// how often snapshots are taken. They start at the first non-zero rate
+    /// rather than climbing from zero.
+    fn smooth_rates(&self, fps: f64, bitrate_mbps: f64, now: Instant) -> (f64, f64) {
+        let Ok(mut smoothed) = self.smoothed.lock() else {
+            return (fps, bitrate_mbps);
+        };
+        let Some(rates) = smoothed.as_mut() else {
+            if fps > 0.0 {
+                *smoothed = Some(SmoothedRates {
+                    fps,
+                    bitrate_mbps,
+                    updated: now,
+                });
+            }
+            return (fps, bitrate_mbps);
+        };
+        if now.saturating_duration_since(rates.updated) >= self.window {
+            let alpha = f64::from_bits(self.rate_smoothing.load(Ordering::Relaxed));
+            rates.fps += alpha * (fps - rates.fps);
+            rates.bitrate_mbps += alpha * (bitrate_mbps - rates.bitrate_mbps);
+            rates.updated = now;
         }
+        (rates.fps, rates.bitrate_mbps)
     }
 
     /// Reset all statistics
+    ///
+    /// Sub-meters are left alone.
     pub fn reset(&self) {
         self.frames.store(0, Ordering::Relaxed);
         self.bytes.store(0, Ordering::Relaxed);
         self.dropped.store(0, Ordering::Relaxed);
-        self.last_frames.store(0, Ordering::Relaxed);
-        self.last_bytes.store(0, Ordering::Relaxed);
+        self.clock_resets.store(0, Ordering::Relaxed);
+        for counters in &self.by_type {
+            counters.frames.store(0, Ordering::Relaxed);
+            counters.bytes.store(0, Ordering::Relaxed);
+        }
+        for bucket in &self.size_buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        if let Ok(mut recent) = self.recent.lock() {
+            recent.clear();
+        }
+        self.latency.reset();
+        self.encode_latency.reset();
+        if let Ok(mut smoothed) = self.smoothed.lock() {
+            *smoothed = None;
+        }
+        if let Ok(mut arrivals) = self.arrivals.lock() {
+            *arrivals = ArrivalTracker::default();
+        }
+        self.clear_path();
+        let uptime_us = self.start_time.elapsed().as_micros() as u64;
+        self.period_start_us.store(uptime_us, Ordering::Relaxed);
     }
 }
 
 impl Default for Stats {
     fn default() -> Self {
+        Self::build(DEFAULT_RATE_WINDOW)
+    }
+}
+
+/// When a peer last sent a stats frame, for dead-peer detection
+///
+/// Not armed until the first stats frame arrives, so peers that never
+/// send stats are not timed out.
+#[derive(Debug)]
+pub struct Heartbeat {
+    start: Instant,
+    // Milliseconds since `start` of the last beat, plus one; 0 = none yet
+    last_beat_ms: AtomicU64,
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
         Self {
-            start_time: Instant::now(),
-            last_snapshot_time: std::sync::Mutex::new(Instant::now()),
-            frames: AtomicU64::new(0),
-            bytes: AtomicU64::new(0),
-            dropped: AtomicU64::new(0),
-            last_frames: AtomicU64::new(0),
-            last_bytes: AtomicU64::new(0),
+            start: Instant::now(),
+            last_beat_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a stats frame from the peer
+    pub fn beat(&self) {
+        self.beat_at(Instant::now());
+    }
+
+    /// Whether the peer has sent stats before but none within `timeout`
+    pub fn is_dead(&self, timeout: Duration) -> bool {
+        self.is_dead_at(Instant::now(), timeout)
+    }
+
+    fn beat_at(&self, now: Instant) {
+        let ms = now.saturating_duration_since(self.start).as_millis() as u64;
+        self.last_beat_ms.store(ms + 1, Ordering::Relaxed);
+    }
+
+    fn is_dead_at(&self, now: Instant, timeout: Duration) -> bool {
+        match self.last_beat_ms.load(Ordering::Relaxed) {
+            0 => false,
+            last => {
+                let last = self.start + Duration::from_millis(last - 1);
+                now.saturating_duration_since(last) > timeout
+            }
         }
     }
 }
 
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,4 +1041,501 @@ mod tests {
         assert_eq!(snapshot.total_bytes, 2000);
         assert_eq!(snapshot.dropped_frames, 1);
     }
+
+    #[test]
+    fn test_snapshot_and_reset() {
+        let stats = Stats::new();
+        let start = stats.start_time;
+        stats.record_frame_typed_at(FrameType::H264Frame, 1000, start);
+        stats.record_drop();
+        stats.record_latency(12.0);
+        stats.record_sequence_at(1, start);
+        stats.record_sequence_at(3, start);
+
+        let first = stats.collect_at(start + Duration::from_millis(500), true);
+        assert_eq!(first.total_frames, 1);
+        assert_eq!(first.dropped_frames, 1);
+        assert_eq!(first.fps, 1.0);
+        assert_eq!(first.latency_ms, Some(12.0));
+        assert_eq!(first.missing_frames, 1);
+        assert_eq!(first.frame_type(FrameType::H264Frame).bytes, 1000);
+        assert_eq!(first.period_secs, 0.5);
+
+        stats.record_frame_typed_at(
+            FrameType::H264Frame,
+            200,
+            start + Duration::from_millis(600),
+        );
+        let second = stats.collect_at(start + Duration::from_secs(2), true);
+        assert_eq!(second.total_frames, 1);
+        assert_eq!(second.total_bytes, 200);
+        assert_eq!(second.dropped_frames, 0);
+        assert_eq!(second.latency_ms, None);
+        assert_eq!(second.missing_frames, 0);
+        assert_eq!(second.period_secs, 1.5);
+        assert_eq!(second.uptime_secs, 2.0, "uptime is not reset");
+        assert_eq!(
+            second.size_histogram.iter().map(|&(_, n)| n).sum::<u64>(),
+            1
+        );
+
+        stats.reset();
+        assert_eq!(stats.snapshot().total_frames, 0);
+        assert!(stats.snapshot().period_secs < 1.0);
+    }
+
+    #[test]
+    fn test_meters_count_separately() {
+        let stats = Stats::new();
+        stats.record_frame(1000);
+        stats.meter("decode").record_frame(10);
+        stats.meter("decode").record_frame(20);
+        stats.meter("network").record_drop();
+
+        assert_eq!(stats.snapshot().total_frames, 1);
+        let meters = stats.meter_snapshots();
+        assert_eq!(meters.keys().collect::<Vec<_>>(), ["decode", "network"]);
+        assert_eq!(meters["decode"].total_bytes, 30);
+        assert_eq!(meters["network"].dropped_frames, 1);
+
+        // Resetting one leaves the others counting
+        stats.meter("decode").snapshot_and_reset();
+        stats.reset();
+        assert_eq!(stats.meter("decode").snapshot().total_frames, 0);
+        assert_eq!(stats.meter("network").snapshot().dropped_frames, 1);
+    }
+
+    #[test]
+    fn test_per_type_counts() {
+        let stats = Stats::new();
+        stats.record_frame_typed(FrameType::H264Frame, 5000);
+        stats.record_frame_typed(FrameType::H264Frame, 3000);
+        stats.record_frame_typed(FrameType::RawFrame, 100);
+        stats.record_frame_typed(FrameType::Audio, 960);
+        stats.record_frame_typed(FrameType::Control, 40);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(
+            snapshot.frame_type(FrameType::H264Frame),
+            FrameTypeCounts {
+                frames: 2,
+                bytes: 8000
+            }
+        );
+        assert_eq!(snapshot.frame_type(FrameType::Audio).bytes, 960);
+        assert_eq!(snapshot.frame_type(FrameType::DeltaFrame).frames, 0);
+        assert!(!snapshot.frame_types.contains_key(&FrameType::DeltaFrame));
+        // Only video frames count towards the overall totals
+        assert_eq!(snapshot.total_frames, 3);
+        assert_eq!(snapshot.total_bytes, 8100);
+
+        stats.reset();
+        assert!(stats.snapshot().frame_types.is_empty());
+    }
+
+    #[test]
+    fn test_size_buckets() {
+        assert_eq!(size_bucket(0), 0);
+        assert_eq!(size_bucket(1024), 0);
+        assert_eq!(size_bucket(1025), 1);
+        assert_eq!(size_bucket(2048), 1);
+        assert_eq!(
+            size_bucket(crate::protocol::MAX_FRAME_SIZE as u64),
+            SIZE_BUCKETS - 1
+        );
+        assert_eq!(size_bucket(u64::MAX), SIZE_BUCKETS - 1);
+    }
+
+    #[test]
+    fn test_size_histogram() {
+        let stats = Stats::new();
+        for bytes in [500, 800, 3000, 3000, 600_000] {
+            stats.record_frame(bytes);
+        }
+
+        let histogram = stats.snapshot().size_histogram;
+        assert_eq!(histogram.len(), SIZE_BUCKETS);
+        assert_eq!(histogram[0], (1024, 2));
+        assert_eq!(histogram[2], (4096, 2));
+        assert_eq!(histogram[10], (1024 * 1024, 1));
+        assert_eq!(histogram.iter().map(|&(_, n)| n).sum::<u64>(), 5);
+        assert_eq!(histogram.last().unwrap().0, 8 * 1024 * 1024);
+
+        stats.reset();
+        assert!(stats.snapshot().size_histogram.iter().all(|&(_, n)| n == 0));
+    }
+
+    #[test]
+    fn test_rate_is_independent_of_snapshot_cadence() {
+        let stats = Stats::with_window(Duration::from_secs(2));
+        let now = Instant::now();
+        for i in 0..10u64 {
+            stats.record_frame_at(1000, now + Duration::from_millis(i * 100));
+        }
+
+        let at = now + Duration::from_millis(1000);
+        let first = stats.snapshot_at(at);
+        let second = stats.snapshot_at(at);
+        assert_eq!(first.fps, 5.0);
+        assert_eq!(second.fps, 5.0);
+        assert_eq!(first.bytes_per_sec, 5000);
+    }
+
+    #[test]
+    fn test_rate_window_drops_old_frames() {
+        let stats = Stats::with_window(Duration::from_secs(1));
+        let now = Instant::now();
+        stats.record_frame_at(100, now);
+        stats.record_frame_at(100, now + Duration::from_millis(900));
+
+        assert_eq!(stats.snapshot_at(now + Duration::from_millis(950)).fps, 2.0);
+        assert_eq!(
+            stats.snapshot_at(now + Duration::from_millis(1500)).fps,
+            1.0
+        );
+
+        let later = stats.snapshot_at(now + Duration::from_secs(5));
+        assert_eq!(later.fps, 0.0);
+        assert_eq!(later.total_frames, 2, "totals are not windowed");
+    }
+
+    #[test]
+    fn test_rate_smoothing() {
+        let stats = Stats::with_window(Duration::from_secs(1));
+        stats.set_rate_smoothing(0.5);
+        let now = Instant::now();
+        let at = |ms| now + Duration::from_millis(ms);
+        assert_eq!(stats.snapshot_at(now).smoothed_fps, 0.0);
+        for i in 0..10u64 {
+            stats.record_frame_at(1000, at(i * 100));
+        }
+
+        // Seeded with the first non-zero rate, then halfway to each new window's
+        let snapshot = stats.snapshot_at(at(950));
+        assert_eq!((snapshot.fps, snapshot.smoothed_fps), (10.0, 10.0));
+        let snapshot = stats.snapshot_at(at(1950));
+        assert_eq!((snapshot.fps, snapshot.smoothed_fps), (0.0, 5.0));
+        assert_eq!(stats.snapshot_at(at(2500)).smoothed_fps, 5.0);
+        assert_eq!(stats.snapshot_at(at(3000)).smoothed_fps, 2.5);
+        assert_eq!(stats.snapshot_at(at(3000)).smoothed_bitrate_mbps, 0.02);
+
+        stats.reset();
+        stats.set_rate_smoothing(1.0);
+        stats.record_frame_at(1000, at(3100));
+        stats.record_frame_at(1000, at(3200));
+        assert_eq!(stats.snapshot_at(at(3300)).smoothed_fps, 2.0);
+    }
+
+    #[test]
+    fn test_latency_smoothing() {
+        let stats = Stats::new();
+        assert_eq!(stats.snapshot().latency_ms, None);
+
+        stats.record_latency(10.0);
+        assert_eq!(stats.snapshot().latency_ms, Some(10.0));
+
+        stats.record_latency(20.0);
+        let latency = stats.snapshot().latency_ms.unwrap();
+        assert!((latency - 11.0).abs() < 1e-9, "got {}", latency);
+
+        // A sender clock reset starts the average over
+        stats.record_clock_reset();
+        let snapshot = stats.snapshot();
+        assert_eq!((snapshot.latency_ms, snapshot.clock_reset_count), (None, 1));
+        stats.record_latency(30.0);
+        assert_eq!(stats.snapshot().latency_ms, Some(30.0));
+
+        stats.reset();
+        let snapshot = stats.snapshot();
+        assert_eq!((snapshot.latency_ms, snapshot.clock_reset_count), (None, 0));
+    }
+
+    #[test]
+    fn test_encode_latency_is_separate() {
+        let stats = Stats::new();
+        assert_eq!(stats.snapshot().encode_latency_ms, None);
+
+        stats.record_encode_latency(6.0);
+        stats.record_latency(4.0);
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.encode_latency_ms, Some(6.0));
+        assert_eq!(snapshot.latency_ms, Some(4.0));
+        assert!(snapshot
+            .to_prometheus()
+            .contains("thundermirror_encode_latency_ms 6"));
+
+        stats.reset();
+        assert_eq!(stats.snapshot().encode_latency_ms, None);
+    }
+
+    #[test]
+    fn test_path_stats() {
+        let stats = Stats::new();
+        stats.record_path(Duration::from_millis(4), 2, 200);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.rtt_ms, Some(4.0));
+        // Without clock sync, half the RTT stands in for one-way latency
+        assert_eq!(snapshot.latency_ms, Some(2.0));
+        assert_eq!(snapshot.loss_percent, 1.0);
+
+        stats.record_latency(3.5);
+        assert_eq!(stats.snapshot().latency_ms, Some(3.5));
+
+        // Sequence-gap loss wins when it is worse
+        for seq in [0, 2] {
+            stats.record_sequence(seq);
+        }
+        let loss = stats.snapshot().loss_percent;
+        assert!((loss - 100.0 / 3.0).abs() < 1e-9, "got {}", loss);
+
+        stats.clear_path();
+        assert_eq!(stats.snapshot().rtt_ms, None);
+        assert_eq!(stats.snapshot().latency_ms, Some(3.5));
+    }
+
+    #[test]
+    fn test_prometheus_export() {
+        let snapshot = StatsSnapshot {
+            fps: 59.5,
+            dropped_frames: 3,
+            ..Default::default()
+        };
+        let text = snapshot.to_prometheus();
+
+        assert!(text.contains("# TYPE thundermirror_fps gauge\nthundermirror_fps 59.5\n"));
+        assert!(text.contains("# TYPE thundermirror_dropped_frames_total counter\n"));
+        assert!(text.contains("thundermirror_dropped_frames_total 3\n"));
+        // No latency sample yet, so the metric is omitted rather than reported as zero
+        assert!(!text.contains("latency_ms"));
+        assert!(!text.contains("type_frames_total"));
+
+        let mut snapshot = snapshot;
+        snapshot.frame_types.insert(
+            FrameType::Audio,
+            FrameTypeCounts {
+                frames: 7,
+                bytes: 700,
+            },
+        );
+        let text = snapshot.to_prometheus();
+        assert!(text.contains("thundermirror_type_frames_total{type=\"audio\"} 7\n"));
+        assert!(text.contains("thundermirror_type_bytes_total{type=\"audio\"} 700\n"));
+
+        snapshot.size_histogram = vec![(1024, 2), (2048, 0), (4096, 3)];
+        snapshot.total_bytes = 9000;
+        let text = snapshot.to_prometheus();
+        assert!(text.contains("# TYPE thundermirror_frame_size_bytes histogram\n"));
+        assert!(text.contains("thundermirror_frame_size_bytes_bucket{le=\"2048\"} 2\n"));
+        assert!(text.contains("thundermirror_frame_size_bytes_bucket{le=\"+Inf\"} 5\n"));
+        assert!(text.contains("thundermirror_frame_size_bytes_sum 9000\n"));
+        assert!(text.contains("thundermirror_frame_size_bytes_count 5\n"));
+    }
+
+    #[test]
+    fn test_json_export() {
+        let mut snapshot = StatsSnapshot {
+            total_frames: 42,
+            ..Default::default()
+        };
+        snapshot.frame_types.insert(
+            FrameType::H264Frame,
+            FrameTypeCounts {
+                frames: 40,
+                bytes: 4000,
+            },
+        );
+        let json = snapshot.to_json().unwrap();
+        let parsed: StatsSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.total_frames, 42);
+        assert_eq!(parsed.frame_type(FrameType::H264Frame).frames, 40);
+    }
+
+    #[test]
+    fn test_sequence_gap_loss() {
+        let stats = Stats::new();
+        for seq in [10, 11, 13, 14] {
+            stats.record_sequence(seq);
+        }
+        // 5 expected (10..=14), 1 missing
+        let snapshot = stats.snapshot();
+        assert!(
+            (snapshot.loss_percent - 20.0).abs() < 1e-9,
+            "got {}",
+            snapshot.loss_percent
+        );
+        assert_eq!((snapshot.expected_frames, snapshot.missing_frames), (5, 1));
+
+        // Late arrival recovers the gap
+        stats.record_sequence(12);
+        assert_eq!(stats.snapshot().loss_percent, 0.0);
+    }
+
+    #[test]
+    fn test_duplicates_and_reordering_never_negative() {
+        let stats = Stats::new();
+        for seq in [5, 5, 4, 3, 5, 6] {
+            stats.record_sequence(seq);
+        }
+        let snapshot = stats.snapshot();
+        assert!(
+            snapshot.loss_percent >= 0.0,
+            "got {}",
+            snapshot.loss_percent
+        );
+        assert_eq!(snapshot.loss_percent, 0.0);
+        assert_eq!(snapshot.out_of_order_frames, 2);
+        assert_eq!(snapshot.duplicate_frames, 2);
+    }
+
+    #[test]
+    fn test_only_late_frames_fill_gaps() {
+        let stats = Stats::new();
+        for seq in (10..20).chain(30..100) {
+            stats.record_sequence(seq);
+        }
+        assert_eq!(stats.snapshot().missing_frames, 10);
+
+        // Repeats and frames from before the first one hide no loss,
+        // however far back they are
+        for seq in [99, 95, 50, 15, 11, 5] {
+            stats.record_sequence(seq);
+        }
+        assert_eq!(stats.snapshot().missing_frames, 10);
+
+        // A gap in the window is filled once
+        stats.record_sequence(120);
+        stats.record_sequence(110);
+        stats.record_sequence(110);
+        assert_eq!(stats.snapshot().missing_frames, 10 + 19);
+    }
+
+    #[test]
+    fn test_sequence_arrivals() {
+        let stats = Stats::new();
+        assert_eq!(stats.record_sequence(1), SequenceArrival::InOrder);
+        assert_eq!(stats.record_sequence(3), SequenceArrival::InOrder);
+        assert_eq!(stats.record_sequence(3), SequenceArrival::Duplicate);
+        assert_eq!(stats.record_sequence(2), SequenceArrival::OutOfOrder);
+        assert_eq!(stats.record_sequence(2), SequenceArrival::Duplicate);
+        assert_eq!(stats.record_sequence(1), SequenceArrival::Duplicate);
+        assert_eq!(stats.snapshot().missing_frames, 0);
+
+        // A jump past the seen window forgets everything before it
+        assert_eq!(stats.record_sequence(100), SequenceArrival::InOrder);
+        assert_eq!(stats.record_sequence(99), SequenceArrival::OutOfOrder);
+        assert_eq!(stats.record_sequence(99), SequenceArrival::Duplicate);
+        assert_eq!(stats.record_sequence(3), SequenceArrival::OutOfOrder);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.missing_frames, 95, "4..=98, as 3 fills no gap");
+        assert_eq!(
+            (snapshot.out_of_order_frames, snapshot.duplicate_frames),
+            (3, 4)
+        );
+        assert!(snapshot
+            .to_prometheus()
+            .contains("thundermirror_duplicate_frames_total 4"));
+    }
+
+    #[test]
+    fn test_jitter_from_intervals() {
+        let stats = Stats::new();
+        let start = Instant::now();
+
+        // Perfectly regular 16ms intervals: no jitter
+        for i in 0..10u64 {
+            stats.record_sequence_at(i, start + Duration::from_millis(i * 16));
+        }
+        assert!(stats.snapshot().jitter_ms < 1e-9);
+
+        // Alternating 8ms/24ms intervals average 16ms with 8ms deviation
+        let mut t = start + Duration::from_millis(9 * 16);
+        for i in 10..200u64 {
+            t += Duration::from_millis(if i % 2 == 0 { 8 } else { 24 });
+            stats.record_sequence_at(i, t);
+        }
+        let jitter = stats.snapshot().jitter_ms;
+        assert!(jitter > 5.0 && jitter < 11.0, "got {}", jitter);
+    }
+
+    #[test]
+    fn test_status_event_json() {
+        let listening = StatusEvent::Listening {
+            port: 9999,
+            address: Some("192.168.50.2:9999".to_string()),
+        };
+        let line = listening.to_json().unwrap();
+        assert_eq!(StatusEvent::from_json(&line).unwrap(), listening);
+        assert_eq!(
+            StatusEvent::from_json(r#"{"event":"listening","port":9999}"#).unwrap(),
+            StatusEvent::Listening {
+                port: 9999,
+                address: None
+            }
+        );
+
+        let event = StatusEvent::Connected {
+            remote: "192.168.50.1:50000".to_string(),
+        };
+        let line = event.to_json().unwrap();
+        assert_eq!(
+            line,
+            r#"{"event":"connected","remote":"192.168.50.1:50000"}"#
+        );
+        assert_eq!(StatusEvent::from_json(&line).unwrap(), event);
+
+        let stats = StatusEvent::Stats {
+            fps: 59.9,
+            mbps: 120.5,
+            width: 1920,
+            height: 1080,
+            codec: "H.264".to_string(),
+            latency_ms: None,
+            smoothed_fps: Some(58.7),
+            smoothed_mbps: Some(118.0),
+        };
+        let line = stats.to_json().unwrap();
+        assert!(!line.contains('\n'));
+        assert_eq!(
+            StatusEvent::from_json(&format!("{}\r\n", line)).unwrap(),
+            stats
+        );
+
+        let sender = StatusEvent::SenderStats {
+            fps: 30.0,
+            loss_percent: 1.5,
+        };
+        let line = sender.to_json().unwrap();
+        assert_eq!(
+            line,
+            r#"{"event":"sender_stats","fps":30.0,"loss_percent":1.5}"#
+        );
+        assert_eq!(StatusEvent::from_json(&line).unwrap(), sender);
+
+        let unavailable = StatusEvent::DecoderUnavailable {
+            message: "OpenH264 failed to load".to_string(),
+        };
+        let line = unavailable.to_json().unwrap();
+        assert!(line.starts_with(r#"{"event":"decoder_unavailable""#));
+        assert_eq!(StatusEvent::from_json(&line).unwrap(), unavailable);
+
+        assert!(StatusEvent::from_json("INFO Stats: 60.0 FPS").is_err());
+        assert!(StatusEvent::from_json(r#"{"event":"unknown"}"#).is_err());
+    }
+
+    #[test]
+    fn test_heartbeat_arms_on_first_beat() {
+        let heartbeat = Heartbeat::new();
+        let timeout = Duration::from_secs(5);
+        let later = heartbeat.start + Duration::from_secs(60);
+        assert!(!heartbeat.is_dead_at(later, timeout), "never beat");
+
+        heartbeat.beat_at(heartbeat.start + Duration::from_secs(10));
+        assert!(!heartbeat.is_dead_at(heartbeat.start + Duration::from_secs(14), timeout));
+        assert!(heartbeat.is_dead_at(heartbeat.start + Duration::from_secs(16), timeout));
+
+        heartbeat.beat_at(heartbeat.start + Duration::from_secs(15));
+        assert!(!heartbeat.is_dead_at(heartbeat.start + Duration::from_secs(16), timeout));
+    }
 }
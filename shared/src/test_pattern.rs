@@ -1,6 +1,7 @@
 //! Test pattern generator for streaming tests
 //!
-//! Generates color bar test patterns for validating the streaming pipeline.
+//! Generates color bar, color accuracy, and animated test patterns for
+//! validating the streaming pipeline.
 
 use bytes::Bytes;
 
@@ -40,7 +41,7 @@ pub fn generate_color_bars(width: u16, height: u16) -> Bytes {
         (0, 0, 0, 255),       // Black
     ];
 
-    let bar_width = width / 8;
+    let bar_width = (width / 8).max(1);
 
     for _y in 0..height {
         for x in 0..width {
@@ -56,6 +57,307 @@ pub fn generate_color_bars(width: u16, height: u16) -> Bytes {
     Bytes::from(buffer)
 }
 
+/// Direction a gradient ramps in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientAxis {
+    /// Black on the left to white on the right
+    Horizontal,
+    /// Black at the top to white at the bottom
+    Vertical,
+}
+
+/// Generate a smooth grayscale ramp from 0 to 255
+///
+/// Useful for spotting banding introduced by limited to full range expansion.
+///
+/// # Arguments
+/// * `width` - Frame width in pixels
+/// * `height` - Frame height in pixels
+/// * `axis` - Direction of the ramp
+///
+/// # Returns
+/// RGBA pixel data as bytes (4 bytes per pixel: R, G, B, A)
+pub fn generate_gradient(width: u16, height: u16, axis: GradientAxis) -> Bytes {
+    let width = width as usize;
+    let height = height as usize;
+    let mut buffer = Vec::with_capacity(width * height * 4);
+
+    let ramp = |pos: usize, len: usize| (pos * 255 / len.saturating_sub(1).max(1)) as u8;
+
+    for y in 0..height {
+        for x in 0..width {
+            let v = match axis {
+                GradientAxis::Horizontal => ramp(x, width),
+                GradientAxis::Vertical => ramp(y, height),
+            };
+            buffer.extend_from_slice(&[v, v, v, 255]);
+        }
+    }
+
+    Bytes::from(buffer)
+}
+
+/// Generate a checkerboard of alternating red and blue cells
+///
+/// Saturated chroma edges between cells expose 4:2:0 subsampling artifacts;
+/// odd `cell_size` values put edges inside chroma blocks.
+///
+/// # Arguments
+/// * `width` - Frame width in pixels
+/// * `height` - Frame height in pixels
+/// * `cell_size` - Cell edge length in pixels (0 is treated as 1)
+///
+/// # Returns
+/// RGBA pixel data as bytes (4 bytes per pixel: R, G, B, A)
+pub fn generate_checkerboard(width: u16, height: u16, cell_size: u16) -> Bytes {
+    let width = width as usize;
+    let height = height as usize;
+    let cell = cell_size.max(1) as usize;
+    let mut buffer = Vec::with_capacity(width * height * 4);
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = if (x / cell + y / cell).is_multiple_of(2) {
+                [255, 0, 0, 255] // Red
+            } else {
+                [0, 0, 255, 255] // Blue
+            };
+            buffer.extend_from_slice(&pixel);
+        }
+    }
+
+    Bytes::from(buffer)
+}
+
+/// Frames it takes the moving bar to cross the screen once
+const MOVING_BAR_PERIOD: u64 = 120;
+
+/// Generate a white vertical bar sweeping across a black frame
+///
+/// The bar crosses the screen every 120 frames, so dropped frames show up as
+/// jumps and tearing as a split bar.
+///
+/// # Arguments
+/// * `width` - Frame width in pixels
+/// * `height` - Frame height in pixels
+/// * `frame_index` - Position in the animation
+///
+/// # Returns
+/// RGBA pixel data as bytes (4 bytes per pixel: R, G, B, A)
+pub fn generate_moving_bar(width: u16, height: u16, frame_index: u64) -> Bytes {
+    let width = width as usize;
+    let height = height as usize;
+    let mut buffer = Vec::with_capacity(width * height * 4);
+
+    let bar_width = (width / 32).max(1);
+    let bar_x = ((frame_index % MOVING_BAR_PERIOD) as usize * width) / MOVING_BAR_PERIOD as usize;
+
+    for _y in 0..height {
+        for x in 0..width {
+            let v = if x >= bar_x && x < bar_x + bar_width {
+                255
+            } else {
+                0
+            };
+            buffer.extend_from_slice(&[v, v, v, 255]);
+        }
+    }
+
+    Bytes::from(buffer)
+}
+
+/// 3x5 bitmaps for digits 0-9, one row per entry, high bit on the left
+const DIGIT_FONT: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111], // 0
+    [0b010, 0b110, 0b010, 0b010, 0b111], // 1
+    [0b111, 0b001, 0b111, 0b100, 0b111], // 2
+    [0b111, 0b001, 0b111, 0b001, 0b111], // 3
+    [0b101, 0b101, 0b111, 0b001, 0b001], // 4
+    [0b111, 0b100, 0b111, 0b001, 0b111], // 5
+    [0b111, 0b100, 0b111, 0b101, 0b111], // 6
+    [0b111, 0b001, 0b001, 0b001, 0b001], // 7
+    [0b111, 0b101, 0b111, 0b101, 0b111], // 8
+    [0b111, 0b101, 0b111, 0b001, 0b111], // 9
+];
+
+/// Generate a frame showing `frame_index` in large blocky digits
+///
+/// White digits are centered on a black background and scaled to fill most
+/// of the frame, so skipped or reordered frames are easy to spot on camera.
+///
+/// # Arguments
+/// * `width` - Frame width in pixels
+/// * `height` - Frame height in pixels
+/// * `frame_index` - Number to render
+///
+/// # Returns
+/// RGBA pixel data as bytes (4 bytes per pixel: R, G, B, A)
+pub fn generate_frame_counter(width: u16, height: u16, frame_index: u64) -> Bytes {
+    let width = width as usize;
+    let height = height as usize;
+    let mut buffer = vec![0u8; width * height * 4];
+    for pixel in buffer.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+
+    let digits: Vec<[u8; 5]> = frame_index
+        .to_string()
+        .bytes()
+        .map(|b| DIGIT_FONT[(b - b'0') as usize])
+        .collect();
+    draw_glyphs(&mut buffer, width, height, &digits, (3, 2), [255; 3]);
+
+    Bytes::from(buffer)
+}
+
+/// Draw 3x5 `glyphs` centered in an RGBA `buffer`, as large as fits in
+/// the middle `fill.0`/4 of its width and `fill.1`/4 of its height
+fn draw_glyphs(
+    buffer: &mut [u8],
+    width: usize,
+    height: usize,
+    glyphs: &[[u8; 5]],
+    fill: (usize, usize),
+    rgb: [u8; 3],
+) {
+    // Each glyph is 3 cells wide plus 1 cell of spacing; 5 cells tall.
+    let cells_wide = glyphs.len() * 4 - 1;
+    let scale = ((width * fill.0 / 4) / cells_wide).min((height * fill.1 / 4) / 5);
+    if scale == 0 {
+        return;
+    }
+    let origin_x = (width - cells_wide * scale) / 2;
+    let origin_y = (height - 5 * scale) / 2;
+
+    for (i, glyph) in glyphs.iter().enumerate() {
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                let x0 = origin_x + (i * 4 + col) * scale;
+                let y0 = origin_y + row * scale;
+                for y in y0..y0 + scale {
+                    let start = (y * width + x0) * 4;
+                    for pixel in buffer[start..start + scale * 4].chunks_exact_mut(4) {
+                        pixel[..3].copy_from_slice(&rgb);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// "NO SIGNAL" in the same 3x5 style as `DIGIT_FONT`
+const NO_SIGNAL_TEXT: [[u8; 5]; 9] = [
+    [0b110, 0b101, 0b101, 0b101, 0b101], // N
+    [0b010, 0b101, 0b101, 0b101, 0b010], // O
+    [0b000, 0b000, 0b000, 0b000, 0b000], // space
+    [0b011, 0b100, 0b010, 0b001, 0b110], // S
+    [0b111, 0b010, 0b010, 0b010, 0b111], // I
+    [0b011, 0b100, 0b101, 0b101, 0b011], // G
+    [0b110, 0b101, 0b101, 0b101, 0b101], // N
+    [0b010, 0b101, 0b111, 0b101, 0b101], // A
+    [0b100, 0b100, 0b100, 0b100, 0b111], // L
+];
+
+/// Frames it takes the "NO SIGNAL" text to fade out and back in
+const NO_SIGNAL_PERIOD: u64 = 120;
+
+/// Generate a "NO SIGNAL" card for a display whose source went quiet
+///
+/// The color bars are dimmed to a quarter so the text stands out, and the
+/// text slowly fades between half and full brightness every 120 frames, so
+/// the card never looks like a frozen frame.
+///
+/// # Arguments
+/// * `width` - Frame width in pixels
+/// * `height` - Frame height in pixels
+/// * `frame_index` - Position in the animation
+///
+/// # Returns
+/// RGBA pixel data as bytes (4 bytes per pixel: R, G, B, A)
+pub fn generate_no_signal(width: u16, height: u16, frame_index: u64) -> Bytes {
+    let mut buffer = generate_color_bars(width, height).to_vec();
+    for pixel in buffer.chunks_exact_mut(4) {
+        for channel in &mut pixel[..3] {
+            *channel /= 4;
+        }
+    }
+
+    let half = NO_SIGNAL_PERIOD / 2;
+    let phase = frame_index % NO_SIGNAL_PERIOD;
+    let ramp = phase.abs_diff(half);
+    let level = (128 + ramp * 127 / half) as u8;
+    draw_glyphs(
+        &mut buffer,
+        width as usize,
+        height as usize,
+        &NO_SIGNAL_TEXT,
+        (2, 1),
+        [level; 3],
+    );
+
+    Bytes::from(buffer)
+}
+
+/// Generate an H.264 stream of the color bar pattern with a moving bar
+///
+/// The bottom quarter of each frame shows `generate_moving_bar`, so
+/// consecutive frames differ and the encoder emits real P-frames after the
+/// initial IDR frame (which carries the SPS/PPS). OpenH264 converts the
+/// pixels as BT.601 limited range, so send the frames with
+/// `ColorSpace::Bt601`. Needs the `h264` feature.
+///
+/// # Arguments
+/// * `width` - Frame width in pixels (even)
+/// * `height` - Frame height in pixels (even)
+/// * `frames` - Number of frames to encode
+///
+/// # Returns
+/// One Annex-B access unit per frame, ready for `FrameType::H264Frame`
+#[cfg(feature = "h264")]
+pub fn generate_h264_test_stream(
+    width: u16,
+    height: u16,
+    frames: usize,
+) -> crate::Result<Vec<Bytes>> {
+    use openh264::encoder::{Encoder, EncoderConfig, RateControlMode};
+    use openh264::formats::{RgbaSliceU8, YUVBuffer};
+    use openh264::OpenH264API;
+
+    if width == 0 || height == 0 || (width | height) & 1 != 0 {
+        return Err(crate::Error::Encode(format!(
+            "H.264 test streams need a non-zero even size, got {}x{}",
+            width, height
+        )));
+    }
+
+    // Without rate control every frame is encoded, however small the budget.
+    let config = EncoderConfig::new()
+        .rate_control_mode(RateControlMode::Off)
+        .enable_skip_frame(false);
+    let mut encoder = Encoder::with_api_config(OpenH264API::from_source(), config)
+        .map_err(|e| crate::Error::Encode(e.to_string()))?;
+
+    let bars = generate_color_bars(width, height);
+    let split = (height as usize * 3 / 4) * width as usize * 4;
+    let mut stream = Vec::with_capacity(frames);
+    for index in 0..frames as u64 {
+        let moving = generate_moving_bar(width, height, index);
+        let mut rgba = bars[..split].to_vec();
+        rgba.extend_from_slice(&moving[split..]);
+
+        let yuv =
+            YUVBuffer::from_rgb_source(RgbaSliceU8::new(&rgba, (width as usize, height as usize)));
+        let encoded = encoder
+            .encode(&yuv)
+            .map_err(|e| crate::Error::Encode(e.to_string()))?;
+        stream.push(Bytes::from(encoded.to_vec()));
+    }
+    Ok(stream)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -103,4 +405,116 @@ mod tests {
         let large = generate_color_bars(3840, 2160);
         assert_eq!(large.len(), 3840 * 2160 * 4);
     }
+
+    fn white_columns(pattern: &[u8], width: usize) -> Vec<usize> {
+        (0..width).filter(|&x| pattern[x * 4] == 255).collect()
+    }
+
+    #[test]
+    fn test_moving_bar_moves() {
+        let first = generate_moving_bar(640, 480, 0);
+        let later = generate_moving_bar(640, 480, 30);
+        assert_eq!(first.len(), 640 * 480 * 4);
+        assert_eq!(later.len(), 640 * 480 * 4);
+
+        let first_cols = white_columns(&first, 640);
+        let later_cols = white_columns(&later, 640);
+        assert_eq!(first_cols.len(), 20);
+        assert_eq!(first_cols[0], 0);
+        assert_eq!(
+            later_cols[0], 160,
+            "quarter of the period is a quarter of the width"
+        );
+
+        // Wraps around after a full period
+        assert_eq!(generate_moving_bar(640, 480, 120), first);
+    }
+
+    #[test]
+    fn test_frame_counter_renders_digits() {
+        let one = generate_frame_counter(1280, 720, 1);
+        let eight = generate_frame_counter(1280, 720, 8);
+        assert_eq!(one.len(), 1280 * 720 * 4);
+
+        let lit = |p: &Bytes| p.chunks_exact(4).filter(|px| px[0] == 255).count();
+        assert!(lit(&one) > 0);
+        assert!(lit(&eight) > lit(&one), "8 lights more cells than 1");
+        assert_ne!(
+            generate_frame_counter(1280, 720, 12),
+            generate_frame_counter(1280, 720, 21)
+        );
+    }
+
+    #[test]
+    fn test_frame_counter_tiny_frame() {
+        let pattern = generate_frame_counter(4, 4, 123_456);
+        assert_eq!(pattern.len(), 4 * 4 * 4);
+        assert!(pattern.chunks_exact(4).all(|px| px == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_no_signal_pulses() {
+        let bright = generate_no_signal(1280, 720, 0);
+        let dim = generate_no_signal(1280, 720, 60);
+        assert_eq!(bright.len(), 1280 * 720 * 4);
+
+        let level = |p: &Bytes| p.chunks_exact(4).map(|px| px[0]).max().unwrap();
+        assert_eq!(level(&bright), 255);
+        assert_eq!(level(&dim), 128);
+        assert_eq!(generate_no_signal(1280, 720, 120), bright);
+
+        // Dimmed bars behind the text
+        assert_eq!(&bright[..4], [63, 63, 63, 255]);
+        assert!(generate_no_signal(2, 2, 0)
+            .chunks_exact(4)
+            .all(|px| px[0] < 128));
+    }
+
+    #[test]
+    fn test_gradient_generation() {
+        let horizontal = generate_gradient(256, 4, GradientAxis::Horizontal);
+        assert_eq!(horizontal.len(), 256 * 4 * 4);
+        assert_eq!(horizontal[0], 0);
+        assert_eq!(horizontal[128 * 4], 128);
+        assert_eq!(horizontal[255 * 4], 255);
+        assert_eq!(horizontal[255 * 4 + 3], 255); // Opaque
+
+        let vertical = generate_gradient(4, 256, GradientAxis::Vertical);
+        assert_eq!(vertical.len(), 4 * 256 * 4);
+        assert_eq!(vertical[0], 0);
+        assert_eq!(vertical[(255 * 4) * 4], 255);
+
+        // Single pixel doesn't divide by zero
+        assert_eq!(generate_gradient(1, 1, GradientAxis::Horizontal).len(), 4);
+    }
+
+    #[test]
+    fn test_checkerboard_generation() {
+        let pattern = generate_checkerboard(64, 32, 8);
+        assert_eq!(pattern.len(), 64 * 32 * 4);
+
+        let pixel = |x: usize, y: usize| &pattern[(y * 64 + x) * 4..(y * 64 + x) * 4 + 4];
+        assert_eq!(pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(7, 7), [255, 0, 0, 255]);
+        assert_eq!(pixel(8, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(0, 8), [0, 0, 255, 255]);
+        assert_eq!(pixel(8, 8), [255, 0, 0, 255]);
+
+        assert_eq!(generate_checkerboard(640, 480, 0).len(), 640 * 480 * 4);
+    }
+
+    #[cfg(feature = "h264")]
+    #[test]
+    fn test_h264_test_stream() {
+        let stream = generate_h264_test_stream(64, 48, 5).unwrap();
+        assert_eq!(stream.len(), 5);
+        for frame in &stream {
+            assert_eq!(&frame[..4], [0, 0, 0, 1], "Annex-B start code");
+        }
+        // The first access unit starts with the SPS (NAL type 7)
+        assert_eq!(stream[0][4] & 0x1f, 7);
+
+        assert!(generate_h264_test_stream(63, 48, 1).is_err());
+        assert!(generate_h264_test_stream(0, 0, 1).is_err());
+    }
 }
@@ -1,36 +1,417 @@
 //! QUIC transport layer for ThunderMirror
 //!
-//! This module provides QUIC server and client functionality using quinn.
+//! This module provides QUIC server and client functionality using quinn,
+//! plus a plain TCP fallback for networks that block UDP. Over TCP the
+//! connection is one byte stream of `FrameHeader` + payload frames in each
+//! direction, the same framing as a QUIC bidirectional stream.
 
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-use quinn::{Endpoint, ServerConfig};
+use quinn::{Endpoint, MtuDiscoveryConfig, ServerConfig, VarInt};
 use rustls::{Certificate, PrivateKey, ServerConfig as RustlsServerConfig};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, TcpStream};
 
+use crate::config::TransportTuning;
 use crate::error::{Error, Result};
+use crate::protocol::Frame;
+use crate::stats::{Stats, StatsSnapshot, StatusEvent};
+
+/// ALPN identifier for the current protocol version
+///
+/// The version suffix lets old and new peers negotiate during a rolling
+/// upgrade: each side lists every version it speaks and TLS picks one.
+pub const ALPN: &str = "thunder-mirror/5";
+
+/// ALPN identifier for protocol v4, still offered by senders without a
+/// capture timestamp in the header
+pub const ALPN_V4: &str = "thunder-mirror/4";
+
+/// ALPN identifier for protocol v3, still offered by senders without HDR
+/// header fields
+pub const ALPN_V3: &str = "thunder-mirror/3";
+
+/// ALPN identifier used before versioned identifiers were introduced
+pub const LEGACY_ALPN: &str = "thunder-mirror";
+
+/// Protocols accepted and offered by default, most preferred first
+///
+/// Every header version up to `PROTOCOL_VERSION` is parsed regardless of
+/// which one was negotiated, so both can be served by the same code.
+pub const DEFAULT_ALPN: &[&str] = &[ALPN, ALPN_V4, ALPN_V3, LEGACY_ALPN];
+
+/// QUIC close code for the TLS `no_application_protocol` alert: the server
+/// accepts none of the ALPN identifiers the client offered
+const NO_APPLICATION_PROTOCOL: u64 = 0x100 + 120;
+
+/// Which IP versions a server listens on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpMode {
+    /// IPv4 only, on `0.0.0.0`
+    #[default]
+    V4,
+    /// IPv6 only, on `[::]`, which includes link-local addresses
+    V6,
+    /// Both, on `[::]` with IPv4 clients showing up as IPv4-mapped addresses
+    Dual,
+}
+
+impl IpMode {
+    /// The wildcard address to listen on `port` with
+    pub fn listen_addr(self, port: u16) -> SocketAddr {
+        match self {
+            IpMode::V4 => (Ipv4Addr::UNSPECIFIED, port).into(),
+            IpMode::V6 | IpMode::Dual => (Ipv6Addr::UNSPECIFIED, port).into(),
+        }
+    }
+
+    /// Create a socket bound to `listen_addr(port)`
+    ///
+    /// `IPV6_V6ONLY` is always set explicitly: its default is on for
+    /// Windows and off for Linux.
+    fn bind(self, port: u16, kind: Type, protocol: Protocol) -> std::io::Result<Socket> {
+        let addr = self.listen_addr(port);
+        let socket = Socket::new(Domain::for_address(addr), kind, Some(protocol))?;
+        if addr.is_ipv6() {
+            socket.set_only_v6(self == IpMode::V6)?;
+        }
+        // Lets a restarted receiver rebind while old connections sit in
+        // TIME_WAIT. Windows doesn't need it for that, and there it would let
+        // another process bind the same port and take our connections.
+        #[cfg(unix)]
+        if kind == Type::STREAM {
+            socket.set_reuse_address(true)?;
+        }
+        socket.bind(&addr.into())?;
+        Ok(socket)
+    }
+}
+
+/// How long a generated self-signed certificate is valid by default
+pub const DEFAULT_CERT_VALIDITY: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Names and lifetime for a generated self-signed certificate
+///
+/// Clients that verify the certificate check the name or IP address they
+/// connected to against `subject_alt_names`. The default lists `localhost`
+/// and this machine's addresses from `local_ip_addresses`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfSignedCert {
+    /// DNS names and IP addresses the certificate is valid for; the first
+    /// is also its common name
+    pub subject_alt_names: Vec<String>,
+
+    /// How long the certificate is valid for, from when it's generated
+    pub validity: Duration,
+}
+
+impl Default for SelfSignedCert {
+    fn default() -> Self {
+        let mut cert = Self::new(vec!["localhost".to_string()]);
+        for ip in local_ip_addresses() {
+            cert.add_name(ip.to_string());
+        }
+        cert
+    }
+}
+
+impl SelfSignedCert {
+    /// A certificate for exactly `subject_alt_names`, valid for
+    /// `DEFAULT_CERT_VALIDITY`
+    pub fn new(subject_alt_names: Vec<String>) -> Self {
+        Self {
+            subject_alt_names,
+            validity: DEFAULT_CERT_VALIDITY,
+        }
+    }
+
+    /// Add a DNS name or IP address unless it's already listed
+    pub fn add_name(&mut self, name: impl Into<String>) {
+        let name = name.into();
+        if !self.subject_alt_names.contains(&name) {
+            self.subject_alt_names.push(name);
+        }
+    }
+
+    /// Generate the certificate and its private key
+    ///
+    /// It becomes valid an hour before now, so clients whose clock is a
+    /// little behind still accept it.
+    fn generate(&self) -> Result<(Certificate, PrivateKey)> {
+        let Some(common_name) = self.subject_alt_names.first() else {
+            return Err(Error::transport(
+                "a self-signed certificate needs at least one name",
+            ));
+        };
+        let mut params = rcgen::CertificateParams::new(self.subject_alt_names.clone());
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, common_name.as_str());
+        let now = SystemTime::now();
+        let not_before = now.checked_sub(Duration::from_secs(60 * 60)).unwrap_or(now);
+        let not_after = now
+            .checked_add(self.validity)
+            .ok_or_else(|| Error::transport("certificate validity is too long"))?;
+        params.not_before = not_before.into();
+        params.not_after = not_after.into();
+
+        let cert = rcgen::Certificate::from_params(params)?;
+        let cert_der = cert.serialize_der()?;
+        let key_der = cert.serialize_private_key_der();
+
+        Ok((Certificate(cert_der), PrivateKey(key_der)))
+    }
+}
+
+/// This machine's addresses on its default IPv4 and IPv6 routes
+///
+/// A link without a default route, like a Thunderbolt Bridge, only shows up
+/// through `local_address_towards` with the peer's address.
+pub fn local_ip_addresses() -> Vec<IpAddr> {
+    // Documentation addresses: never answered, but routed like the internet
+    [
+        IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+        IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+    ]
+    .into_iter()
+    .filter_map(local_address_towards)
+    .collect()
+}
+
+/// The local address the OS would send from to reach `peer`
+///
+/// Connecting a UDP socket only picks a route, so nothing is sent.
+pub fn local_address_towards(peer: IpAddr) -> Option<IpAddr> {
+    let unspecified: IpAddr = match peer {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let socket = std::net::UdpSocket::bind((unspecified, 0)).ok()?;
+    socket.connect((peer, 9)).ok()?;
+    let local = socket.local_addr().ok()?.ip();
+    (!local.is_unspecified()).then_some(local)
+}
+
+/// A step in the life of a server or one of its connections
+///
+/// Servers report these as they happen, so front ends and embedders get
+/// the lifecycle as data instead of parsing log lines. `to_status` gives
+/// the `StatusEvent` a front end sees for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// The server is bound and waiting for senders
+    Listening { addr: SocketAddr },
+
+    /// A sender connected; `alpn` is what the handshake settled on (none
+    /// over TCP)
+    Accepted {
+        addr: SocketAddr,
+        alpn: Option<String>,
+    },
+
+    /// A sender opened a stream of frames
+    ///
+    /// Legacy unidirectional streams, one per frame, aren't reported.
+    StreamOpened { addr: SocketAddr },
+
+    /// A connection ended without an error
+    Closed { addr: SocketAddr, reason: String },
+
+    /// A connection, or the server itself (no `addr`), failed
+    Error {
+        addr: Option<SocketAddr>,
+        msg: String,
+    },
+}
+
+impl ConnectionEvent {
+    /// The event as `--status-json` reports it, if it reports it at all
+    pub fn to_status(&self) -> Option<StatusEvent> {
+        match self {
+            Self::Listening { addr } => Some(StatusEvent::Listening {
+                port: addr.port(),
+                address: Some(addr.to_string()),
+            }),
+            Self::Accepted { addr, .. } => Some(StatusEvent::Connected {
+                remote: addr.to_string(),
+            }),
+            Self::StreamOpened { .. } => None,
+            Self::Closed { addr, .. } => Some(StatusEvent::Disconnected {
+                remote: addr.to_string(),
+            }),
+            Self::Error { msg, .. } => Some(StatusEvent::Error {
+                message: msg.clone(),
+            }),
+        }
+    }
+}
 
 /// QUIC server for receiving connections
 pub struct QuicServer {
     endpoint: Endpoint,
     addr: SocketAddr,
+    closed: Arc<AtomicBool>,
+    // Open connections by `stable_id`, each with its own stats
+    connections: Arc<Mutex<HashMap<usize, TrackedConnection>>>,
+}
+
+/// A client whose handshake is still running, from `QuicServer::accept_handshake`
+pub struct Handshake {
+    connecting: quinn::Connecting,
+    connections: Arc<Mutex<HashMap<usize, TrackedConnection>>>,
+}
+
+impl Handshake {
+    /// The address the client connects from
+    pub fn remote_address(&self) -> SocketAddr {
+        self.connecting.remote_address()
+    }
+
+    /// Wait for the handshake to complete
+    ///
+    /// The connection is then tracked like one from `QuicServer::accept`.
+    pub async fn finish(self) -> Result<quinn::Connection> {
+        let conn = self.connecting.await?;
+        track(&self.connections, &conn);
+        Ok(conn)
+    }
+}
+
+/// An accepted connection the server keeps stats for until it closes
+struct TrackedConnection {
+    remote: SocketAddr,
+    stats: Arc<Stats>,
+}
+
+/// Keep stats for `conn` in `connections` until it closes
+fn track(connections: &Arc<Mutex<HashMap<usize, TrackedConnection>>>, conn: &quinn::Connection) {
+    let id = conn.stable_id();
+    let tracked = TrackedConnection {
+        remote: conn.remote_address(),
+        stats: Stats::new(),
+    };
+    if let Ok(mut connections) = connections.lock() {
+        connections.insert(id, tracked);
+    }
+
+    let conn = conn.clone();
+    let connections = connections.clone();
+    tokio::spawn(async move {
+        conn.closed().await;
+        if let Ok(mut connections) = connections.lock() {
+            connections.remove(&id);
+        }
+    });
 }
 
 impl QuicServer {
     /// Create a new QUIC server bound to the given address
     ///
+    /// Uses a throwaway self-signed certificate and default transport
+    /// parameters, same as `new_self_signed(addr, &TransportTuning::default())`.
+    ///
     /// # Arguments
     /// * `addr` - Socket address to bind to (e.g., "0.0.0.0:9999")
     ///
     /// # Returns
     /// A `QuicServer` instance ready to accept connections
     pub async fn new(addr: SocketAddr) -> Result<Self> {
-        let server_config = Self::create_server_config()?;
-        let endpoint = Endpoint::server(server_config, addr)?;
+        Self::new_self_signed(addr, &TransportTuning::default()).await
+    }
+
+    /// Create a QUIC server with a freshly generated self-signed certificate
+    ///
+    /// The certificate is `SelfSignedCert::default()`. Clients without it in
+    /// their trust store must disable verification.
+    /// Only clients offering one of `tuning.alpn` are accepted.
+    pub async fn new_self_signed(addr: SocketAddr, tuning: &TransportTuning) -> Result<Self> {
+        Self::with_self_signed(addr, tuning, &SelfSignedCert::default()).await
+    }
+
+    /// Create a QUIC server with a self-signed certificate generated from `cert`
+    pub async fn with_self_signed(
+        addr: SocketAddr,
+        tuning: &TransportTuning,
+        cert: &SelfSignedCert,
+    ) -> Result<Self> {
+        let (cert, key) = cert.generate()?;
+        Self::bind(addr, vec![cert], key, tuning)
+    }
+
+    /// Create a QUIC server with a self-signed certificate on the wildcard
+    /// address of `mode`
+    pub async fn listen(mode: IpMode, port: u16, tuning: &TransportTuning) -> Result<Self> {
+        Self::listen_self_signed(mode, port, tuning, &SelfSignedCert::default()).await
+    }
+
+    /// Like `listen`, with a self-signed certificate generated from `cert`
+    pub async fn listen_self_signed(
+        mode: IpMode,
+        port: u16,
+        tuning: &TransportTuning,
+        cert: &SelfSignedCert,
+    ) -> Result<Self> {
+        let (cert, key) = cert.generate()?;
+        let socket = mode.bind(port, Type::DGRAM, Protocol::UDP)?;
+        Self::serve(socket.into(), vec![cert], key, tuning)
+    }
+
+    /// Create a QUIC server using a PEM certificate chain and private key
+    ///
+    /// # Arguments
+    /// * `addr` - Socket address to bind to
+    /// * `cert_path` - PEM file with the certificate chain, leaf first
+    /// * `key_path` - PEM file with the private key (PKCS#8, RSA, or SEC1)
+    /// * `tuning` - QUIC transport parameters and accepted ALPN identifiers
+    pub async fn new_with_cert(
+        addr: SocketAddr,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+        tuning: &TransportTuning,
+    ) -> Result<Self> {
+        let certs = load_certs(cert_path.as_ref())?;
+        let key = load_private_key(key_path.as_ref())?;
+        Self::bind(addr, certs, key, tuning)
+    }
+
+    fn bind(
+        addr: SocketAddr,
+        certs: Vec<Certificate>,
+        key: PrivateKey,
+        tuning: &TransportTuning,
+    ) -> Result<Self> {
+        Self::serve(std::net::UdpSocket::bind(addr)?, certs, key, tuning)
+    }
+
+    fn serve(
+        socket: std::net::UdpSocket,
+        certs: Vec<Certificate>,
+        key: PrivateKey,
+        tuning: &TransportTuning,
+    ) -> Result<Self> {
+        let server_config = Self::create_server_config(certs, key, tuning)?;
+        let runtime =
+            quinn::default_runtime().ok_or_else(|| Error::transport("no async runtime found"))?;
+        let endpoint = Endpoint::new(
+            quinn::EndpointConfig::default(),
+            Some(server_config),
+            socket,
+            runtime,
+        )?;
 
         Ok(Self {
             addr: endpoint.local_addr()?,
             endpoint,
+            closed: Arc::new(AtomicBool::new(false)),
+            connections: Arc::default(),
         })
     }
 
@@ -41,60 +422,296 @@ impl QuicServer {
 
     /// Accept the next incoming connection
     ///
+    /// Fails both for a handshake that didn't complete and once the server is
+    /// closed; check `is_closed` to tell the two apart in an accept loop.
+    /// Clients offering none of the accepted ALPN identifiers are turned
+    /// away during the TLS handshake and never show up here.
+    ///
+    /// The connection gets its own `Stats` (see `stats_for`) until it
+    /// closes. The server holds on to it until then, so dropping the
+    /// returned handle doesn't close it; call `close` on it instead.
+    ///
     /// # Returns
     /// A `quinn::Connection` when a client connects
     pub async fn accept(&self) -> Result<quinn::Connection> {
-        let incoming = self.endpoint.accept().await;
-        let conn = incoming
-            .ok_or_else(|| Error::transport("server endpoint closed"))?
+        self.accept_handshake().await?.finish().await
+    }
+
+    /// Wait for the next client to start connecting, without finishing its
+    /// handshake
+    ///
+    /// An accept loop spawns `Handshake::finish` for each client, so a slow
+    /// or stalled handshake doesn't hold up the clients behind it until it
+    /// times out. Fails once the server is closed.
+    pub async fn accept_handshake(&self) -> Result<Handshake> {
+        let connecting = self
+            .endpoint
+            .accept()
             .await
-            .map_err(|e| Error::transport(format!("connection failed: {}", e)))?;
+            .ok_or_else(|| Error::transport("server endpoint closed"))?;
+        Ok(Handshake {
+            connecting,
+            connections: self.connections.clone(),
+        })
+    }
 
-        Ok(conn)
+    /// Accept the next incoming connection, or `Ok(None)` if no client
+    /// connects within `dur`
+    ///
+    /// Handy in a `select!` loop that has other work to do or has to notice
+    /// a shutdown. Only the wait for a client counts towards `dur`: a
+    /// handshake that started in time is allowed to finish, so a client is
+    /// never dropped halfway. Otherwise behaves like `accept`.
+    pub async fn accept_timeout(&self, dur: Duration) -> Result<Option<quinn::Connection>> {
+        match tokio::time::timeout(dur, self.accept_handshake()).await {
+            Ok(handshake) => handshake?.finish().await.map(Some),
+            Err(_) => Ok(None),
+        }
     }
 
-    /// Create a server configuration with self-signed certificate
+    /// Stats for a connection returned by `accept`, while it is open
     ///
-    /// For development/testing purposes, generates a self-signed certificate.
-    /// In production, this should use proper certificates.
-    fn create_server_config() -> Result<ServerConfig> {
-        // Generate a self-signed certificate for testing
-        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])
-            .map_err(|e| Error::transport(format!("certificate generation failed: {}", e)))?;
+    /// Callers record the connection's traffic here; the server only
+    /// creates and forgets them.
+    pub fn stats_for(&self, conn: &quinn::Connection) -> Option<Arc<Stats>> {
+        let connections = self.connections.lock().ok()?;
+        connections
+            .get(&conn.stable_id())
+            .map(|tracked| tracked.stats.clone())
+    }
 
-        let cert_der = cert
-            .serialize_der()
-            .map_err(|e| Error::transport(format!("certificate serialization failed: {}", e)))?;
+    /// A snapshot of every open connection's stats, by remote address
+    pub fn connection_stats(&self) -> HashMap<SocketAddr, StatsSnapshot> {
+        let Ok(connections) = self.connections.lock() else {
+            return HashMap::new();
+        };
+        connections
+            .values()
+            .map(|tracked| (tracked.remote, tracked.stats.snapshot()))
+            .collect()
+    }
 
-        let key_der = cert.serialize_private_key_der();
+    /// Close all connections with `code` and `reason`, stop accepting, and
+    /// wait for the close to reach connected clients
+    pub async fn close(&self, code: u32, reason: &[u8]) {
+        self.shutdown_handle().close(code, reason);
+        self.endpoint.wait_idle().await;
+    }
 
-        let cert = Certificate(cert_der);
-        let key = PrivateKey(key_der);
+    /// Whether `close` has been called on the server or one of its handles
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
 
+    /// Handle for closing the server from another task or thread
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            endpoint: self.endpoint.clone(),
+            closed: self.closed.clone(),
+        }
+    }
+
+    /// Create a server configuration for the given certificate chain and key
+    fn create_server_config(
+        certs: Vec<Certificate>,
+        key: PrivateKey,
+        tuning: &TransportTuning,
+    ) -> Result<ServerConfig> {
         let mut rustls_config = RustlsServerConfig::builder()
             .with_safe_defaults()
             .with_no_client_auth()
-            .with_single_cert(vec![cert], key)
-            .map_err(|e| Error::transport(format!("TLS config failed: {}", e)))?;
+            .with_single_cert(certs, key)?;
 
         // Configure for low latency
         rustls_config.max_early_data_size = u32::MAX;
-        rustls_config.alpn_protocols = vec![b"thunder-mirror".to_vec()];
+        rustls_config.alpn_protocols = alpn_bytes(&tuning.alpn);
 
         let mut server_config = ServerConfig::with_crypto(Arc::new(rustls_config));
-        server_config.transport = Arc::new(quinn::TransportConfig::default());
+        server_config.transport = Arc::new(transport_config(tuning)?);
 
         Ok(server_config)
     }
 }
 
+/// Build quinn transport parameters from `tuning`
+fn transport_config(tuning: &TransportTuning) -> Result<quinn::TransportConfig> {
+    tuning.validate()?;
+
+    let mut transport = quinn::TransportConfig::default();
+    transport.receive_window(VarInt::from_u32(tuning.receive_window));
+    transport.stream_receive_window(VarInt::from_u32(tuning.stream_receive_window));
+    transport.datagram_receive_buffer_size(
+        (tuning.datagram_buffer > 0).then_some(tuning.datagram_buffer),
+    );
+
+    transport.initial_mtu(tuning.initial_mtu);
+    let discovery = (tuning.max_mtu > 0).then(|| {
+        let mut discovery = MtuDiscoveryConfig::default();
+        discovery.upper_bound(tuning.max_mtu);
+        discovery
+    });
+    transport.mtu_discovery_config(discovery);
+
+    let keep_alive =
+        (tuning.keep_alive_ms > 0).then(|| Duration::from_millis(tuning.keep_alive_ms));
+    transport.keep_alive_interval(keep_alive);
+
+    let idle_timeout = match tuning.idle_timeout_ms {
+        0 => None,
+        ms => Some(
+            Duration::from_millis(ms)
+                .try_into()
+                .map_err(|e| Error::transport(format!("invalid idle timeout: {}", e)))?,
+        ),
+    };
+    transport.max_idle_timeout(idle_timeout);
+
+    Ok(transport)
+}
+
+/// Read every certificate from a PEM file
+fn load_certs(path: &Path) -> Result<Vec<Certificate>> {
+    let mut reader = open_pem(path)?;
+    let certs = rustls_pemfile::certs(&mut reader).map_err(|e| {
+        Error::transport(format!(
+            "failed to parse certificates in {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    if certs.is_empty() {
+        return Err(Error::transport(format!(
+            "no certificates found in {}",
+            path.display()
+        )));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Read the first private key from a PEM file
+fn load_private_key(path: &Path) -> Result<PrivateKey> {
+    let mut reader = open_pem(path)?;
+    loop {
+        let item = rustls_pemfile::read_one(&mut reader).map_err(|e| {
+            Error::transport(format!(
+                "failed to parse private key in {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        match item {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => {
+                return Err(Error::transport(format!(
+                    "no private key found in {}",
+                    path.display()
+                )))
+            }
+        }
+    }
+}
+
+fn open_pem(path: &Path) -> Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| Error::transport(format!("failed to open {}: {}", path.display(), e)))
+}
+
+/// ALPN identifiers in the form rustls expects
+fn alpn_bytes(alpn: &[String]) -> Vec<Vec<u8>> {
+    alpn.iter().map(|a| a.as_bytes().to_vec()).collect()
+}
+
+/// The ALPN identifier the handshake of `conn` settled on, if any
+pub fn negotiated_alpn(conn: &quinn::Connection) -> Option<Vec<u8>> {
+    conn.handshake_data()?
+        .downcast::<quinn::crypto::rustls::HandshakeData>()
+        .ok()?
+        .protocol
+}
+
+/// Send `frame` over `conn` as a single QUIC datagram
+///
+/// A datagram can't be split across packets, so it holds at most
+/// `conn.max_datagram_size()` bytes: a little under the path MTU, which
+/// starts at `TransportTuning::initial_mtu` and grows as MTU discovery
+/// probes the path. A frame larger than that, typically a keyframe, is an
+/// error here instead of going missing, and has to be sent on a stream.
+pub fn send_frame_datagram(conn: &quinn::Connection, frame: &Frame) -> Result<()> {
+    let Some(limit) = conn.max_datagram_size() else {
+        return Err(Error::transport(
+            "datagrams are disabled on this connection",
+        ));
+    };
+    let data = frame.encode().freeze();
+    if data.len() > limit {
+        return Err(Error::transport(format!(
+            "{}-byte frame is over the {}-byte datagram limit; send it on a stream",
+            data.len(),
+            limit
+        )));
+    }
+    conn.send_datagram(data)
+        .map_err(|e| Error::transport(format!("datagram send failed: {}", e)))
+}
+
+/// Path statistics of a QUIC connection
+///
+/// Packet counts cover packets this end sent, so on the receiver they
+/// mostly reflect the ACKs and control frames going back to the sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathStats {
+    /// Smoothed round-trip time
+    pub rtt: Duration,
+    /// Congestion window in bytes
+    pub cwnd: u64,
+    pub lost_packets: u64,
+    pub sent_packets: u64,
+}
+
+/// Current path statistics of `conn`, from `quinn::Connection::stats`
+pub fn path_stats(conn: &quinn::Connection) -> PathStats {
+    let path = conn.stats().path;
+    PathStats {
+        rtt: path.rtt,
+        cwnd: path.cwnd,
+        lost_packets: path.lost_packets,
+        sent_packets: path.sent_packets,
+    }
+}
+
+/// Closes a `QuicServer` without needing access to the server itself
+///
+/// Lets an embedding application stop streaming while the server's accept
+/// loop runs on its own task.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    endpoint: Endpoint,
+    closed: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    /// Close all connections with `code` and `reason` and stop accepting
+    ///
+    /// Returns immediately; a pending `QuicServer::accept` fails and
+    /// `QuicServer::is_closed` reports `true` afterwards.
+    pub fn close(&self, code: u32, reason: &[u8]) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.endpoint.close(VarInt::from_u32(code), reason);
+    }
+}
+
 /// QUIC client for connecting to servers
 pub struct QuicClient {
     endpoint: Endpoint,
+    alpn: Vec<String>,
 }
 
 impl QuicClient {
-    /// Create a new QUIC client
+    /// Create a new QUIC client offering `DEFAULT_ALPN`
     ///
     /// # Arguments
     /// * `bind_addr` - Local address to bind to (e.g., "0.0.0.0:0" for any available port)
@@ -102,11 +719,42 @@ impl QuicClient {
     /// # Returns
     /// A `QuicClient` instance ready to connect
     pub fn new(bind_addr: SocketAddr) -> Result<Self> {
-        let client_config = Self::create_client_config();
+        Self::with_alpn(bind_addr, DEFAULT_ALPN)
+    }
+
+    /// Create a QUIC client offering the given ALPN identifiers, most preferred first
+    pub fn with_alpn(bind_addr: SocketAddr, alpn: &[&str]) -> Result<Self> {
+        let client_config = Self::create_client_config(alpn);
         let mut endpoint = Endpoint::client(bind_addr)?;
         endpoint.set_default_client_config(client_config);
 
-        Ok(Self { endpoint })
+        Ok(Self {
+            endpoint,
+            alpn: alpn.iter().map(|a| a.to_string()).collect(),
+        })
+    }
+
+    /// Create a QUIC client with the transport parameters and ALPN list of
+    /// `tuning`
+    ///
+    /// For a receiver that dials its sender: it receives with the same
+    /// windows and timeouts it would listen with.
+    pub fn with_tuning(bind_addr: SocketAddr, tuning: &TransportTuning) -> Result<Self> {
+        let alpn: Vec<&str> = tuning.alpn.iter().map(String::as_str).collect();
+        let mut client_config = Self::create_client_config(&alpn);
+        client_config.transport_config(Arc::new(transport_config(tuning)?));
+        let mut endpoint = Endpoint::client(bind_addr)?;
+        endpoint.set_default_client_config(client_config);
+
+        Ok(Self {
+            endpoint,
+            alpn: tuning.alpn.clone(),
+        })
+    }
+
+    /// The local address the client's endpoint is bound to
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.endpoint.local_addr()?)
     }
 
     /// Connect to a QUIC server
@@ -127,7 +775,17 @@ impl QuicClient {
             .connect(server_addr, server_name)
             .map_err(|e| Error::transport(format!("connect error: {}", e)))?
             .await
-            .map_err(|e| Error::transport(format!("connection failed: {}", e)))?;
+            .map_err(|e| match e {
+                quinn::ConnectionError::ConnectionClosed(ref close)
+                    if u64::from(close.error_code) == NO_APPLICATION_PROTOCOL =>
+                {
+                    Error::transport(format!(
+                        "server supports none of the offered protocols ({})",
+                        self.alpn.join(", ")
+                    ))
+                }
+                e => Error::from(e),
+            })?;
 
         Ok(conn)
     }
@@ -136,7 +794,7 @@ impl QuicClient {
     ///
     /// For development/testing purposes, accepts any certificate.
     /// In production, this should use proper certificate verification.
-    fn create_client_config() -> quinn::ClientConfig {
+    fn create_client_config(alpn: &[&str]) -> quinn::ClientConfig {
         let roots = rustls::RootCertStore::empty();
         let mut client_config = rustls::ClientConfig::builder()
             .with_safe_defaults()
@@ -148,7 +806,7 @@ impl QuicClient {
             .dangerous()
             .set_certificate_verifier(Arc::new(NoVerifier));
 
-        client_config.alpn_protocols = vec![b"thunder-mirror".to_vec()];
+        client_config.alpn_protocols = alpn.iter().map(|a| a.as_bytes().to_vec()).collect();
 
         quinn::ClientConfig::new(Arc::new(client_config))
     }
@@ -170,6 +828,70 @@ impl rustls::client::ServerCertVerifier for NoVerifier {
     }
 }
 
+/// TCP server for senders that can't reach the QUIC port
+///
+/// There is no TLS or multiplexing: each accepted `TcpStream` carries
+/// frames from the sender and control frames back to it.
+pub struct TcpServer {
+    listener: TcpListener,
+    addr: SocketAddr,
+}
+
+impl TcpServer {
+    /// Create a TCP server bound to the given address
+    pub async fn bind(addr: SocketAddr) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Self {
+            addr: listener.local_addr()?,
+            listener,
+        })
+    }
+
+    /// Create a TCP server on the wildcard address of `mode`
+    pub async fn listen(mode: IpMode, port: u16) -> Result<Self> {
+        let socket = mode.bind(port, Type::STREAM, Protocol::TCP)?;
+        socket.listen(128)?;
+        socket.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(socket.into())?;
+        Ok(Self {
+            addr: listener.local_addr()?,
+            listener,
+        })
+    }
+
+    /// Get the address the server is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Accept the next incoming connection
+    ///
+    /// Nagle's algorithm is turned off so small control frames aren't held back.
+    pub async fn accept(&self) -> Result<(TcpStream, SocketAddr)> {
+        let (stream, remote) = self
+            .listener
+            .accept()
+            .await
+            .map_err(|e| Error::transport(format!("accept failed: {}", e)))?;
+        stream.set_nodelay(true)?;
+        Ok((stream, remote))
+    }
+}
+
+/// TCP client for connecting to a `TcpServer`
+pub struct TcpClient;
+
+impl TcpClient {
+    /// Connect to a TCP server
+    pub async fn connect(server_addr: SocketAddr) -> Result<TcpStream> {
+        let stream = TcpStream::connect(server_addr)
+            .await
+            .map_err(|e| Error::transport(format!("connection failed: {}", e)))?;
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -205,6 +927,76 @@ mod tests {
         assert_eq!(client_conn.remote_address(), server_addr);
     }
 
+    #[tokio::test]
+    async fn test_accept_timeout() {
+        let server = QuicServer::new("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let conn = server
+            .accept_timeout(Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert!(conn.is_none(), "nobody connected");
+
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let (client_conn, conn) = tokio::join!(
+            client.connect(server.local_addr(), "localhost"),
+            server.accept_timeout(Duration::from_secs(5))
+        );
+        client_conn.unwrap();
+        let conn = conn.unwrap().expect("client connected in time");
+        assert_eq!(conn.remote_address().ip().to_string(), "127.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn test_stalled_handshake_does_not_block_others() {
+        let server = QuicServer::new("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let server_addr = server.local_addr();
+
+        // Passes the first client's packets on to the server but drops the
+        // replies, so its handshake never completes
+        let proxy = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0; 65536];
+            while let Ok((len, from)) = proxy.recv_from(&mut buf).await {
+                if from != server_addr {
+                    let _ = proxy.send_to(&buf[..len], server_addr).await;
+                }
+            }
+        });
+        let stalled = tokio::spawn(async move {
+            let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+            client.connect(proxy_addr, "localhost").await
+        });
+
+        let handshake = timeout(Duration::from_secs(5), server.accept_handshake())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(handshake.remote_address(), proxy_addr);
+        let pending = tokio::spawn(handshake.finish());
+
+        // The next client gets in while the first handshake hangs
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let (client_conn, conn) = timeout(Duration::from_secs(5), async {
+            tokio::join!(client.connect(server_addr, "localhost"), async {
+                server.accept_handshake().await?.finish().await
+            })
+        })
+        .await
+        .expect("the second client is accepted without waiting for the first");
+        client_conn.unwrap();
+        let conn = conn.unwrap();
+        assert!(server.stats_for(&conn).is_some(), "tracked like accept");
+        assert!(!pending.is_finished());
+
+        pending.abort();
+        stalled.abort();
+    }
+
     #[tokio::test]
     async fn test_quic_client_connects_to_server() {
         // Bind to a random available port
@@ -234,4 +1026,491 @@ mod tests {
         // Verify client connection is established
         assert_eq!(client_conn.remote_address(), server_addr);
     }
+
+    #[tokio::test]
+    async fn test_path_stats_after_handshake() {
+        let server = QuicServer::new("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let (client_conn, server_conn) = tokio::join!(
+            client.connect(server.local_addr(), "localhost"),
+            server.accept()
+        );
+        let (client_conn, _server_conn) = (client_conn.unwrap(), server_conn.unwrap());
+
+        // The handshake alone gives an RTT estimate and some sent packets
+        let path = path_stats(&client_conn);
+        assert!(path.sent_packets > 0);
+        assert!(path.cwnd > 0);
+        assert!(path.lost_packets <= path.sent_packets);
+    }
+
+    #[tokio::test]
+    async fn test_connection_stats_per_connection() {
+        let server = QuicServer::new("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let server_addr = server.local_addr();
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let (client_conn, server_conn) =
+            tokio::join!(client.connect(server_addr, "localhost"), server.accept());
+        let (client_conn, server_conn) = (client_conn.unwrap(), server_conn.unwrap());
+        let stats = server.stats_for(&server_conn).expect("tracked while open");
+        stats.record_frame(1000);
+
+        let snapshots = server.connection_stats();
+        assert_eq!(snapshots.len(), 1);
+        let snapshot = &snapshots[&server_conn.remote_address()];
+        assert_eq!(snapshot.total_frames, 1);
+        assert_eq!(snapshot.total_bytes, 1000);
+
+        client_conn.close(0u32.into(), b"done");
+        timeout(Duration::from_secs(5), server_conn.closed())
+            .await
+            .expect("server should see the close");
+        // The tracking task runs right after the close is seen
+        for _ in 0..100 {
+            if server.connection_stats().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(server.connection_stats().is_empty());
+        assert!(server.stats_for(&server_conn).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_handle_stops_accept_loop() {
+        let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let server = QuicServer::new(addr).await.unwrap();
+        let server_addr = server.local_addr();
+        let shutdown = server.shutdown_handle();
+
+        let accept_loop = tokio::spawn(async move {
+            let mut connections = Vec::new();
+            loop {
+                match server.accept().await {
+                    Ok(conn) => connections.push(conn),
+                    Err(_) if server.is_closed() => break,
+                    Err(_) => continue,
+                }
+            }
+            connections.len()
+        });
+
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let client_conn = client.connect(server_addr, "localhost").await.unwrap();
+        // Make sure the server has taken the connection before closing.
+        client_conn.open_uni().await.unwrap().finish().await.ok();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        shutdown.close(7, b"shutting down");
+        let accepted = timeout(Duration::from_secs(5), accept_loop)
+            .await
+            .expect("accept loop should exit after close")
+            .unwrap();
+        assert_eq!(accepted, 1);
+
+        match timeout(Duration::from_secs(5), client_conn.closed())
+            .await
+            .unwrap()
+        {
+            quinn::ConnectionError::ApplicationClosed(close) => {
+                assert_eq!(close.error_code, VarInt::from_u32(7));
+                assert_eq!(&close.reason[..], b"shutting down");
+            }
+            other => panic!("unexpected close: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close_waits_for_idle() {
+        let server = QuicServer::new("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        timeout(Duration::from_secs(5), server.close(0, b""))
+            .await
+            .expect("close with no connections should finish promptly");
+        assert!(server.is_closed());
+        assert!(server.accept().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_self_signed_cert_verifies_by_ip() {
+        let mut params = SelfSignedCert::new(vec!["localhost".to_string()]);
+        params.add_name("127.0.0.1");
+        params.add_name("localhost");
+        assert_eq!(params.subject_alt_names, ["localhost", "127.0.0.1"]);
+        let (cert, key) = params.generate().unwrap();
+
+        let tuning = TransportTuning::default();
+        let server = QuicServer::bind(
+            "127.0.0.1:0".parse().unwrap(),
+            vec![cert.clone()],
+            key,
+            &tuning,
+        )
+        .unwrap();
+        let server_addr = server.local_addr();
+        let server_handle = tokio::spawn(async move { server.accept().await });
+
+        // A client that checks the certificate, trusting only this one
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&cert).unwrap();
+        let mut crypto = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        crypto.alpn_protocols = alpn_bytes(&tuning.alpn);
+        let client_config = quinn::ClientConfig::new(Arc::new(crypto));
+        let endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        let connect = |name| {
+            endpoint
+                .connect_with(client_config.clone(), server_addr, name)
+                .unwrap()
+        };
+        connect("127.0.0.1").await.unwrap();
+        let accepted = timeout(Duration::from_secs(5), server_handle)
+            .await
+            .expect("connection should be accepted within 5 seconds")
+            .unwrap();
+        assert!(accepted.is_ok());
+        let err = connect("127.0.0.2").await.unwrap_err();
+        assert!(err.to_string().contains("NotValidForName"), "got {}", err);
+
+        assert!(SelfSignedCert::new(Vec::new()).generate().is_err());
+        assert!(SelfSignedCert::default()
+            .subject_alt_names
+            .contains(&"localhost".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_quic_server_with_cert_files() {
+        let dir = std::env::temp_dir().join(format!("thunder_tls_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+
+        let server = QuicServer::new_with_cert(
+            "127.0.0.1:0".parse().unwrap(),
+            &cert_path,
+            &key_path,
+            &TransportTuning::default(),
+        )
+        .await
+        .unwrap();
+        let server_addr = server.local_addr();
+        let server_handle = tokio::spawn(async move { server.accept().await.unwrap() });
+
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.connect(server_addr, "localhost").await.unwrap();
+        timeout(Duration::from_secs(5), server_handle)
+            .await
+            .expect("connection should be accepted within 5 seconds")
+            .unwrap();
+
+        // Swapped files: neither holds what the loader is looking for
+        let err = QuicServer::new_with_cert(
+            "127.0.0.1:0".parse().unwrap(),
+            &key_path,
+            &cert_path,
+            &TransportTuning::default(),
+        )
+        .await
+        .err()
+        .unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(matches!(err, Error::Transport(_)));
+        assert!(err.to_string().contains("key.pem"), "got {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_quic_server_with_missing_cert_file() {
+        let err = QuicServer::new_with_cert(
+            "127.0.0.1:0".parse().unwrap(),
+            "/nonexistent/cert.pem",
+            "/nonexistent/key.pem",
+            &TransportTuning::default(),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(err, Error::Transport(_)));
+        assert!(
+            err.to_string().contains("/nonexistent/cert.pem"),
+            "got {}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_tuning_is_rejected() {
+        let tuning = TransportTuning {
+            receive_window: 0,
+            ..Default::default()
+        };
+        let err = QuicServer::new_self_signed("127.0.0.1:0".parse().unwrap(), &tuning)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Config(_)), "got {:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_alpn_negotiation() {
+        let current = format!("thunder-mirror/{}", crate::protocol::PROTOCOL_VERSION);
+        assert_eq!(ALPN, current, "ALPN should track the protocol version");
+
+        let server =
+            QuicServer::new_self_signed("127.0.0.1:0".parse().unwrap(), &Default::default())
+                .await
+                .unwrap();
+        let server_addr = server.local_addr();
+        let server = Arc::new(server);
+
+        // Older clients offering only the identifiers they know still get in
+        for (offered, expected) in [
+            (&[LEGACY_ALPN][..], LEGACY_ALPN),
+            (&[ALPN_V3, LEGACY_ALPN][..], ALPN_V3),
+            (&[ALPN_V4, ALPN_V3, LEGACY_ALPN][..], ALPN_V4),
+            (DEFAULT_ALPN, ALPN),
+        ] {
+            let accept = tokio::spawn({
+                let server = server.clone();
+                async move { server.accept().await }
+            });
+            let client = QuicClient::with_alpn("127.0.0.1:0".parse().unwrap(), offered).unwrap();
+            let conn = client.connect(server_addr, "localhost").await.unwrap();
+            assert_eq!(negotiated_alpn(&conn).unwrap(), expected.as_bytes());
+            timeout(Duration::from_secs(5), accept)
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_datagram_frames_fit_the_mtu() {
+        use bytes::Bytes;
+
+        use crate::protocol::{FrameHeader, FrameType};
+
+        // No discovery, so the datagram limit stays put at the initial MTU
+        let tuning = TransportTuning {
+            initial_mtu: 1400,
+            max_mtu: 0,
+            ..Default::default()
+        };
+        let server = QuicServer::new_self_signed("127.0.0.1:0".parse().unwrap(), &tuning)
+            .await
+            .unwrap();
+        let server_addr = server.local_addr();
+        let accept = tokio::spawn(async move { server.accept().await.unwrap() });
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let conn = client.connect(server_addr, "localhost").await.unwrap();
+        let server_conn = timeout(Duration::from_secs(5), accept)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // The server's limit is its initial MTU less packet overhead
+        let limit = server_conn.max_datagram_size().unwrap();
+        assert!((1200..1400).contains(&limit), "got {}", limit);
+
+        let frame = |size: usize| {
+            let header = FrameHeader::new(FrameType::H264Frame, 0, 0, 64, 64, size as u32);
+            Frame::new(header, Bytes::from(vec![7; size]))
+        };
+        send_frame_datagram(&server_conn, &frame(1024)).unwrap();
+        let received = timeout(Duration::from_secs(5), conn.read_datagram())
+            .await
+            .unwrap()
+            .unwrap();
+        let decoded = Frame::decode(&mut received.clone()).unwrap();
+        assert_eq!(decoded.payload.len(), 1024);
+
+        let err = send_frame_datagram(&server_conn, &frame(limit)).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("send it on a stream"), "got {}", msg);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_alpn_is_rejected() {
+        let tuning = TransportTuning {
+            alpn: vec![ALPN.to_string()],
+            ..Default::default()
+        };
+        let server = QuicServer::new_self_signed("127.0.0.1:0".parse().unwrap(), &tuning)
+            .await
+            .unwrap();
+        let server_addr = server.local_addr();
+
+        let client =
+            QuicClient::with_alpn("127.0.0.1:0".parse().unwrap(), &["thunder-mirror/99"]).unwrap();
+        let err = client
+            .connect(server_addr, "localhost")
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("none of the offered protocols (thunder-mirror/99)"),
+            "got {}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tcp_frames_round_trip() {
+        use crate::protocol::{ControlMessage, FrameHeader};
+        use bytes::Bytes;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let server = TcpServer::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let server_addr = server.local_addr();
+
+        let server_task = tokio::spawn(async move {
+            let (mut stream, remote) = server.accept().await.unwrap();
+            assert_eq!(remote.ip().to_string(), "127.0.0.1");
+            let mut data = Vec::new();
+            stream.read_to_end(&mut data).await.unwrap();
+            data
+        });
+
+        let mut client = TcpClient::connect(server_addr).await.unwrap();
+        let frame = ControlMessage::RequestKeyframe.to_frame(7, 0).unwrap();
+        client.write_all(&frame.encode()).await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let data = timeout(Duration::from_secs(5), server_task)
+            .await
+            .expect("frame should arrive within 5 seconds")
+            .unwrap();
+        let mut data = Bytes::from(data);
+        let header = FrameHeader::decode(&mut data).unwrap();
+        assert_eq!(header.sequence, 7);
+        assert!(matches!(
+            ControlMessage::decode(&data).unwrap(),
+            ControlMessage::RequestKeyframe
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connect_to_closed_port_fails() {
+        let server = TcpServer::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let server_addr = server.local_addr();
+        drop(server);
+
+        let err = TcpClient::connect(server_addr).await.unwrap_err();
+        assert!(err.to_string().starts_with("Transport error"));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_listen_modes() {
+        let v4 = TcpServer::listen(IpMode::V4, 0).await.unwrap();
+        assert!(v4.local_addr().is_ipv4());
+
+        let dual = TcpServer::listen(IpMode::Dual, 0).await.unwrap();
+        let port = dual.local_addr().port();
+        assert!(dual.local_addr().is_ipv6());
+        TcpClient::connect((Ipv4Addr::LOCALHOST, port).into())
+            .await
+            .unwrap();
+        TcpClient::connect((Ipv6Addr::LOCALHOST, port).into())
+            .await
+            .unwrap();
+
+        let v6 = TcpServer::listen(IpMode::V6, 0).await.unwrap();
+        let port = v6.local_addr().port();
+        TcpClient::connect((Ipv6Addr::LOCALHOST, port).into())
+            .await
+            .unwrap();
+        assert!(TcpClient::connect((Ipv4Addr::LOCALHOST, port).into())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_quic_dual_stack_accepts_ipv4() {
+        let server = QuicServer::listen(IpMode::Dual, 0, &TransportTuning::default())
+            .await
+            .unwrap();
+        let server_addr = (Ipv4Addr::LOCALHOST, server.local_addr().port()).into();
+
+        let server_handle = tokio::spawn(async move {
+            let conn = server.accept().await.unwrap();
+            // IPv4 clients show up as IPv4-mapped IPv6 addresses
+            let SocketAddr::V6(remote) = conn.remote_address() else {
+                panic!("expected an IPv6 remote address");
+            };
+            assert_eq!(remote.ip().to_ipv4_mapped(), Some(Ipv4Addr::LOCALHOST));
+        });
+
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.connect(server_addr, "localhost").await.unwrap();
+        timeout(Duration::from_secs(5), server_handle)
+            .await
+            .expect("connection should be accepted within 5 seconds")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_ip_mode_listen_addr() {
+        assert_eq!(IpMode::V4.listen_addr(9999).to_string(), "0.0.0.0:9999");
+        assert_eq!(IpMode::V6.listen_addr(9999).to_string(), "[::]:9999");
+        assert_eq!(IpMode::Dual.listen_addr(9999).to_string(), "[::]:9999");
+    }
+
+    #[test]
+    fn test_connection_event_status() {
+        let addr: SocketAddr = "192.168.50.1:50000".parse().unwrap();
+        let listening = ConnectionEvent::Listening {
+            addr: "0.0.0.0:9999".parse().unwrap(),
+        };
+        assert_eq!(
+            listening.to_status(),
+            Some(StatusEvent::Listening {
+                port: 9999,
+                address: Some("0.0.0.0:9999".to_string()),
+            })
+        );
+        let accepted = ConnectionEvent::Accepted { addr, alpn: None };
+        assert_eq!(
+            accepted.to_status(),
+            Some(StatusEvent::Connected {
+                remote: "192.168.50.1:50000".to_string(),
+            })
+        );
+        assert_eq!(ConnectionEvent::StreamOpened { addr }.to_status(), None);
+        let closed = ConnectionEvent::Closed {
+            addr,
+            reason: "closed by peer".to_string(),
+        };
+        assert_eq!(
+            closed.to_status(),
+            Some(StatusEvent::Disconnected {
+                remote: "192.168.50.1:50000".to_string(),
+            })
+        );
+        let error = ConnectionEvent::Error {
+            addr: None,
+            msg: "QUIC server error: address in use".to_string(),
+        };
+        assert_eq!(
+            error.to_status(),
+            Some(StatusEvent::Error {
+                message: "QUIC server error: address in use".to_string(),
+            })
+        );
+    }
 }
@@ -0,0 +1,321 @@
+//! Audio playback for `FrameType::Audio` frames
+//!
+//! Incoming PCM is converted to the output device's channel count and sample
+//! rate, then queued in a jitter buffer. Playback only starts once the buffer
+//! holds the configured number of milliseconds, and restarts that priming after
+//! an underrun so bursty delivery doesn't turn into crackling.
+//!
+//! Only the Windows build opens an output device; elsewhere the conversion and
+//! buffering code is still compiled for tests.
+
+#![cfg_attr(not(windows), allow(dead_code))]
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use thunder_shared::protocol::{AudioCodec, AudioHeader};
+use tracing::warn;
+
+/// Audio codecs `AudioPlayer` can decode, most preferred first
+///
+/// Advertised in `HelloAck`. There's no Opus decoder, so Opus isn't
+/// listed and senders that could encode it stick to PCM; a build with one
+/// would list Opus first.
+pub const DECODABLE_CODECS: &[AudioCodec] = &[AudioCodec::Pcm];
+
+/// Queue of interleaved output samples with a priming threshold
+pub struct JitterBuffer {
+    samples: VecDeque<f32>,
+    /// Samples required before playback (re)starts
+    target: usize,
+    /// Samples kept at most; older ones are dropped to bound latency
+    max: usize,
+    primed: bool,
+}
+
+impl JitterBuffer {
+    /// Create a buffer holding `buffer_ms` of audio at the given output format
+    pub fn new(buffer_ms: u32, sample_rate: u32, channels: u16) -> Self {
+        let target = (sample_rate as usize * channels as usize * buffer_ms as usize) / 1000;
+        Self {
+            samples: VecDeque::with_capacity(target * 4),
+            target,
+            // Allow a few buffers of slack before dropping so normal jitter is absorbed.
+            max: (target * 4).max(channels as usize),
+            primed: false,
+        }
+    }
+
+    /// Queue samples, dropping the oldest if the buffer overflows
+    pub fn push(&mut self, samples: &[f32]) {
+        self.samples.extend(samples.iter().copied());
+        if self.samples.len() > self.max {
+            let excess = self.samples.len() - self.target;
+            self.samples.drain(..excess);
+        }
+        if self.samples.len() >= self.target {
+            self.primed = true;
+        }
+    }
+
+    /// Fill `out` with queued samples, or silence while priming/underrun
+    pub fn pop_into(&mut self, out: &mut [f32]) {
+        if !self.primed {
+            out.fill(0.0);
+            return;
+        }
+        for sample in out.iter_mut() {
+            match self.samples.pop_front() {
+                Some(s) => *sample = s,
+                None => {
+                    *sample = 0.0;
+                    self.primed = false;
+                }
+            }
+        }
+    }
+}
+
+/// Decode interleaved s16le PCM into `f32` samples in [-1.0, 1.0)
+pub fn decode_pcm_s16le(data: &[u8]) -> Vec<f32> {
+    data.chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+        .collect()
+}
+
+/// Convert interleaved samples between channel counts and sample rates
+///
+/// Channels are mapped by duplicating mono or dropping/averaging extras, and
+/// rates are converted with linear interpolation, which is plenty for speech
+/// and system sounds on a monitoring link.
+pub fn convert_format(
+    samples: &[f32],
+    in_channels: u16,
+    in_rate: u32,
+    out_channels: u16,
+    out_rate: u32,
+) -> Vec<f32> {
+    let in_ch = in_channels.max(1) as usize;
+    let out_ch = out_channels.max(1) as usize;
+    let in_frames = samples.len() / in_ch;
+    if in_frames == 0 {
+        return Vec::new();
+    }
+
+    // Channel mapping first so resampling works on the output layout.
+    let mut mapped = Vec::with_capacity(in_frames * out_ch);
+    for frame in samples.chunks_exact(in_ch) {
+        for c in 0..out_ch {
+            let sample = if in_ch == 1 {
+                frame[0]
+            } else if out_ch == 1 {
+                frame.iter().sum::<f32>() / in_ch as f32
+            } else {
+                frame[c.min(in_ch - 1)]
+            };
+            mapped.push(sample);
+        }
+    }
+
+    if in_rate == out_rate || in_rate == 0 || out_rate == 0 {
+        return mapped;
+    }
+
+    let out_frames = (in_frames as u64 * out_rate as u64 / in_rate as u64) as usize;
+    let step = in_rate as f64 / out_rate as f64;
+    let mut resampled = Vec::with_capacity(out_frames * out_ch);
+    for i in 0..out_frames {
+        let pos = i as f64 * step;
+        let idx = pos as usize;
+        let frac = (pos - idx as f64) as f32;
+        let next = (idx + 1).min(in_frames - 1);
+        for c in 0..out_ch {
+            let a = mapped[idx * out_ch + c];
+            let b = mapped[next * out_ch + c];
+            resampled.push(a + (b - a) * frac);
+        }
+    }
+    resampled
+}
+
+/// Plays audio frames on the default output device
+pub struct AudioPlayer {
+    buffer: Arc<Mutex<JitterBuffer>>,
+    sample_rate: u32,
+    channels: u16,
+    warned_codec: bool,
+    #[cfg(windows)]
+    _stream: cpal::Stream,
+}
+
+impl AudioPlayer {
+    /// Open the default output device with a jitter buffer of `buffer_ms`
+    #[cfg(windows)]
+    pub fn new(buffer_ms: u32) -> anyhow::Result<Self> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .ok_or_else(|| anyhow::anyhow!("No audio output device available"))?;
+        let supported = device.default_output_config()?;
+        let sample_format = supported.sample_format();
+        let config: cpal::StreamConfig = supported.into();
+
+        let buffer = Arc::new(Mutex::new(JitterBuffer::new(
+            buffer_ms,
+            config.sample_rate.0,
+            config.channels,
+        )));
+
+        let err_fn = |e| warn!("Audio output error: {}", e);
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => {
+                let buffer = buffer.clone();
+                device.build_output_stream(
+                    &config,
+                    move |out: &mut [f32], _| {
+                        if let Ok(mut b) = buffer.lock() {
+                            b.pop_into(out);
+                        }
+                    },
+                    err_fn,
+                    None,
+                )?
+            }
+            cpal::SampleFormat::I16 => {
+                let buffer = buffer.clone();
+                let mut scratch = Vec::new();
+                device.build_output_stream(
+                    &config,
+                    move |out: &mut [i16], _| {
+                        scratch.resize(out.len(), 0.0);
+                        if let Ok(mut b) = buffer.lock() {
+                            b.pop_into(&mut scratch);
+                        }
+                        for (o, s) in out.iter_mut().zip(&scratch) {
+                            *o = (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                        }
+                    },
+                    err_fn,
+                    None,
+                )?
+            }
+            other => anyhow::bail!("Unsupported audio output format: {:?}", other),
+        };
+        stream.play()?;
+
+        tracing::info!(
+            "Audio output opened: {} Hz, {} channels, {} ms jitter buffer",
+            config.sample_rate.0,
+            config.channels,
+            buffer_ms
+        );
+
+        Ok(Self {
+            buffer,
+            sample_rate: config.sample_rate.0,
+            channels: config.channels,
+            warned_codec: false,
+            _stream: stream,
+        })
+    }
+
+    /// Audio playback is only implemented on Windows
+    #[cfg(not(windows))]
+    pub fn new(_buffer_ms: u32) -> anyhow::Result<Self> {
+        anyhow::bail!("Audio playback is only supported on Windows")
+    }
+
+    /// Queue one `FrameType::Audio` payload for playback
+    pub fn push_frame(&mut self, payload: &[u8]) -> anyhow::Result<()> {
+        let mut bytes = Bytes::copy_from_slice(payload);
+        let header = AudioHeader::decode(&mut bytes)?;
+
+        let samples = match header.codec {
+            AudioCodec::Pcm => decode_pcm_s16le(&bytes),
+            AudioCodec::Opus => {
+                if !self.warned_codec {
+                    warn!("Opus audio can't be decoded by this build; dropping audio frames");
+                    self.warned_codec = true;
+                }
+                return Ok(());
+            }
+        };
+
+        let samples = convert_format(
+            &samples,
+            header.channels as u16,
+            header.sample_rate,
+            self.channels,
+            self.sample_rate,
+        );
+
+        if let Ok(mut b) = self.buffer.lock() {
+            b.push(&samples);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jitter_buffer_primes_before_playback() {
+        // 10ms at 1kHz mono = 10 samples
+        let mut jb = JitterBuffer::new(10, 1000, 1);
+        let mut out = [1.0f32; 4];
+
+        jb.push(&[0.5; 5]);
+        jb.pop_into(&mut out);
+        assert_eq!(out, [0.0; 4], "should output silence until primed");
+        assert_eq!(jb.samples.len(), 5);
+
+        jb.push(&[0.5; 5]);
+        jb.pop_into(&mut out);
+        assert_eq!(out, [0.5; 4]);
+        assert_eq!(jb.samples.len(), 6);
+    }
+
+    #[test]
+    fn test_jitter_buffer_reprimes_after_underrun() {
+        let mut jb = JitterBuffer::new(10, 1000, 1);
+        jb.push(&[0.25; 10]);
+
+        let mut out = [1.0f32; 12];
+        jb.pop_into(&mut out);
+        assert_eq!(out[9], 0.25);
+        assert_eq!(out[10], 0.0);
+
+        jb.push(&[0.25; 3]);
+        let mut out = [1.0f32; 2];
+        jb.pop_into(&mut out);
+        assert_eq!(out, [0.0; 2], "should wait for the target again");
+    }
+
+    #[test]
+    fn test_jitter_buffer_bounds_latency() {
+        let mut jb = JitterBuffer::new(10, 1000, 1);
+        jb.push(&[0.0; 100]);
+        assert_eq!(jb.samples.len(), 10);
+    }
+
+    #[test]
+    fn test_decode_pcm_s16le() {
+        let samples = decode_pcm_s16le(&[0x00, 0x00, 0x00, 0x40, 0x00, 0x80]);
+        assert_eq!(samples, vec![0.0, 0.5, -1.0]);
+    }
+
+    #[test]
+    fn test_convert_format_mono_to_stereo_and_resample() {
+        let stereo = convert_format(&[0.1, 0.2], 1, 1000, 2, 1000);
+        assert_eq!(stereo, vec![0.1, 0.1, 0.2, 0.2]);
+
+        let upsampled = convert_format(&[0.0, 1.0], 1, 1000, 1, 2000);
+        assert_eq!(upsampled.len(), 4);
+        assert!((upsampled[1] - 0.5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,319 @@
+//! What happens to frames when the display loop falls behind the network
+//!
+//! With `Latest` the network tasks never wait for the display: a video frame
+//! that doesn't fit in the frame channel is dropped, and the display loop
+//! skips any video older than the newest frame it can show on its own (a raw
+//! frame or an H.264 keyframe). Frames that depend on the ones before them,
+//! H.264 deltas and delta tiles, are only dropped together with everything
+//! up to the next independent frame so the picture never shows garbage.
+//! H.264 keyframes are never dropped, since everything up to the next one
+//! depends on them: they wait for room instead. Once deltas are dropped the
+//! sender is asked for a keyframe, so the decoder doesn't wait out the rest
+//! of the GOP. Audio, control and stats frames are never dropped.
+//!
+//! With `Queue` every frame is kept and the network tasks wait for room, as
+//! a plain bounded channel does.
+
+use std::collections::VecDeque;
+
+use clap::ValueEnum;
+use thunder_shared::protocol::ControlMessage;
+use thunder_shared::stats::Stats;
+use tokio::sync::{broadcast, mpsc};
+
+/// How to handle a full frame channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BackpressurePolicy {
+    /// Drop stale video and show the freshest frame
+    Latest,
+    /// Keep every frame and wait for the display loop
+    Queue,
+}
+
+/// Frames that can be skipped when they are stale
+pub trait Coalesce {
+    /// Video frames can be dropped; everything else is always delivered
+    fn is_video(&self) -> bool;
+
+    /// Whether the frame can be shown without the frames before it
+    fn is_independent(&self) -> bool;
+
+    /// Whether the frames after it depend on it, like an H.264 keyframe
+    /// (a raw frame is independent, but nothing refers back to it)
+    fn is_keyframe(&self) -> bool;
+}
+
+/// The network side of the frame channel
+///
+/// Each connection's streams send through their own clone, since skipping
+/// dependent frames after a drop is tracked per sender.
+pub struct FrameSender<T> {
+    tx: mpsc::Sender<T>,
+    policy: BackpressurePolicy,
+    // A video frame was dropped; dependent ones are useless until the next independent one
+    skipping: bool,
+    keyframe_requests: Option<broadcast::Sender<ControlMessage>>,
+    // A keyframe was requested for the current run of dropped frames
+    requested: bool,
+}
+
+impl<T> Clone for FrameSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            policy: self.policy,
+            skipping: false,
+            keyframe_requests: self.keyframe_requests.clone(),
+            requested: false,
+        }
+    }
+}
+
+/// The display loop is gone
+#[derive(Debug)]
+pub struct Closed;
+
+impl<T: Coalesce> FrameSender<T> {
+    pub fn new(tx: mpsc::Sender<T>, policy: BackpressurePolicy) -> Self {
+        Self {
+            tx,
+            policy,
+            skipping: false,
+            keyframe_requests: None,
+            requested: false,
+        }
+    }
+
+    /// Send a `RequestKeyframe` on `control` when dependent frames are
+    /// dropped, once for each run of drops
+    pub fn with_keyframe_requests(mut self, control: broadcast::Sender<ControlMessage>) -> Self {
+        self.keyframe_requests = Some(control);
+        self
+    }
+
+    /// Whether the display loop has stopped receiving
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Hand `frame` to the display loop, dropping it (counted in `stats`)
+    /// if the policy says so
+    pub async fn send(&mut self, frame: T, stats: &Stats) -> Result<(), Closed> {
+        if self.policy == BackpressurePolicy::Queue || !frame.is_video() {
+            return self.tx.send(frame).await.map_err(|_| Closed);
+        }
+
+        if frame.is_keyframe() {
+            self.tx.send(frame).await.map_err(|_| Closed)?;
+            self.resume();
+            return Ok(());
+        }
+        if self.skipping && !frame.is_independent() {
+            stats.record_drop();
+            self.request_keyframe();
+            return Ok(());
+        }
+        match self.tx.try_send(frame) {
+            Ok(()) => {
+                self.resume();
+                Ok(())
+            }
+            Err(mpsc::error::TrySendError::Full(frame)) => {
+                stats.record_drop();
+                self.skipping = true;
+                // The next raw frame replaces a dropped one; a dropped delta
+                // leaves a hole only a keyframe fills.
+                if !frame.is_independent() {
+                    self.request_keyframe();
+                }
+                Ok(())
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(Closed),
+        }
+    }
+
+    /// A frame got through, so dropping starts over
+    fn resume(&mut self) {
+        self.skipping = false;
+        self.requested = false;
+    }
+
+    fn request_keyframe(&mut self) {
+        if std::mem::replace(&mut self.requested, true) {
+            return;
+        }
+        if let Some(control) = &self.keyframe_requests {
+            // No receivers just means no bi stream to send it on yet.
+            let _ = control.send(ControlMessage::RequestKeyframe);
+        }
+    }
+}
+
+/// Drop the video frames in `backlog` that come before its newest
+/// independent video frame
+///
+/// Other frames keep their order. Returns how many frames were dropped.
+pub fn coalesce<T: Coalesce>(backlog: &mut VecDeque<T>) -> usize {
+    let Some(newest) = backlog
+        .iter()
+        .rposition(|frame| frame.is_video() && frame.is_independent())
+    else {
+        return 0;
+    };
+
+    let before = backlog.len();
+    let mut index = 0;
+    backlog.retain(|frame| {
+        let keep = index >= newest || !frame.is_video();
+        index += 1;
+        keep
+    });
+    before - backlog.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 'K' keyframe, 'r' raw (independent) video, 'd' dependent video, 'a' audio
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct TestFrame(char, u32);
+
+    impl Coalesce for TestFrame {
+        fn is_video(&self) -> bool {
+            self.0 != 'a'
+        }
+
+        fn is_independent(&self) -> bool {
+            self.0 == 'K' || self.0 == 'r'
+        }
+
+        fn is_keyframe(&self) -> bool {
+            self.0 == 'K'
+        }
+    }
+
+    fn frames(kinds: &str) -> VecDeque<TestFrame> {
+        kinds
+            .chars()
+            .enumerate()
+            .map(|(i, kind)| TestFrame(kind, i as u32))
+            .collect()
+    }
+
+    fn ids(backlog: &VecDeque<TestFrame>) -> Vec<u32> {
+        backlog.iter().map(|frame| frame.1).collect()
+    }
+
+    #[test]
+    fn test_coalesce_keeps_newest_keyframe_and_its_deltas() {
+        let mut backlog = frames("KddaKdd");
+        assert_eq!(coalesce(&mut backlog), 3);
+        assert_eq!(ids(&backlog), [3, 4, 5, 6]);
+
+        let mut raw = frames("KKKK");
+        assert_eq!(coalesce(&mut raw), 3);
+        assert_eq!(ids(&raw), [3]);
+    }
+
+    #[test]
+    fn test_coalesce_without_keyframe_keeps_everything() {
+        let mut backlog = frames("ddad");
+        assert_eq!(coalesce(&mut backlog), 0);
+        assert_eq!(ids(&backlog), [0, 1, 2, 3]);
+
+        let mut empty = frames("");
+        assert_eq!(coalesce(&mut empty), 0);
+    }
+
+    #[tokio::test]
+    async fn test_latest_drops_until_next_independent_frame() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let stats = Stats::new();
+        let mut sender = FrameSender::new(tx, BackpressurePolicy::Latest);
+
+        for frame in [TestFrame('K', 0), TestFrame('d', 1), TestFrame('d', 2)] {
+            sender.send(frame, &stats).await.unwrap();
+        }
+        assert_eq!(stats.snapshot().dropped_frames, 2);
+        assert_eq!(rx.recv().await, Some(TestFrame('K', 0)));
+
+        // Room again, but a delta after a dropped frame can't be shown
+        sender.send(TestFrame('d', 3), &stats).await.unwrap();
+        assert_eq!(stats.snapshot().dropped_frames, 3);
+        sender.send(TestFrame('K', 4), &stats).await.unwrap();
+        assert_eq!(rx.recv().await, Some(TestFrame('K', 4)));
+        sender.send(TestFrame('d', 5), &stats).await.unwrap();
+        assert_eq!(rx.recv().await, Some(TestFrame('d', 5)));
+        assert_eq!(stats.snapshot().dropped_frames, 3);
+
+        drop(rx);
+        assert!(sender.send(TestFrame('K', 6), &stats).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_latest_keeps_keyframes_and_requests_one_per_gap() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let (control, mut requests) = broadcast::channel(4);
+        let mut sender =
+            FrameSender::new(tx, BackpressurePolicy::Latest).with_keyframe_requests(control);
+
+        // Two GOPs while the display loop takes nothing but the first keyframe
+        let sending = tokio::spawn(async move {
+            let stats = Stats::new();
+            for frame in frames("KdddKdd") {
+                sender.send(frame, &stats).await.unwrap();
+            }
+            stats.snapshot().dropped_frames
+        });
+        assert!(matches!(
+            requests.recv().await,
+            Ok(ControlMessage::RequestKeyframe)
+        ));
+        assert_eq!(rx.recv().await, Some(TestFrame('K', 0)));
+
+        // The second keyframe waited for that room; its deltas are dropped
+        assert_eq!(sending.await.unwrap(), 5);
+        assert_eq!(rx.recv().await, Some(TestFrame('K', 4)));
+        assert!(rx.try_recv().is_err());
+        assert!(matches!(
+            requests.recv().await,
+            Ok(ControlMessage::RequestKeyframe)
+        ));
+        assert!(requests.try_recv().is_err(), "one request per run of drops");
+
+        // Raw frames replace each other without a request
+        let (tx, _rx) = mpsc::channel(1);
+        let (control, mut requests) = broadcast::channel(4);
+        let mut sender =
+            FrameSender::new(tx, BackpressurePolicy::Latest).with_keyframe_requests(control);
+        let stats = Stats::new();
+        for frame in frames("rrr") {
+            sender.send(frame, &stats).await.unwrap();
+        }
+        assert_eq!(stats.snapshot().dropped_frames, 2);
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_queue_and_audio_wait_for_room() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let stats = Stats::new();
+        let mut sender = FrameSender::new(tx.clone(), BackpressurePolicy::Latest);
+        sender.send(TestFrame('K', 0), &stats).await.unwrap();
+
+        let mut audio = sender.clone();
+        let waiting = tokio::spawn(async move {
+            let stats = Stats::new();
+            audio.send(TestFrame('a', 1), &stats).await.unwrap();
+        });
+        assert_eq!(rx.recv().await, Some(TestFrame('K', 0)));
+        waiting.await.unwrap();
+        assert_eq!(rx.recv().await, Some(TestFrame('a', 1)));
+
+        let mut queue = FrameSender::new(tx, BackpressurePolicy::Queue);
+        queue.send(TestFrame('d', 2), &stats).await.unwrap();
+        assert_eq!(rx.recv().await, Some(TestFrame('d', 2)));
+        assert_eq!(stats.snapshot().dropped_frames, 0);
+    }
+}
@@ -1,3 +1,14 @@
+use clap::Parser;
+
+/// ThunderMirror receiver window
+#[derive(Parser)]
+struct Args {
+    /// Start the receiver right away, as if Start was clicked
+    #[arg(long)]
+    autostart: bool,
+}
+
 fn main() -> anyhow::Result<()> {
-    thunder_receiver::ui::run()
+    let args = Args::parse();
+    thunder_receiver::ui::run(args.autostart)
 }
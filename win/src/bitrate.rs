@@ -0,0 +1,240 @@
+//! Bitrate the receiver suggests to the sender
+//!
+//! Once a second the display loop feeds its stats to a `BitrateEstimator`,
+//! which cuts the bitrate when the link looks congested and raises it again
+//! step by step once the link has been clean for a few seconds. Congestion
+//! is sequence-gap loss over the last second, or a round-trip time well
+//! above the lowest one seen, which means packets are queueing somewhere.
+//!
+//! Loss between the clean and congested thresholds holds the bitrate, and a
+//! new suggestion is only sent once it moved far enough from the last one,
+//! so the encoder isn't retuned for every small swing. Nothing is sent while
+//! the link stays clean at the maximum: the sender keeps its own setting.
+
+use thunder_shared::stats::StatsSnapshot;
+
+/// Loss over one interval, in percent, above which the bitrate is cut
+const LOSS_CONGESTED_PERCENT: f64 = 2.0;
+
+/// Loss below which an interval counts as clean
+const LOSS_CLEAN_PERCENT: f64 = 0.5;
+
+/// Round-trip time this far above the lowest seen means queueing
+const RTT_QUEUEING_MS: f64 = 30.0;
+
+/// Share of the bitrate kept after a congested interval
+const BACKOFF_FACTOR: f64 = 0.8;
+
+/// Clean intervals in a row before the bitrate goes back up
+const CLEAN_INTERVALS_BEFORE_INCREASE: u32 = 3;
+
+/// Increase per clean interval, as a share of the maximum
+const INCREASE_STEP: f64 = 0.05;
+
+/// Smallest change from the last suggestion worth sending, as a share of it
+const MIN_CHANGE: f64 = 0.1;
+
+/// Turns link stats into bitrate suggestions within a configured range
+#[derive(Debug)]
+pub struct BitrateEstimator {
+    min_kbps: u32,
+    max_kbps: u32,
+    kbps: f64,
+    sent_kbps: u32,
+    clean_intervals: u32,
+    lowest_rtt_ms: Option<f64>,
+    // (expected, missing) video frames at the previous update
+    last_counts: (u64, u64),
+}
+
+impl BitrateEstimator {
+    /// Start at `max_kbps` and suggest nothing below `min_kbps` (which is
+    /// lowered to `max_kbps` if it's higher)
+    pub fn new(min_kbps: u32, max_kbps: u32) -> Self {
+        Self {
+            min_kbps: min_kbps.min(max_kbps),
+            max_kbps,
+            kbps: max_kbps as f64,
+            sent_kbps: max_kbps,
+            clean_intervals: 0,
+            lowest_rtt_ms: None,
+            last_counts: (0, 0),
+        }
+    }
+
+    /// Fold in the stats of the interval since the last call
+    ///
+    /// Returns a bitrate to send the sender when the suggestion changed
+    /// enough since the last one returned.
+    pub fn update(&mut self, snapshot: &StatsSnapshot) -> Option<u32> {
+        let loss = self.interval_loss(snapshot);
+        let queueing = snapshot.rtt_ms.is_some_and(|rtt| {
+            let lowest = self.lowest_rtt_ms.map_or(rtt, |lowest| lowest.min(rtt));
+            self.lowest_rtt_ms = Some(lowest);
+            rtt > lowest + RTT_QUEUEING_MS
+        });
+
+        if loss > LOSS_CONGESTED_PERCENT || queueing {
+            self.clean_intervals = 0;
+            self.kbps = (self.kbps * BACKOFF_FACTOR).max(self.min_kbps as f64);
+        } else if loss < LOSS_CLEAN_PERCENT {
+            self.clean_intervals += 1;
+            if self.clean_intervals >= CLEAN_INTERVALS_BEFORE_INCREASE {
+                let step = self.max_kbps as f64 * INCREASE_STEP;
+                self.kbps = (self.kbps + step).min(self.max_kbps as f64);
+            }
+        } else {
+            self.clean_intervals = 0;
+        }
+
+        self.suggestion()
+    }
+
+    /// Start over at `max_kbps`, forgetting what was learned about the link,
+    /// e.g. when a sender connects or disconnects
+    ///
+    /// The stats keep counting across connections, so the counts from the
+    /// last update stay to measure the next interval against.
+    pub fn reset(&mut self) {
+        *self = Self {
+            last_counts: self.last_counts,
+            ..Self::new(self.min_kbps, self.max_kbps)
+        };
+    }
+
+    /// The bitrate last suggested, `max_kbps` before the first suggestion
+    pub fn suggested_kbps(&self) -> u32 {
+        self.sent_kbps
+    }
+
+    /// Percent of the video frames expected since the last update that are missing
+    fn interval_loss(&mut self, snapshot: &StatsSnapshot) -> f64 {
+        let counts = (snapshot.expected_frames, snapshot.missing_frames);
+        let (last_expected, last_missing) = std::mem::replace(&mut self.last_counts, counts);
+        // Both go down when the stats are reset, and late frames lower the missing count.
+        let expected = counts.0.saturating_sub(last_expected);
+        let missing = counts.1.saturating_sub(last_missing);
+        if expected == 0 {
+            0.0
+        } else {
+            missing as f64 / expected as f64 * 100.0
+        }
+    }
+
+    fn suggestion(&mut self) -> Option<u32> {
+        let kbps = self.kbps.round() as u32;
+        if kbps == self.sent_kbps {
+            return None;
+        }
+        let at_limit = kbps == self.min_kbps || kbps == self.max_kbps;
+        let change = (kbps as f64 - self.sent_kbps as f64).abs();
+        if !at_limit && change < self.sent_kbps as f64 * MIN_CHANGE {
+            return None;
+        }
+        self.sent_kbps = kbps;
+        Some(kbps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds an estimator cumulative frame counts like `Stats` reports them
+    struct Link {
+        estimator: BitrateEstimator,
+        snapshot: StatsSnapshot,
+    }
+
+    impl Link {
+        fn new(min_kbps: u32, max_kbps: u32) -> Self {
+            Self {
+                estimator: BitrateEstimator::new(min_kbps, max_kbps),
+                snapshot: StatsSnapshot::default(),
+            }
+        }
+
+        /// One second with 100 expected frames, `missing` of them lost
+        fn second(&mut self, missing: u64, rtt_ms: Option<f64>) -> Option<u32> {
+            self.snapshot.expected_frames += 100;
+            self.snapshot.missing_frames += missing;
+            self.snapshot.rtt_ms = rtt_ms;
+            self.estimator.update(&self.snapshot)
+        }
+    }
+
+    #[test]
+    fn test_clean_link_sends_nothing() {
+        let mut link = Link::new(1_000, 10_000);
+        for _ in 0..10 {
+            assert_eq!(link.second(0, Some(2.0)), None);
+        }
+        assert_eq!(link.estimator.suggested_kbps(), 10_000);
+    }
+
+    #[test]
+    fn test_loss_backs_off_down_to_min() {
+        let mut link = Link::new(5_000, 10_000);
+        assert_eq!(link.second(5, None), Some(8_000));
+        assert_eq!(link.second(5, None), Some(6_400));
+        assert_eq!(link.second(5, None), Some(5_120));
+        assert_eq!(link.second(5, None), Some(5_000));
+        assert_eq!(link.second(5, None), None);
+        assert_eq!(link.estimator.suggested_kbps(), 5_000);
+    }
+
+    #[test]
+    fn test_recovery_is_gradual_and_damped() {
+        let mut link = Link::new(1_000, 10_000);
+        assert_eq!(link.second(10, None), Some(8_000));
+
+        // Some loss, but not enough to cut: hold
+        assert_eq!(link.second(1, None), None);
+
+        // Three clean seconds before the first step; +5% isn't worth sending yet
+        assert_eq!(link.second(0, None), None);
+        assert_eq!(link.second(0, None), None);
+        assert_eq!(link.second(0, None), None);
+        assert_eq!(link.second(0, None), Some(9_000));
+        assert_eq!(link.second(0, None), None);
+        assert_eq!(link.second(0, None), Some(10_000));
+        assert_eq!(link.second(0, None), None);
+    }
+
+    #[test]
+    fn test_rtt_growth_backs_off() {
+        let mut link = Link::new(1_000, 10_000);
+        assert_eq!(link.second(0, Some(2.0)), None);
+        assert_eq!(link.second(0, Some(20.0)), None);
+        assert_eq!(link.second(0, Some(45.0)), Some(8_000));
+    }
+
+    #[test]
+    fn test_stats_reset_is_not_loss() {
+        let mut link = Link::new(1_000, 10_000);
+        link.second(20, None);
+        link.second(0, None);
+        link.snapshot = StatsSnapshot::default();
+        assert_eq!(link.estimator.update(&link.snapshot), None);
+        assert_eq!(link.second(0, None), None);
+    }
+
+    #[test]
+    fn test_reset_forgets_the_link() {
+        let mut link = Link::new(1_000, 10_000);
+        link.second(0, Some(2.0));
+        assert_eq!(link.second(10, None), Some(8_000));
+        link.estimator.reset();
+        assert_eq!(link.estimator.suggested_kbps(), 10_000);
+
+        // The old loss isn't counted again, and the new link's RTT sets the floor
+        assert_eq!(link.second(0, Some(40.0)), None);
+        assert_eq!(link.second(0, Some(45.0)), None);
+    }
+
+    #[test]
+    fn test_min_above_max_is_lowered() {
+        let mut link = Link::new(20_000, 10_000);
+        assert_eq!(link.second(50, None), None);
+    }
+}
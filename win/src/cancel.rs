@@ -0,0 +1,84 @@
+//! Stopping the tasks of a connection together
+//!
+//! A QUIC connection is served by several tasks (path stats, the peer
+//! watchdog, control writers). When one of them finishes, it usually saw
+//! the connection close, and the others have nothing left to do.
+//! Running each task through the same `CancelToken` stops them all as soon
+//! as the first one is done, even one stuck waiting on the display loop.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Shared flag that tells a group of tasks to stop
+#[derive(Clone)]
+pub struct CancelToken(Arc<watch::Sender<bool>>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self(Arc::new(watch::Sender::new(false)))
+    }
+
+    /// Tell every task using the token to stop
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+
+    /// Resolve once the token is cancelled
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.0.subscribe();
+        // The sender lives as long as `self`, so this only returns once cancelled.
+        let _ = cancelled.wait_for(|&cancelled| cancelled).await;
+    }
+
+    /// Run `task` until it finishes or the token is cancelled
+    pub async fn until_cancelled<F: Future<Output = ()>>(self, task: F) {
+        tokio::select! {
+            biased;
+            () = self.cancelled() => {}
+            () = task => {}
+        }
+    }
+
+    /// Like `until_cancelled`, but also cancel the token once `task` is
+    /// done, or if it panics, so the other tasks stop with it
+    pub async fn run<F: Future<Output = ()>>(self, task: F) {
+        let _guard = CancelOnDrop(self.clone());
+        self.until_cancelled(task).await;
+    }
+}
+
+struct CancelOnDrop(CancelToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_first_task_to_finish_stops_the_others() {
+        let token = CancelToken::new();
+        let forever = tokio::spawn(token.clone().run(std::future::pending()));
+        tokio::task::yield_now().await;
+        assert!(!forever.is_finished());
+
+        token.clone().run(async {}).await;
+        tokio::time::timeout(std::time::Duration::from_secs(1), forever)
+            .await
+            .expect("pending task should stop once cancelled")
+            .unwrap();
+
+        // Already cancelled: a new task doesn't start its work
+        token
+            .clone()
+            .until_cancelled(async { panic!("should not run") })
+            .await;
+        token.run(async { panic!("should not run") }).await;
+    }
+}
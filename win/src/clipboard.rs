@@ -0,0 +1,190 @@
+//! Windows clipboard updates from `ControlMessage::Clipboard`, and copies
+//! of the displayed frame
+//!
+//! Only plain text is applied for now; other MIME types and oversized
+//! payloads are rejected before touching the clipboard. Frames are copied
+//! as a 32-bit DIB that can be pasted into chat and document apps.
+//! Non-Windows builds keep the validation and DIB encoding so they can be
+//! tested, but `set_text` and `set_frame` always fail.
+
+#![cfg_attr(not(windows), allow(dead_code))]
+
+/// Largest clipboard payload we will apply (1 MiB of UTF-8)
+pub const MAX_CLIPBOARD_BYTES: usize = 1024 * 1024;
+
+/// Text carried by a clipboard message, if it is one we apply
+///
+/// Accepts `text/plain` with an optional UTF-8 charset parameter.
+pub fn text_from_message<'a>(mime: &str, data: &'a [u8]) -> anyhow::Result<&'a str> {
+    let mut params = mime.split(';').map(str::trim);
+    let essence = params.next().unwrap_or_default();
+    if !essence.eq_ignore_ascii_case("text/plain") {
+        anyhow::bail!("Unsupported clipboard type: {}", mime);
+    }
+    for param in params {
+        let Some((name, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = value.trim_matches('"');
+        if name.trim().eq_ignore_ascii_case("charset")
+            && !value.eq_ignore_ascii_case("utf-8")
+            && !value.eq_ignore_ascii_case("utf8")
+        {
+            anyhow::bail!("Unsupported clipboard charset: {}", value);
+        }
+    }
+
+    if data.len() > MAX_CLIPBOARD_BYTES {
+        anyhow::bail!(
+            "Clipboard payload too large: {} bytes (max {})",
+            data.len(),
+            MAX_CLIPBOARD_BYTES
+        );
+    }
+    Ok(std::str::from_utf8(data)?)
+}
+
+/// Size of a `BITMAPINFOHEADER`
+const DIB_HEADER_SIZE: usize = 40;
+
+/// Encode a `0RGB` frame buffer as a top-down 32-bit DIB (`CF_DIB` data)
+pub fn frame_to_dib(buffer: &[u32], width: usize, height: usize) -> anyhow::Result<Vec<u8>> {
+    if buffer.len() != width * height {
+        anyhow::bail!(
+            "Buffer holds {} pixels, expected {}x{}",
+            buffer.len(),
+            width,
+            height
+        );
+    }
+    let image_size = u32::try_from(buffer.len() * 4)?;
+    let width = i32::try_from(width)?;
+    // A negative height marks the rows as stored top to bottom
+    let height = -i32::try_from(height)?;
+
+    let mut dib = Vec::with_capacity(DIB_HEADER_SIZE + image_size as usize);
+    dib.extend_from_slice(&(DIB_HEADER_SIZE as u32).to_le_bytes());
+    dib.extend_from_slice(&width.to_le_bytes());
+    dib.extend_from_slice(&height.to_le_bytes());
+    dib.extend_from_slice(&1u16.to_le_bytes()); // planes
+    dib.extend_from_slice(&32u16.to_le_bytes()); // bits per pixel
+    dib.extend_from_slice(&0u32.to_le_bytes()); // BI_RGB, uncompressed
+    dib.extend_from_slice(&image_size.to_le_bytes());
+    // Resolution and palette fields are unused
+    dib.extend_from_slice(&[0; 16]);
+    // 0x00RRGGBB in little-endian is the B, G, R, unused byte order DIBs use
+    for pixel in buffer {
+        dib.extend_from_slice(&pixel.to_le_bytes());
+    }
+    Ok(dib)
+}
+
+/// Replace the clipboard contents with `text`
+#[cfg(windows)]
+pub fn set_text(text: &str) -> anyhow::Result<()> {
+    use windows::Win32::System::Ole::CF_UNICODETEXT;
+
+    let bytes: Vec<u8> = text
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    set_data(CF_UNICODETEXT.0 as u32, &bytes)
+}
+
+/// Replace the clipboard contents with a `width`x`height` frame
+#[cfg(windows)]
+pub fn set_frame(buffer: &[u32], width: usize, height: usize) -> anyhow::Result<()> {
+    use windows::Win32::System::Ole::CF_DIB;
+
+    set_data(CF_DIB.0 as u32, &frame_to_dib(buffer, width, height)?)
+}
+
+/// Replace the clipboard contents with `data` in clipboard `format`
+#[cfg(windows)]
+fn set_data(format: u32, data: &[u8]) -> anyhow::Result<()> {
+    use windows::Win32::Foundation::{GlobalFree, HANDLE, HWND};
+    use windows::Win32::System::DataExchange::{
+        CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData,
+    };
+    use windows::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+
+    unsafe {
+        let mem = GlobalAlloc(GMEM_MOVEABLE, data.len())?;
+        let ptr = GlobalLock(mem) as *mut u8;
+        if ptr.is_null() {
+            let _ = GlobalFree(mem);
+            anyhow::bail!("GlobalLock failed");
+        }
+        std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+        // Reports an "error" once the lock count reaches zero, which is expected.
+        let _ = GlobalUnlock(mem);
+
+        if let Err(e) = OpenClipboard(HWND(0)) {
+            let _ = GlobalFree(mem);
+            return Err(e.into());
+        }
+        let result =
+            EmptyClipboard().and_then(|()| SetClipboardData(format, HANDLE(mem.0 as isize)));
+        let _ = CloseClipboard();
+
+        // On success the clipboard owns the memory.
+        if let Err(e) = result {
+            let _ = GlobalFree(mem);
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn set_text(_text: &str) -> anyhow::Result<()> {
+    anyhow::bail!("Clipboard sync is only supported on Windows")
+}
+
+#[cfg(not(windows))]
+pub fn set_frame(_buffer: &[u32], _width: usize, _height: usize) -> anyhow::Result<()> {
+    anyhow::bail!("Copying frames to the clipboard is only supported on Windows")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepts_plain_text() {
+        assert_eq!(text_from_message("text/plain", b"hello").unwrap(), "hello");
+        assert_eq!(
+            text_from_message("Text/Plain; charset=\"UTF-8\"", "héllo".as_bytes()).unwrap(),
+            "héllo"
+        );
+    }
+
+    #[test]
+    fn test_rejects_other_payloads() {
+        assert!(text_from_message("image/png", b"\x89PNG").is_err());
+        assert!(text_from_message("text/plain; charset=utf-16", b"hi").is_err());
+        assert!(text_from_message("text/plain", &[0xff, 0xfe]).is_err());
+
+        let big = vec![b'a'; MAX_CLIPBOARD_BYTES + 1];
+        assert!(text_from_message("text/plain", &big).is_err());
+    }
+
+    #[test]
+    fn test_frame_to_dib() {
+        let field = |dib: &[u8], at: usize| i32::from_le_bytes(dib[at..at + 4].try_into().unwrap());
+        let dib = frame_to_dib(&[0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0], 2, 2).unwrap();
+        assert_eq!(dib.len(), DIB_HEADER_SIZE + 16);
+        assert_eq!(field(&dib, 0), DIB_HEADER_SIZE as i32);
+        assert_eq!((field(&dib, 4), field(&dib, 8)), (2, -2), "top-down");
+        assert_eq!(&dib[12..16], [1, 0, 32, 0]);
+        assert_eq!(field(&dib, 20), 16);
+        // Red first, stored blue, green, red
+        assert_eq!(
+            &dib[DIB_HEADER_SIZE..DIB_HEADER_SIZE + 8],
+            [0, 0, 255, 0, 0, 255, 0, 0]
+        );
+
+        assert!(frame_to_dib(&[0; 3], 2, 2).is_err());
+    }
+}
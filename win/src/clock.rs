@@ -0,0 +1,63 @@
+//! Sender timestamps that jump backwards
+//!
+//! `timestamp_us` counts from the epoch the sender announced in its
+//! `ClockSync`, so latency only means something while the sender sticks to
+//! that epoch. A sender that restarts without sending a new one, or whose
+//! clock steps back, sends timestamps from behind the ones already seen and
+//! every latency after that is garbage. Frames that are merely late are at
+//! most a little behind, so only a step back of more than `MAX_LATE` counts.
+
+use std::time::Duration;
+
+/// How far behind the newest timestamp a late frame can be
+pub const MAX_LATE: Duration = Duration::from_secs(1);
+
+/// Remembers the newest timestamp since the last clock sync
+#[derive(Debug, Default)]
+pub struct TimestampGuard {
+    newest_us: Option<u64>,
+}
+
+impl TimestampGuard {
+    /// Check the timestamp of the next frame
+    ///
+    /// Returns how far it went back if it's a regression, in which case the
+    /// guard starts over from it.
+    pub fn check(&mut self, timestamp_us: u64) -> Option<Duration> {
+        let newest = self.newest_us.get_or_insert(timestamp_us);
+        let behind = newest.saturating_sub(timestamp_us);
+        if behind > MAX_LATE.as_micros() as u64 {
+            *newest = timestamp_us;
+            return Some(Duration::from_micros(behind));
+        }
+        *newest = (*newest).max(timestamp_us);
+        None
+    }
+
+    /// Forget the timestamps seen so far, e.g. for a new clock sync
+    pub fn reset(&mut self) {
+        self.newest_us = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_large_steps_back_count() {
+        let mut guard = TimestampGuard::default();
+        assert_eq!(guard.check(5_000_000), None);
+        assert_eq!(guard.check(5_016_000), None);
+        assert_eq!(guard.check(5_016_000), None, "repeated timestamp");
+        assert_eq!(guard.check(4_900_000), None, "late frame");
+        assert_eq!(guard.check(5_033_000), None);
+
+        // Sender restarted: timestamps start over near zero
+        assert_eq!(guard.check(16_000), Some(Duration::from_micros(5_017_000)));
+        assert_eq!(guard.check(33_000), None);
+
+        guard.reset();
+        assert_eq!(guard.check(0), None);
+    }
+}
@@ -0,0 +1,1043 @@
+//! YUV to RGB conversion for decoded video frames
+//!
+//! 8-bit conversions use integer fixed-point math (coefficients scaled by
+//! 1024) and write `0x00RRGGBB` pixels, the format minifb expects. 10-bit
+//! images (behind the `hdr` feature) go through floating point so HDR
+//! content can be tone mapped to SDR on the way.
+
+use clap::ValueEnum;
+use thunder_shared::protocol::{ColorRange, ColorSpace, TransferFunction};
+
+/// Fixed-point YUV->RGB coefficients (scaled by 1024)
+#[derive(Debug, Clone, Copy)]
+struct YuvCoefficients {
+    /// Black level subtracted from Y (16 for limited range, 0 for full)
+    y_offset: i32,
+    /// Y scale (255/219 for limited range, 1.0 for full)
+    y_scale: i32,
+    /// R coefficient for V
+    rv: i32,
+    /// G coefficient for U
+    gu: i32,
+    /// G coefficient for V
+    gv: i32,
+    /// B coefficient for U
+    bu: i32,
+}
+
+// BT.709 limited range (VideoToolbox default): Y=[16,235], UV=[16,240]
+// Y scale: 255/219 * 1024 ≈ 1192
+// UV scale: 255/224 ≈ 1.138, so coefficients become:
+// R coeff for V: 1.5748 * 1.138 * 1024 ≈ 1836
+// G coeff for U: 0.1873 * 1.138 * 1024 ≈ 218
+// G coeff for V: 0.4681 * 1.138 * 1024 ≈ 545
+// B coeff for U: 1.8556 * 1.138 * 1024 ≈ 2160
+const BT709_LIMITED: YuvCoefficients = YuvCoefficients {
+    y_offset: 16,
+    y_scale: 1192,
+    rv: 1836,
+    gu: 218,
+    gv: 545,
+    bu: 2160,
+};
+
+// BT.709 full range: R = Y + 1.5748 V, G = Y - 0.1873 U - 0.4681 V, B = Y + 1.8556 U
+const BT709_FULL: YuvCoefficients = YuvCoefficients {
+    y_offset: 0,
+    y_scale: 1024,
+    rv: 1613,
+    gu: 192,
+    gv: 479,
+    bu: 1900,
+};
+
+// BT.601 limited range, same Y/UV expansion as BT.709 limited:
+// R coeff for V: 1.402 * 1.138 * 1024 ≈ 1634
+// G coeff for U: 0.3441 * 1.138 * 1024 ≈ 401
+// G coeff for V: 0.7141 * 1.138 * 1024 ≈ 832
+// B coeff for U: 1.772 * 1.138 * 1024 ≈ 2066
+const BT601_LIMITED: YuvCoefficients = YuvCoefficients {
+    y_offset: 16,
+    y_scale: 1192,
+    rv: 1634,
+    gu: 401,
+    gv: 832,
+    bu: 2066,
+};
+
+// BT.601 full range (JPEG/JFIF): R = Y + 1.402 V, G = Y - 0.3441 U - 0.7141 V, B = Y + 1.772 U
+const BT601_FULL: YuvCoefficients = YuvCoefficients {
+    y_offset: 0,
+    y_scale: 1024,
+    rv: 1436,
+    gu: 352,
+    gv: 731,
+    bu: 1815,
+};
+
+// BT.2020 limited range, same Y/UV expansion as BT.709 limited:
+// R coeff for V: 1.4746 * 1.138 * 1024 ≈ 1719
+// G coeff for U: 0.1646 * 1.138 * 1024 ≈ 192
+// G coeff for V: 0.5714 * 1.138 * 1024 ≈ 666
+// B coeff for U: 1.8814 * 1.138 * 1024 ≈ 2193
+const BT2020_LIMITED: YuvCoefficients = YuvCoefficients {
+    y_offset: 16,
+    y_scale: 1192,
+    rv: 1719,
+    gu: 192,
+    gv: 666,
+    bu: 2193,
+};
+
+// BT.2020 full range: R = Y + 1.4746 V, G = Y - 0.1646 U - 0.5714 V, B = Y + 1.8814 U
+const BT2020_FULL: YuvCoefficients = YuvCoefficients {
+    y_offset: 0,
+    y_scale: 1024,
+    rv: 1510,
+    gu: 169,
+    gv: 585,
+    bu: 1927,
+};
+
+#[inline(always)]
+fn yuv_to_rgb_fixed(c: YuvCoefficients, y: u8, u: u8, v: u8) -> (u8, u8, u8) {
+    let y_i = y as i32 - c.y_offset;
+    let u_i = u as i32 - 128;
+    let v_i = v as i32 - 128;
+
+    let y_scaled = (y_i * c.y_scale) >> 10;
+
+    let r = y_scaled + ((c.rv * v_i) >> 10);
+    let g = y_scaled - ((c.gu * u_i + c.gv * v_i) >> 10);
+    let b = y_scaled + ((c.bu * u_i) >> 10);
+
+    (
+        r.clamp(0, 255) as u8,
+        g.clamp(0, 255) as u8,
+        b.clamp(0, 255) as u8,
+    )
+}
+
+/// BT.601 limited range YUV to full range RGB
+#[inline(always)]
+pub fn yuv_to_rgb_bt601_limited(y: u8, u: u8, v: u8) -> (u8, u8, u8) {
+    yuv_to_rgb_fixed(BT601_LIMITED, y, u, v)
+}
+
+/// BT.601 full range YUV to RGB
+#[inline(always)]
+pub fn yuv_to_rgb_bt601_full(y: u8, u: u8, v: u8) -> (u8, u8, u8) {
+    yuv_to_rgb_fixed(BT601_FULL, y, u, v)
+}
+
+/// BT.709 limited range YUV to full range RGB
+///
+/// VideoToolbox outputs limited range: Y=[16,235], UV=[16,240].
+/// This function expands to full RGB [0,255].
+#[inline(always)]
+pub fn yuv_to_rgb_bt709_limited(y: u8, u: u8, v: u8) -> (u8, u8, u8) {
+    yuv_to_rgb_fixed(BT709_LIMITED, y, u, v)
+}
+
+/// BT.709 full range YUV to RGB
+#[inline(always)]
+pub fn yuv_to_rgb_bt709_full(y: u8, u: u8, v: u8) -> (u8, u8, u8) {
+    yuv_to_rgb_fixed(BT709_FULL, y, u, v)
+}
+
+/// BT.2020 limited range YUV to full range RGB
+///
+/// The primaries are left as they are, so wide-gamut colors look slightly
+/// undersaturated on a BT.709 monitor.
+#[inline(always)]
+pub fn yuv_to_rgb_bt2020_limited(y: u8, u: u8, v: u8) -> (u8, u8, u8) {
+    yuv_to_rgb_fixed(BT2020_LIMITED, y, u, v)
+}
+
+/// BT.2020 full range YUV to RGB
+#[inline(always)]
+pub fn yuv_to_rgb_bt2020_full(y: u8, u: u8, v: u8) -> (u8, u8, u8) {
+    yuv_to_rgb_fixed(BT2020_FULL, y, u, v)
+}
+
+/// Decoded YUV 4:2:0 image in the decoder's pixel format
+#[derive(Clone, Copy)]
+pub enum YuvPlanes<'a> {
+    /// Separate Y, U and V planes (OpenH264)
+    I420(I420Planes<'a>),
+    /// Y plane followed by interleaved UV (hardware decoders, VideoToolbox)
+    // Only the Windows hardware decoder produces it so far
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Nv12(Nv12Planes<'a>),
+    /// 10-bit Y plane followed by interleaved UV (HEVC Main10)
+    #[cfg(feature = "hdr")]
+    // No decoder produces it yet
+    #[allow(dead_code)]
+    P010(P010Planes<'a>),
+}
+
+/// Planar YUV 4:2:0 (I420) image borrowed from a decoder
+#[derive(Clone, Copy)]
+pub struct I420Planes<'a> {
+    pub y: &'a [u8],
+    pub u: &'a [u8],
+    pub v: &'a [u8],
+    pub y_stride: usize,
+    pub u_stride: usize,
+    pub v_stride: usize,
+}
+
+/// Semi-planar YUV 4:2:0 (NV12) image borrowed from a decoder
+#[derive(Clone, Copy)]
+pub struct Nv12Planes<'a> {
+    pub y: &'a [u8],
+    /// Interleaved U/V samples, one pair per 2x2 block
+    pub uv: &'a [u8],
+    pub y_stride: usize,
+    pub uv_stride: usize,
+}
+
+/// 10-bit semi-planar YUV 4:2:0 (P010) image borrowed from a decoder
+///
+/// Each sample sits in the high 10 bits of a `u16`. Strides are in samples,
+/// not bytes.
+#[cfg(feature = "hdr")]
+#[derive(Clone, Copy)]
+pub struct P010Planes<'a> {
+    pub y: &'a [u16],
+    /// Interleaved U/V samples, one pair per 2x2 block
+    pub uv: &'a [u16],
+    pub y_stride: usize,
+    pub uv_stride: usize,
+}
+
+/// Convert a decoded image into `buffer`, whatever its pixel format
+///
+/// Only 10-bit images are tone mapped; 8-bit ones are shown as SDR whatever
+/// `transfer` says.
+#[cfg_attr(not(feature = "hdr"), allow(unused_variables))]
+pub fn yuv_to_rgb(
+    planes: &YuvPlanes,
+    width: usize,
+    height: usize,
+    buffer: &mut [u32],
+    space: ColorSpace,
+    range: ColorRange,
+    transfer: TransferFunction,
+) {
+    match planes {
+        YuvPlanes::I420(planes) => i420_to_rgb(planes, width, height, buffer, space, range),
+        YuvPlanes::Nv12(planes) => nv12_to_rgb(planes, width, height, buffer, space, range),
+        #[cfg(feature = "hdr")]
+        YuvPlanes::P010(planes) => {
+            p010_to_rgb(planes, width, height, buffer, space, range, transfer)
+        }
+    }
+}
+
+/// Convert only the luma of a decoded image into gray pixels in `buffer`
+///
+/// Chroma is never read, so this is cheaper than `yuv_to_rgb` and shows
+/// exactly what the Y plane holds. Limited-range luma is expanded to full
+/// range the same way the color conversions do; 10-bit images are not tone
+/// mapped.
+pub fn yuv_to_gray(
+    planes: &YuvPlanes,
+    width: usize,
+    height: usize,
+    buffer: &mut [u32],
+    range: ColorRange,
+) {
+    let (y_offset, y_scale) = match range {
+        ColorRange::Limited => (BT709_LIMITED.y_offset, BT709_LIMITED.y_scale),
+        ColorRange::Full => (BT709_FULL.y_offset, BT709_FULL.y_scale),
+    };
+    let gray8 = |y: u8| (((y as i32 - y_offset) * y_scale) >> 10).clamp(0, 255) as u8;
+    match planes {
+        YuvPlanes::I420(planes) => {
+            convert_luma(planes.y, planes.y_stride, width, height, buffer, gray8)
+        }
+        YuvPlanes::Nv12(planes) => {
+            convert_luma(planes.y, planes.y_stride, width, height, buffer, gray8)
+        }
+        #[cfg(feature = "hdr")]
+        YuvPlanes::P010(planes) => {
+            // Black level and span of the 10-bit codes
+            let (offset, span) = match range {
+                ColorRange::Limited => (64, 876),
+                ColorRange::Full => (0, 1023),
+            };
+            let gray10 = |y: u16| {
+                let code = (y >> 6) as i32 - offset;
+                ((code * 255 + span / 2) / span).clamp(0, 255) as u8
+            };
+            convert_luma(planes.y, planes.y_stride, width, height, buffer, gray10)
+        }
+    }
+}
+
+/// Which YUV range to convert decoded video with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RangeMode {
+    /// The range in the frame header, or a guess from the picture for v1
+    /// senders whose headers don't carry one
+    Auto,
+    /// Always limited range (Y 16-235)
+    Limited,
+    /// Always full range (Y 0-255)
+    Full,
+}
+
+impl RangeMode {
+    /// The range to use given the header's (`None` if it has no range
+    /// field) and the one `RangeDetector` guessed
+    pub fn resolve(self, declared: Option<ColorRange>, detected: ColorRange) -> ColorRange {
+        match self {
+            RangeMode::Auto => declared.unwrap_or(detected),
+            RangeMode::Limited => ColorRange::Limited,
+            RangeMode::Full => ColorRange::Full,
+        }
+    }
+}
+
+/// Decoded frames `RangeDetector` looks at before its guess is final
+const RANGE_SAMPLE_FRAMES: u32 = 5;
+
+/// Only every `RANGE_SAMPLE_STEP`th row and column is sampled
+const RANGE_SAMPLE_STEP: usize = 4;
+
+/// Guesses the range of video whose header doesn't declare one
+///
+/// Limited-range encoders keep Y within 16-235, so luma outside that in
+/// the first few frames means the sender is encoding full range. A few
+/// stray samples are tolerated since limited-range encoders overshoot a
+/// little around sharp edges.
+#[derive(Debug, Default)]
+pub struct RangeDetector {
+    frames: u32,
+    full: bool,
+}
+
+impl RangeDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check the luma of a decoded frame, until enough frames were seen
+    pub fn sample(&mut self, planes: &YuvPlanes, width: usize, height: usize) {
+        if self.full || self.frames >= RANGE_SAMPLE_FRAMES {
+            return;
+        }
+        let (y_plane, stride) = match planes {
+            YuvPlanes::I420(p) => (p.y, p.y_stride),
+            YuvPlanes::Nv12(p) => (p.y, p.y_stride),
+            // 10-bit streams come with v4 headers, which declare their range.
+            #[cfg(feature = "hdr")]
+            YuvPlanes::P010(_) => return,
+        };
+        self.frames += 1;
+
+        let (mut sampled, mut outside) = (0usize, 0usize);
+        for row in y_plane
+            .chunks(stride.max(1))
+            .take(height)
+            .step_by(RANGE_SAMPLE_STEP)
+        {
+            for &y in row.iter().take(width).step_by(RANGE_SAMPLE_STEP) {
+                sampled += 1;
+                if !(16..=235).contains(&y) {
+                    outside += 1;
+                }
+            }
+        }
+        // More than 1% of the picture can't be overshoot.
+        if outside > 0 && outside * 100 > sampled {
+            self.full = true;
+        }
+    }
+
+    /// Limited range unless full-range luma has been seen
+    pub fn range(&self) -> ColorRange {
+        if self.full {
+            ColorRange::Full
+        } else {
+            ColorRange::Limited
+        }
+    }
+}
+
+/// Convert an I420 image into `buffer` using the conversion for `space`/`range`
+///
+/// `buffer` is row-major with a stride of `width` pixels; pixels that don't
+/// fit are skipped.
+pub fn i420_to_rgb(
+    planes: &I420Planes,
+    width: usize,
+    height: usize,
+    buffer: &mut [u32],
+    space: ColorSpace,
+    range: ColorRange,
+) {
+    // Dispatch once per frame so the per-pixel conversion is inlined.
+    match (space, range) {
+        (ColorSpace::Bt601, ColorRange::Limited) => {
+            convert_i420(planes, width, height, buffer, yuv_to_rgb_bt601_limited)
+        }
+        (ColorSpace::Bt601, ColorRange::Full) => {
+            convert_i420(planes, width, height, buffer, yuv_to_rgb_bt601_full)
+        }
+        (ColorSpace::Bt709, ColorRange::Limited) => {
+            convert_i420(planes, width, height, buffer, yuv_to_rgb_bt709_limited)
+        }
+        (ColorSpace::Bt709, ColorRange::Full) => {
+            convert_i420(planes, width, height, buffer, yuv_to_rgb_bt709_full)
+        }
+        (ColorSpace::Bt2020, ColorRange::Limited) => {
+            convert_i420(planes, width, height, buffer, yuv_to_rgb_bt2020_limited)
+        }
+        (ColorSpace::Bt2020, ColorRange::Full) => {
+            convert_i420(planes, width, height, buffer, yuv_to_rgb_bt2020_full)
+        }
+    }
+}
+
+/// Convert an NV12 image into `buffer` using the conversion for `space`/`range`
+pub fn nv12_to_rgb(
+    planes: &Nv12Planes,
+    width: usize,
+    height: usize,
+    buffer: &mut [u32],
+    space: ColorSpace,
+    range: ColorRange,
+) {
+    match (space, range) {
+        (ColorSpace::Bt601, ColorRange::Limited) => {
+            convert_nv12(planes, width, height, buffer, yuv_to_rgb_bt601_limited)
+        }
+        (ColorSpace::Bt601, ColorRange::Full) => {
+            convert_nv12(planes, width, height, buffer, yuv_to_rgb_bt601_full)
+        }
+        (ColorSpace::Bt709, ColorRange::Limited) => {
+            nv12_to_rgb_bt709_limited(planes, width, height, buffer)
+        }
+        (ColorSpace::Bt709, ColorRange::Full) => {
+            convert_nv12(planes, width, height, buffer, yuv_to_rgb_bt709_full)
+        }
+        (ColorSpace::Bt2020, ColorRange::Limited) => {
+            convert_nv12(planes, width, height, buffer, yuv_to_rgb_bt2020_limited)
+        }
+        (ColorSpace::Bt2020, ColorRange::Full) => {
+            convert_nv12(planes, width, height, buffer, yuv_to_rgb_bt2020_full)
+        }
+    }
+}
+
+/// Convert a BT.709 limited range NV12 image (the common hardware decoder output)
+pub fn nv12_to_rgb_bt709_limited(
+    planes: &Nv12Planes,
+    width: usize,
+    height: usize,
+    buffer: &mut [u32],
+) {
+    convert_nv12(planes, width, height, buffer, yuv_to_rgb_bt709_limited)
+}
+
+/// Convert a P010 image into `buffer`, tone mapping HDR content to SDR
+#[cfg(feature = "hdr")]
+pub fn p010_to_rgb(
+    planes: &P010Planes,
+    width: usize,
+    height: usize,
+    buffer: &mut [u32],
+    space: ColorSpace,
+    range: ColorRange,
+    transfer: TransferFunction,
+) {
+    let Some(bounds) = PlaneBounds::new(
+        width,
+        height,
+        (planes.y.len(), planes.y_stride),
+        &[(planes.uv.len(), planes.uv_stride, 2)],
+    ) else {
+        return;
+    };
+
+    let (kr, kb) = match space {
+        ColorSpace::Bt601 => (0.299, 0.114),
+        ColorSpace::Bt709 => (0.2126, 0.0722),
+        ColorSpace::Bt2020 => (0.2627, 0.0593),
+    };
+    let kg = 1.0 - kr - kb;
+    // Black level and span of the 10-bit codes
+    let (y_offset, y_span, uv_span) = match range {
+        ColorRange::Limited => (64.0, 876.0, 896.0),
+        ColorRange::Full => (0.0, 1023.0, 1023.0),
+    };
+    let sample = |value: u16| (value >> 6) as f32;
+
+    for row in 0..bounds.height {
+        let uv_row = (row / 2).min(bounds.last_uv_row);
+        for col in 0..bounds.width {
+            let y_idx = row * planes.y_stride + col;
+            let uv_idx = uv_row * planes.uv_stride + (col / 2).min(bounds.last_uv_col) * 2;
+
+            let y = (sample(planes.y[y_idx]) - y_offset) / y_span;
+            let u = (sample(planes.uv[uv_idx]) - 512.0) / uv_span;
+            let v = (sample(planes.uv[uv_idx + 1]) - 512.0) / uv_span;
+            let r = y + 2.0 * (1.0 - kr) * v;
+            let b = y + 2.0 * (1.0 - kb) * u;
+            let g = (y - kr * r - kb * b) / kg;
+
+            let rgb = [r, g, b].map(|c| c.clamp(0.0, 1.0));
+            let (r, g, b) = crate::tonemap::tone_map(rgb, space, transfer);
+
+            let pixel_idx = row * width + col;
+            if pixel_idx < buffer.len() {
+                buffer[pixel_idx] = ((r as u32) << 16) | ((g as u32) << 8) | (b as u32);
+            }
+        }
+    }
+}
+
+/// Number of rows of `row_len` samples that fit in a plane of `len` bytes
+///
+/// Decoders may hand over planes whose last row is shorter than the
+/// stride, or that stop a row early when the height is odd.
+fn plane_rows(len: usize, stride: usize, row_len: usize) -> usize {
+    if row_len == 0 || len < row_len {
+        0
+    } else {
+        // A zero stride only ever addresses the first row
+        (len - row_len)
+            .checked_div(stride)
+            .map_or(1, |rows| rows + 1)
+    }
+}
+
+/// Visible area of a 4:2:0 image that can be converted without reading past
+/// its planes, plus the last chroma row/column to clamp to
+struct PlaneBounds {
+    width: usize,
+    height: usize,
+    last_uv_row: usize,
+    last_uv_col: usize,
+}
+
+impl PlaneBounds {
+    /// `chroma` holds a `(len, stride, bytes per sample)` entry per chroma plane
+    fn new(
+        width: usize,
+        height: usize,
+        y: (usize, usize),
+        chroma: &[(usize, usize, usize)],
+    ) -> Option<Self> {
+        let width = width.min(y.1);
+        let height = height.min(plane_rows(y.0, y.1, width));
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        // Odd sizes still need a chroma sample for the last row/column.
+        let mut uv_cols = width.div_ceil(2);
+        let mut uv_rows = height.div_ceil(2);
+        for &(len, stride, sample) in chroma {
+            uv_cols = uv_cols.min(stride / sample);
+            uv_rows = uv_rows.min(plane_rows(len, stride, uv_cols * sample));
+        }
+        if uv_cols == 0 || uv_rows == 0 {
+            return None;
+        }
+
+        Some(Self {
+            width,
+            height,
+            last_uv_row: uv_rows - 1,
+            last_uv_col: uv_cols - 1,
+        })
+    }
+}
+
+#[inline(always)]
+fn convert_luma<T: Copy, F>(
+    y: &[T],
+    stride: usize,
+    width: usize,
+    height: usize,
+    buffer: &mut [u32],
+    gray: F,
+) where
+    F: Fn(T) -> u8,
+{
+    let visible = width.min(stride);
+    let rows = height.min(plane_rows(y.len(), stride, visible));
+
+    for row in 0..rows {
+        for col in 0..visible {
+            let value = gray(y[row * stride + col]) as u32;
+
+            let pixel_idx = row * width + col;
+            if pixel_idx < buffer.len() {
+                buffer[pixel_idx] = (value << 16) | (value << 8) | value;
+            }
+        }
+    }
+}
+
+#[inline(always)]
+fn convert_nv12<F>(planes: &Nv12Planes, width: usize, height: usize, buffer: &mut [u32], convert: F)
+where
+    F: Fn(u8, u8, u8) -> (u8, u8, u8),
+{
+    let Some(bounds) = PlaneBounds::new(
+        width,
+        height,
+        (planes.y.len(), planes.y_stride),
+        &[(planes.uv.len(), planes.uv_stride, 2)],
+    ) else {
+        return;
+    };
+
+    for row in 0..bounds.height {
+        let uv_row = (row / 2).min(bounds.last_uv_row);
+        for col in 0..bounds.width {
+            let y_idx = row * planes.y_stride + col;
+            // One U/V pair per 2x2 block, stored U first
+            let uv_idx = uv_row * planes.uv_stride + (col / 2).min(bounds.last_uv_col) * 2;
+
+            let (r, g, b) = convert(planes.y[y_idx], planes.uv[uv_idx], planes.uv[uv_idx + 1]);
+
+            let pixel_idx = row * width + col;
+            if pixel_idx < buffer.len() {
+                buffer[pixel_idx] = ((r as u32) << 16) | ((g as u32) << 8) | (b as u32);
+            }
+        }
+    }
+}
+
+#[inline(always)]
+fn convert_i420<F>(planes: &I420Planes, width: usize, height: usize, buffer: &mut [u32], convert: F)
+where
+    F: Fn(u8, u8, u8) -> (u8, u8, u8),
+{
+    let Some(bounds) = PlaneBounds::new(
+        width,
+        height,
+        (planes.y.len(), planes.y_stride),
+        &[
+            (planes.u.len(), planes.u_stride, 1),
+            (planes.v.len(), planes.v_stride, 1),
+        ],
+    ) else {
+        return;
+    };
+
+    for row in 0..bounds.height {
+        // U and V are subsampled 2x2 (YUV 4:2:0)
+        let uv_row = (row / 2).min(bounds.last_uv_row);
+        for col in 0..bounds.width {
+            let y_idx = row * planes.y_stride + col;
+            let uv_col = (col / 2).min(bounds.last_uv_col);
+            let u_idx = uv_row * planes.u_stride + uv_col;
+            let v_idx = uv_row * planes.v_stride + uv_col;
+
+            let (r, g, b) = convert(planes.y[y_idx], planes.u[u_idx], planes.v[v_idx]);
+
+            let pixel_idx = row * width + col;
+            if pixel_idx < buffer.len() {
+                buffer[pixel_idx] = ((r as u32) << 16) | ((g as u32) << 8) | (b as u32);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An I420 image whose Y plane is `luma` (chroma unused)
+    fn gray_planes<'a>(luma: &'a [u8], chroma: &'a [u8], width: usize) -> YuvPlanes<'a> {
+        YuvPlanes::I420(I420Planes {
+            y: luma,
+            u: chroma,
+            v: chroma,
+            y_stride: width,
+            u_stride: width / 2,
+            v_stride: width / 2,
+        })
+    }
+
+    #[test]
+    fn test_range_detector() {
+        let chroma = [128; 8 * 8];
+        let limited = [16u8; 16 * 16];
+        let mut full = limited;
+        full[..16 * 4].fill(0);
+
+        let mut detector = RangeDetector::new();
+        detector.sample(&gray_planes(&limited, &chroma, 16), 16, 16);
+        assert_eq!(detector.range(), ColorRange::Limited);
+        detector.sample(&gray_planes(&full, &chroma, 16), 16, 16);
+        assert_eq!(detector.range(), ColorRange::Full);
+
+        // One overshooting sample isn't enough
+        let mut ringing = [128u8; 64 * 64];
+        ringing[0] = 240;
+        let chroma = [128; 32 * 32];
+        let mut detector = RangeDetector::new();
+        detector.sample(&gray_planes(&ringing, &chroma, 64), 64, 64);
+        assert_eq!(detector.range(), ColorRange::Limited);
+
+        // The guess is final after a few frames
+        for _ in 0..RANGE_SAMPLE_FRAMES {
+            detector.sample(&gray_planes(&ringing, &chroma, 64), 64, 64);
+        }
+        let mut dark = [0u8; 64 * 64];
+        dark[0] = 16;
+        detector.sample(&gray_planes(&dark, &chroma, 64), 64, 64);
+        assert_eq!(detector.range(), ColorRange::Limited);
+    }
+
+    #[test]
+    fn test_range_mode_resolve() {
+        let header = Some(ColorRange::Full);
+        assert_eq!(
+            RangeMode::Auto.resolve(header, ColorRange::Limited),
+            ColorRange::Full
+        );
+        assert_eq!(
+            RangeMode::Auto.resolve(None, ColorRange::Full),
+            ColorRange::Full
+        );
+        assert_eq!(
+            RangeMode::Limited.resolve(header, ColorRange::Full),
+            ColorRange::Limited
+        );
+        assert_eq!(
+            RangeMode::Full.resolve(None, ColorRange::Limited),
+            ColorRange::Full
+        );
+    }
+
+    #[test]
+    fn test_limited_range_black_and_white() {
+        assert_eq!(yuv_to_rgb_bt709_limited(16, 128, 128), (0, 0, 0));
+        assert_eq!(yuv_to_rgb_bt601_limited(16, 128, 128), (0, 0, 0));
+
+        // Fixed-point truncation lands nominal white one code below 255
+        let (r, g, b) = yuv_to_rgb_bt709_limited(235, 128, 128);
+        assert!(r >= 254 && g >= 254 && b >= 254);
+        let (r, g, b) = yuv_to_rgb_bt601_limited(235, 128, 128);
+        assert!(r >= 254 && g >= 254 && b >= 254);
+    }
+
+    #[test]
+    fn test_bt2020_black_white_and_red() {
+        assert_eq!(yuv_to_rgb_bt2020_limited(16, 128, 128), (0, 0, 0));
+        let (r, g, b) = yuv_to_rgb_bt2020_limited(235, 128, 128);
+        assert!(r >= 254 && g >= 254 && b >= 254);
+        assert_eq!(yuv_to_rgb_bt2020_full(200, 128, 128), (200, 200, 200));
+
+        // Pure red encoded with BT.2020 full range: Y=67, U=92, V=255
+        let (r, g, b) = yuv_to_rgb_bt2020_full(67, 92, 255);
+        assert!(r >= 250 && g <= 5 && b <= 5, "got ({}, {}, {})", r, g, b);
+    }
+
+    #[test]
+    fn test_full_range_passes_luma_through() {
+        assert_eq!(yuv_to_rgb_bt709_full(0, 128, 128), (0, 0, 0));
+        assert_eq!(yuv_to_rgb_bt709_full(200, 128, 128), (200, 200, 200));
+        assert_eq!(yuv_to_rgb_bt601_full(255, 128, 128), (255, 255, 255));
+    }
+
+    #[test]
+    fn test_matrices_differ_for_saturated_red() {
+        // Pure red encoded with BT.601 full range: Y=76, U=85, V=255
+        let (r, g, b) = yuv_to_rgb_bt601_full(76, 85, 255);
+        assert!(r >= 250 && g <= 5 && b <= 5, "got ({}, {}, {})", r, g, b);
+
+        // Decoding the same samples as BT.709 leaks a visible amount of green
+        let (_, g709, _) = yuv_to_rgb_bt709_full(76, 85, 255);
+        assert!(g709 > g + 10, "got g709={} vs g601={}", g709, g);
+    }
+
+    #[test]
+    fn test_gray_uses_luma_only() {
+        let y = [16u8, 235, 126, 0];
+        // Chroma that would tint the picture if it were read
+        let chroma = [255u8; 2];
+        let planes = gray_planes(&y, &chroma, 2);
+        let mut buffer = [0u32; 4];
+        yuv_to_gray(&planes, 2, 2, &mut buffer, ColorRange::Limited);
+        assert_eq!(buffer[0], 0);
+        // Same level as the color conversion gives white with neutral chroma
+        let (white, _, _) = yuv_to_rgb_bt709_limited(235, 128, 128);
+        assert_eq!(buffer[1], white as u32 * 0x0001_0101);
+        let (r, g, b) = (buffer[2] >> 16, (buffer[2] >> 8) & 0xFF, buffer[2] & 0xFF);
+        assert!(
+            r == g && g == b && (126..=130).contains(&r),
+            "got {:06x}",
+            buffer[2]
+        );
+        assert_eq!(buffer[3], 0, "below black clamps");
+
+        // Full range passes luma through, and NV12 only differs in chroma layout
+        let nv12 = YuvPlanes::Nv12(Nv12Planes {
+            y: &y,
+            uv: &chroma,
+            y_stride: 2,
+            uv_stride: 2,
+        });
+        yuv_to_gray(&nv12, 2, 2, &mut buffer, ColorRange::Full);
+        assert_eq!(buffer, [0x0010_1010, 0x00EB_EBEB, 0x007E_7E7E, 0]);
+
+        // A short Y plane only fills the rows it has
+        let mut buffer = [7u32; 4];
+        yuv_to_gray(
+            &gray_planes(&y[..3], &chroma, 2),
+            2,
+            2,
+            &mut buffer,
+            ColorRange::Full,
+        );
+        assert_eq!(buffer, [0x0010_1010, 0x00EB_EBEB, 7, 7]);
+    }
+
+    #[test]
+    fn test_i420_dispatch_uses_range() {
+        let y = [16u8; 4];
+        let u = [128u8; 1];
+        let v = [128u8; 1];
+        let planes = I420Planes {
+            y: &y,
+            u: &u,
+            v: &v,
+            y_stride: 2,
+            u_stride: 1,
+            v_stride: 1,
+        };
+        let mut buffer = vec![0u32; 4];
+
+        i420_to_rgb(
+            &planes,
+            2,
+            2,
+            &mut buffer,
+            ColorSpace::Bt709,
+            ColorRange::Limited,
+        );
+        assert!(buffer.iter().all(|&p| p == 0));
+
+        i420_to_rgb(
+            &planes,
+            2,
+            2,
+            &mut buffer,
+            ColorSpace::Bt709,
+            ColorRange::Full,
+        );
+        assert!(buffer.iter().all(|&p| p == 0x101010));
+    }
+
+    #[test]
+    fn test_nv12_known_block() {
+        // 4x2 image: left 2x2 block is BT.709 limited red, right block is white
+        let y = [63u8, 63, 235, 235, 63, 63, 235, 235];
+        let uv = [102u8, 240, 128, 128];
+        let planes = Nv12Planes {
+            y: &y,
+            uv: &uv,
+            y_stride: 4,
+            uv_stride: 4,
+        };
+        let mut buffer = vec![0u32; 8];
+        nv12_to_rgb_bt709_limited(&planes, 4, 2, &mut buffer);
+
+        for &idx in &[0, 1, 4, 5] {
+            let (r, g, b) = (
+                buffer[idx] >> 16,
+                (buffer[idx] >> 8) & 0xff,
+                buffer[idx] & 0xff,
+            );
+            assert!(
+                r >= 250 && g <= 5 && b <= 5,
+                "pixel {}: ({}, {}, {})",
+                idx,
+                r,
+                g,
+                b
+            );
+        }
+        for &idx in &[2, 3, 6, 7] {
+            assert!(
+                buffer[idx] >= 0xfefefe,
+                "pixel {}: {:06x}",
+                idx,
+                buffer[idx]
+            );
+        }
+    }
+
+    #[test]
+    fn test_nv12_matches_i420() {
+        let y: Vec<u8> = (0..16).map(|i| 16 + i * 13).collect();
+        let u = [90u8, 160, 128, 40];
+        let v = [200u8, 60, 128, 220];
+        let uv: Vec<u8> = u.iter().zip(&v).flat_map(|(&u, &v)| [u, v]).collect();
+
+        let i420 = YuvPlanes::I420(I420Planes {
+            y: &y,
+            u: &u,
+            v: &v,
+            y_stride: 4,
+            u_stride: 2,
+            v_stride: 2,
+        });
+        let nv12 = YuvPlanes::Nv12(Nv12Planes {
+            y: &y,
+            uv: &uv,
+            y_stride: 4,
+            uv_stride: 4,
+        });
+
+        let mut expected = vec![0u32; 16];
+        let mut actual = vec![0u32; 16];
+        yuv_to_rgb(
+            &i420,
+            4,
+            4,
+            &mut expected,
+            ColorSpace::Bt601,
+            ColorRange::Full,
+            TransferFunction::Sdr,
+        );
+        yuv_to_rgb(
+            &nv12,
+            4,
+            4,
+            &mut actual,
+            ColorSpace::Bt601,
+            ColorRange::Full,
+            TransferFunction::Sdr,
+        );
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_i420_odd_size_with_truncated_chroma() {
+        // 1921x1081 with tight strides; like OpenH264, the chroma planes stop
+        // at height / 2 rows, one short of what the last luma row needs.
+        let (width, height) = (1921, 1081);
+        let uv_stride = 961;
+        let y = vec![128u8; width * height];
+        let mut u = vec![128u8; uv_stride * (height / 2)];
+        for row in u.chunks_mut(uv_stride) {
+            row[uv_stride - 1] = 240;
+        }
+        let v = vec![128u8; u.len()];
+        let planes = I420Planes {
+            y: &y,
+            u: &u,
+            v: &v,
+            y_stride: width,
+            u_stride: uv_stride,
+            v_stride: uv_stride,
+        };
+        let mut buffer = vec![0u32; width * height];
+        i420_to_rgb(
+            &planes,
+            width,
+            height,
+            &mut buffer,
+            ColorSpace::Bt601,
+            ColorRange::Full,
+        );
+
+        let pixel = |col: usize, row: usize| buffer[row * width + col];
+        // The odd last column has its own chroma sample; its neighbour doesn't.
+        assert_eq!(pixel(width - 2, 0), 0x808080);
+        assert!(
+            pixel(width - 1, 0) & 0xff > 0xf0,
+            "{:06x}",
+            pixel(width - 1, 0)
+        );
+        // The last row reuses the last chroma row that exists.
+        assert_eq!(pixel(width - 1, height - 1), pixel(width - 1, height - 2));
+        assert_eq!(pixel(0, height - 1), 0x808080);
+    }
+
+    #[test]
+    fn test_nv12_odd_size_and_short_planes() {
+        // 5x3: three chroma columns and two chroma rows
+        let y = [100u8; 15];
+        let uv = [128u8, 128, 128, 128, 200, 60, 128, 128, 128, 128, 200, 60];
+        let planes = Nv12Planes {
+            y: &y,
+            uv: &uv,
+            y_stride: 5,
+            uv_stride: 6,
+        };
+        let mut buffer = vec![0u32; 15];
+        nv12_to_rgb(
+            &planes,
+            5,
+            3,
+            &mut buffer,
+            ColorSpace::Bt709,
+            ColorRange::Full,
+        );
+        assert_eq!(buffer[3], 0x646464);
+        assert_ne!(buffer[4], 0x646464);
+        assert_eq!(buffer[14], buffer[4]);
+
+        // Planes too small for the claimed size convert what they hold.
+        let short = Nv12Planes {
+            y: &y[..12],
+            uv: &uv[..6],
+            ..planes
+        };
+        let mut short_buffer = vec![0u32; 15];
+        nv12_to_rgb(
+            &short,
+            5,
+            3,
+            &mut short_buffer,
+            ColorSpace::Bt709,
+            ColorRange::Full,
+        );
+        assert_eq!(short_buffer[..10], buffer[..10]);
+        assert_eq!(short_buffer[10..], [0; 5]);
+    }
+
+    #[cfg(feature = "hdr")]
+    #[test]
+    fn test_p010_tone_maps_pq() {
+        // 2x1 image, BT.2020 limited range grey: left is black, right is the
+        // PQ code for 1000 nits
+        let code = |value: u16| value << 6;
+        let black = code(64);
+        let bright = code(64 + (0.7518 * 876.0) as u16);
+        let y = [black, bright];
+        let uv = [code(512), code(512)];
+        let planes = P010Planes {
+            y: &y,
+            uv: &uv,
+            y_stride: 2,
+            uv_stride: 2,
+        };
+        let mut buffer = vec![0u32; 2];
+        p010_to_rgb(
+            &planes,
+            2,
+            1,
+            &mut buffer,
+            ColorSpace::Bt2020,
+            ColorRange::Limited,
+            TransferFunction::Pq,
+        );
+
+        assert_eq!(buffer[0], 0);
+        // Mastering peak rolls off to just below white instead of clipping early
+        let (r, g, b) = (buffer[1] >> 16, (buffer[1] >> 8) & 0xff, buffer[1] & 0xff);
+        assert!(r >= 245 && r == g && g == b, "got {:06x}", buffer[1]);
+    }
+}
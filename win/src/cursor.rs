@@ -0,0 +1,174 @@
+//! Mouse pointer drawn over the video from `FrameType::Cursor` updates
+//!
+//! Senders that capture without the hardware cursor send its image and
+//! position separately. Like the status overlay, the pointer is blended into
+//! the frame buffer right before it is shown and taken out right after, so
+//! an update that only moves it just redraws the current frame.
+
+use thunder_shared::protocol::CursorUpdate;
+
+use crate::overlay::Patch;
+
+/// Cursor image as 0xAARRGGBB pixels
+struct Image {
+    width: usize,
+    height: usize,
+    hotspot_x: usize,
+    hotspot_y: usize,
+    pixels: Vec<u32>,
+}
+
+/// The sender's pointer as last reported
+#[derive(Default)]
+pub struct Cursor {
+    image: Option<Image>,
+    x: i32,
+    y: i32,
+    hidden: bool,
+}
+
+impl Cursor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the pointer, replacing its image if the update carries one
+    pub fn update(&mut self, update: &CursorUpdate) {
+        self.x = update.x.into();
+        self.y = update.y.into();
+        if update.has_image() {
+            self.image = Some(Image {
+                width: update.width.into(),
+                height: update.height.into(),
+                hotspot_x: update.hotspot_x.into(),
+                hotspot_y: update.hotspot_y.into(),
+                pixels: update
+                    .data
+                    .chunks_exact(4)
+                    .map(|p| u32::from_be_bytes([p[3], p[0], p[1], p[2]]))
+                    .collect(),
+            });
+        }
+    }
+
+    /// Stop (or resume) drawing the pointer, as the sender asked
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+
+    /// Blend the pointer onto `buffer` (`width` x `height` pixels)
+    ///
+    /// The image is clipped to the frame. Returns `None` if nothing was
+    /// drawn: no image yet, hidden, or entirely outside the frame.
+    pub fn draw(&self, buffer: &mut [u32], width: usize, height: usize) -> Option<Patch> {
+        let image = self.image.as_ref().filter(|_| !self.hidden)?;
+        if buffer.len() < width * height {
+            return None;
+        }
+
+        let left = self.x as i64 - image.hotspot_x as i64;
+        let top = self.y as i64 - image.hotspot_y as i64;
+        let x0 = left.max(0) as usize;
+        let y0 = top.max(0) as usize;
+        let x1 = (left + image.width as i64).clamp(0, width as i64) as usize;
+        let y1 = (top + image.height as i64).clamp(0, height as i64) as usize;
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+
+        let patch = Patch::save(buffer, width, x0, y0, x1 - x0, y1 - y0);
+        for y in y0..y1 {
+            let image_row = (y as i64 - top) as usize * image.width;
+            for x in x0..x1 {
+                let src = image.pixels[image_row + (x as i64 - left) as usize];
+                let dst = &mut buffer[y * width + x];
+                *dst = blend(src, *dst);
+            }
+        }
+        Some(patch)
+    }
+}
+
+/// Blend an 0xAARRGGBB pixel over an 0x00RRGGBB one
+fn blend(src: u32, dst: u32) -> u32 {
+    let alpha = src >> 24;
+    let channel = |shift: u32| {
+        let s = (src >> shift) & 0xFF;
+        let d = (dst >> shift) & 0xFF;
+        ((s * alpha + d * (255 - alpha)) / 255) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    /// A `size` x `size` opaque white cursor with its hotspot in the middle
+    fn white_square(x: i16, y: i16, size: u16) -> CursorUpdate {
+        CursorUpdate {
+            x,
+            y,
+            hotspot_x: size / 2,
+            hotspot_y: size / 2,
+            width: size,
+            height: size,
+            data: Bytes::from(vec![0xFF; size as usize * size as usize * 4]),
+        }
+    }
+
+    #[test]
+    fn test_draw_and_restore() {
+        let (width, height) = (16, 16);
+        let original: Vec<u32> = (0..width * height).map(|i| i as u32).collect();
+        let mut buffer = original.clone();
+
+        let mut cursor = Cursor::new();
+        assert!(cursor.draw(&mut buffer, width, height).is_none());
+
+        cursor.update(&white_square(8, 8, 4));
+        let patch = cursor.draw(&mut buffer, width, height).unwrap();
+        // The hotspot lands on the position
+        assert_eq!(buffer[6 * width + 6], 0x00FF_FFFF);
+        assert_eq!(buffer[9 * width + 9], 0x00FF_FFFF);
+        assert_eq!(buffer[5 * width + 5], original[5 * width + 5]);
+        assert_eq!(buffer[10 * width + 10], original[10 * width + 10]);
+        patch.restore(&mut buffer, width);
+        assert_eq!(buffer, original);
+
+        // A move without an image keeps the last image
+        cursor.update(&CursorUpdate::moved(2, 2));
+        let patch = cursor.draw(&mut buffer, width, height).unwrap();
+        assert_eq!(buffer[0], 0x00FF_FFFF);
+        patch.restore(&mut buffer, width);
+
+        cursor.set_hidden(true);
+        assert!(cursor.draw(&mut buffer, width, height).is_none());
+        assert_eq!(buffer, original);
+    }
+
+    #[test]
+    fn test_clips_to_frame() {
+        let mut buffer = vec![0; 8 * 8];
+        let mut cursor = Cursor::new();
+
+        cursor.update(&white_square(-1, 7, 4));
+        let patch = cursor.draw(&mut buffer, 8, 8).unwrap();
+        assert_eq!(buffer.iter().filter(|&&p| p != 0).count(), 3);
+        patch.restore(&mut buffer, 8);
+        assert!(buffer.iter().all(|&p| p == 0));
+
+        cursor.update(&CursorUpdate::moved(100, 100));
+        assert!(cursor.draw(&mut buffer, 8, 8).is_none());
+        cursor.update(&CursorUpdate::moved(i16::MIN, i16::MIN));
+        assert!(cursor.draw(&mut buffer, 8, 8).is_none());
+    }
+
+    #[test]
+    fn test_blend() {
+        assert_eq!(blend(0xFF12_3456, 0x00AB_CDEF), 0x0012_3456);
+        assert_eq!(blend(0x0012_3456, 0x00AB_CDEF), 0x00AB_CDEF);
+        assert_eq!(blend(0x80FF_FFFF, 0x0000_0000), 0x0080_8080);
+    }
+}
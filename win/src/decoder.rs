@@ -0,0 +1,547 @@
+//! H.264 decoding with an optional hardware path
+//!
+//! Software decode uses OpenH264 and works everywhere. On Windows the
+//! hardware path drives the Media Foundation H.264 decoder MFT with a D3D11
+//! device manager attached, which makes it decode through DXVA on the GPU.
+//! If the hardware decoder can't be created the receiver falls back to
+//! OpenH264 so a missing driver never stops the stream.
+
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use openh264::decoder::{DecodedYUV, Decoder};
+use openh264::formats::YUVSource;
+use tracing::{info, warn};
+
+use crate::color::{I420Planes, YuvPlanes};
+
+/// Which H.264 decoder to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DecoderKind {
+    /// OpenH264 on the CPU
+    Software,
+    /// Media Foundation with DXVA (Windows only, falls back to software)
+    Hardware,
+}
+
+/// A decoded picture borrowed from the decoder
+pub struct DecodedFrame<'a> {
+    pub width: usize,
+    pub height: usize,
+    source: FrameSource<'a>,
+}
+
+enum FrameSource<'a> {
+    OpenH264(DecodedYUV<'a>),
+    #[cfg(windows)]
+    Planes(YuvPlanes<'a>),
+}
+
+impl DecodedFrame<'_> {
+    /// The picture's Y/U/V planes
+    pub fn planes(&self) -> YuvPlanes<'_> {
+        match &self.source {
+            FrameSource::OpenH264(yuv) => {
+                let (y_stride, u_stride, v_stride) = yuv.strides();
+                YuvPlanes::I420(I420Planes {
+                    y: yuv.y(),
+                    u: yuv.u(),
+                    v: yuv.v(),
+                    y_stride,
+                    u_stride,
+                    v_stride,
+                })
+            }
+            #[cfg(windows)]
+            FrameSource::Planes(planes) => *planes,
+        }
+    }
+}
+
+/// H.264 decoder selected at startup
+pub enum VideoDecoder {
+    Software(Decoder),
+    #[cfg(windows)]
+    Hardware(mf::MfDecoder),
+}
+
+impl VideoDecoder {
+    /// Create the requested decoder, falling back to software if hardware init fails
+    pub fn new(kind: DecoderKind) -> anyhow::Result<Self> {
+        if kind == DecoderKind::Hardware {
+            #[cfg(windows)]
+            match mf::MfDecoder::new() {
+                Ok(decoder) => {
+                    info!("H.264 decoder initialized (Media Foundation, hardware)");
+                    return Ok(Self::Hardware(decoder));
+                }
+                Err(e) => warn!("Hardware decoder unavailable, using software: {}", e),
+            }
+
+            #[cfg(not(windows))]
+            warn!("Hardware decode is only available on Windows, using software");
+        }
+
+        let decoder = Decoder::new()?;
+        info!("H.264 decoder initialized (OpenH264)");
+        Ok(Self::Software(decoder))
+    }
+
+    /// Decode one access unit; `None` means the decoder needs more data
+    pub fn decode(&mut self, data: &[u8]) -> anyhow::Result<Option<DecodedFrame<'_>>> {
+        match self {
+            Self::Software(decoder) => {
+                let Some(yuv) = decoder
+                    .decode(data)
+                    .map_err(|e| anyhow::anyhow!("{:?}", e))?
+                else {
+                    return Ok(None);
+                };
+                let (width, height) = yuv.dimensions();
+                Ok(Some(DecodedFrame {
+                    width,
+                    height,
+                    source: FrameSource::OpenH264(yuv),
+                }))
+            }
+            #[cfg(windows)]
+            Self::Hardware(decoder) => decoder.decode(data),
+        }
+    }
+}
+
+/// Decides when decode errors warrant asking the sender for a keyframe
+///
+/// A single bad access unit is often followed by a good one, so a request is
+/// only made after `threshold` consecutive errors, and at most once per
+/// `min_interval` so a broken stream doesn't flood the sender.
+pub struct KeyframeRequester {
+    threshold: u32,
+    min_interval: Duration,
+    consecutive_errors: u32,
+    last_request: Option<Instant>,
+}
+
+impl KeyframeRequester {
+    pub fn new(threshold: u32, min_interval: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            min_interval,
+            consecutive_errors: 0,
+            last_request: None,
+        }
+    }
+
+    /// A picture decoded cleanly
+    pub fn record_success(&mut self) {
+        self.consecutive_errors = 0;
+    }
+
+    /// A decode failed; returns whether a keyframe should be requested now
+    pub fn record_error(&mut self, now: Instant) -> bool {
+        self.consecutive_errors += 1;
+        if self.consecutive_errors < self.threshold {
+            return false;
+        }
+        if let Some(last) = self.last_request {
+            if now.duration_since(last) < self.min_interval {
+                return false;
+            }
+        }
+        self.consecutive_errors = 0;
+        self.last_request = Some(now);
+        true
+    }
+}
+
+#[cfg(windows)]
+mod mf {
+    //! Media Foundation H.264 decoder MFT with D3D11 acceleration
+
+    use std::mem::ManuallyDrop;
+
+    use windows::core::Interface;
+    use windows::Win32::Foundation::{HMODULE, TRUE};
+    use windows::Win32::Graphics::Direct3D::D3D_DRIVER_TYPE_HARDWARE;
+    use windows::Win32::Graphics::Direct3D11::{
+        D3D11CreateDevice, ID3D11Device, ID3D11Multithread, D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+        D3D11_CREATE_DEVICE_VIDEO_SUPPORT, D3D11_SDK_VERSION,
+    };
+    use windows::Win32::Media::MediaFoundation::*;
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED,
+    };
+
+    use super::{DecodedFrame, FrameSource};
+    use crate::color::{Nv12Planes, YuvPlanes};
+
+    /// Synchronous H.264 MFT decoding to NV12, read back into system memory
+    pub struct MfDecoder {
+        // The COM objects are released in `drop`, before `MFShutdown`.
+        transform: ManuallyDrop<IMFTransform>,
+        // Kept alive for the transform's lifetime
+        manager: ManuallyDrop<IMFDXGIDeviceManager>,
+        device: ManuallyDrop<ID3D11Device>,
+        provides_samples: bool,
+        output_size: u32,
+        width: usize,
+        height: usize,
+        // Top-left corner of the visible area within the coded picture
+        crop: (usize, usize),
+        // Latest NV12 picture as laid out by the decoder
+        nv12: Vec<u8>,
+        pitch: usize,
+        coded_height: usize,
+    }
+
+    impl MfDecoder {
+        pub fn new() -> anyhow::Result<Self> {
+            unsafe {
+                // Already-initialized apartments are fine; the MFT works in either.
+                let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+                MFStartup(MF_VERSION, MFSTARTUP_FULL)?;
+
+                let mut device = None;
+                D3D11CreateDevice(
+                    None,
+                    D3D_DRIVER_TYPE_HARDWARE,
+                    HMODULE::default(),
+                    D3D11_CREATE_DEVICE_VIDEO_SUPPORT | D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+                    None,
+                    D3D11_SDK_VERSION,
+                    Some(&mut device),
+                    None,
+                    None,
+                )?;
+                let device = device
+                    .ok_or_else(|| anyhow::anyhow!("D3D11CreateDevice returned no device"))?;
+                // The MFT uses the device from its own worker threads.
+                let _ = device
+                    .cast::<ID3D11Multithread>()?
+                    .SetMultithreadProtected(TRUE);
+
+                let mut reset_token = 0u32;
+                let mut manager = None;
+                MFCreateDXGIDeviceManager(&mut reset_token, &mut manager)?;
+                let manager = manager.ok_or_else(|| {
+                    anyhow::anyhow!("MFCreateDXGIDeviceManager returned no manager")
+                })?;
+                manager.ResetDevice(&device, reset_token)?;
+
+                let transform: IMFTransform =
+                    CoCreateInstance(&CMSH264DecoderMFT, None, CLSCTX_INPROC_SERVER)?;
+
+                let attributes = transform.GetAttributes()?;
+                if attributes.GetUINT32(&MF_SA_D3D11_AWARE).unwrap_or(0) == 0 {
+                    anyhow::bail!("H.264 decoder MFT is not D3D11 aware");
+                }
+                let _ = attributes.SetUINT32(&MF_LOW_LATENCY, 1);
+                transform.ProcessMessage(MFT_MESSAGE_SET_D3D_MANAGER, manager.as_raw() as usize)?;
+
+                let input_type = MFCreateMediaType()?;
+                input_type.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video)?;
+                input_type.SetGUID(&MF_MT_SUBTYPE, &MFVideoFormat_H264)?;
+                input_type
+                    .SetUINT32(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32)?;
+                transform.SetInputType(0, &input_type, 0)?;
+
+                let mut decoder = Self {
+                    transform: ManuallyDrop::new(transform),
+                    manager: ManuallyDrop::new(manager),
+                    device: ManuallyDrop::new(device),
+                    provides_samples: false,
+                    output_size: 0,
+                    width: 0,
+                    height: 0,
+                    crop: (0, 0),
+                    nv12: Vec::new(),
+                    pitch: 0,
+                    coded_height: 0,
+                };
+                decoder.select_nv12_output()?;
+
+                decoder
+                    .transform
+                    .ProcessMessage(MFT_MESSAGE_NOTIFY_BEGIN_STREAMING, 0)?;
+                decoder
+                    .transform
+                    .ProcessMessage(MFT_MESSAGE_NOTIFY_START_OF_STREAM, 0)?;
+                Ok(decoder)
+            }
+        }
+
+        /// Pick NV12 output and refresh the stream info; needed again after a format change
+        unsafe fn select_nv12_output(&mut self) -> anyhow::Result<()> {
+            let mut index = 0;
+            loop {
+                let output_type = self
+                    .transform
+                    .GetOutputAvailableType(0, index)
+                    .map_err(|e| anyhow::anyhow!("No NV12 output type offered: {}", e))?;
+                if output_type.GetGUID(&MF_MT_SUBTYPE)? == MFVideoFormat_NV12 {
+                    self.transform.SetOutputType(0, &output_type, 0)?;
+                    break;
+                }
+                index += 1;
+            }
+
+            let info = self.transform.GetOutputStreamInfo(0)?;
+            self.provides_samples = info.dwFlags & MFT_OUTPUT_STREAM_PROVIDES_SAMPLES.0 as u32 != 0;
+            self.output_size = info.cbSize;
+            Ok(())
+        }
+
+        pub fn decode(&mut self, data: &[u8]) -> anyhow::Result<Option<DecodedFrame<'_>>> {
+            unsafe {
+                let sample = input_sample(data)?;
+                let mut produced = false;
+                match self.transform.ProcessInput(0, &sample, 0) {
+                    Ok(()) => {}
+                    Err(e) if e.code() == MF_E_NOTACCEPTING => {
+                        // Drain pending output, then the input is accepted. A
+                        // picture drained here is shown even if this input
+                        // doesn't produce one yet.
+                        produced = self.drain_output()?;
+                        self.transform.ProcessInput(0, &sample, 0)?;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+
+                if !self.drain_output()? && !produced {
+                    return Ok(None);
+                }
+            }
+
+            let (width, height) = (self.width, self.height);
+            let (luma, chroma) = self.nv12.split_at(self.pitch * self.coded_height);
+            // Start the planes at the visible area; the converter clamps the rest.
+            let (x, y) = self.crop;
+            let y_start = (y * self.pitch + x).min(luma.len());
+            let uv_start = ((y / 2) * self.pitch + (x & !1)).min(chroma.len());
+            Ok(Some(DecodedFrame {
+                width,
+                height,
+                source: FrameSource::Planes(YuvPlanes::Nv12(Nv12Planes {
+                    y: &luma[y_start..],
+                    uv: &chroma[uv_start..],
+                    y_stride: self.pitch,
+                    uv_stride: self.pitch,
+                })),
+            }))
+        }
+
+        /// Pull every ready picture, keeping the newest; returns whether one was produced
+        unsafe fn drain_output(&mut self) -> anyhow::Result<bool> {
+            let mut produced = false;
+            loop {
+                let mut buffer = MFT_OUTPUT_DATA_BUFFER {
+                    dwStreamID: 0,
+                    pSample: ManuallyDrop::new(if self.provides_samples {
+                        None
+                    } else {
+                        Some(output_sample(self.output_size)?)
+                    }),
+                    dwStatus: 0,
+                    pEvents: ManuallyDrop::new(None),
+                };
+                let mut status = 0u32;
+                let result =
+                    self.transform
+                        .ProcessOutput(0, std::slice::from_mut(&mut buffer), &mut status);
+                let sample = ManuallyDrop::take(&mut buffer.pSample);
+                drop(ManuallyDrop::take(&mut buffer.pEvents));
+
+                match result {
+                    Ok(()) => {
+                        if let Some(sample) = sample {
+                            self.read_nv12(&sample)?;
+                            produced = true;
+                        }
+                    }
+                    Err(e) if e.code() == MF_E_TRANSFORM_NEED_MORE_INPUT => return Ok(produced),
+                    Err(e) if e.code() == MF_E_TRANSFORM_STREAM_CHANGE => {
+                        self.select_nv12_output()?;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+
+        /// Copy an NV12 output sample out of the (possibly GPU-backed) buffer
+        unsafe fn read_nv12(&mut self, sample: &IMFSample) -> anyhow::Result<()> {
+            let output_type = self.transform.GetOutputCurrentType(0)?;
+            let frame_size = output_type.GetUINT64(&MF_MT_FRAME_SIZE)?;
+            let coded_width = (frame_size >> 32) as usize;
+            let coded_height = (frame_size & 0xffff_ffff) as usize;
+            // The coded size is macroblock aligned (e.g. 1088 lines for 1080p).
+            let (crop, (width, height)) =
+                display_aperture(&output_type).unwrap_or(((0, 0), (coded_width, coded_height)));
+
+            let media_buffer = sample.ConvertToContiguousBuffer()?;
+            let mut data = std::ptr::null_mut();
+            let mut pitch = 0i32;
+            let buffer_2d = media_buffer.cast::<IMF2DBuffer>().ok();
+            match &buffer_2d {
+                Some(b) => b.Lock2D(&mut data, &mut pitch)?,
+                None => {
+                    media_buffer.Lock(&mut data, None, None)?;
+                    pitch = coded_width as i32;
+                }
+            }
+
+            let pitch = pitch.unsigned_abs() as usize;
+            let src = std::slice::from_raw_parts(data, pitch * coded_height * 3 / 2);
+            self.nv12.clear();
+            self.nv12.extend_from_slice(src);
+
+            match &buffer_2d {
+                Some(b) => b.Unlock2D()?,
+                None => media_buffer.Unlock()?,
+            }
+
+            self.width = width;
+            self.height = height;
+            self.crop = crop;
+            self.pitch = pitch;
+            self.coded_height = coded_height;
+            Ok(())
+        }
+    }
+
+    impl Drop for MfDecoder {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = self
+                    .transform
+                    .ProcessMessage(MFT_MESSAGE_NOTIFY_END_STREAMING, 0);
+                // Media Foundation objects must be gone before it shuts down.
+                ManuallyDrop::drop(&mut self.transform);
+                ManuallyDrop::drop(&mut self.manager);
+                ManuallyDrop::drop(&mut self.device);
+                let _ = MFShutdown();
+            }
+        }
+    }
+
+    unsafe fn input_sample(data: &[u8]) -> anyhow::Result<IMFSample> {
+        let buffer = MFCreateMemoryBuffer(data.len() as u32)?;
+        let mut ptr = std::ptr::null_mut();
+        buffer.Lock(&mut ptr, None, None)?;
+        std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+        buffer.Unlock()?;
+        buffer.SetCurrentLength(data.len() as u32)?;
+
+        let sample = MFCreateSample()?;
+        sample.AddBuffer(&buffer)?;
+        Ok(sample)
+    }
+
+    unsafe fn output_sample(size: u32) -> anyhow::Result<IMFSample> {
+        let sample = MFCreateSample()?;
+        sample.AddBuffer(&MFCreateMemoryBuffer(size)?)?;
+        Ok(sample)
+    }
+
+    /// Visible `(offset, size)` from `MF_MT_MINIMUM_DISPLAY_APERTURE`, if the decoder set one
+    unsafe fn display_aperture(
+        media_type: &IMFMediaType,
+    ) -> Option<((usize, usize), (usize, usize))> {
+        let mut area = MFVideoArea::default();
+        let bytes = std::slice::from_raw_parts_mut(
+            &mut area as *mut MFVideoArea as *mut u8,
+            std::mem::size_of::<MFVideoArea>(),
+        );
+        media_type
+            .GetBlob(&MF_MT_MINIMUM_DISPLAY_APERTURE, bytes, None)
+            .ok()?;
+        let offset = (
+            area.OffsetX.value.max(0) as usize,
+            area.OffsetY.value.max(0) as usize,
+        );
+        (area.Area.cx > 0 && area.Area.cy > 0)
+            .then_some((offset, (area.Area.cx as usize, area.Area.cy as usize)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyframe_request_needs_consecutive_errors() {
+        let mut requester = KeyframeRequester::new(3, Duration::from_secs(1));
+        let now = Instant::now();
+
+        assert!(!requester.record_error(now));
+        assert!(!requester.record_error(now));
+        requester.record_success();
+        assert!(!requester.record_error(now));
+        assert!(!requester.record_error(now));
+        assert!(requester.record_error(now));
+    }
+
+    #[test]
+    fn test_keyframe_requests_are_rate_limited() {
+        let mut requester = KeyframeRequester::new(1, Duration::from_secs(1));
+        let now = Instant::now();
+
+        assert!(requester.record_error(now));
+        assert!(!requester.record_error(now + Duration::from_millis(500)));
+        assert!(requester.record_error(now + Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn test_software_decode_of_test_stream() {
+        use thunder_shared::protocol::{ColorRange, ColorSpace, TransferFunction};
+
+        let (width, height) = (64, 48);
+        let stream =
+            thunder_shared::test_pattern::generate_h264_test_stream(width, height, 3).unwrap();
+        let (width, height) = (width as usize, height as usize);
+
+        let mut decoder = VideoDecoder::new(DecoderKind::Software).unwrap();
+        let mut buffer = vec![0u32; width * height];
+        for access_unit in &stream {
+            let decoded = decoder
+                .decode(access_unit)
+                .unwrap()
+                .expect("a picture for every access unit");
+            assert_eq!((decoded.width, decoded.height), (width, height));
+            crate::color::yuv_to_rgb(
+                &decoded.planes(),
+                width,
+                height,
+                &mut buffer,
+                ColorSpace::Bt601,
+                ColorRange::Limited,
+                TransferFunction::Sdr,
+            );
+        }
+
+        // Middle of each color bar, well above the moving bar
+        let bars = [
+            (255, 255, 255),
+            (255, 255, 0),
+            (0, 255, 255),
+            (0, 255, 0),
+            (255, 0, 255),
+            (255, 0, 0),
+            (0, 0, 255),
+            (0, 0, 0),
+        ];
+        for (i, expected) in bars.into_iter().enumerate() {
+            let pixel = buffer[8 * width + i * 8 + 4];
+            let actual = (pixel >> 16, (pixel >> 8) & 0xff, pixel & 0xff);
+            let close = |a: u32, e: u32| a.abs_diff(e) <= 24;
+            assert!(
+                close(actual.0, expected.0)
+                    && close(actual.1, expected.1)
+                    && close(actual.2, expected.2),
+                "bar {}: got {:?}, expected {:?}",
+                i,
+                actual,
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Windows the stream is shown in
+//!
+//! `--windows fullscreen,window:fit` opens one window per comma-separated
+//! spec, e.g. a fullscreen one for a projector and a windowed preview. Each
+//! frame is decoded once into a single buffer and every window is updated
+//! from it. Closing one window (or pressing the exit key in it) leaves the others
+//! and the connection running; the receiver exits once all are closed.
+
+use std::str::FromStr;
+
+use minifb::ScaleMode;
+
+/// How one window shows the stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowSpec {
+    /// Cover the monitor without decorations instead of a resizable window
+    pub fullscreen: bool,
+
+    /// How frames are scaled to the window
+    pub scale_mode: ScaleMode,
+}
+
+impl WindowSpec {
+    pub fn new(fullscreen: bool) -> Self {
+        Self {
+            fullscreen,
+            scale_mode: ScaleMode::Stretch,
+        }
+    }
+}
+
+impl FromStr for WindowSpec {
+    type Err = String;
+
+    /// `window` or `fullscreen`, optionally followed by `:stretch` (the
+    /// default), `:fit` (keep the aspect ratio) or `:center` (no scaling)
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (kind, scaling) = match value.split_once(':') {
+            Some((kind, scaling)) => (kind, Some(scaling)),
+            None => (value, None),
+        };
+        let mut spec = match kind.trim() {
+            "window" => WindowSpec::new(false),
+            "fullscreen" => WindowSpec::new(true),
+            other => {
+                return Err(format!(
+                    "unknown window `{}`; use `window` or `fullscreen`",
+                    other
+                ))
+            }
+        };
+        if let Some(scaling) = scaling {
+            spec.scale_mode = match scaling.trim() {
+                "stretch" => ScaleMode::Stretch,
+                "fit" => ScaleMode::AspectRatioStretch,
+                "center" => ScaleMode::Center,
+                other => {
+                    return Err(format!(
+                        "unknown scaling `{}`; use `stretch`, `fit` or `center`",
+                        other
+                    ))
+                }
+            };
+        }
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_window_specs() {
+        assert_eq!("window".parse(), Ok(WindowSpec::new(false)));
+        let spec: WindowSpec = "fullscreen:fit".parse().unwrap();
+        assert!(spec.fullscreen);
+        assert_eq!(spec.scale_mode, ScaleMode::AspectRatioStretch);
+        let spec: WindowSpec = "window:center".parse().unwrap();
+        assert_eq!(spec.scale_mode, ScaleMode::Center);
+
+        assert!("projector".parse::<WindowSpec>().is_err());
+        assert!("window:zoom".parse::<WindowSpec>().is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Per-monitor DPI for the receiver windows
+//!
+//! A DPI-unaware process is drawn at 96 DPI and bitmap-stretched by Windows
+//! on scaled monitors, which blurs text in the mirrored content. The receiver
+//! opts into per-monitor awareness like the UI does, so window sizes are in
+//! physical pixels on every monitor. minifb doesn't react to DPI changes, so
+//! each window is subclassed to catch `WM_DPICHANGED` when it's dragged to a
+//! monitor with a different scale; the display loop then refits it there.
+
+use std::sync::atomic::AtomicBool;
+#[cfg(windows)]
+use std::sync::atomic::Ordering;
+
+use minifb::Window;
+
+/// Opt the process into per-monitor DPI awareness
+///
+/// Has to run before the first window is created.
+#[cfg(windows)]
+pub fn enable_per_monitor_awareness() {
+    use windows::Win32::UI::HiDpi::{
+        SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+    };
+
+    // Fails if awareness was already set, e.g. by a manifest; that's fine.
+    unsafe {
+        let _ = SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+    }
+}
+
+#[cfg(not(windows))]
+pub fn enable_per_monitor_awareness() {}
+
+/// Notices when a window moves to a monitor with a different DPI
+///
+/// Must outlive the window: the subclass holds a pointer to `changed` until
+/// the window is destroyed.
+pub struct DpiWatch {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    changed: Box<AtomicBool>,
+}
+
+#[cfg(windows)]
+const SUBCLASS_ID: usize = 1;
+
+#[cfg(windows)]
+impl DpiWatch {
+    pub fn new(window: &Window) -> Self {
+        use windows::Win32::Foundation::HWND;
+        use windows::Win32::UI::Shell::SetWindowSubclass;
+
+        let watch = Self {
+            changed: Box::default(),
+        };
+        unsafe {
+            let hwnd = HWND(window.get_window_handle() as isize);
+            let changed = &*watch.changed as *const _ as usize;
+            if hwnd.0 != 0
+                && !SetWindowSubclass(hwnd, Some(subclass_proc), SUBCLASS_ID, changed).as_bool()
+            {
+                tracing::warn!("Failed to watch window for DPI changes");
+            }
+        }
+        watch
+    }
+
+    /// Whether the DPI changed since the last call
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+#[cfg(not(windows))]
+impl DpiWatch {
+    pub fn new(_window: &Window) -> Self {
+        Self {
+            changed: Box::default(),
+        }
+    }
+
+    /// DPI changes only happen on Windows
+    pub fn take_changed(&self) -> bool {
+        false
+    }
+}
+
+/// Moves the window to the rect Windows suggests for the new DPI and flags
+/// the change for the display loop
+#[cfg(windows)]
+unsafe extern "system" fn subclass_proc(
+    hwnd: windows::Win32::Foundation::HWND,
+    msg: u32,
+    wparam: windows::Win32::Foundation::WPARAM,
+    lparam: windows::Win32::Foundation::LPARAM,
+    _id: usize,
+    changed: usize,
+) -> windows::Win32::Foundation::LRESULT {
+    use windows::Win32::Foundation::{LRESULT, RECT};
+    use windows::Win32::UI::Shell::{DefSubclassProc, RemoveWindowSubclass};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        SetWindowPos, SWP_NOACTIVATE, SWP_NOZORDER, WM_DPICHANGED, WM_NCDESTROY,
+    };
+
+    match msg {
+        WM_DPICHANGED => {
+            // The suggested rect keeps the window under the pointer while it's
+            // dragged; the display loop fixes up the size afterwards.
+            let suggested = &*(lparam.0 as *const RECT);
+            let _ = SetWindowPos(
+                hwnd,
+                None,
+                suggested.left,
+                suggested.top,
+                suggested.right - suggested.left,
+                suggested.bottom - suggested.top,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+            (*(changed as *const AtomicBool)).store(true, Ordering::Relaxed);
+            return LRESULT(0);
+        }
+        WM_NCDESTROY => {
+            let _ = RemoveWindowSubclass(hwnd, Some(subclass_proc), SUBCLASS_ID);
+        }
+        _ => {}
+    }
+    DefSubclassProc(hwnd, msg, wparam, lparam)
+}
@@ -0,0 +1,136 @@
+//! Keys that close a window or leave fullscreen
+//!
+//! `--exit-key` defaults to Escape. A kiosk can require a combination such
+//! as `ctrl+shift+q` instead, so a stray key press doesn't end the mirror,
+//! and `--leave-fullscreen-key escape` makes Escape turn a fullscreen window
+//! into a normal one rather than closing it. Key names are minifb's (`q`,
+//! `f12`, `key0`, `numpad1`, ...) in any case; unknown names are rejected
+//! when the arguments are parsed.
+
+use std::fmt;
+use std::str::FromStr;
+
+use minifb::{Key, KeyRepeat, Window};
+use thunder_shared::protocol::InputEvent;
+
+use crate::input;
+
+/// Modifier names and the `InputEvent::MOD_*` bits they require
+const MODIFIERS: [(&str, u8); 7] = [
+    ("ctrl", InputEvent::MOD_CONTROL),
+    ("control", InputEvent::MOD_CONTROL),
+    ("shift", InputEvent::MOD_SHIFT),
+    ("alt", InputEvent::MOD_ALT),
+    ("win", InputEvent::MOD_META),
+    ("super", InputEvent::MOD_META),
+    ("meta", InputEvent::MOD_META),
+];
+
+/// A key, plus the modifiers that have to be held with it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyCombo {
+    /// `InputEvent::MOD_*` bits
+    pub modifiers: u8,
+    pub key: Key,
+}
+
+impl KeyCombo {
+    /// Whether the combination was just pressed in `window`
+    ///
+    /// Holding extra modifiers doesn't stop a match.
+    pub fn pressed(&self, window: &Window) -> bool {
+        window.is_key_pressed(self.key, KeyRepeat::No)
+            && input::modifiers(&window.get_keys()) & self.modifiers == self.modifiers
+    }
+}
+
+/// minifb key for a name, with a few short forms: `esc` and bare digits
+fn parse_key(name: &str) -> Option<Key> {
+    if name.eq_ignore_ascii_case("esc") {
+        return Some(Key::Escape);
+    }
+    if name.len() == 1 && name.as_bytes()[0].is_ascii_digit() {
+        return input::key_named(&format!("Key{}", name));
+    }
+    input::key_named(name)
+}
+
+impl FromStr for KeyCombo {
+    type Err = String;
+
+    /// `+`-separated modifiers (`ctrl`, `shift`, `alt`, `win`) ending in a
+    /// key, e.g. `escape` or `ctrl+shift+q`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = value.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+        let mut modifiers = 0;
+        for part in parts {
+            let Some(&(_, bit)) = MODIFIERS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(part))
+            else {
+                return Err(format!(
+                    "unknown modifier `{}`; use `ctrl`, `shift`, `alt` or `win`",
+                    part
+                ));
+            };
+            modifiers |= bit;
+        }
+        if key.is_empty() {
+            return Err(format!("`{}` has no key", value));
+        }
+        match parse_key(key) {
+            Some(key) => Ok(Self { modifiers, key }),
+            None => Err(format!("unknown key `{}`", key)),
+        }
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    /// e.g. `Ctrl+Shift+Q`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, bit) in [
+            ("Ctrl", InputEvent::MOD_CONTROL),
+            ("Shift", InputEvent::MOD_SHIFT),
+            ("Alt", InputEvent::MOD_ALT),
+            ("Win", InputEvent::MOD_META),
+        ] {
+            if self.modifiers & bit != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(key: Key) -> KeyCombo {
+        KeyCombo { modifiers: 0, key }
+    }
+
+    #[test]
+    fn test_parse_key_combos() {
+        assert_eq!("escape".parse(), Ok(plain(Key::Escape)));
+        assert_eq!("Esc".parse(), Ok(plain(Key::Escape)));
+        assert_eq!("F12".parse(), Ok(plain(Key::F12)));
+        assert_eq!("7".parse(), Ok(plain(Key::Key7)));
+
+        let combo: KeyCombo = "Ctrl+Shift+Q".parse().unwrap();
+        assert_eq!(combo.key, Key::Q);
+        assert_eq!(
+            combo.modifiers,
+            InputEvent::MOD_CONTROL | InputEvent::MOD_SHIFT
+        );
+        assert_eq!(combo.to_string(), "Ctrl+Shift+Q");
+        let combo: KeyCombo = "win + alt + numpad1".parse().unwrap();
+        assert_eq!(combo.to_string(), "Alt+Win+NumPad1");
+
+        assert!("escpe".parse::<KeyCombo>().is_err());
+        assert!("hyper+q".parse::<KeyCombo>().is_err());
+        assert!("ctrl+".parse::<KeyCombo>().is_err());
+        assert!("".parse::<KeyCombo>().is_err());
+    }
+}
@@ -0,0 +1,98 @@
+//! Sender and receiver numbers side by side
+//!
+//! H toggles a heads-up display with what the sender reports in its `Stats`
+//! frames next to what the receiver measures itself, e.g. "Mac: 60 FPS" over
+//! "Win: 60 FPS received, 55 shown". A slow encoder, a lossy link and a slow
+//! display each show up as a different one of those numbers dropping. It is
+//! drawn with the status overlay, below it when both are on.
+
+use thunder_shared::stats::StatsSnapshot;
+
+/// The smoothed rate where there is one; senders from before it was added
+/// only report the raw rate
+fn fps(snapshot: &StatsSnapshot) -> f64 {
+    if snapshot.smoothed_fps > 0.0 {
+        snapshot.smoothed_fps
+    } else {
+        snapshot.fps
+    }
+}
+
+/// HUD lines for the sender's latest stats, the receiver's network stats
+/// and the frames the display loop showed
+pub fn text(
+    sender: Option<&StatsSnapshot>,
+    received: &StatsSnapshot,
+    shown: &StatsSnapshot,
+) -> String {
+    // Encode time comes from the frame headers when the sender doesn't report it.
+    let encode = sender
+        .and_then(|s| s.encode_latency_ms)
+        .or(received.encode_latency_ms)
+        .map(|ms| format!(", encode {:.1} ms", ms))
+        .unwrap_or_default();
+    let mac = match sender {
+        Some(sender) => format!("Mac: {:.0} FPS{}", fps(sender), encode),
+        None => format!("Mac: no stats{}", encode),
+    };
+
+    let latency = received
+        .latency_ms
+        .map(|ms| format!(", {:.1} ms", ms))
+        .unwrap_or_default();
+    let loss = if received.loss_percent > 0.0 {
+        format!(", {:.1}% loss", received.loss_percent)
+    } else {
+        String::new()
+    };
+    let win = format!(
+        "Win: {:.0} FPS received, {:.0} shown{}{}",
+        fps(received),
+        fps(shown),
+        latency,
+        loss
+    );
+
+    format!("{}\n{}", mac, win)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hud_text() {
+        let sender = StatsSnapshot {
+            fps: 59.8,
+            smoothed_fps: 60.0,
+            encode_latency_ms: Some(4.2),
+            ..Default::default()
+        };
+        let received = StatsSnapshot {
+            fps: 58.0,
+            latency_ms: Some(12.0),
+            loss_percent: 1.5,
+            ..Default::default()
+        };
+        let shown = StatsSnapshot {
+            fps: 54.6,
+            smoothed_fps: 55.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            text(Some(&sender), &received, &shown),
+            "Mac: 60 FPS, encode 4.2 ms\nWin: 58 FPS received, 55 shown, 12.0 ms, 1.5% loss"
+        );
+
+        // Without sender stats the encode time still comes from the headers
+        let received = StatsSnapshot {
+            fps: 30.0,
+            encode_latency_ms: Some(8.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            text(None, &received, &shown),
+            "Mac: no stats, encode 8.0 ms\nWin: 30 FPS received, 55 shown"
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! Keyboard and mouse forwarding from the receiver window to the sender
+//!
+//! Input is polled from minifb once per display loop iteration and turned
+//! into `InputEvent`s for `ControlMessage::Input`. Mouse positions are
+//! normalized to the window, which matches the frame because the window
+//! stretches the picture to fit. Keys are sent as `KeyCode`s (USB HID usage
+//! IDs) so the sender doesn't need to know Windows or minifb key codes.
+//!
+//! Key events follow the held keys from one poll to the next, so each press
+//! and release is sent once. A held key repeats the way an OS keyboard
+//! driver does it, see `KeyTracker`.
+
+use std::time::{Duration, Instant};
+
+use minifb::{Key, MouseMode, Window};
+use thunder_shared::protocol::{InputEvent, KeyCode, MouseButton};
+
+const BUTTONS: [(minifb::MouseButton, MouseButton); 3] = [
+    (minifb::MouseButton::Left, MouseButton::Left),
+    (minifb::MouseButton::Middle, MouseButton::Middle),
+    (minifb::MouseButton::Right, MouseButton::Right),
+];
+
+/// How long a key is held before it starts repeating
+const REPEAT_DELAY: Duration = Duration::from_millis(500);
+
+/// Time between repeats of a held key
+const REPEAT_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Tracks input state between polls so only changes are forwarded
+#[derive(Default)]
+pub struct InputForwarder {
+    last_mouse: Option<(f32, f32)>,
+    buttons: [bool; 3],
+    keys: KeyTracker,
+}
+
+impl InputForwarder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Input events since the previous poll
+    pub fn poll(&mut self, window: &Window) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        let position = window
+            .get_unscaled_mouse_pos(MouseMode::Discard)
+            .and_then(|pos| normalize(pos, window.get_size()));
+        if let Some((x, y)) = position {
+            if self.last_mouse != position {
+                events.push(InputEvent::MouseMove { x, y });
+                self.last_mouse = position;
+            }
+        }
+
+        for ((local, remote), was_down) in BUTTONS.iter().zip(&mut self.buttons) {
+            let down = window.get_mouse_down(*local);
+            if down != *was_down {
+                *was_down = down;
+                events.push(InputEvent::MouseButton {
+                    button: *remote,
+                    down,
+                });
+            }
+        }
+
+        let keys = window.get_keys();
+        let held: Vec<KeyCode> = keys.iter().filter_map(|&key| key_code(key)).collect();
+        events.extend(self.keys.update(&held, modifiers(&keys), Instant::now()));
+
+        events
+    }
+}
+
+/// Turns the keys held at each poll into press, release and repeat events
+///
+/// Like an OS keyboard driver, only the most recently pressed key repeats,
+/// and modifiers never do. A late poll repeats once instead of catching up
+/// on every repeat it missed.
+#[derive(Default)]
+struct KeyTracker {
+    held: Vec<KeyCode>,
+    /// The repeating key and when it repeats next
+    repeating: Option<(KeyCode, Instant)>,
+}
+
+impl KeyTracker {
+    /// Key events for the keys held `now`
+    fn update(&mut self, held: &[KeyCode], modifiers: u8, now: Instant) -> Vec<InputEvent> {
+        let event = |keycode, down, repeat| InputEvent::KeyEvent {
+            keycode,
+            down,
+            modifiers,
+            repeat,
+        };
+        let mut events = Vec::new();
+
+        for &key in self.held.iter().filter(|key| !held.contains(key)) {
+            events.push(event(key, false, false));
+            if self
+                .repeating
+                .is_some_and(|(repeating, _)| repeating == key)
+            {
+                self.repeating = None;
+            }
+        }
+        for &key in held.iter().filter(|key| !self.held.contains(key)) {
+            events.push(event(key, true, false));
+            if !key.is_modifier() {
+                self.repeating = Some((key, now + REPEAT_DELAY));
+            }
+        }
+        if let Some((key, next)) = &mut self.repeating {
+            if now >= *next {
+                events.push(event(*key, true, true));
+                *next = now + REPEAT_INTERVAL;
+            }
+        }
+
+        self.held = held.to_vec();
+        events
+    }
+}
+
+/// Window pixel position as a 0.0-1.0 fraction of `size`
+fn normalize((x, y): (f32, f32), (width, height): (usize, usize)) -> Option<(f32, f32)> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some((
+        (x / width as f32).clamp(0.0, 1.0),
+        (y / height as f32).clamp(0.0, 1.0),
+    ))
+}
+
+/// `InputEvent::MOD_*` bits for the keys currently held
+pub fn modifiers(keys: &[Key]) -> u8 {
+    keys.iter().fold(0, |bits, key| {
+        bits | match key {
+            Key::LeftShift | Key::RightShift => InputEvent::MOD_SHIFT,
+            Key::LeftCtrl | Key::RightCtrl => InputEvent::MOD_CONTROL,
+            Key::LeftAlt | Key::RightAlt => InputEvent::MOD_ALT,
+            Key::LeftSuper | Key::RightSuper => InputEvent::MOD_META,
+            _ => 0,
+        }
+    })
+}
+
+/// Protocol key for a minifb key
+fn key_code(key: Key) -> Option<KeyCode> {
+    KEYS.iter()
+        .find(|(local, _)| *local == key)
+        .map(|&(_, remote)| remote)
+}
+
+/// minifb key with this name, ignoring case, e.g. `Escape` or `F12`
+pub fn key_named(name: &str) -> Option<Key> {
+    KEYS.iter()
+        .map(|&(key, _)| key)
+        .find(|key| format!("{:?}", key).eq_ignore_ascii_case(name))
+}
+
+/// minifb keys and the protocol keys they are sent as
+const KEYS: [(Key, KeyCode); 106] = [
+    (Key::A, KeyCode::A),
+    (Key::B, KeyCode::B),
+    (Key::C, KeyCode::C),
+    (Key::D, KeyCode::D),
+    (Key::E, KeyCode::E),
+    (Key::F, KeyCode::F),
+    (Key::G, KeyCode::G),
+    (Key::H, KeyCode::H),
+    (Key::I, KeyCode::I),
+    (Key::J, KeyCode::J),
+    (Key::K, KeyCode::K),
+    (Key::L, KeyCode::L),
+    (Key::M, KeyCode::M),
+    (Key::N, KeyCode::N),
+    (Key::O, KeyCode::O),
+    (Key::P, KeyCode::P),
+    (Key::Q, KeyCode::Q),
+    (Key::R, KeyCode::R),
+    (Key::S, KeyCode::S),
+    (Key::T, KeyCode::T),
+    (Key::U, KeyCode::U),
+    (Key::V, KeyCode::V),
+    (Key::W, KeyCode::W),
+    (Key::X, KeyCode::X),
+    (Key::Y, KeyCode::Y),
+    (Key::Z, KeyCode::Z),
+    (Key::Key1, KeyCode::Digit1),
+    (Key::Key2, KeyCode::Digit2),
+    (Key::Key3, KeyCode::Digit3),
+    (Key::Key4, KeyCode::Digit4),
+    (Key::Key5, KeyCode::Digit5),
+    (Key::Key6, KeyCode::Digit6),
+    (Key::Key7, KeyCode::Digit7),
+    (Key::Key8, KeyCode::Digit8),
+    (Key::Key9, KeyCode::Digit9),
+    (Key::Key0, KeyCode::Digit0),
+    (Key::Enter, KeyCode::Enter),
+    (Key::Escape, KeyCode::Escape),
+    (Key::Backspace, KeyCode::Backspace),
+    (Key::Tab, KeyCode::Tab),
+    (Key::Space, KeyCode::Space),
+    (Key::Minus, KeyCode::Minus),
+    (Key::Equal, KeyCode::Equal),
+    (Key::LeftBracket, KeyCode::LeftBracket),
+    (Key::RightBracket, KeyCode::RightBracket),
+    (Key::Backslash, KeyCode::Backslash),
+    (Key::Semicolon, KeyCode::Semicolon),
+    (Key::Apostrophe, KeyCode::Apostrophe),
+    (Key::Backquote, KeyCode::Backquote),
+    (Key::Comma, KeyCode::Comma),
+    (Key::Period, KeyCode::Period),
+    (Key::Slash, KeyCode::Slash),
+    (Key::CapsLock, KeyCode::CapsLock),
+    (Key::F1, KeyCode::F1),
+    (Key::F2, KeyCode::F2),
+    (Key::F3, KeyCode::F3),
+    (Key::F4, KeyCode::F4),
+    (Key::F5, KeyCode::F5),
+    (Key::F6, KeyCode::F6),
+    (Key::F7, KeyCode::F7),
+    (Key::F8, KeyCode::F8),
+    (Key::F9, KeyCode::F9),
+    (Key::F10, KeyCode::F10),
+    (Key::F11, KeyCode::F11),
+    (Key::F12, KeyCode::F12),
+    (Key::ScrollLock, KeyCode::ScrollLock),
+    (Key::Pause, KeyCode::Pause),
+    (Key::Insert, KeyCode::Insert),
+    (Key::Home, KeyCode::Home),
+    (Key::PageUp, KeyCode::PageUp),
+    (Key::Delete, KeyCode::Delete),
+    (Key::End, KeyCode::End),
+    (Key::PageDown, KeyCode::PageDown),
+    (Key::Right, KeyCode::Right),
+    (Key::Left, KeyCode::Left),
+    (Key::Down, KeyCode::Down),
+    (Key::Up, KeyCode::Up),
+    (Key::NumLock, KeyCode::NumLock),
+    (Key::NumPadSlash, KeyCode::NumPadSlash),
+    (Key::NumPadAsterisk, KeyCode::NumPadAsterisk),
+    (Key::NumPadMinus, KeyCode::NumPadMinus),
+    (Key::NumPadPlus, KeyCode::NumPadPlus),
+    (Key::NumPadEnter, KeyCode::NumPadEnter),
+    (Key::NumPad1, KeyCode::NumPad1),
+    (Key::NumPad2, KeyCode::NumPad2),
+    (Key::NumPad3, KeyCode::NumPad3),
+    (Key::NumPad4, KeyCode::NumPad4),
+    (Key::NumPad5, KeyCode::NumPad5),
+    (Key::NumPad6, KeyCode::NumPad6),
+    (Key::NumPad7, KeyCode::NumPad7),
+    (Key::NumPad8, KeyCode::NumPad8),
+    (Key::NumPad9, KeyCode::NumPad9),
+    (Key::NumPad0, KeyCode::NumPad0),
+    (Key::NumPadDot, KeyCode::NumPadDot),
+    (Key::Menu, KeyCode::Menu),
+    (Key::F13, KeyCode::F13),
+    (Key::F14, KeyCode::F14),
+    (Key::F15, KeyCode::F15),
+    (Key::LeftCtrl, KeyCode::LeftCtrl),
+    (Key::LeftShift, KeyCode::LeftShift),
+    (Key::LeftAlt, KeyCode::LeftAlt),
+    (Key::LeftSuper, KeyCode::LeftSuper),
+    (Key::RightCtrl, KeyCode::RightCtrl),
+    (Key::RightShift, KeyCode::RightShift),
+    (Key::RightAlt, KeyCode::RightAlt),
+    (Key::RightSuper, KeyCode::RightSuper),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_mouse_position() {
+        assert_eq!(normalize((0.0, 0.0), (1920, 1080)), Some((0.0, 0.0)));
+        assert_eq!(normalize((960.0, 270.0), (1920, 1080)), Some((0.5, 0.25)));
+        assert_eq!(normalize((2000.0, -5.0), (1920, 1080)), Some((1.0, 0.0)));
+        assert_eq!(normalize((10.0, 10.0), (0, 0)), None);
+    }
+
+    #[test]
+    fn test_modifier_bits() {
+        assert_eq!(modifiers(&[Key::A]), 0);
+        assert_eq!(
+            modifiers(&[Key::RightShift, Key::A, Key::LeftCtrl]),
+            InputEvent::MOD_SHIFT | InputEvent::MOD_CONTROL
+        );
+        assert_eq!(
+            modifiers(&[Key::LeftAlt, Key::RightSuper]),
+            InputEvent::MOD_ALT | InputEvent::MOD_META
+        );
+    }
+
+    #[test]
+    fn test_key_table_covers_every_key_code_once() {
+        for key in KeyCode::ALL {
+            let mapped = KEYS.iter().filter(|(_, remote)| *remote == key).count();
+            assert_eq!(mapped, 1, "{:?}", key);
+        }
+        for (i, (local, _)) in KEYS.iter().enumerate() {
+            assert!(
+                !KEYS[..i].iter().any(|(other, _)| other == local),
+                "{:?}",
+                local
+            );
+        }
+
+        assert_eq!(key_code(Key::A), Some(KeyCode::A));
+        assert_eq!(key_code(Key::Key0), Some(KeyCode::Digit0));
+        assert_eq!(key_code(Key::NumPadEnter), Some(KeyCode::NumPadEnter));
+        assert_eq!(key_code(Key::RightSuper), Some(KeyCode::RightSuper));
+        assert_eq!(key_code(Key::Unknown), None);
+    }
+
+    /// `(key, down, repeat)` for each key event
+    fn changes(events: Vec<InputEvent>) -> Vec<(KeyCode, bool, bool)> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                InputEvent::KeyEvent {
+                    keycode,
+                    down,
+                    repeat,
+                    ..
+                } => (keycode, down, repeat),
+                other => panic!("not a key event: {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_held_keys_press_once_then_repeat() {
+        let mut tracker = KeyTracker::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        let a = KeyCode::A;
+        assert_eq!(changes(tracker.update(&[a], 0, at(0))), [(a, true, false)]);
+        // Held on every poll before the delay: nothing new
+        assert!(tracker.update(&[a], 0, at(16)).is_empty());
+        assert!(tracker.update(&[a], 0, at(400)).is_empty());
+        assert_eq!(changes(tracker.update(&[a], 0, at(500))), [(a, true, true)]);
+        assert!(tracker.update(&[a], 0, at(520)).is_empty());
+        // A poll late by several intervals repeats just once
+        assert_eq!(changes(tracker.update(&[a], 0, at(900))), [(a, true, true)]);
+
+        // A second key takes over the repeat; releasing it doesn't bring A's back
+        let b = KeyCode::B;
+        assert_eq!(
+            changes(tracker.update(&[a, b], 0, at(910))),
+            [(b, true, false)]
+        );
+        assert!(tracker.update(&[a, b], 0, at(1200)).is_empty());
+        assert_eq!(
+            changes(tracker.update(&[a], 0, at(1300))),
+            [(b, false, false)]
+        );
+        assert!(tracker.update(&[a], 0, at(3000)).is_empty());
+        assert_eq!(
+            changes(tracker.update(&[], 0, at(3016))),
+            [(a, false, false)]
+        );
+    }
+
+    #[test]
+    fn test_modifiers_do_not_repeat() {
+        let mut tracker = KeyTracker::default();
+        let start = Instant::now();
+        let shift = KeyCode::LeftShift;
+
+        let events = tracker.update(&[shift], InputEvent::MOD_SHIFT, start);
+        assert_eq!(changes(events), [(shift, true, false)]);
+        let later = start + Duration::from_secs(2);
+        assert!(tracker
+            .update(&[shift], InputEvent::MOD_SHIFT, later)
+            .is_empty());
+    }
+}
@@ -2,100 +2,104 @@
 //!
 //! Receives screen stream from Mac and displays it.
 
-use std::net::SocketAddr;
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use bytes::{Buf, Bytes};
-use clap::Parser;
-use minifb::{Key, Window, WindowOptions};
+use bytes::Bytes;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use minifb::{Key, KeyRepeat, ScaleMode, Window, WindowOptions};
 
-#[cfg(windows)]
-use windows::Win32::Foundation::HWND;
 #[cfg(windows)]
 use windows::Win32::Graphics::Gdi::{GetMonitorInfoW, MonitorFromWindow, MONITORINFO, MONITOR_DEFAULTTOPRIMARY};
 #[cfg(windows)]
 use windows::Win32::UI::WindowsAndMessaging::{
-    FindWindowW, GetWindowLongW, SetWindowLongW, SetWindowPos, 
+    GetWindowLongW, SetWindowLongW, SetWindowPos, 
     GWL_STYLE, HWND_TOPMOST, SWP_FRAMECHANGED, SWP_SHOWWINDOW,
     WS_BORDER, WS_CAPTION, WS_DLGFRAME, WS_MAXIMIZEBOX, WS_MINIMIZEBOX,
     WS_SYSMENU, WS_THICKFRAME,
 };
-use openh264::decoder::Decoder;
-use openh264::formats::YUVSource;
-
-/// Fast YUV to RGB conversion using integer math (BT.709 LIMITED range)
-/// VideoToolbox outputs limited range: Y=[16,235], UV=[16,240]
-/// This function expands to full RGB [0,255]
-#[inline(always)]
-fn yuv_to_rgb_bt709_limited(y: u8, u: u8, v: u8) -> (u8, u8, u8) {
-    // BT.709 limited range to full range RGB conversion
-    // First expand Y from [16,235] to [0,255]: Y' = (Y - 16) * 255 / 219
-    // Expand UV from [16,240] centered at 128 to [-128,127]: UV' = (UV - 128) * 255 / 224
-    //
-    // Then apply BT.709 matrix:
-    // R = Y' + 1.5748 * V'
-    // G = Y' - 0.1873 * U' - 0.4681 * V'  
-    // B = Y' + 1.8556 * U'
-    //
-    // Combined with fixed-point (shift by 10 = divide by 1024):
-    // Y scale: 255/219 * 1024 ≈ 1192
-    // UV scale: 255/224 ≈ 1.138, so coefficients become:
-    // R coeff for V: 1.5748 * 1.138 * 1024 ≈ 1836
-    // G coeff for U: 0.1873 * 1.138 * 1024 ≈ 218
-    // G coeff for V: 0.4681 * 1.138 * 1024 ≈ 545
-    // B coeff for U: 1.8556 * 1.138 * 1024 ≈ 2160
-    
-    let y_i = y as i32 - 16;
-    let u_i = u as i32 - 128;
-    let v_i = v as i32 - 128;
-
-    // Scale Y from limited to full range, apply matrix
-    let y_scaled = (y_i * 1192) >> 10;  // Expands Y from [16,235] to [0,255]
-    
-    let r = y_scaled + ((1836 * v_i) >> 10);
-    let g = y_scaled - ((218 * u_i + 545 * v_i) >> 10);
-    let b = y_scaled + ((2160 * u_i) >> 10);
-
-    (
-        r.clamp(0, 255) as u8,
-        g.clamp(0, 255) as u8,
-        b.clamp(0, 255) as u8,
-    )
-}
-use quinn::{Endpoint, ServerConfig};
-use rustls::{Certificate, PrivateKey};
-use tokio::sync::mpsc;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::StreamExt;
 use tracing::{debug, error, info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
-/// Frame header size in bytes
-const FRAME_HEADER_SIZE: usize = 26;
-/// Maximum payload size we will accept (matches shared protocol's intent; keep conservative).
-const MAX_FRAME_PAYLOAD_SIZE: usize = 16 * 1024 * 1024;
-
-/// Frame types from protocol
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(u8)]
-enum FrameType {
-    Raw = 0,
-    H264 = 1,
-    Control = 2,
-    Stats = 3,
-}
-
-impl TryFrom<u8> for FrameType {
-    type Error = anyhow::Error;
-
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(FrameType::Raw),
-            1 => Ok(FrameType::H264),
-            2 => Ok(FrameType::Control),
-            3 => Ok(FrameType::Stats),
-            _ => Err(anyhow::anyhow!("Unknown frame type: {}", value)),
-        }
-    }
+use thunder_shared::config::{Config, TransportTuning};
+use thunder_shared::protocol::{
+    AudioCodec, ColorRange, ColorSpace, ControlMessage, CursorUpdate, DeltaTile, Frame,
+    FrameDecoder, FrameHeader, FrameType, H264Profile, StatsFrame, TransferFunction, VideoCodec,
+    CLOSE_VERSION_MISMATCH, MAX_FRAME_DIMENSION, MAX_FRAME_SIZE, MIN_PROTOCOL_VERSION,
+    PEER_TIMEOUT, PROTOCOL_VERSION, STATS_INTERVAL,
+};
+use thunder_shared::receiver::{ConnectionHook, ConnectionTap, FrameReceiver, FrameStream};
+use thunder_shared::recording;
+use thunder_shared::source::FileFrameSource;
+use thunder_shared::stats::{Heartbeat, SequenceArrival, Stats, StatsSnapshot, StatusEvent};
+use thunder_shared::transport::{
+    local_address_towards, path_stats, ConnectionEvent, IpMode, QuicClient, QuicServer,
+    SelfSignedCert, TcpServer,
+};
+use thunder_shared::DEFAULT_FRAME_QUEUE;
+
+mod audio;
+mod backpressure;
+mod bitrate;
+mod cancel;
+mod clipboard;
+mod clock;
+mod color;
+mod cursor;
+mod decoder;
+mod display;
+mod dpi;
+mod hotkey;
+mod hud;
+mod input;
+mod metrics;
+mod no_signal;
+mod overlay;
+mod reorder;
+mod scale;
+mod screenshot;
+#[cfg(feature = "hdr")]
+mod tonemap;
+
+/// Consecutive H.264 decode errors before asking the sender for a keyframe
+const KEYFRAME_ERROR_THRESHOLD: u32 = 3;
+
+/// Minimum time between keyframe requests
+const KEYFRAME_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often each QUIC sender's own stats are logged
+const SENDER_STATS_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Highest accepted `--fps`
+const MAX_FPS: u8 = 240;
+
+/// Wait before dialing the sender again with `--connect`
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Set by `--status-json`; read wherever a status event happens
+static STATUS_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Largest frame payload accepted, from `Config::max_frame_size`
+static MAX_FRAME_PAYLOAD_SIZE: AtomicUsize = AtomicUsize::new(MAX_FRAME_SIZE);
+
+/// Largest frame width or height accepted, from `Config::max_frame_dimension`
+static MAX_FRAME_SIDE: AtomicU16 = AtomicU16::new(MAX_FRAME_DIMENSION);
+
+/// How the receiver listens for the sender
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum TransportKind {
+    /// QUIC over UDP
+    Quic,
+    /// Plain TCP, for networks that block UDP
+    Tcp,
 }
 
 /// ThunderMirror Windows Receiver
@@ -113,13 +117,298 @@ struct Args {
     #[arg(short, long, default_value_t = 9999)]
     port: u16,
 
+    /// Transport to listen on (the sender must use the same one)
+    #[arg(long, value_enum, default_value_t = TransportKind::Quic)]
+    transport: TransportKind,
+
+    /// Listen on IPv4 only (the default)
+    #[arg(long, group = "ip_version")]
+    ipv4: bool,
+
+    /// Listen on IPv6 only, including link-local addresses
+    #[arg(long, group = "ip_version")]
+    ipv6: bool,
+
+    /// Listen on both IPv4 and IPv6
+    #[arg(long, group = "ip_version")]
+    dual: bool,
+
+    /// Listen on this address only, e.g. the Thunderbolt Bridge one, instead
+    /// of every interface (`0.0.0.0`)
+    #[arg(long, value_name = "IP", conflicts_with = "ip_version")]
+    listen: Option<IpAddr>,
+
+    /// Connect to the sender at this address instead of listening, e.g.
+    /// when a firewall here blocks incoming connections (QUIC only). The
+    /// sender still opens the streams, and the connection is redialed
+    /// whenever it ends. Needs a sender that listens; the current Mac
+    /// builds only dial out.
+    #[arg(long, value_name = "IP:PORT", conflicts_with_all = ["listen", "ip_version", "replay"])]
+    connect: Option<SocketAddr>,
+
     /// Run in fullscreen mode
     #[arg(short, long)]
     fullscreen: bool,
 
+    /// Show the stream in several windows, e.g. `fullscreen,window:fit`
+    ///
+    /// Each is `window` or `fullscreen`, optionally followed by `:stretch`,
+    /// `:fit` or `:center`. Overrides --fullscreen.
+    #[arg(long, value_delimiter = ',', conflicts_with = "fullscreen")]
+    windows: Vec<display::WindowSpec>,
+
+    /// Open no window: count received frames without decoding or showing
+    /// them, and print a throughput summary on exit (Ctrl+C)
+    #[arg(long, conflicts_with_all = ["windows", "forward_input"])]
+    headless: bool,
+
+    /// Fixed window title, e.g. for capture tools that find the window by
+    /// name (by default the title shows the stream's size and rates)
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Key that closes a window, e.g. `escape` or `ctrl+shift+q`
+    #[arg(long, default_value = "escape")]
+    exit_key: hotkey::KeyCombo,
+
+    /// Key that makes a fullscreen window a normal one instead of closing it
+    ///
+    /// Takes precedence over --exit-key in fullscreen windows, so
+    /// `--leave-fullscreen-key escape --exit-key ctrl+q` makes Escape leave
+    /// fullscreen and Ctrl+Q quit.
+    #[arg(long)]
+    leave_fullscreen_key: Option<hotkey::KeyCombo>,
+
+    /// How frames larger than the window are shrunk: nearest pixel (fast)
+    /// or bilinear filtering, which keeps text readable when e.g. a 4K
+    /// stream is shown in a 1080p window
+    #[arg(long, value_enum, default_value_t = scale::ScalingQuality::Fast)]
+    scaling_quality: scale::ScalingQuality,
+
+    /// Seconds without video before the windows show an animated "No
+    /// Signal" card instead of the last frame (0 keeps the last frame)
+    #[arg(long, default_value_t = 5)]
+    no_signal_after_secs: u16,
+
     /// Log level (trace, debug, info, warn, error)
     #[arg(long, default_value = "info")]
     log_level: String,
+
+    /// Audio jitter buffer size in milliseconds
+    #[arg(long, default_value_t = 60)]
+    audio_buffer_ms: u32,
+
+    /// Display frame rate, also requested from senders that pace capture
+    /// (1-240; 0 means as fast as frames arrive)
+    #[arg(long, default_value_t = 60, value_parser = parse_fps)]
+    fps: u8,
+
+    /// Hold out-of-order video frames for up to this many milliseconds so they
+    /// display in sequence (0 shows frames in arrival order)
+    #[arg(long, default_value_t = 0)]
+    jitter_buffer_ms: u32,
+
+    /// What to do when frames arrive faster than they are shown: skip stale
+    /// video (latest) or show every frame late (queue)
+    #[arg(long, value_enum, default_value_t = backpressure::BackpressurePolicy::Latest)]
+    backpressure: backpressure::BackpressurePolicy,
+
+    /// Frames waiting for the display before backpressure applies: fewer
+    /// keep latency down but drop frames in bursts, more ride bursts out
+    #[arg(
+        long,
+        visible_alias = "frame-buffer",
+        default_value_t = DEFAULT_FRAME_QUEUE,
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    frame_queue: u16,
+
+    /// Lowest bitrate to suggest to the sender when the link is congested, in kbps
+    #[arg(long, default_value_t = 2_000, value_parser = clap::value_parser!(u32).range(1..))]
+    min_bitrate_kbps: u32,
+
+    /// Highest bitrate to suggest to the sender, in kbps
+    #[arg(long, default_value_t = 50_000, value_parser = clap::value_parser!(u32).range(1..))]
+    max_bitrate_kbps: u32,
+
+    /// Serve Prometheus metrics on this port at /metrics, on the same IP
+    /// the receiver listens on
+    #[arg(long)]
+    metrics_port: Option<u16>,
+
+    /// Load settings from a .toml or .json config file (flags given on the
+    /// command line take precedence)
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// H.264 decoder (hardware falls back to software if unavailable)
+    #[arg(long, value_enum, default_value_t = decoder::DecoderKind::Software)]
+    decoder: decoder::DecoderKind,
+
+    /// YUV range of H.264 video (auto trusts the sender, or guesses for old senders)
+    #[arg(long, value_enum, default_value_t = color::RangeMode::Auto)]
+    color_range: color::RangeMode,
+
+    /// Show H.264 video in grayscale, converting only its luma
+    #[arg(long)]
+    mono: bool,
+
+    /// Copy text sent from the Mac clipboard to the Windows clipboard
+    #[arg(long)]
+    clipboard: bool,
+
+    /// Send keyboard and mouse input in the window to the Mac
+    #[arg(long)]
+    forward_input: bool,
+
+    /// Print status as JSON lines (`StatusEvent`) on stdout for front ends;
+    /// logs go to stderr instead
+    #[arg(long)]
+    status_json: bool,
+
+    /// Save every received frame, exactly as on the wire, to this file
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Play back a `--record` file instead of listening for a sender
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Start the replay at the last keyframe at or before this frame sequence number
+    #[arg(long, requires = "replay")]
+    replay_from: Option<u64>,
+}
+
+impl Args {
+    /// Fill in settings from `config` that weren't given on the command line
+    fn apply_config(&mut self, config: &Config, matches: &ArgMatches) {
+        let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+        if !from_cli("mac_ip") {
+            self.mac_ip = config.target_address.clone();
+        }
+        if !from_cli("port") {
+            self.port = config.port;
+        }
+        if !from_cli("log_level") {
+            self.log_level = config.log_level.clone();
+        }
+        if !from_cli("frame_queue") {
+            self.frame_queue = config.frame_queue;
+        }
+    }
+
+    /// `ControlMessage::CAP_*` features the receiver handles with these args
+    fn capabilities(&self) -> u32 {
+        let mut capabilities = ControlMessage::CAP_AUDIO
+            | ControlMessage::CAP_CURSOR
+            | ControlMessage::CAP_DELTA_FRAMES;
+        if self.clipboard {
+            capabilities |= ControlMessage::CAP_CLIPBOARD;
+        }
+        if self.forward_input {
+            capabilities |= ControlMessage::CAP_INPUT;
+        }
+        if cfg!(feature = "hdr") {
+            capabilities |= ControlMessage::CAP_HDR;
+        }
+        capabilities
+    }
+
+    /// The `Start` asking the sender for what we show best: `size` frames
+    /// (`None` leaves it to the sender) at `--fps`, and H.264 at `kbps`, or
+    /// raw frames without `h264` (no decoder)
+    fn start_message(&self, size: Option<(usize, usize)>, h264: bool, kbps: u32) -> ControlMessage {
+        let max = max_frame_dimension();
+        let dimension = |pixels: usize| u16::try_from(pixels).map_or(max, |p| p.min(max));
+        let (width, height) = size.map_or((0, 0), |(w, h)| (dimension(w), dimension(h)));
+        let codec = if h264 {
+            VideoCodec::H264
+        } else {
+            VideoCodec::Raw
+        };
+        ControlMessage::Start {
+            width,
+            height,
+            fps: self.fps,
+            bitrate_kbps: h264.then_some(kbps),
+            codec: Some(codec),
+            // Both decoders handle High, which the Mac encodes by default.
+            profile: h264.then_some(H264Profile::High),
+        }
+    }
+
+    /// Which IP versions to listen on
+    fn ip_mode(&self) -> IpMode {
+        if self.dual {
+            IpMode::Dual
+        } else if self.ipv6 {
+            IpMode::V6
+        } else {
+            IpMode::V4
+        }
+    }
+
+    /// Address to bind: `--listen`, or the wildcard address of `ip_mode`
+    fn listen_addr(&self) -> SocketAddr {
+        match self.listen {
+            Some(ip) => SocketAddr::new(ip, self.port),
+            None => self.ip_mode().listen_addr(self.port),
+        }
+    }
+
+    /// Address to serve metrics on with `--metrics-port`: the listen IP,
+    /// so `--listen` and `--ipv6` keep the endpoint off other interfaces
+    fn metrics_addr(&self) -> Option<SocketAddr> {
+        let ip = self.listen_addr().ip();
+        self.metrics_port.map(|port| SocketAddr::new(ip, port))
+    }
+
+    /// Certificate names: the defaults, plus `--listen` and our address on
+    /// the route to the Mac, which the bridge's default-less link needs
+    fn self_signed_cert(&self) -> SelfSignedCert {
+        let mut cert = SelfSignedCert::default();
+        let towards_mac = self.mac_ip.parse().ok().and_then(local_address_towards);
+        for ip in self.listen.into_iter().chain(towards_mac) {
+            if !ip.is_unspecified() {
+                cert.add_name(ip.to_string());
+            }
+        }
+        cert
+    }
+
+    /// The windows to open: `--windows`, or just one following `--fullscreen`
+    fn window_specs(&self) -> Vec<display::WindowSpec> {
+        if self.windows.is_empty() {
+            vec![display::WindowSpec::new(self.fullscreen)]
+        } else {
+            self.windows.clone()
+        }
+    }
+
+    /// The effective config: `base` with the settings these args control
+    fn to_config(&self, base: Config) -> Config {
+        Config {
+            bind_address: self.listen_addr().ip().to_string(),
+            target_address: self.mac_ip.clone(),
+            port: self.port,
+            log_level: self.log_level.clone(),
+            frame_queue: self.frame_queue,
+            ..base
+        }
+    }
+}
+
+/// Parse `--fps`, allowing 0 (no limit) or 1..=`MAX_FPS`
+fn parse_fps(value: &str) -> Result<u8, String> {
+    match value.parse::<u32>() {
+        Ok(fps) if fps <= MAX_FPS as u32 => Ok(fps as u8),
+        Ok(fps) => Err(format!(
+            "{} is too high; use 1-{} (or 0 for no limit)",
+            fps, MAX_FPS
+        )),
+        Err(_) => Err(format!("`{}` is not a frame rate", value)),
+    }
 }
 
 /// Frame data received from sender
@@ -127,9 +416,55 @@ struct FrameData {
     width: u16,
     height: u16,
     rgba_data: Vec<u8>,
-    #[allow(dead_code)]
     sequence: u64,
+    timestamp_us: u64,
+    /// Sender's capture-to-encode time; `None` before v5 headers
+    encode_time_us: Option<u64>,
     frame_type: FrameType,
+    color_space: ColorSpace,
+    /// `None` for v1 headers, which don't say
+    color_range: Option<ColorRange>,
+    is_keyframe: bool,
+    bit_depth: u8,
+    transfer: TransferFunction,
+}
+
+impl From<Frame> for FrameData {
+    fn from(frame: Frame) -> Self {
+        let header = frame.header;
+        Self {
+            width: header.width,
+            height: header.height,
+            rgba_data: frame.payload.to_vec(),
+            sequence: header.sequence,
+            timestamp_us: header.timestamp_us,
+            encode_time_us: header.encode_time_us(),
+            frame_type: header.frame_type,
+            color_space: header.color_space,
+            color_range: (header.version >= 2).then_some(header.color_range),
+            is_keyframe: header.is_keyframe,
+            bit_depth: header.bit_depth,
+            transfer: header.transfer,
+        }
+    }
+}
+
+impl backpressure::Coalesce for FrameData {
+    fn is_video(&self) -> bool {
+        self.frame_type.is_video()
+    }
+
+    fn is_independent(&self) -> bool {
+        match self.frame_type {
+            FrameType::RawFrame => true,
+            FrameType::H264Frame => self.is_keyframe,
+            _ => false,
+        }
+    }
+
+    fn is_keyframe(&self) -> bool {
+        self.frame_type == FrameType::H264Frame && self.is_keyframe
+    }
 }
 
 /// Get screen dimensions for fullscreen mode
@@ -153,13 +488,15 @@ fn get_screen_dimensions() -> Option<(usize, usize)> {
 }
 
 /// Set window to true fullscreen by removing all decorations and positioning at (0,0)
+///
+/// The HWND comes from minifb. Don't look the window up by its title: that
+/// changes every second with the stats, and `--title` can be anything.
 #[cfg(windows)]
 fn set_window_fullscreen(window: &Window) {
+    use windows::Win32::Foundation::HWND;
+
     unsafe {
-        use windows::core::w;
-        
-        // Find the window by searching for our window title
-        let hwnd = FindWindowW(None, w!("ThunderMirror - Waiting for stream..."));
+        let hwnd = HWND(window.get_window_handle() as isize);
         
         if hwnd.0 != 0 {
             // Remove all window decorations by modifying window style
@@ -194,8 +531,6 @@ fn set_window_fullscreen(window: &Window) {
             }
         }
     }
-    // Suppress unused warning on non-windows
-    let _ = window;
 }
 
 #[cfg(not(windows))]
@@ -203,562 +538,1916 @@ fn set_window_fullscreen(_window: &Window) {
     // No-op on non-Windows
 }
 
-fn resize_window_and_buffers(
-    _window: &mut Window,
-    width: &mut usize,
-    height: &mut usize,
-    buffer: &mut Vec<u32>,
-    new_width: usize,
-    new_height: usize,
-) {
-    if new_width == 0 || new_height == 0 {
-        return;
+/// Give a fullscreen window its decorations back, sized for a
+/// `width`x`height` stream
+#[cfg(windows)]
+fn set_window_windowed(window: &Window, width: usize, height: usize) {
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        HWND_NOTOPMOST, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOSIZE,
+    };
+
+    unsafe {
+        let hwnd = HWND(window.get_window_handle() as isize);
+        if hwnd.0 == 0 {
+            return;
+        }
+        let styles_to_add =
+            WS_CAPTION.0 | WS_THICKFRAME.0 | WS_MINIMIZEBOX.0 | WS_MAXIMIZEBOX.0 | WS_SYSMENU.0;
+        let style = GetWindowLongW(hwnd, GWL_STYLE) | styles_to_add as i32;
+        SetWindowLongW(hwnd, GWL_STYLE, style);
+        let _ = SetWindowPos(
+            hwnd,
+            HWND_NOTOPMOST,
+            0,
+            0,
+            0,
+            0,
+            SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE | SWP_FRAMECHANGED,
+        );
     }
+    resize_window(window, width, height);
+}
 
-    if new_width != *width || new_height != *height {
-        *width = new_width;
-        *height = new_height;
-        buffer.resize(*width * *height, 0);
+#[cfg(not(windows))]
+fn set_window_windowed(_window: &Window, _width: usize, _height: usize) {
+    // No-op on non-Windows
+}
 
-        // Note: minifb 0.28 doesn't support programmatic window resizing after creation.
-        // The buffer will be scaled to fit the current window size.
-        info!("Resolution changed to {}x{}", *width, *height);
+/// Largest size with the aspect ratio of `width`x`height` that fits in `max`
+///
+/// Sizes that already fit are returned unchanged; the window is never
+/// scaled up past the stream resolution.
+fn fit_window_size(width: usize, height: usize, max: (usize, usize)) -> (usize, usize) {
+    let (max_w, max_h) = max;
+    if width == 0 || height == 0 || (width <= max_w && height <= max_h) {
+        return (width, height);
+    }
+    // Scale by whichever side overflows more.
+    if width * max_h > height * max_w {
+        (max_w, (height * max_w / width).max(1))
+    } else {
+        ((width * max_h / height).max(1), max_h)
     }
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-
-    // Initialize logging
-    let level = match args.log_level.as_str() {
-        "trace" => Level::TRACE,
-        "debug" => Level::DEBUG,
-        "info" => Level::INFO,
-        "warn" => Level::WARN,
-        "error" => Level::ERROR,
-        _ => Level::INFO,
+/// Resize the window so its client area matches a `width`x`height` stream
+///
+/// minifb 0.28 can't resize a window after creation, so this goes through
+/// `SetWindowPos` on its HWND. The client area is kept within the work area
+/// of the window's monitor.
+#[cfg(windows)]
+fn resize_window(window: &Window, width: usize, height: usize) {
+    use windows::Win32::Foundation::{HWND, RECT};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        AdjustWindowRectEx, GWL_EXSTYLE, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOZORDER, WINDOW_EX_STYLE,
+        WINDOW_STYLE,
     };
 
-    let subscriber = FmtSubscriber::builder().with_max_level(level).finish();
+    unsafe {
+        let hwnd = HWND(window.get_window_handle() as isize);
+        if hwnd.0 == 0 {
+            return;
+        }
+        let style = WINDOW_STYLE(GetWindowLongW(hwnd, GWL_STYLE) as u32);
+        let ex_style = WINDOW_EX_STYLE(GetWindowLongW(hwnd, GWL_EXSTYLE) as u32);
 
-    tracing::subscriber::set_global_default(subscriber)?;
+        // Size of the borders and title bar around the client area
+        let mut frame = RECT::default();
+        if AdjustWindowRectEx(&mut frame, style, false, ex_style).is_err() {
+            return;
+        }
+        let frame_w = (frame.right - frame.left).max(0) as usize;
+        let frame_h = (frame.bottom - frame.top).max(0) as usize;
 
-    info!("ThunderMirror Windows Receiver v0.2.0");
-    info!("Listening on port: {}", args.port);
-    info!("Fullscreen: {}", args.fullscreen);
-    if args.fullscreen {
-        info!("Press Escape to exit fullscreen");
+        let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTOPRIMARY);
+        let mut mi = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        let (client_w, client_h) = if GetMonitorInfoW(monitor, &mut mi).as_bool() {
+            let work_w = (mi.rcWork.right - mi.rcWork.left).max(0) as usize;
+            let work_h = (mi.rcWork.bottom - mi.rcWork.top).max(0) as usize;
+            let max = (
+                work_w.saturating_sub(frame_w),
+                work_h.saturating_sub(frame_h),
+            );
+            fit_window_size(width, height, max)
+        } else {
+            (width, height)
+        };
+
+        if let Err(e) = SetWindowPos(
+            hwnd,
+            None,
+            0,
+            0,
+            (client_w + frame_w) as i32,
+            (client_h + frame_h) as i32,
+            SWP_NOMOVE | SWP_NOZORDER | SWP_NOACTIVATE,
+        ) {
+            warn!("Failed to resize window: {}", e);
+        }
     }
+}
 
-    // Create tokio runtime
-    let rt = tokio::runtime::Runtime::new()?;
+#[cfg(not(windows))]
+fn resize_window(_window: &Window, _width: usize, _height: usize) {
+    // No-op on non-Windows; the frame is scaled to the window instead
+}
 
-    // Run QUIC server in background and receive frames
-    // Larger buffer to handle frame bursts and prevent backpressure
-    let (tx, mut rx) = mpsc::channel::<FrameData>(60);
+/// Window title with the paused marker appended while paused
+fn with_pause_tag(title: &str, paused: bool) -> String {
+    if paused {
+        format!("{} [PAUSED]", title)
+    } else {
+        title.to_string()
+    }
+}
+
+/// Current wall-clock time in microseconds since the UNIX epoch
+fn unix_time_us() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+/// One-way latency of a frame given the sender's clock-sync epoch
+///
+/// Returns `None` if the result would be negative, which means the clocks
+/// disagree more than the actual latency and the number is meaningless.
+fn frame_latency_ms(sender_epoch_us: u64, timestamp_us: u64, now_us: u64) -> Option<f64> {
+    let sent_us = sender_epoch_us.checked_add(timestamp_us)?;
+    now_us
+        .checked_sub(sent_us)
+        .map(|latency_us| latency_us as f64 / 1000.0)
+}
+
+/// Composite the tiles of a `DeltaFrame` payload onto the current frame
+///
+/// Returns the number of tiles drawn. Nothing is drawn unless every tile
+/// decodes and fits, so a bad delta never leaves a half-updated frame.
+fn apply_delta_frame(
+    buffer: &mut [u32],
+    width: usize,
+    height: usize,
+    payload: &Bytes,
+) -> anyhow::Result<usize> {
+    let tiles = DeltaTile::decode_all(payload)?;
+    let (Ok(frame_w), Ok(frame_h)) = (u16::try_from(width), u16::try_from(height)) else {
+        anyhow::bail!("Frame {}x{} too large for delta tiles", width, height);
+    };
+    if let Some(tile) = tiles.iter().find(|t| !t.fits_within(frame_w, frame_h)) {
+        anyhow::bail!(
+            "Tile {}x{} at ({}, {}) outside {}x{} frame",
+            tile.width,
+            tile.height,
+            tile.x,
+            tile.y,
+            width,
+            height
+        );
+    }
 
-    let port = args.port;
-    rt.spawn(async move {
-        if let Err(e) = run_quic_server(port, tx).await {
-            error!("QUIC server error: {}", e);
+    for tile in &tiles {
+        let tile_w = tile.width as usize;
+        if tile_w == 0 {
+            continue;
         }
-    });
+        for (row, rgba_row) in tile.data.chunks_exact(tile_w * 4).enumerate() {
+            let start = (tile.y as usize + row) * width + tile.x as usize;
+            for (pixel, rgba) in buffer[start..start + tile_w]
+                .iter_mut()
+                .zip(rgba_row.chunks_exact(4))
+            {
+                *pixel = ((rgba[0] as u32) << 16) | ((rgba[1] as u32) << 8) | rgba[2] as u32;
+            }
+        }
+    }
+    Ok(tiles.len())
+}
 
-    // Initialize H.264 decoder
-    let mut h264_decoder = Decoder::new().expect("Failed to create H.264 decoder");
-    info!("H.264 decoder initialized (OpenH264)");
+/// One of the windows the stream is shown in
+struct Display {
+    window: Window,
+    // Declared after `window` so it is dropped after the window is destroyed
+    dpi: dpi::DpiWatch,
+    fullscreen: bool,
+    scale_mode: ScaleMode,
+    // The frame resampled to the window size, with --scaling-quality bilinear
+    scaled: Vec<u32>,
+}
 
-    // Initialize window with default size (will resize when we receive frames)
-    let mut width: usize = 1920;
-    let mut height: usize = 1080;
-    let mut buffer: Vec<u32> = vec![0; width * height];
+impl Display {
+    /// Show a `width`x`height` frame, resampling it to the window size first
+    /// if it's larger and `quality` asks for it
+    fn show(
+        &mut self,
+        buffer: &[u32],
+        width: usize,
+        height: usize,
+        quality: scale::ScalingQuality,
+    ) -> minifb::Result<()> {
+        let window = self.window.get_size();
+        let (w, h) = match self.scale_mode {
+            ScaleMode::AspectRatioStretch => fit_window_size(width, height, window),
+            _ => window,
+        };
+        let shrink = quality == scale::ScalingQuality::Bilinear
+            && self.scale_mode != ScaleMode::Center
+            && (1..=width).contains(&w)
+            && (1..=height).contains(&h)
+            && (w, h) != (width, height);
+        if !shrink {
+            return self.window.update_with_buffer(buffer, width, height);
+        }
+        scale::bilinear(buffer, width, height, w, h, &mut self.scaled);
+        self.window.update_with_buffer(&self.scaled, w, h)
+    }
 
-    let (window_width, window_height) = if args.fullscreen {
+    /// Fit the window to its monitor again after it moved to one with a
+    /// different DPI
+    fn refit_after_dpi_change(&self, width: usize, height: usize) {
+        if !self.dpi.take_changed() {
+            return;
+        }
+        if self.fullscreen {
+            set_window_fullscreen(&self.window);
+        } else {
+            resize_window(&self.window, width, height);
+        }
+    }
+
+    /// Handle the exit and leave-fullscreen keys; false if the window should
+    /// close
+    fn handle_window_keys(&mut self, args: &Args, width: usize, height: usize) -> bool {
+        if !self.window.is_open() {
+            return false;
+        }
+        // Checked first so the same key can leave fullscreen, then quit.
+        let leave_fullscreen = args.leave_fullscreen_key.as_ref();
+        if self.fullscreen && leave_fullscreen.is_some_and(|key| key.pressed(&self.window)) {
+            set_window_windowed(&self.window, width, height);
+            self.fullscreen = false;
+            return true;
+        }
+        !args.exit_key.pressed(&self.window)
+    }
+}
+
+/// Pace the display loop to `fps` with the first window
+///
+/// Each update sleeps to its window's target rate, so only the first one
+/// gets one. Called again when windows close, or the window that moved up
+/// would leave the loop unthrottled. 0 leaves it unthrottled on purpose.
+fn pace_displays(displays: &mut [Display], fps: u8) {
+    for (i, display) in displays.iter_mut().enumerate() {
+        let fps = if i == 0 { fps.into() } else { 0 };
+        display.window.set_target_fps(fps);
+    }
+}
+
+/// Open a window for `spec` titled `title`, sized for a `width`x`height` stream
+fn open_display(
+    spec: display::WindowSpec,
+    title: &str,
+    width: usize,
+    height: usize,
+) -> anyhow::Result<Display> {
+    let (window_width, window_height) = if spec.fullscreen {
         // Get the primary monitor dimensions for true fullscreen
         get_screen_dimensions().unwrap_or((width, height))
     } else {
         (width, height)
     };
 
-    let window_opts = if args.fullscreen {
+    let window_opts = if spec.fullscreen {
         WindowOptions {
             resize: false,
             borderless: true,
             topmost: true,
+            scale_mode: spec.scale_mode,
             ..Default::default()
         }
     } else {
         WindowOptions {
             resize: true,
+            scale_mode: spec.scale_mode,
             ..Default::default()
         }
     };
 
-    let mut window = Window::new(
-        "ThunderMirror - Waiting for stream...",
-        window_width,
-        window_height,
-        window_opts,
-    )?;
+    let window = Window::new(title, window_width, window_height, window_opts)?;
 
     // For true fullscreen, position window at (0,0) to cover entire screen
-    if args.fullscreen {
+    if spec.fullscreen {
         set_window_fullscreen(&window);
     }
 
-    // Limit to ~60 fps for display
-    window.set_target_fps(60);
+    let dpi = dpi::DpiWatch::new(&window);
+    Ok(Display {
+        window,
+        dpi,
+        fullscreen: spec.fullscreen,
+        scale_mode: spec.scale_mode,
+        scaled: Vec::new(),
+    })
+}
 
-    let mut last_stats = Instant::now();
-    let mut frame_count = 0u64;
-    let mut total_bytes = 0u64;
-    let mut h264_frames = 0u64;
-    let mut raw_frames = 0u64;
+/// Whether `key` was just pressed in any of the windows
+fn key_pressed(displays: &[Display], key: Key) -> bool {
+    displays
+        .iter()
+        .any(|display| display.window.is_key_pressed(key, KeyRepeat::No))
+}
 
-    info!("Window created, waiting for frames...");
+/// Switch the frame buffer to a new resolution
+///
+/// Windowed displays are resized to match too. Fullscreen ones keep
+/// covering the monitor and the frame is scaled to fit.
+fn resize_window_and_buffers(
+    displays: &[Display],
+    width: &mut usize,
+    height: &mut usize,
+    buffer: &mut Vec<u32>,
+    new_width: usize,
+    new_height: usize,
+) {
+    if new_width == 0 || new_height == 0 {
+        return;
+    }
 
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        // Check for new frames (non-blocking)
-        while let Ok(frame) = rx.try_recv() {
-            let new_width = frame.width as usize;
-            let new_height = frame.height as usize;
+    if new_width != *width || new_height != *height {
+        *width = new_width;
+        *height = new_height;
+        buffer.resize(*width * *height, 0);
 
-            // Resize window + buffer if sender resolution changed.
-            resize_window_and_buffers(
-                &mut window,
-                &mut width,
-                &mut height,
-                &mut buffer,
-                new_width,
-                new_height,
-            );
+        for display in displays.iter().filter(|display| !display.fullscreen) {
+            resize_window(&display.window, *width, *height);
+        }
+        info!("Resolution changed to {}x{}", *width, *height);
+    }
+}
 
-            match frame.frame_type {
-                FrameType::H264 => {
-                    // Decode H.264 frame
-                    match h264_decoder.decode(&frame.rgba_data) {
-                        Ok(Some(decoded)) => {
-                            // Get dimensions from decoded frame
-                            let (dec_width, dec_height) = decoded.dimensions();
+fn main() -> anyhow::Result<()> {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches)?;
+    let mut file_config = match &args.config {
+        Some(path) => Config::load_over(path, Config::win_receiver())?,
+        None => Config::win_receiver(),
+    };
+    file_config.apply_env()?;
+    args.apply_config(&file_config, &matches);
+    let config = args.to_config(file_config);
+    config.validate()?;
+    if args.connect.is_some() && args.transport == TransportKind::Tcp {
+        anyhow::bail!("--connect only works with --transport quic");
+    }
+    MAX_FRAME_PAYLOAD_SIZE.store(config.max_frame_size, Ordering::Relaxed);
+    MAX_FRAME_SIDE.store(config.max_frame_dimension, Ordering::Relaxed);
 
-                            // If decoder output dims differ from header, trust decoder.
-                            resize_window_and_buffers(
-                                &mut window,
-                                &mut width,
-                                &mut height,
-                                &mut buffer,
-                                dec_width,
-                                dec_height,
-                            );
+    // Initialize logging
+    let level = match args.log_level.as_str() {
+        "trace" => Level::TRACE,
+        "debug" => Level::DEBUG,
+        "info" => Level::INFO,
+        "warn" => Level::WARN,
+        "error" => Level::ERROR,
+        _ => Level::INFO,
+    };
 
-                            // Convert YUV to RGB directly to u32 buffer using BT.709 full range
-                            // This gives much better color accuracy than write_rgb8()
-                            let y_plane = decoded.y();
-                            let u_plane = decoded.u();
-                            let v_plane = decoded.v();
-                            let y_stride = decoded.strides().0;
-                            let u_stride = decoded.strides().1;
-                            let v_stride = decoded.strides().2;
-
-                            for row in 0..dec_height {
-                                for col in 0..dec_width {
-                                    let y_idx = row * y_stride + col;
-                                    // U and V are subsampled 2x2 (YUV 4:2:0)
-                                    let uv_row = row / 2;
-                                    let uv_col = col / 2;
-                                    let u_idx = uv_row * u_stride + uv_col;
-                                    let v_idx = uv_row * v_stride + uv_col;
-
-                                    let y = y_plane[y_idx];
-                                    let u = u_plane[u_idx];
-                                    let v = v_plane[v_idx];
-
-                                    let (r, g, b) = yuv_to_rgb_bt709_limited(y, u, v);
-                                    
-                                    let pixel_idx = row * dec_width + col;
-                                    if pixel_idx < buffer.len() {
-                                        buffer[pixel_idx] = ((r as u32) << 16) | ((g as u32) << 8) | (b as u32);
-                                    }
-                                }
-                            }
-                            h264_frames += 1;
-                        }
-                        Ok(None) => {
-                            // Decoder needs more data (buffering)
-                            debug!("H.264 decoder buffering...");
-                        }
-                        Err(e) => {
-                            warn!("H.264 decode error: {:?}", e);
-                        }
-                    }
-                }
-                FrameType::Raw => {
-                    // Raw RGBA data - convert directly
-                    let max_pixels = buffer.len().min(frame.rgba_data.len() / 4);
-                    for i in 0..max_pixels {
-                        let base = i * 4;
-                        let r = frame.rgba_data[base] as u32;
-                        let g = frame.rgba_data[base + 1] as u32;
-                        let b = frame.rgba_data[base + 2] as u32;
-                        buffer[i] = (r << 16) | (g << 8) | b;
-                    }
-                    raw_frames += 1;
-                }
-                _ => {
-                    debug!("Ignoring frame type: {:?}", frame.frame_type);
-                }
-            }
+    // Keep stdout for status lines in --status-json mode.
+    let builder = FmtSubscriber::builder().with_max_level(level);
+    if args.status_json {
+        STATUS_JSON.store(true, Ordering::Relaxed);
+        tracing::subscriber::set_global_default(builder.with_writer(std::io::stderr).finish())?;
+    } else {
+        tracing::subscriber::set_global_default(builder.finish())?;
+    }
 
-            frame_count += 1;
-            total_bytes += frame.rgba_data.len() as u64;
+    info!("ThunderMirror Windows Receiver v0.2.0");
+    if let Some(path) = &args.config {
+        info!("Loaded config from {}", path.display());
+    }
+    match (&args.replay, args.connect) {
+        (Some(path), _) => info!("Replaying recording: {}", path.display()),
+        (None, Some(sender)) => info!("Connecting to: {}", sender),
+        (None, None) => info!("Listening on: {}", args.listen_addr()),
+    }
+    let window_specs = args.window_specs();
+    if args.headless {
+        info!("Headless: counting frames without showing them, Ctrl+C to stop");
+    } else {
+        if window_specs.len() > 1 {
+            info!("Windows: {}", window_specs.len());
+        } else {
+            info!("Fullscreen: {}", window_specs[0].fullscreen);
+        }
+        let any_fullscreen = window_specs.iter().any(|spec| spec.fullscreen);
+        if let Some(key) = args.leave_fullscreen_key.filter(|_| any_fullscreen) {
+            info!("Press {} to leave fullscreen", key);
+        }
+        info!("Press {} to close a window", args.exit_key);
+        if !args.forward_input {
+            info!("Press Space to pause/resume the stream");
+        }
+        info!("Press I to toggle the status overlay");
+        info!("Press S to save a screenshot, C to copy the frame to the clipboard");
+        info!("Press H to compare the sender's frame rate with the receiver's");
+        if args.forward_input {
+            info!("These keys are also forwarded to the sender, and Space only goes there");
         }
+    }
 
-        // Update window
-        window.update_with_buffer(&buffer, width, height)?;
+    // Window and monitor sizes are in physical pixels from here on.
+    dpi::enable_per_monitor_awareness();
 
-        // Log stats every second
-        if last_stats.elapsed() >= Duration::from_secs(1) {
-            let fps = frame_count as f64 / last_stats.elapsed().as_secs_f64();
-            let mbps =
-                (total_bytes as f64 * 8.0) / (last_stats.elapsed().as_secs_f64() * 1_000_000.0);
-            let codec = if h264_frames > raw_frames {
-                "H.264"
-            } else {
-                "raw"
-            };
-            info!(
-                "Stats: {:.1} FPS, {:.1} Mbps, {} (h264:{}, raw:{})",
-                fps, mbps, codec, h264_frames, raw_frames
-            );
+    // Without an H.264 decoder raw frames still work; H.264 frames are dropped.
+    // It's created before any sender connects, so senders can be asked for raw
+    // frames instead. Headless mode decodes nothing.
+    let mut h264_decoder = None;
+    if !args.headless {
+        match decoder::VideoDecoder::new(args.decoder) {
+            Ok(decoder) => h264_decoder = Some(decoder),
+            Err(e) => {
+                warn!("No H.264 decoder, only raw frames can be shown: {}", e);
+                report_status(StatusEvent::DecoderUnavailable {
+                    message: e.to_string(),
+                });
+            }
+        }
+    }
 
-            window.set_title(&format!(
-                "ThunderMirror - {}x{} @ {:.0} FPS, {:.0} Mbps [{}]",
-                width, height, fps, mbps, codec
-            ));
+    // Create tokio runtime
+    let rt = tokio::runtime::Runtime::new()?;
 
-            frame_count = 0;
-            total_bytes = 0;
-            h264_frames = 0;
-            raw_frames = 0;
-            last_stats = Instant::now();
+    // Run QUIC server in background and receive frames
+    let (tx, mut rx) = mpsc::channel::<FrameData>(args.frame_queue.into());
+
+    // Shared with the network tasks so arrival jitter is measured before queueing.
+    let stats = Stats::new();
+
+    // Bumped whenever a sender connects or disconnects, so the display loop
+    // starts its link estimates over instead of carrying them to the next one.
+    let connection_changes = Arc::new(AtomicUsize::new(0));
+
+    // Control messages for the sender (e.g. keyframe requests), written on every bi stream.
+    // Sized for bursts of forwarded input; a lagging stream skips what it missed.
+    let (control_tx, _) = broadcast::channel::<ControlMessage>(256);
+    let tx = backpressure::FrameSender::new(tx, args.backpressure)
+        .with_keyframe_requests(control_tx.clone());
+
+    let recorder = match &args.record {
+        Some(path) => {
+            let recorder = recording::RecordingWriter::create(path)?;
+            info!("Recording received frames to {}", path.display());
+            Some(Arc::new(recorder))
         }
+        None => None,
+    };
+
+    let server_stats = stats.clone();
+    if let Some(path) = args.replay.clone() {
+        let from = args.replay_from;
+        rt.spawn(async move {
+            if let Err(e) = run_replay(path, from, tx, server_stats).await {
+                error!("Replay error: {}", e);
+            }
+        });
+    } else {
+        let (ip_mode, port) = (args.ip_mode(), args.port);
+        let listen = args.listen.is_some().then(|| args.listen_addr());
+        let connect = args.connect;
+        let tuning = config.transport.clone();
+        let cert = args.self_signed_cert();
+        let server_control = control_tx.clone();
+        // Sent on every control stream: our version and features, then the
+        // frame rate so the sender paces capture to the display, and the
+        // encoding we'd like for senders that read `Start`: the screen's
+        // size, and the bitrate that bitrate feedback starts from.
+        let start = args.start_message(
+            get_screen_dimensions(),
+            args.headless || h264_decoder.is_some(),
+            args.max_bitrate_kbps,
+        );
+        let greeting = vec![
+            ControlMessage::HelloAck {
+                protocol_version: PROTOCOL_VERSION,
+                capabilities: args.capabilities(),
+                audio_codecs: audio::DECODABLE_CODECS.to_vec(),
+            },
+            ControlMessage::SetFps { fps: args.fps },
+            start,
+        ];
+        let transport = args.transport;
+        let name = match transport {
+            TransportKind::Quic => "QUIC",
+            TransportKind::Tcp => "TCP",
+        };
+        let (events, mut event_rx) = mpsc::unbounded_channel();
+        let changes = connection_changes.clone();
+        rt.spawn(async move {
+            while let Some(event) = event_rx.recv().await {
+                report_connection_event(name, &event);
+                if matches!(
+                    event,
+                    ConnectionEvent::Accepted { .. } | ConnectionEvent::Closed { .. }
+                ) {
+                    changes.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+        rt.spawn(async move {
+            let result = match transport {
+                TransportKind::Quic => {
+                    async {
+                        if let Some(sender) = connect {
+                            return run_quic_client(
+                                sender,
+                                &tuning,
+                                tx,
+                                server_stats,
+                                server_control,
+                                greeting,
+                                recorder,
+                                events.clone(),
+                            )
+                            .await;
+                        }
+                        let server = match listen {
+                            Some(addr) => {
+                                QuicServer::with_self_signed(addr, &tuning, &cert).await?
+                            }
+                            None => {
+                                QuicServer::listen_self_signed(ip_mode, port, &tuning, &cert)
+                                    .await?
+                            }
+                        };
+                        run_quic_server(
+                            server,
+                            tx,
+                            server_stats,
+                            server_control,
+                            greeting,
+                            recorder,
+                            events.clone(),
+                        )
+                        .await
+                    }
+                    .await
+                }
+                TransportKind::Tcp => {
+                    async {
+                        let server = match listen {
+                            Some(addr) => TcpServer::bind(addr).await?,
+                            None => TcpServer::listen(ip_mode, port).await?,
+                        };
+                        run_tcp_server(
+                            server,
+                            tx,
+                            server_stats,
+                            server_control,
+                            greeting,
+                            recorder,
+                            events.clone(),
+                        )
+                        .await
+                    }
+                    .await
+                }
+            };
+            if let Err(e) = result {
+                let _ = events.send(ConnectionEvent::Error {
+                    addr: None,
+                    msg: format!("{} server error: {}", name, e),
+                });
+            }
+        });
     }
 
-    info!("Window closed, shutting down...");
-    Ok(())
-}
+    if let Some(metrics_addr) = args.metrics_addr() {
+        let metrics_stats = stats.clone();
+        rt.spawn(async move {
+            if let Err(e) = metrics::serve(metrics_addr, metrics_stats).await {
+                error!("Metrics endpoint error: {}", e);
+            }
+        });
+    }
+
+    if args.headless {
+        return rt.block_on(run_headless(rx, stats));
+    }
+
+    let mut keyframe_requester =
+        decoder::KeyframeRequester::new(KEYFRAME_ERROR_THRESHOLD, KEYFRAME_REQUEST_INTERVAL);
+
+    // Initialize window with default size (will resize when we receive frames)
+    let mut width: usize = 1920;
+    let mut height: usize = 1080;
+    let mut buffer: Vec<u32> = vec![0; width * height];
+
+    // A --title stays put; otherwise the title shows the stream's stats.
+    let mut title = args
+        .title
+        .clone()
+        .unwrap_or_else(|| String::from("ThunderMirror - Waiting for stream..."));
+
+    let mut displays = window_specs
+        .into_iter()
+        .map(|spec| open_display(spec, &title, width, height))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    pace_displays(&mut displays, args.fps);
+
+    // Audio output is opened lazily on the first audio frame.
+    let mut audio_player: Option<audio::AudioPlayer> = None;
+    let mut audio_unavailable = false;
+
+    // Latency needs the sender's epoch from a ClockSync control message.
+    let mut sender_epoch_us: Option<u64> = None;
+    let mut timestamps = clock::TimestampGuard::default();
+    // Latest stats frame from the sender, reported with our own once a second
+    let mut sender_stats: Option<StatsSnapshot> = None;
+    let mut bitrate = bitrate::BitrateEstimator::new(args.min_bitrate_kbps, args.max_bitrate_kbps);
+    let mut seen_connection_changes = 0;
+
+    // H.264 deltas decoded before their keyframe show up as green/garbage frames.
+    let mut awaiting_keyframe = true;
+    let mut warned_bit_depth = false;
+    let mut warned_no_decoder = false;
+    let mut range_detector = color::RangeDetector::new();
+
+    let mut input_forwarder = args.forward_input.then(input::InputForwarder::new);
+
+    let mut last_stats = Instant::now();
+    let mut frame_count = 0u64;
+    let mut total_bytes = 0u64;
+    // Out-of-order and duplicate counts as of the last summary
+    let mut reported_reordering = (0, 0);
+    // Frames shown, for the smoothed rates in the title bar and status
+    let shown = stats.meter("display");
+    // Codec of the last frame shown, for the title bar and status
+    let mut codec = "raw";
+
+    // Space toggles pause: the last frame stays up and the sender is asked to stop.
+    // Not while forwarding input, where every Space typed remotely would pause.
+    let mut paused = false;
+
+    // I toggles a status overlay on the video, for fullscreen where there's no title bar.
+    let mut show_overlay = false;
+    let mut overlay_stats = String::new();
+    // H toggles the sender's numbers next to ours, from the latest stats frame.
+    let mut show_hud = false;
+    let mut hud_text = String::new();
+    let mut hud_sender: Option<StatsSnapshot> = None;
+    let mut last_video_at: Option<Instant> = None;
+
+    // Shown over the last frame once the video has stopped for a while
+    let no_signal_after = Duration::from_secs(args.no_signal_after_secs.into());
+    let mut no_signal = no_signal::NoSignal::new(no_signal_after);
+    let mut waiting_since = Instant::now();
+
+    // Pointer sent alongside the video by senders that capture without it
+    let mut cursor = cursor::Cursor::new();
+
+    info!("Window created, waiting for frames...");
 
-async fn run_quic_server(port: u16, tx: mpsc::Sender<FrameData>) -> anyhow::Result<()> {
-    let addr: SocketAddr = format!("0.0.0.0:{}", port).parse()?;
-    let server_config = create_server_config()?;
-    let endpoint = Endpoint::server(server_config, addr)?;
+    // Only video frames are reordered; 0 ms keeps arrival order.
+    let jitter_buffer = Duration::from_millis(args.jitter_buffer_ms.into());
+    let mut reorder =
+        (!jitter_buffer.is_zero()).then(|| reorder::ReorderBuffer::new(jitter_buffer));
 
-    info!("QUIC server listening on {}", addr);
+    // Frames ready to handle this iteration
+    let mut backlog = VecDeque::new();
 
     loop {
-        let incoming = endpoint.accept().await;
-        if let Some(connecting) = incoming {
-            let tx = tx.clone();
-            tokio::spawn(async move {
-                match connecting.await {
-                    Ok(conn) => {
-                        info!("Connection accepted from {}", conn.remote_address());
-                        if let Err(e) = handle_connection(conn, tx).await {
-                            error!("Connection error: {}", e);
+        // Closing one window, or the exit key in it, leaves the others showing the stream.
+        let open = displays.len();
+        displays.retain_mut(|display| display.handle_window_keys(&args, width, height));
+        if displays.is_empty() {
+            break;
+        }
+        if displays.len() < open {
+            info!("Window closed, {} still open", displays.len());
+            pace_displays(&mut displays, args.fps);
+        }
+
+        // A new sender has a link of its own; don't suggest it the last one's bitrate.
+        let changes = connection_changes.load(Ordering::Relaxed);
+        if changes != seen_connection_changes {
+            seen_connection_changes = changes;
+            bitrate.reset();
+        }
+
+        // Check for new frames (non-blocking); if we fell behind, skip to the freshest video
+        while let Some(frame) = next_frame(&mut rx, reorder.as_mut(), &stats) {
+            backlog.push_back(frame);
+        }
+        if args.backpressure == backpressure::BackpressurePolicy::Latest {
+            let skipped = backpressure::coalesce(&mut backlog);
+            if skipped > 0 {
+                debug!("Skipped {} stale video frames", skipped);
+                for _ in 0..skipped {
+                    stats.record_drop();
+                }
+            }
+        }
+        while let Some(mut frame) = backlog.pop_front() {
+            if frame.frame_type == FrameType::Audio {
+                if audio_player.is_none() && !audio_unavailable {
+                    match audio::AudioPlayer::new(args.audio_buffer_ms) {
+                        Ok(player) => audio_player = Some(player),
+                        Err(e) => {
+                            warn!("Audio playback unavailable: {}", e);
+                            audio_unavailable = true;
                         }
                     }
-                    Err(e) => {
-                        error!("Connection failed: {}", e);
-                    }
                 }
-            });
-        }
-    }
-}
-
-async fn handle_connection(
-    conn: quinn::Connection,
-    tx: mpsc::Sender<FrameData>,
-) -> anyhow::Result<()> {
-    // macOS uses Network.framework's QUIC via NWConnection, which commonly maps to a
-    // client-initiated bidirectional stream rather than per-frame unidirectional streams.
-    //
-    // To maximize interop, accept BOTH uni and bi streams. For bi streams, parse a continuous
-    // byte stream containing repeated (header + payload) frames.
-
-    let conn_bi = conn.clone();
-    let conn_uni = conn.clone();
-    let conn_dgram = conn;
-
-    let tx_bi = tx.clone();
-    let bi_task = tokio::spawn(async move {
-        loop {
-            match conn_bi.accept_bi().await {
-                Ok((_send, mut recv)) => {
-                    info!("Accepted bidirectional stream; starting frame parser");
-                    if let Err(e) = handle_frame_byte_stream(&mut recv, tx_bi.clone()).await {
-                        warn!("Bidirectional stream handler error: {}", e);
+                if let Some(player) = audio_player.as_mut() {
+                    if let Err(e) = player.push_frame(&frame.rgba_data) {
+                        debug!("Dropping audio frame: {}", e);
                     }
                 }
-                Err(e) => {
-                    info!("Connection closed (bi accept): {}", e);
-                    break;
+                continue;
+            }
+
+            if frame.frame_type == FrameType::Cursor {
+                // Drawn over whatever is on screen, so a move alone needs no video frame.
+                let payload = Bytes::from(std::mem::take(&mut frame.rgba_data));
+                match CursorUpdate::decode(&payload) {
+                    Ok(update) => cursor.update(&update),
+                    Err(e) => warn!("Invalid cursor update: {}", e),
                 }
+                continue;
             }
-        }
-    });
 
-    let tx_uni = tx.clone();
-    let uni_task = tokio::spawn(async move {
-        loop {
-            match conn_uni.accept_uni().await {
-                Ok(mut recv) => {
-                    // Legacy path: one frame per unidirectional stream.
-                    let data = match recv
-                        .read_to_end(MAX_FRAME_PAYLOAD_SIZE + FRAME_HEADER_SIZE)
-                        .await
-                    {
-                        Ok(d) => d,
-                        Err(e) => {
-                            warn!("Failed reading uni stream: {}", e);
-                            continue;
+            if paused && frame.frame_type.is_video() {
+                // Still in flight from before the sender saw Stop.
+                continue;
+            }
+
+            let new_width = frame.width as usize;
+            let new_height = frame.height as usize;
+
+            let frame_bytes = frame.rgba_data.len() as u64;
+            frame_count += 1;
+            total_bytes += frame_bytes;
+            shown.record_frame(frame_bytes);
+
+            // Resize window + buffer if sender resolution changed. Deltas only
+            // patch the current frame, so they never change the resolution.
+            if frame.frame_type != FrameType::DeltaFrame {
+                resize_window_and_buffers(
+                    &displays,
+                    &mut width,
+                    &mut height,
+                    &mut buffer,
+                    new_width,
+                    new_height,
+                );
+            }
+
+            let mut displayed = false;
+            match frame.frame_type {
+                FrameType::H264Frame if frame.bit_depth > 8 && !cfg!(feature = "hdr") => {
+                    if !warned_bit_depth {
+                        warn!(
+                            "Dropping {}-bit video; the receiver was built without the hdr feature",
+                            frame.bit_depth
+                        );
+                        warned_bit_depth = true;
+                    }
+                    stats.record_drop();
+                }
+                FrameType::H264Frame if awaiting_keyframe && !frame.is_keyframe => {
+                    debug!(
+                        "Skipping H.264 frame before first keyframe (seq={})",
+                        frame.sequence
+                    );
+                }
+                FrameType::H264Frame => {
+                    let Some(decoder) = h264_decoder.as_mut() else {
+                        if !warned_no_decoder {
+                            warn!("Dropping H.264 video; no decoder is available");
+                            warned_no_decoder = true;
                         }
+                        stats.record_drop();
+                        continue;
                     };
+                    awaiting_keyframe = false;
+                    match decoder.decode(&frame.rgba_data) {
+                        Ok(Some(decoded)) => {
+                            // If decoder output dims differ from header, trust decoder.
+                            resize_window_and_buffers(
+                                &displays,
+                                &mut width,
+                                &mut height,
+                                &mut buffer,
+                                decoded.width,
+                                decoded.height,
+                            );
 
-                    if data.len() < FRAME_HEADER_SIZE {
-                        warn!("Received uni data too small for header");
-                        continue;
-                    }
+                            let planes = decoded.planes();
+                            if frame.color_range.is_none() {
+                                range_detector.sample(&planes, decoded.width, decoded.height);
+                            }
+                            let range = args
+                                .color_range
+                                .resolve(frame.color_range, range_detector.range());
 
-                    if let Err(e) = handle_single_frame_datagramlike(data, tx_uni.clone()).await {
-                        warn!("Failed to parse uni frame: {}", e);
+                            // Convert YUV to RGB directly to u32 buffer using the
+                            // sender-declared matrix and range (BT.709 for v1 senders,
+                            // with the range guessed from the picture).
+                            // This gives much better color accuracy than write_rgb8()
+                            if args.mono {
+                                color::yuv_to_gray(
+                                    &planes,
+                                    decoded.width,
+                                    decoded.height,
+                                    &mut buffer,
+                                    range,
+                                );
+                            } else {
+                                color::yuv_to_rgb(
+                                    &planes,
+                                    decoded.width,
+                                    decoded.height,
+                                    &mut buffer,
+                                    frame.color_space,
+                                    range,
+                                    frame.transfer,
+                                );
+                            }
+                            codec = "H.264";
+                            displayed = true;
+                            keyframe_requester.record_success();
+                        }
+                        Ok(None) => {
+                            // Decoder needs more data (buffering)
+                            debug!("H.264 decoder buffering...");
+                        }
+                        Err(e) => {
+                            warn!("H.264 decode error: {}", e);
+                            stats.record_drop();
+                            if keyframe_requester.record_error(Instant::now()) {
+                                info!("Requesting keyframe after repeated decode errors");
+                                // No receivers just means no bi stream to send it on yet.
+                                let _ = control_tx.send(ControlMessage::RequestKeyframe);
+                            }
+                        }
+                    }
+                }
+                FrameType::RawFrame => {
+                    // Raw RGBA data - convert directly
+                    for (pixel, rgba) in buffer.iter_mut().zip(frame.rgba_data.chunks_exact(4)) {
+                        let r = rgba[0] as u32;
+                        let g = rgba[1] as u32;
+                        let b = rgba[2] as u32;
+                        *pixel = (r << 16) | (g << 8) | b;
+                    }
+                    codec = "raw";
+                    displayed = true;
+                }
+                FrameType::DeltaFrame => {
+                    if (new_width, new_height) != (width, height) {
+                        // Tiles from before a resolution change; wait for the next full frame.
+                        debug!(
+                            "Dropping {}x{} delta frame, display is {}x{}",
+                            new_width, new_height, width, height
+                        );
+                        stats.record_drop();
+                    } else {
+                        let payload = Bytes::from(std::mem::take(&mut frame.rgba_data));
+                        match apply_delta_frame(&mut buffer, width, height, &payload) {
+                            Ok(_) => {
+                                codec = "raw";
+                                displayed = true;
+                            }
+                            Err(e) => {
+                                warn!("Invalid delta frame: {}", e);
+                                stats.record_drop();
+                            }
+                        }
                     }
                 }
-                Err(e) => {
-                    info!("Connection closed (uni accept): {}", e);
-                    break;
+                FrameType::Control => match ControlMessage::decode(&frame.rgba_data) {
+                    Ok(ControlMessage::ClockSync { .. }) if args.replay.is_some() => {
+                        // The recorded epoch is in the past, so latency would be meaningless.
+                        debug!("Ignoring clock sync from recording");
+                    }
+                    Ok(ControlMessage::ClockSync {
+                        sender_epoch_us: epoch,
+                    }) => {
+                        info!("Clock sync received (sender epoch {} us)", epoch);
+                        sender_epoch_us = Some(epoch);
+                        timestamps.reset();
+                    }
+                    Ok(ControlMessage::ResolutionChange {
+                        width: new_width,
+                        height: new_height,
+                    }) => {
+                        resize_window_and_buffers(
+                            &displays,
+                            &mut width,
+                            &mut height,
+                            &mut buffer,
+                            new_width.into(),
+                            new_height.into(),
+                        );
+                    }
+                    Ok(ControlMessage::Clipboard { mime, data }) => {
+                        if !args.clipboard {
+                            debug!("Ignoring clipboard update (--clipboard not set)");
+                        } else if let Err(e) =
+                            clipboard::text_from_message(&mime, &data).and_then(clipboard::set_text)
+                        {
+                            warn!("Clipboard update ignored: {}", e);
+                        } else {
+                            debug!("Clipboard updated ({} bytes)", data.len());
+                        }
+                    }
+                    Ok(ControlMessage::CursorHidden { hidden }) => cursor.set_hidden(hidden),
+                    Ok(msg) => debug!("Ignoring control message: {:?}", msg),
+                    Err(e) => warn!("Invalid control message: {}", e),
+                },
+                FrameType::Stats => match StatsFrame::decode(&frame.rgba_data) {
+                    Ok(StatsFrame(snapshot)) => sender_stats = Some(snapshot),
+                    Err(e) => warn!("Invalid stats frame: {}", e),
+                },
+                _ => {
+                    debug!("Ignoring frame type: {:?}", frame.frame_type);
                 }
             }
-        }
-    });
 
-    // macOS Network.framework's QUIC integration may deliver application data via QUIC DATAGRAMS
-    // when using NWConnection.send(content:...). Support that as well for maximum interop.
-    let tx_dgram = tx;
-    let dgram_task = tokio::spawn(async move {
-        loop {
-            match conn_dgram.read_datagram().await {
-                Ok(dgram) => {
-                    // Datagram should contain exactly one frame (header + payload).
-                    if let Err(e) =
-                        handle_single_frame_datagramlike(dgram.to_vec(), tx_dgram.clone()).await
+            if displayed {
+                last_video_at = Some(Instant::now());
+                if let Some(us) = frame.encode_time_us {
+                    stats.record_encode_latency(us as f64 / 1000.0);
+                }
+                if let Some(epoch) = sender_epoch_us {
+                    if let Some(back) = timestamps.check(frame.timestamp_us) {
+                        warn!(
+                            "Sender timestamps went back {:.1} s; no latency until its next clock sync",
+                            back.as_secs_f64()
+                        );
+                        sender_epoch_us = None;
+                        stats.record_clock_reset();
+                    } else if let Some(ms) =
+                        frame_latency_ms(epoch, frame.timestamp_us, unix_time_us())
                     {
-                        debug!("Failed to parse datagram frame: {}", e);
+                        stats.record_latency(ms);
                     }
                 }
-                Err(e) => {
-                    info!("Connection closed (datagram recv): {}", e);
-                    break;
+            }
+        }
+
+        // A paused stream is stopped on purpose, so its last frame stays up.
+        let idle = match last_video_at {
+            _ if paused => Duration::ZERO,
+            Some(at) => at.max(waiting_since).elapsed(),
+            None => waiting_since.elapsed(),
+        };
+        let was_showing = no_signal.showing();
+        let card = no_signal.frame(idle, width, height);
+        if card.is_some() != was_showing {
+            if was_showing {
+                info!("Video resumed");
+            } else {
+                info!("No video for {} s, showing no signal", idle.as_secs());
+            }
+        }
+        let screen = card.unwrap_or(&mut buffer);
+
+        // Update window, with the pointer and overlay drawn only for this update
+        let cursor_patch = cursor.draw(screen, width, height);
+        let mut lines = Vec::new();
+        if show_overlay {
+            let since_last_video = last_video_at.map(|t| t.elapsed());
+            lines.push(with_pause_tag(
+                overlay::status_text(since_last_video, &overlay_stats),
+                paused,
+            ));
+        }
+        if show_hud {
+            lines.push(hud_text.clone());
+        }
+        let patch = overlay::draw_text(screen, width, height, &lines.join("\n"));
+        for display in &mut displays {
+            display.show(screen, width, height, args.scaling_quality)?;
+            display.refit_after_dpi_change(width, height);
+        }
+        if let Some(patch) = patch {
+            patch.restore(screen, width);
+        }
+        if let Some(patch) = cursor_patch {
+            patch.restore(screen, width);
+        }
+
+        if key_pressed(&displays, Key::I) {
+            show_overlay = !show_overlay;
+        }
+        if key_pressed(&displays, Key::H) {
+            show_hud = !show_hud;
+        }
+
+        // The pointer is mapped against one window, so input comes from the first.
+        if let Some(forwarder) = input_forwarder.as_mut() {
+            for event in forwarder.poll(&displays[0].window) {
+                // No receivers just means no bi stream to send it on yet.
+                let _ = control_tx.send(ControlMessage::Input(event));
+            }
+        }
+
+        if input_forwarder.is_none() && key_pressed(&displays, Key::Space) {
+            paused = !paused;
+            let message = if paused {
+                info!("Paused");
+                ControlMessage::Stop
+            } else {
+                info!("Resumed");
+                waiting_since = Instant::now();
+                // Whatever was decoded before the pause is stale reference data.
+                awaiting_keyframe = true;
+                // Pick up where the stream and the link left off.
+                args.start_message(
+                    Some((width, height)),
+                    h264_decoder.is_some(),
+                    bitrate.suggested_kbps(),
+                )
+            };
+            // No receivers just means no bi stream to send it on yet.
+            let _ = control_tx.send(message);
+            if !paused {
+                let _ = control_tx.send(ControlMessage::RequestKeyframe);
+            }
+            if args.title.is_none() {
+                for display in &mut displays {
+                    display.window.set_title(&with_pause_tag(&title, paused));
+                }
+            }
+        }
+
+        if key_pressed(&displays, Key::S) {
+            match screenshot::save_png(&buffer, width, height, Path::new(".")) {
+                Ok(path) => info!("Saved screenshot to {}", path.display()),
+                Err(e) => warn!("Failed to save screenshot: {}", e),
+            }
+        }
+
+        if key_pressed(&displays, Key::C) {
+            match clipboard::set_frame(&buffer, width, height) {
+                Ok(()) => info!("Copied frame to the clipboard"),
+                Err(e) => warn!("Failed to copy frame to the clipboard: {}", e),
+            }
+        }
+
+        // Log stats every second
+        if last_stats.elapsed() >= Duration::from_secs(1) {
+            let fps = frame_count as f64 / last_stats.elapsed().as_secs_f64();
+            let mbps =
+                (total_bytes as f64 * 8.0) / (last_stats.elapsed().as_secs_f64() * 1_000_000.0);
+            let snapshot = stats.snapshot();
+            let smoothed = shown.snapshot();
+            let (smoothed_fps, smoothed_mbps) =
+                (smoothed.smoothed_fps, smoothed.smoothed_bitrate_mbps);
+            info!(
+                "Stats: {:.1} FPS, {:.1} Mbps, {} (h264:{}, raw:{}, delta:{})",
+                fps,
+                mbps,
+                codec,
+                snapshot.frame_type(FrameType::H264Frame).frames,
+                snapshot.frame_type(FrameType::RawFrame).frames,
+                snapshot.frame_type(FrameType::DeltaFrame).frames
+            );
+            debug!(
+                "Link: jitter {:.1} ms, loss {:.1}%",
+                snapshot.jitter_ms, snapshot.loss_percent
+            );
+            let reordering = (snapshot.out_of_order_frames, snapshot.duplicate_frames);
+            if reordering != reported_reordering {
+                info!(
+                    "Sequence: {} frames arrived out of order, {} duplicates dropped",
+                    reordering.0.saturating_sub(reported_reordering.0),
+                    reordering.1.saturating_sub(reported_reordering.1)
+                );
+                reported_reordering = reordering;
+            }
+            if let Some((encode, latency)) = snapshot.encode_latency_ms.zip(snapshot.latency_ms) {
+                debug!(
+                    "Latency: capture to encode {:.1} ms, encode to display {:.1} ms",
+                    encode, latency
+                );
+            }
+            if let Some(kbps) = bitrate.update(&snapshot) {
+                info!("Suggesting {} kbps to the sender", kbps);
+                // No receivers just means no bi stream to send it on yet.
+                let _ = control_tx.send(ControlMessage::SetBitrate { kbps });
+            }
+
+            let latency = snapshot
+                .latency_ms
+                .map(|ms| format!(", {:.1} ms", ms))
+                .unwrap_or_default();
+            let no_decoder = if h264_decoder.is_none() {
+                " [NO H.264]"
+            } else {
+                ""
+            };
+            title = format!(
+                "ThunderMirror - {}x{} @ {:.0} FPS{}, {:.0} Mbps [{}]{}",
+                width, height, smoothed_fps, latency, smoothed_mbps, codec, no_decoder
+            );
+            overlay_stats = format!(
+                "{}x{} {:.0} FPS{} {:.1} Mbps {}{}",
+                width, height, smoothed_fps, latency, smoothed_mbps, codec, no_decoder
+            );
+            if args.title.is_none() {
+                for display in &mut displays {
+                    display.window.set_title(&with_pause_tag(&title, paused));
+                }
+            }
+            report_status(StatusEvent::Stats {
+                fps,
+                mbps,
+                width: width as u32,
+                height: height as u32,
+                codec: codec.to_string(),
+                latency_ms: snapshot.latency_ms,
+                smoothed_fps: Some(smoothed_fps),
+                smoothed_mbps: Some(smoothed_mbps),
+            });
+            if let Some(sender) = sender_stats.take() {
+                debug!(
+                    "Sender: {:.1} FPS, loss {:.1}%",
+                    sender.fps, sender.loss_percent
+                );
+                report_status(StatusEvent::SenderStats {
+                    fps: sender.fps,
+                    loss_percent: sender.loss_percent,
+                });
+                hud_sender = Some(sender);
+            }
+            hud_text = hud::text(hud_sender.as_ref(), &snapshot, &smoothed);
+
+            frame_count = 0;
+            total_bytes = 0;
+            last_stats = Instant::now();
+        }
+    }
+
+    info!("Window closed, shutting down...");
+    Ok(())
+}
+
+/// Count frames off the channel without decoding or showing them
+///
+/// For `--headless`: the network tasks still parse every frame and update
+/// `stats`, so this measures what the transport alone delivers. Logs the
+/// rate once a second and a summary on Ctrl+C or once the frames run out,
+/// as at the end of a replay.
+async fn run_headless(mut rx: mpsc::Receiver<FrameData>, stats: Arc<Stats>) -> anyhow::Result<()> {
+    let drained = stats.meter("headless");
+    let mut first_frame_at = None;
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        tokio::select! {
+            frame = rx.recv() => match frame {
+                Some(frame) => {
+                    first_frame_at.get_or_insert_with(Instant::now);
+                    drained.record_frame(frame.rgba_data.len() as u64);
+                }
+                None => break,
+            },
+            _ = ticker.tick() => {
+                let snapshot = drained.snapshot();
+                if snapshot.total_frames > 0 {
+                    info!("Stats: {:.1} FPS, {:.1} Mbps", snapshot.fps, snapshot.bitrate_mbps);
                 }
             }
+            _ = &mut ctrl_c => break,
+        }
+    }
+
+    let secs = first_frame_at.map_or(0.0, |at: Instant| at.elapsed().as_secs_f64());
+    let summary = headless_summary(&drained.snapshot(), &stats.snapshot(), secs);
+    info!("{}", summary);
+    Ok(())
+}
+
+/// One line on what `--headless` took off the channel (`drained`) over
+/// `secs`, and what happened to the rest on the way (`received`)
+fn headless_summary(drained: &StatsSnapshot, received: &StatsSnapshot, secs: f64) -> String {
+    let per_sec = |value: f64| if secs > 0.0 { value / secs } else { 0.0 };
+    let fps = per_sec(drained.total_frames as f64);
+    let mbps = per_sec(drained.total_bytes as f64 * 8.0 / 1_000_000.0);
+    format!(
+        "Received {} frames ({:.1} MB) in {:.1} s: {:.1} FPS, {:.1} Mbps; \
+         {} dropped, {} missing, {} out of order, {} duplicates",
+        drained.total_frames,
+        drained.total_bytes as f64 / 1_000_000.0,
+        secs,
+        fps,
+        mbps,
+        received.dropped_frames,
+        received.missing_frames,
+        received.out_of_order_frames,
+        received.duplicate_frames
+    )
+}
+
+/// Frames with a larger payload are rejected as invalid
+fn max_frame_payload_size() -> usize {
+    MAX_FRAME_PAYLOAD_SIZE.load(Ordering::Relaxed)
+}
+
+/// Frames wider or taller than this are rejected as invalid
+fn max_frame_dimension() -> u16 {
+    MAX_FRAME_SIDE.load(Ordering::Relaxed)
+}
+
+/// Print `event` on stdout if `--status-json` is on
+fn report_status(event: StatusEvent) {
+    if !STATUS_JSON.load(Ordering::Relaxed) {
+        return;
+    }
+    match event.to_json() {
+        Ok(line) => println!("{}", line),
+        Err(e) => warn!("Failed to report status: {}", e),
+    }
+}
+
+/// Log a connection event from the `transport` server and report its status
+///
+/// The servers only send events, so the log and `--status-json` output come
+/// from the same place and can't disagree.
+fn report_connection_event(transport: &str, event: &ConnectionEvent) {
+    match event {
+        ConnectionEvent::Listening { addr } => {
+            info!("{} server listening on {}", transport, addr)
+        }
+        ConnectionEvent::Accepted { addr, alpn } => match alpn {
+            Some(alpn) => info!("Connection accepted from {} ({})", addr, alpn),
+            None => info!("Connection accepted from {}", addr),
+        },
+        ConnectionEvent::StreamOpened { addr } => {
+            info!("Frame stream from {}; starting frame parser", addr)
+        }
+        ConnectionEvent::Closed { addr, reason } => {
+            info!("Connection closed: {} ({})", addr, reason)
+        }
+        ConnectionEvent::Error { msg, .. } => error!("{}", msg),
+    }
+    if let Some(status) = event.to_status() {
+        report_status(status);
+    }
+}
+
+async fn run_quic_server(
+    server: QuicServer,
+    tx: backpressure::FrameSender<FrameData>,
+    stats: Arc<Stats>,
+    control: broadcast::Sender<ControlMessage>,
+    greeting: Vec<ControlMessage>,
+    recorder: Option<Arc<recording::RecordingWriter>>,
+    events: mpsc::UnboundedSender<ConnectionEvent>,
+) -> anyhow::Result<()> {
+    let server = Arc::new(server);
+
+    // Per-sender numbers, so two Macs connected at once aren't blurred together.
+    let log_server = server.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SENDER_STATS_LOG_INTERVAL);
+        while !log_server.is_closed() {
+            interval.tick().await;
+            for (remote, snapshot) in log_server.connection_stats() {
+                info!(
+                    "Sender {}: {:.1} FPS, {:.1} Mbps, {} frames",
+                    remote, snapshot.fps, snapshot.bitrate_mbps, snapshot.total_frames
+                );
+            }
         }
     });
 
-    // Wait for either accept loop to finish (connection closed).
-    let _ = tokio::join!(bi_task, uni_task, dgram_task);
+    let hook = QuicHook {
+        server: Some(server.clone()),
+        stats: stats.clone(),
+        control,
+        greeting,
+        recorder,
+        events: events.clone(),
+    };
+    let frames = quic_receiver(Arc::new(hook), events).serve(server);
+    forward_frames(frames, tx, &stats).await;
     Ok(())
 }
 
-async fn handle_single_frame_datagramlike(
-    data: Vec<u8>,
-    tx: mpsc::Sender<FrameData>,
+/// Dial the sender at `sender` and receive from it, like an accepted
+/// connection, dialing again whenever the connection ends
+#[allow(clippy::too_many_arguments)]
+async fn run_quic_client(
+    sender: SocketAddr,
+    tuning: &TransportTuning,
+    tx: backpressure::FrameSender<FrameData>,
+    stats: Arc<Stats>,
+    control: broadcast::Sender<ControlMessage>,
+    greeting: Vec<ControlMessage>,
+    recorder: Option<Arc<recording::RecordingWriter>>,
+    events: mpsc::UnboundedSender<ConnectionEvent>,
 ) -> anyhow::Result<()> {
-    // Parse frame header (big-endian)
-    let mut bytes = Bytes::from(data);
-    let _version = bytes.get_u8();
-    let frame_type_raw = bytes.get_u8();
-    let sequence = bytes.get_u64();
-    let _timestamp_us = bytes.get_u64();
-    let width = bytes.get_u16();
-    let height = bytes.get_u16();
-    let payload_size = bytes.get_u32() as usize;
+    let bind: SocketAddr = if sender.is_ipv6() {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    };
+    let client = QuicClient::with_tuning(bind, tuning)?;
+    let hook: Arc<dyn ConnectionHook> = Arc::new(QuicHook {
+        server: None,
+        stats: stats.clone(),
+        control,
+        greeting,
+        recorder,
+        events: events.clone(),
+    });
 
-    if payload_size > MAX_FRAME_PAYLOAD_SIZE {
-        anyhow::bail!("Payload too large: {} bytes", payload_size);
+    while !tx.is_closed() {
+        match quic_receiver(hook.clone(), events.clone())
+            .connect(&client, sender)
+            .await
+        {
+            Ok(frames) => forward_frames(frames, tx.clone(), &stats).await,
+            Err(e) => warn!("Couldn't connect to {}: {}; retrying", sender, e),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
     }
+    Ok(())
+}
 
-    // Parse frame type
-    let frame_type = FrameType::try_from(frame_type_raw)?;
+/// A `FrameReceiver` for this receiver's QUIC senders
+fn quic_receiver(
+    hook: Arc<dyn ConnectionHook>,
+    events: mpsc::UnboundedSender<ConnectionEvent>,
+) -> FrameReceiver {
+    // Tuning only matters to `FrameReceiver::run`; the server or client is ours.
+    FrameReceiver::new(TransportTuning::default())
+        .with_max_payload_size(max_frame_payload_size())
+        .with_max_dimension(max_frame_dimension())
+        // The display loop's queue and backpressure policy do the buffering.
+        .with_queue(1)
+        .with_events(events)
+        .with_hook(hook)
+}
 
-    if bytes.remaining() < payload_size {
-        anyhow::bail!(
-            "Payload size mismatch: expected {}, got {}",
-            payload_size,
-            bytes.remaining()
-        );
+/// Hand frames to the display loop until either side is gone
+async fn forward_frames(
+    mut frames: FrameStream,
+    mut tx: backpressure::FrameSender<FrameData>,
+    stats: &Stats,
+) {
+    while let Some(frame) = frames.next().await {
+        if tx.send(FrameData::from(frame), stats).await.is_err() {
+            return;
+        }
     }
+}
 
-    let rgba_data = bytes.slice(..payload_size).to_vec();
+/// Everything a QUIC connection needs beyond its frames: control messages
+/// back to the sender, per-sender stats and liveness, and the recording
+struct QuicHook {
+    /// Where per-sender stats live; a dialed sender gets its own
+    server: Option<Arc<QuicServer>>,
+    stats: Arc<Stats>,
+    control: broadcast::Sender<ControlMessage>,
+    greeting: Vec<ControlMessage>,
+    recorder: Option<Arc<recording::RecordingWriter>>,
+    events: mpsc::UnboundedSender<ConnectionEvent>,
+}
 
-    debug!(
-        "Received frame (uni): seq={}, type={:?}, {}x{}, {} bytes",
-        sequence, frame_type, width, height, payload_size
-    );
+impl ConnectionHook for QuicHook {
+    fn connected(&self, conn: &quinn::Connection) -> Arc<dyn ConnectionTap> {
+        let peer_stats = self
+            .server
+            .as_ref()
+            .and_then(|server| server.stats_for(conn))
+            .unwrap_or_default();
+        let peer = Arc::new(Peer::new(peer_stats));
+
+        // The connection's tasks stop together once it is over, or once the
+        // watchdog closed it.
+        let cancel = cancel::CancelToken::new();
+
+        // Senders that send stats frames are dropped once they stop.
+        let (conn_watch, peer_watch) = (conn.clone(), peer.clone());
+        tokio::spawn(cancel.clone().run(async move {
+            peer_timeout(&peer_watch.heartbeat).await;
+            warn!(
+                "No stats from sender in {:?}, closing connection",
+                PEER_TIMEOUT
+            );
+            conn_watch.close(0u32.into(), b"peer timeout");
+        }));
+
+        // QUIC's own RTT and loss are truer than frame timestamps alone.
+        let (conn_path, stats_path, peer_path) = (conn.clone(), self.stats.clone(), peer.clone());
+        tokio::spawn(cancel.clone().run(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                let path = path_stats(&conn_path);
+                stats_path.record_path(path.rtt, path.lost_packets, path.sent_packets);
+                peer_path
+                    .stats
+                    .record_path(path.rtt, path.lost_packets, path.sent_packets);
+                debug!(
+                    "Path: rtt {:.1} ms, cwnd {} KB, lost {}/{} packets",
+                    path.rtt.as_secs_f64() * 1000.0,
+                    path.cwnd / 1024,
+                    path.lost_packets,
+                    path.sent_packets
+                );
+            }
+        }));
+        debug!("Datagram limit: {:?} bytes", conn.max_datagram_size());
+
+        Arc::new(QuicPeer {
+            conn: conn.clone(),
+            peer,
+            stats: self.stats.clone(),
+            control: self.control.clone(),
+            greeting: self.greeting.clone(),
+            recorder: self.recorder.clone(),
+            events: self.events.clone(),
+            cancel,
+        })
+    }
+}
 
-    tx.send(FrameData {
-        width,
-        height,
-        rgba_data,
-        sequence,
-        frame_type,
-    })
-    .await
-    .map_err(|_| anyhow::anyhow!("Frame channel closed"))?;
+/// One QUIC sender, as its `FrameReceiver` connection sees it
+struct QuicPeer {
+    conn: quinn::Connection,
+    peer: Arc<Peer>,
+    stats: Arc<Stats>,
+    control: broadcast::Sender<ControlMessage>,
+    greeting: Vec<ControlMessage>,
+    recorder: Option<Arc<recording::RecordingWriter>>,
+    events: mpsc::UnboundedSender<ConnectionEvent>,
+    cancel: cancel::CancelToken,
+}
 
-    Ok(())
+impl QuicPeer {
+    /// Turn away a sender this receiver can't talk to
+    fn reject(&self, mismatch: VersionMismatch) -> thunder_shared::Error {
+        let reason = mismatch.to_string();
+        let _ = self.events.send(ConnectionEvent::Error {
+            addr: Some(self.conn.remote_address()),
+            msg: reason.clone(),
+        });
+        // The reason reaches the sender, so it can tell its user too.
+        self.conn
+            .close(CLOSE_VERSION_MISMATCH.into(), reason.as_bytes());
+        thunder_shared::Error::protocol(reason)
+    }
 }
 
-async fn handle_frame_byte_stream(
-    recv: &mut quinn::RecvStream,
-    tx: mpsc::Sender<FrameData>,
-) -> anyhow::Result<()> {
-    use bytes::BytesMut;
+impl ConnectionTap for QuicPeer {
+    fn stream_opened(&self, send: quinn::SendStream) {
+        // The send half carries control messages (keyframe requests) back.
+        let control_writer = forward_control_messages(
+            send,
+            self.control.subscribe(),
+            self.greeting.clone(),
+            self.stats.clone(),
+        );
+        tokio::spawn(self.cancel.clone().until_cancelled(control_writer));
+    }
 
-    let mut buf = BytesMut::with_capacity(256 * 1024);
+    fn check_version(&self, version: u8) -> thunder_shared::Result<()> {
+        check_sender_version(version).map_err(|e| self.reject(e))
+    }
 
-    loop {
-        // Ensure we have enough to parse at least a header.
-        while buf.len() < FRAME_HEADER_SIZE {
-            match recv.read_chunk(64 * 1024, true).await? {
-                Some(chunk) => buf.extend_from_slice(&chunk.bytes),
-                None => return Ok(()), // EOF
+    fn frame(&self, frame: &Frame) -> bool {
+        match accept_frame(frame, &self.stats, &self.peer, self.recorder.as_deref()) {
+            Ok(accepted) => accepted,
+            Err(mismatch) => {
+                self.reject(mismatch);
+                false
             }
         }
+    }
+
+    fn closed(&self) {
+        self.cancel.cancel();
+        self.stats.clear_path();
+    }
+}
+
+/// Accept senders over plain TCP
+///
+/// Each connection is a single byte stream of frames, handled like a QUIC
+/// bidirectional stream: frames in, control messages out.
+async fn run_tcp_server(
+    server: TcpServer,
+    tx: backpressure::FrameSender<FrameData>,
+    stats: Arc<Stats>,
+    control: broadcast::Sender<ControlMessage>,
+    greeting: Vec<ControlMessage>,
+    recorder: Option<Arc<recording::RecordingWriter>>,
+    events: mpsc::UnboundedSender<ConnectionEvent>,
+) -> anyhow::Result<()> {
+    let _ = events.send(ConnectionEvent::Listening {
+        addr: server.local_addr(),
+    });
+
+    loop {
+        let (stream, addr) = match server.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("{}", e);
+                continue;
+            }
+        };
 
-        // Peek header without consuming until payload is present.
-        let mut header_bytes = Bytes::copy_from_slice(&buf[..FRAME_HEADER_SIZE]);
-        let version = header_bytes.get_u8();
-        let frame_type_raw = header_bytes.get_u8();
-        let sequence = header_bytes.get_u64();
-        let _timestamp_us = header_bytes.get_u64();
-        let _width = header_bytes.get_u16();
-        let _height = header_bytes.get_u16();
-        let payload_size = header_bytes.get_u32() as usize;
+        let _ = events.send(ConnectionEvent::Accepted { addr, alpn: None });
+        let (mut reader, writer) = stream.into_split();
+        let writer = tokio::spawn(forward_control_messages(
+            writer,
+            control.subscribe(),
+            greeting.clone(),
+            stats.clone(),
+        ));
+        let tx = tx.clone();
+        let stats = stats.clone();
+        let recorder = recorder.clone();
+        let events = events.clone();
+        tokio::spawn(async move {
+            let peer = Peer::new(Stats::new());
+            let result = tokio::select! {
+                result = handle_frame_byte_stream(
+                    &mut reader,
+                    tx,
+                    &stats,
+                    &peer,
+                    recorder.as_deref(),
+                ) => result,
+                () = peer_timeout(&peer.heartbeat) => {
+                    Err(anyhow::anyhow!("No stats from sender in {:?}", PEER_TIMEOUT))
+                }
+            };
+            writer.abort();
+            let event = match result {
+                Ok(()) => ConnectionEvent::Closed {
+                    addr,
+                    reason: "end of stream".to_string(),
+                },
+                Err(e) => ConnectionEvent::Error {
+                    addr: Some(addr),
+                    msg: format!("Connection error: {}", e),
+                },
+            };
+            let _ = events.send(event);
+        });
+    }
+}
+
+/// Write control messages from the display loop to the sender as control frames
+///
+/// A stats frame with our own measurements goes out every `STATS_INTERVAL`
+/// in between, so the sender can tell the receiver is still there.
+async fn forward_control_messages<W: AsyncWrite + Unpin>(
+    mut send: W,
+    mut control: broadcast::Receiver<ControlMessage>,
+    greeting: Vec<ControlMessage>,
+    stats: Arc<Stats>,
+) {
+    let start = Instant::now();
+    let mut sequence = 0u64;
+    let mut greeting = greeting.into_iter();
+    let mut heartbeat = tokio::time::interval(STATS_INTERVAL);
+    heartbeat.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let control_frame = |message: &ControlMessage, sequence| {
+        debug!("Sending control message: {:?}", message);
+        message.to_frame(sequence, start.elapsed().as_micros() as u64)
+    };
+    loop {
+        let frame = match greeting.next() {
+            Some(message) => control_frame(&message, sequence),
+            None => tokio::select! {
+                received = control.recv() => match received {
+                    Ok(message) => control_frame(&message, sequence),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                _ = heartbeat.tick() => StatsFrame(stats.snapshot())
+                    .to_frame(sequence, start.elapsed().as_micros() as u64),
+            },
+        };
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Failed to encode frame for the sender: {}", e);
+                continue;
+            }
+        };
+        sequence += 1;
 
-        if version != 1 {
-            anyhow::bail!("Unsupported protocol version: {}", version);
+        if let Err(e) = send.write_all(&frame.encode()).await {
+            debug!("Control stream closed: {}", e);
+            return;
         }
+    }
+}
 
-        if payload_size > MAX_FRAME_PAYLOAD_SIZE {
-            anyhow::bail!("Payload too large: {} bytes", payload_size);
+/// Resolve once `heartbeat` shows the peer stopped sending stats frames
+async fn peer_timeout(heartbeat: &Heartbeat) {
+    let mut interval = tokio::time::interval(STATS_INTERVAL);
+    loop {
+        interval.tick().await;
+        if heartbeat.is_dead(PEER_TIMEOUT) {
+            return;
         }
+    }
+}
+
+/// Feed a recording through the same parsing path as a live connection
+///
+/// Frames are released at their recorded arrival times so decode timing and
+/// jitter behave like the original session. With `from`, playback starts at
+/// the last indexed keyframe at or before that sequence number.
+async fn run_replay(
+    path: PathBuf,
+    from: Option<u64>,
+    mut tx: backpressure::FrameSender<FrameData>,
+    stats: Arc<Stats>,
+) -> anyhow::Result<()> {
+    let mut source = FileFrameSource::open(&path, max_frame_payload_size()).await?;
+    let header = source.header();
+    info!(
+        "Recording is {}x{} with {} indexed keyframes",
+        header.width,
+        header.height,
+        source.keyframes().len()
+    );
 
-        let total_needed = FRAME_HEADER_SIZE + payload_size;
+    if let Some(sequence) = from {
+        let Some(keyframe) = source.seek_to_keyframe(sequence).await? else {
+            anyhow::bail!("No indexed keyframe at or before frame {}", sequence);
+        };
+        info!("Starting at keyframe {}", keyframe.sequence);
+    }
 
-        // Read until full frame present.
-        while buf.len() < total_needed {
-            match recv.read_chunk(256 * 1024, true).await? {
-                Some(chunk) => buf.extend_from_slice(&chunk.bytes),
-                None => return Ok(()), // EOF mid-frame; just stop
-            }
+    let mut frames = 0u64;
+    while let Some(entry) = source.next_entry().await? {
+        if tx.is_closed() {
+            return Ok(());
+        }
+        if let Err(e) = handle_recorded_frame(entry.frame, &mut tx, &stats).await {
+            warn!("Failed to parse recorded frame: {}", e);
         }
+        frames += 1;
+    }
 
-        // Now we can consume this full frame from buf.
-        let mut frame_bytes = buf.split_to(total_needed).freeze();
-        let _version2 = frame_bytes.get_u8();
-        let frame_type_raw2 = frame_bytes.get_u8();
-        let sequence2 = frame_bytes.get_u64();
-        let _timestamp_us2 = frame_bytes.get_u64();
-        let width2 = frame_bytes.get_u16();
-        let height2 = frame_bytes.get_u16();
-        let payload_size2 = frame_bytes.get_u32() as usize;
+    info!("Replay finished ({} frames)", frames);
+    Ok(())
+}
 
-        if payload_size2 != payload_size
-            || frame_type_raw2 != frame_type_raw
-            || sequence2 != sequence
-        {
-            debug!("Frame header changed between peek/consume; continuing with consumed header");
+/// Append a frame to the recording, if one is active
+fn record_raw_frame(recorder: Option<&recording::RecordingWriter>, frame: &[u8]) {
+    if let Some(recorder) = recorder {
+        if let Err(e) = recorder.write_frame(frame) {
+            warn!("Failed to record frame: {}", e);
         }
+    }
+}
 
-        let frame_type = match FrameType::try_from(frame_type_raw2) {
-            Ok(ft) => ft,
+/// Parse one frame of a recording and hand it to the display loop
+async fn handle_recorded_frame(
+    mut data: Bytes,
+    tx: &mut backpressure::FrameSender<FrameData>,
+    stats: &Stats,
+) -> anyhow::Result<()> {
+    let frame =
+        Frame::decode_with_limits(&mut data, max_frame_payload_size(), max_frame_dimension())?;
+    let header = &frame.header;
+
+    debug!(
+        "Replaying frame: seq={}, type={:?}, {}x{}, {} bytes",
+        header.sequence, header.frame_type, header.width, header.height, header.payload_size
+    );
+    if record_arrival(stats, header) {
+        debug!("Dropping duplicate video frame (seq={})", header.sequence);
+        return Ok(());
+    }
+
+    tx.send(FrameData::from(frame), stats)
+        .await
+        .map_err(|_| anyhow::anyhow!("Frame channel closed"))?;
+
+    Ok(())
+}
+
+/// Parse back-to-back frames from a TCP connection
+async fn handle_frame_byte_stream<R: AsyncRead + Unpin>(
+    recv: &mut R,
+    mut tx: backpressure::FrameSender<FrameData>,
+    stats: &Stats,
+    peer: &Peer,
+    recorder: Option<&recording::RecordingWriter>,
+) -> anyhow::Result<()> {
+    let mut decoder =
+        FrameDecoder::new(max_frame_payload_size()).with_max_dimension(max_frame_dimension());
+    let mut skipped = 0;
+    // Senders start on a frame boundary, so the first byte is a header
+    // version. Checking it up front reports an incompatible sender instead
+    // of skipping its frames as garbage.
+    let mut version_checked = false;
+
+    loop {
+        if !version_checked {
+            if let Some(&version) = decoder.buffer_mut().first() {
+                check_sender_version(version)?;
+                version_checked = true;
+            }
+        }
+        let frame = match decoder.decode() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                if !decoder.read_from(recv, 256 * 1024).await? {
+                    // A partial frame left over means the sender was cut off.
+                    decoder.end_of_stream().into_result()?;
+                    return Ok(());
+                }
+                continue;
+            }
             Err(e) => {
-                warn!("Invalid frame type in stream: {}", e);
+                warn!("Invalid frame in stream: {}", e);
                 continue;
             }
         };
 
-        if frame_bytes.remaining() < payload_size2 {
+        if decoder.skipped_bytes() > skipped {
             warn!(
-                "Stream payload size mismatch: expected {}, got {}",
-                payload_size2,
-                frame_bytes.remaining()
+                "Resynced frame stream after skipping {} bytes",
+                decoder.skipped_bytes() - skipped
             );
+            skipped = decoder.skipped_bytes();
+        }
+        if !accept_frame(&frame, stats, peer, recorder)? {
             continue;
         }
+        if tx.send(FrameData::from(frame), stats).await.is_err() {
+            return Ok(());
+        }
+    }
+}
 
-        let rgba_data = frame_bytes.split_to(payload_size2).to_vec();
+/// Count and record a frame from `peer`, and whether to hand it on
+///
+/// Duplicates aren't handed on, and neither is the sender's hello, which
+/// fails with `VersionMismatch` if this receiver can't decode the sender's
+/// protocol version.
+fn accept_frame(
+    frame: &Frame,
+    stats: &Stats,
+    peer: &Peer,
+    recorder: Option<&recording::RecordingWriter>,
+) -> Result<bool, VersionMismatch> {
+    if recorder.is_some() {
+        record_raw_frame(recorder, &frame.encode());
+    }
 
-        debug!(
-            "Received frame (bi): seq={}, type={:?}, {}x{}, {} bytes",
-            sequence2, frame_type, width2, height2, payload_size2
-        );
+    let header = &frame.header;
+    debug!(
+        "Received frame: seq={}, type={:?}, {}x{}, {} bytes",
+        header.sequence, header.frame_type, header.width, header.height, header.payload_size
+    );
+    record_arrival(stats, header);
+    if peer.record_arrival(header) {
+        debug!("Dropping duplicate video frame (seq={})", header.sequence);
+        return Ok(false);
+    }
 
-        if tx
-            .send(FrameData {
-                width: width2,
-                height: height2,
-                rgba_data,
-                sequence: sequence2,
-                frame_type,
-            })
-            .await
-            .is_err()
+    if header.frame_type == FrameType::Control {
+        if let Ok(ControlMessage::Hello {
+            protocol_version,
+            capabilities,
+            audio_codecs,
+        }) = ControlMessage::decode(&frame.payload)
         {
-            return Ok(());
+            check_sender_version(protocol_version)?;
+            let audio = AudioCodec::negotiate(audio::DECODABLE_CODECS, &audio_codecs);
+            info!(
+                "Sender speaks protocol v{} (capabilities {:#x}), audio as {:?}",
+                protocol_version, capabilities, audio
+            );
+            return Ok(false);
         }
     }
+    Ok(true)
 }
 
-fn create_server_config() -> anyhow::Result<ServerConfig> {
-    // Generate a self-signed certificate for testing
-    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
-    let cert_der = cert.serialize_der()?;
-    let key_der = cert.serialize_private_key_der();
+/// The sender speaks a protocol version this receiver can't decode
+#[derive(Debug, PartialEq)]
+struct VersionMismatch(u8);
+
+impl std::fmt::Display for VersionMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outdated = if self.0 > PROTOCOL_VERSION {
+            "this receiver"
+        } else {
+            "the Mac app"
+        };
+        write!(
+            f,
+            "Version mismatch: the sender speaks protocol v{}, this receiver supports v{} to v{}; update {}",
+            self.0, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, outdated
+        )
+    }
+}
+
+impl std::error::Error for VersionMismatch {}
+
+/// Check the protocol version a sender announced or framed its stream with
+fn check_sender_version(version: u8) -> Result<(), VersionMismatch> {
+    if (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
+        Ok(())
+    } else {
+        Err(VersionMismatch(version))
+    }
+}
 
-    let cert = Certificate(cert_der);
-    let key = PrivateKey(key_der);
+/// Next frame to handle in the display loop, without blocking
+///
+/// With a reorder buffer, video frames pass through it while audio and control
+/// frames are handed out as soon as they arrive.
+fn next_frame(
+    rx: &mut mpsc::Receiver<FrameData>,
+    reorder: Option<&mut reorder::ReorderBuffer<FrameData>>,
+    stats: &Stats,
+) -> Option<FrameData> {
+    let Some(reorder) = reorder else {
+        return rx.try_recv().ok();
+    };
 
-    let mut rustls_config = rustls::ServerConfig::builder()
-        .with_safe_defaults()
-        .with_no_client_auth()
-        .with_single_cert(vec![cert], key)?;
+    let now = Instant::now();
+    while let Ok(frame) = rx.try_recv() {
+        if !frame.frame_type.is_video() {
+            return Some(frame);
+        }
+        let sequence = frame.sequence;
+        if !reorder.push(sequence, frame, now) {
+            debug!("Dropping late video frame (seq={})", sequence);
+            stats.record_drop();
+        }
+    }
+    reorder.pop_ready(now)
+}
 
-    // Configure for low latency
-    rustls_config.max_early_data_size = u32::MAX;
-    rustls_config.alpn_protocols = vec![b"thunder-mirror".to_vec()];
+/// State shared by all streams of one sender's connection
+struct Peer {
+    /// Frames from this sender only; the server-wide `Stats` counts everyone
+    stats: Arc<Stats>,
+    heartbeat: Heartbeat,
+}
 
-    let mut server_config = ServerConfig::with_crypto(Arc::new(rustls_config));
+impl Peer {
+    fn new(stats: Arc<Stats>) -> Self {
+        Self {
+            stats,
+            heartbeat: Heartbeat::new(),
+        }
+    }
 
-    // Configure transport for high-bandwidth low-latency streaming
-    let mut transport = quinn::TransportConfig::default();
-    transport.datagram_receive_buffer_size(Some(16 * 1024 * 1024));
-    
-    // Increase stream receive window for high-bandwidth streaming
-    transport.receive_window((16u32 * 1024 * 1024).try_into().unwrap());
-    transport.stream_receive_window((8u32 * 1024 * 1024).try_into().unwrap());
-    
-    // Keep connection alive
-    transport.keep_alive_interval(Some(Duration::from_secs(5)));
-    transport.max_idle_timeout(Some(Duration::from_secs(60).try_into().unwrap()));
-    
-    server_config.transport = Arc::new(transport);
+    /// Count a frame from this sender; stats frames also prove it's alive
+    ///
+    /// Returns whether it's a duplicate, going by this sender's sequence
+    /// numbers only.
+    fn record_arrival(&self, header: &FrameHeader) -> bool {
+        if header.frame_type == FrameType::Stats {
+            self.heartbeat.beat();
+        }
+        record_arrival(&self.stats, header)
+    }
+}
 
-    Ok(server_config)
+/// Count a received frame and feed video sequences into the jitter/loss estimate
+///
+/// Audio and control frames only get per-type counts since they don't
+/// arrive at the frame rate. Returns whether a video frame's sequence
+/// number was already seen.
+fn record_arrival(stats: &Stats, header: &FrameHeader) -> bool {
+    stats.record_frame_typed(header.frame_type, header.payload_size as u64);
+    header.frame_type.is_video()
+        && stats.record_sequence(header.sequence) == SequenceArrival::Duplicate
 }
 
 #[cfg(test)]
 mod tests {
+    use bytes::BytesMut;
+
     use super::*;
 
     #[test]
@@ -767,5 +2456,546 @@ mod tests {
         assert_eq!(args.mac_ip, "192.168.50.1");
         assert_eq!(args.port, 9999);
         assert!(!args.fullscreen);
+        assert!(!args.mono);
+        assert_eq!(args.window_specs(), [display::WindowSpec::new(false)]);
+        assert_eq!(args.audio_buffer_ms, 60);
+        assert_eq!(args.fps, 60);
+        assert_eq!(args.jitter_buffer_ms, 0);
+        assert_eq!(args.backpressure, backpressure::BackpressurePolicy::Latest);
+        assert_eq!(args.frame_queue, 60);
+        assert_eq!(args.no_signal_after_secs, 5);
+        assert_eq!(args.min_bitrate_kbps, 2_000);
+        assert_eq!(args.max_bitrate_kbps, 50_000);
+        assert_eq!(args.metrics_port, None);
+        assert_eq!(args.metrics_addr(), None);
+        assert_eq!(args.decoder, decoder::DecoderKind::Software);
+        assert_eq!(args.color_range, color::RangeMode::Auto);
+        assert!(!args.clipboard);
+        assert!(!args.forward_input);
+        assert!(!args.status_json);
+        assert_eq!(args.transport, TransportKind::Quic);
+        assert_eq!(args.ip_mode(), IpMode::V4);
+        assert_eq!(args.listen_addr(), "0.0.0.0:9999".parse().unwrap());
+        assert_eq!(args.record, None);
+        assert_eq!(args.replay, None);
+        assert_eq!(args.replay_from, None);
+        assert_eq!(args.exit_key.key, Key::Escape);
+        assert_eq!(args.exit_key.modifiers, 0);
+        assert_eq!(args.leave_fullscreen_key, None);
+        assert_eq!(args.scaling_quality, scale::ScalingQuality::Fast);
+        assert_eq!(args.title, None);
+
+        let args = Args::parse_from(["thunder_receiver", "--title", "Projector"]);
+        assert_eq!(args.title.as_deref(), Some("Projector"));
+    }
+
+    #[test]
+    fn test_listen_address() {
+        let args = Args::parse_from(["thunder_receiver", "--listen", "192.168.50.2"]);
+        assert_eq!(args.listen_addr(), "192.168.50.2:9999".parse().unwrap());
+        let config = args.to_config(Config::win_receiver());
+        assert_eq!(config.bind_address, "192.168.50.2");
+        let names = args.self_signed_cert().subject_alt_names;
+        assert_eq!(names[0], "localhost");
+        assert!(names.contains(&"192.168.50.2".to_string()));
+        let args = Args::parse_from(["thunder_receiver", "--listen", "fe80::1", "-p", "5000"]);
+        assert_eq!(args.listen_addr(), "[fe80::1]:5000".parse().unwrap());
+
+        // The metrics endpoint binds where the receiver does
+        let metrics = |listen: &[&str]| {
+            let args = ["thunder_receiver", "--metrics-port", "9100"];
+            let args = Args::parse_from(args.iter().chain(listen));
+            args.metrics_addr().unwrap()
+        };
+        let addr = metrics(&["--listen", "192.168.50.2"]);
+        assert_eq!(addr, "192.168.50.2:9100".parse().unwrap());
+        assert_eq!(metrics(&["--ipv6"]), "[::]:9100".parse().unwrap());
+
+        let parse = |args: &[&str]| Args::try_parse_from(["thunder_receiver"].iter().chain(args));
+        assert!(parse(&["--listen", "thunderbolt0"]).is_err());
+        assert!(parse(&["--listen", "10.0.0.1", "--dual"]).is_err());
+    }
+
+    #[test]
+    fn test_start_message_asks_for_what_we_show() {
+        let args = Args::parse_from(["thunder_receiver", "--fps", "144"]);
+        assert!(matches!(
+            args.start_message(Some((2560, 1440)), true, 50_000),
+            ControlMessage::Start {
+                width: 2560,
+                height: 1440,
+                fps: 144,
+                bitrate_kbps: Some(50_000),
+                codec: Some(VideoCodec::H264),
+                profile: Some(H264Profile::High),
+            }
+        ));
+
+        // Without a decoder only raw frames can be shown
+        assert!(matches!(
+            args.start_message(Some((1920, 1080)), false, 50_000),
+            ControlMessage::Start {
+                bitrate_kbps: None,
+                codec: Some(VideoCodec::Raw),
+                profile: None,
+                ..
+            }
+        ));
+
+        // An unknown screen leaves the size to the sender, and no frame
+        // may be larger than the protocol allows
+        assert!(matches!(
+            args.start_message(None, true, 8_000),
+            ControlMessage::Start {
+                width: 0,
+                height: 0,
+                bitrate_kbps: Some(8_000),
+                ..
+            }
+        ));
+        assert!(matches!(
+            args.start_message(Some((100_000, 1080)), true, 8_000),
+            ControlMessage::Start {
+                width: MAX_FRAME_DIMENSION,
+                height: 1080,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let parse = |arg: &str, value: &str| Args::try_parse_from(["thunder_receiver", arg, value]);
+        let args = parse("--exit-key", "ctrl+shift+q").unwrap();
+        assert_eq!(args.exit_key.key, Key::Q);
+        assert!(parse("--exit-key", "ctrl+shift+qq").is_err());
+        assert!(parse("--leave-fullscreen-key", "exit").is_err());
+    }
+
+    #[test]
+    fn test_frame_size_limit_follows_the_protocol() {
+        assert_eq!(max_frame_payload_size(), MAX_FRAME_SIZE);
+        assert_eq!(max_frame_dimension(), MAX_FRAME_DIMENSION);
+        let config = Args::parse_from(["thunder_receiver"]).to_config(Config::win_receiver());
+        assert_eq!(config.max_frame_size, MAX_FRAME_SIZE);
+        assert_eq!(config.max_frame_dimension, MAX_FRAME_DIMENSION);
+    }
+
+    #[test]
+    fn test_fps_range() {
+        let fps = |value: &str| Args::try_parse_from(["thunder_receiver", "--fps", value]);
+        assert_eq!(fps("0").unwrap().fps, 0);
+        assert_eq!(fps("144").unwrap().fps, 144);
+        assert_eq!(fps("240").unwrap().fps, 240);
+
+        for bad in ["241", "1000", "-1", "sixty"] {
+            assert!(fps(bad).is_err(), "accepted {}", bad);
+        }
+    }
+
+    #[test]
+    fn test_record_conflicts_with_replay() {
+        assert!(Args::try_parse_from([
+            "thunder_receiver",
+            "--record",
+            "a.rec",
+            "--replay",
+            "b.rec"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_connect_conflicts_with_listening() {
+        let parse = |args: &[&str]| Args::try_parse_from(["thunder_receiver"].iter().chain(args));
+        let args = parse(&["--connect", "192.168.50.1:9999"]).unwrap();
+        assert_eq!(args.connect, Some("192.168.50.1:9999".parse().unwrap()));
+        assert!(parse(&["--connect", "192.168.50.1"]).is_err(), "no port");
+        assert!(parse(&["--connect", "192.168.50.1:9999", "--listen", "0.0.0.0"]).is_err());
+        assert!(parse(&["--connect", "192.168.50.1:9999", "--ipv6"]).is_err());
+        assert!(parse(&["--connect", "192.168.50.1:9999", "--replay", "a.rec"]).is_err());
+    }
+
+    #[test]
+    fn test_config_fills_unset_args() {
+        let matches = Args::command().get_matches_from(["thunder_receiver", "--port", "1234"]);
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        let config = Config {
+            port: 5555,
+            log_level: "debug".to_string(),
+            frame_queue: 4,
+            ..Config::win_receiver()
+        };
+        args.apply_config(&config, &matches);
+
+        assert_eq!(args.port, 1234, "command line wins over the file");
+        assert_eq!(args.log_level, "debug");
+        assert_eq!(args.mac_ip, "192.168.50.1");
+        assert_eq!(args.frame_queue, 4);
+
+        let matches = Args::command().get_matches_from(["thunder_receiver", "--frame-buffer", "8"]);
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        args.apply_config(&config, &matches);
+        assert_eq!(args.frame_queue, 8);
+    }
+
+    #[test]
+    fn test_partial_config_file_keeps_the_mac_ip() {
+        let path =
+            std::env::temp_dir().join(format!("thunder_partial_{}.toml", std::process::id()));
+        std::fs::write(&path, "port = 7000\n").unwrap();
+        let config = Config::load_over(&path, Config::win_receiver()).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let matches = Args::command().get_matches_from(["thunder_receiver"]);
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        args.apply_config(&config, &matches);
+        assert_eq!(args.port, 7000);
+        assert_eq!(args.mac_ip, "192.168.50.1");
+    }
+
+    #[test]
+    fn test_args_are_validated() {
+        let args = Args::parse_from(["thunder_receiver"]);
+        args.to_config(Config::win_receiver()).validate().unwrap();
+
+        let args = Args::parse_from(["thunder_receiver", "--mac-ip", "192.168.50.x"]);
+        let err = args
+            .to_config(Config::win_receiver())
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("192.168.50.x"), "got {}", err);
+    }
+
+    #[test]
+    fn test_fit_window_size() {
+        let work_area = (2560, 1400);
+        assert_eq!(fit_window_size(1920, 1080, work_area), (1920, 1080));
+        assert_eq!(fit_window_size(2560, 1600, work_area), (2240, 1400));
+        assert_eq!(fit_window_size(3840, 1080, work_area), (2560, 720));
+        assert_eq!(fit_window_size(0, 1080, work_area), (0, 1080));
+    }
+
+    #[test]
+    fn test_pause_tag() {
+        assert_eq!(with_pause_tag("ThunderMirror", false), "ThunderMirror");
+        assert_eq!(
+            with_pause_tag("ThunderMirror", true),
+            "ThunderMirror [PAUSED]"
+        );
+    }
+
+    #[test]
+    fn test_frame_latency_ms() {
+        assert_eq!(frame_latency_ms(1_000_000, 500, 1_012_500), Some(12.0));
+        // Receiver clock behind the sender: no meaningful latency
+        assert_eq!(frame_latency_ms(1_000_000, 500, 1_000_000), None);
+    }
+
+    #[test]
+    fn test_apply_delta_frame_composites_tiles() {
+        let (width, height) = (4, 3);
+        let mut buffer = vec![0x00aabbccu32; width * height];
+
+        // 2x2 red tile at the bottom-right corner, 1x1 blue at the origin
+        let red = Bytes::from([255, 0, 0, 255].repeat(4));
+        let tiles = [
+            DeltaTile::new(2, 1, 2, 2, red),
+            DeltaTile::new(0, 0, 1, 1, Bytes::from_static(&[0, 0, 255, 255])),
+        ];
+        let payload = DeltaTile::encode_all(&tiles).unwrap();
+        assert_eq!(
+            apply_delta_frame(&mut buffer, width, height, &payload).unwrap(),
+            2
+        );
+
+        let (b, g, r) = (0x0000ffu32, 0x00aabbcc, 0xff0000);
+        #[rustfmt::skip]
+        assert_eq!(buffer, [
+            b, g, g, g,
+            g, g, r, r,
+            g, g, r, r,
+        ]);
+    }
+
+    #[test]
+    fn test_apply_delta_frame_rejects_out_of_bounds_tiles() {
+        let mut buffer = vec![7u32; 4];
+        let tiles = [
+            DeltaTile::new(0, 0, 1, 1, Bytes::from_static(&[1, 1, 1, 1])),
+            DeltaTile::new(1, 1, 2, 1, Bytes::from_static(&[0; 8])),
+        ];
+        let payload = DeltaTile::encode_all(&tiles).unwrap();
+
+        assert!(apply_delta_frame(&mut buffer, 2, 2, &payload).is_err());
+        assert_eq!(buffer, [7; 4], "nothing drawn for a rejected delta");
+    }
+
+    /// A 1x1 raw frame, encoded
+    fn raw_frame(sequence: u64) -> Bytes {
+        let header = FrameHeader::new(FrameType::RawFrame, sequence, 0, 1, 1, 4);
+        Frame::new(header, Bytes::from_static(&[1, 2, 3, 4]))
+            .encode()
+            .freeze()
+    }
+
+    /// Parse `bytes` as one sender's byte stream, returning how it ended,
+    /// the frames handed on and the sender's own stats
+    async fn run_byte_stream(bytes: &[u8]) -> (anyhow::Result<()>, Vec<FrameData>, Arc<Stats>) {
+        let (tx, mut rx) = mpsc::channel(16);
+        let tx = backpressure::FrameSender::new(tx, backpressure::BackpressurePolicy::Queue);
+        let (stats, peer) = (Stats::new(), Peer::new(Stats::new()));
+        let result = handle_frame_byte_stream(&mut &bytes[..], tx, &stats, &peer, None).await;
+        let mut frames = Vec::new();
+        while let Some(frame) = rx.recv().await {
+            frames.push(frame);
+        }
+        (result, frames, peer.stats)
+    }
+
+    #[tokio::test]
+    async fn test_byte_stream_resyncs_after_bad_length() {
+        let mut stream = BytesMut::from(&raw_frame(6)[..]);
+        FrameHeader::new(FrameType::RawFrame, 0, 0, 1, 1, u32::MAX).encode(&mut stream);
+        stream.extend_from_slice(&[0xff; 16]);
+        stream.extend_from_slice(&raw_frame(7));
+
+        let (result, frames, _) = run_byte_stream(&stream).await;
+        result.unwrap();
+        assert_eq!(frames.len(), 2, "valid frame after the bad one");
+        assert_eq!(frames[0].sequence, 6);
+        assert_eq!(frames[1].sequence, 7);
+        assert_eq!(frames[1].rgba_data, [1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_byte_stream_reports_truncation() {
+        let mut stream = raw_frame(1).to_vec();
+        stream.extend_from_slice(&raw_frame(2)[..10]);
+
+        let (result, frames, _) = run_byte_stream(&stream).await;
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains("10 bytes"), "{}", err);
+        let sequences: Vec<_> = frames.iter().map(|frame| frame.sequence).collect();
+        assert_eq!(sequences, [1]);
+    }
+
+    #[tokio::test]
+    async fn test_byte_stream_drops_duplicates() {
+        let stream: Vec<u8> = [1, 3, 3, 2, 1].into_iter().flat_map(raw_frame).collect();
+
+        let (result, frames, peer_stats) = run_byte_stream(&stream).await;
+        result.unwrap();
+        let sequences: Vec<_> = frames.iter().map(|frame| frame.sequence).collect();
+        assert_eq!(sequences, [1, 3, 2]);
+        let snapshot = peer_stats.snapshot();
+        assert_eq!(snapshot.out_of_order_frames, 1);
+        assert_eq!(snapshot.duplicate_frames, 2);
+    }
+
+    #[tokio::test]
+    async fn test_headless_drains_until_the_channel_closes() {
+        let (tx, rx) = mpsc::channel(8);
+        for sequence in 0..3 {
+            let frame = Frame::decode(&mut raw_frame(sequence)).unwrap();
+            tx.send(FrameData::from(frame)).await.unwrap();
+        }
+        drop(tx);
+
+        let stats = Stats::new();
+        run_headless(rx, stats.clone()).await.unwrap();
+        let drained = stats.meter("headless").snapshot();
+        assert_eq!((drained.total_frames, drained.total_bytes), (3, 12));
+
+        let mut received = stats.snapshot();
+        received.dropped_frames = 2;
+        let summary = headless_summary(&drained, &received, 0.5);
+        assert!(summary.starts_with("Received 3 frames"), "{}", summary);
+        assert!(summary.contains(" 6.0 FPS"), "{}", summary);
+        assert!(summary.contains("2 dropped"), "{}", summary);
+        assert!(headless_summary(&drained, &received, 0.0).contains(" 0.0 FPS"));
+
+        let parse = |args: &[&str]| Args::try_parse_from(["thunder_receiver"].iter().chain(args));
+        assert!(parse(&["--headless"]).unwrap().headless);
+        assert!(parse(&["--headless", "--forward-input"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connection_tasks_stop_when_sender_disconnects() {
+        let server = QuicServer::new("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let server_addr = server.local_addr();
+
+        // Nobody drains the frame queue, so frames back up behind it when
+        // the sender goes away.
+        let (tx, mut rx) = mpsc::channel(1);
+        let tx = backpressure::FrameSender::new(tx, backpressure::BackpressurePolicy::Queue);
+        let (control, _) = broadcast::channel(4);
+        let (events, mut event_rx) = mpsc::unbounded_channel();
+        let receiver = tokio::spawn(run_quic_server(
+            server,
+            tx,
+            Stats::new(),
+            control,
+            Vec::new(),
+            None,
+            events,
+        ));
+
+        let client = QuicClient::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let client_conn = client.connect(server_addr, "localhost").await.unwrap();
+        let (mut send, mut recv) = client_conn.open_bi().await.unwrap();
+        for sequence in 0..3 {
+            send.write_all(&raw_frame(sequence)).await.unwrap();
+        }
+        assert_eq!(rx.recv().await.unwrap().sequence, 0);
+        while rx.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // The stream's other half carries the receiver's heartbeat
+        let mut decoder = FrameDecoder::new(MAX_FRAME_SIZE);
+        let heartbeat = loop {
+            if let Some(frame) = decoder.decode().unwrap() {
+                break frame;
+            }
+            assert!(decoder.read_from(&mut recv, 1024).await.unwrap());
+        };
+        assert_eq!(heartbeat.header.frame_type, FrameType::Stats);
+
+        client_conn.close(0u32.into(), b"bye");
+        let mut seen = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let event = event_rx.recv().await.unwrap();
+                if matches!(event, ConnectionEvent::Closed { .. }) {
+                    return;
+                }
+                seen.push(event);
+            }
+        })
+        .await
+        .expect("the connection should close once the sender is gone");
+        assert!(
+            matches!(
+                seen.as_slice(),
+                [
+                    ConnectionEvent::Listening { .. },
+                    ConnectionEvent::Accepted { .. },
+                    ConnectionEvent::StreamOpened { addr },
+                ] if addr.ip().is_loopback()
+            ),
+            "{:?}",
+            seen
+        );
+        receiver.abort();
+    }
+
+    #[tokio::test]
+    async fn test_client_mode_receives_from_listening_sender() {
+        // The sender listens and the receiver dials it
+        let sender = QuicServer::new("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let (tx, mut rx) = mpsc::channel(4);
+        let tx = backpressure::FrameSender::new(tx, backpressure::BackpressurePolicy::Queue);
+        let (control, _) = broadcast::channel(4);
+        let (events, mut event_rx) = mpsc::unbounded_channel();
+        let sender_addr = sender.local_addr();
+        let receiver = tokio::spawn(async move {
+            let tuning = TransportTuning::default();
+            let greeting = vec![ControlMessage::SetFps { fps: 30 }];
+            run_quic_client(
+                sender_addr,
+                &tuning,
+                tx,
+                Stats::new(),
+                control,
+                greeting,
+                None,
+                events,
+            )
+            .await
+        });
+
+        let conn = tokio::time::timeout(Duration::from_secs(5), sender.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let (mut send, mut recv) = conn.open_bi().await.unwrap();
+        send.write_all(&raw_frame(7)).await.unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.sequence, 7);
+
+        // Control messages come back on the sender's stream
+        let mut decoder = FrameDecoder::new(MAX_FRAME_SIZE);
+        let greeting = loop {
+            if let Some(frame) = decoder.decode().unwrap() {
+                break frame;
+            }
+            assert!(decoder.read_from(&mut recv, 1024).await.unwrap());
+        };
+        assert!(matches!(
+            ControlMessage::decode(&greeting.payload).unwrap(),
+            ControlMessage::SetFps { fps: 30 }
+        ));
+        assert_eq!(
+            event_rx.recv().await.unwrap(),
+            ConnectionEvent::Accepted {
+                addr: sender_addr,
+                alpn: Some(thunder_shared::transport::ALPN.to_string()),
+            }
+        );
+        receiver.abort();
+    }
+
+    #[tokio::test]
+    async fn test_byte_stream_checks_sender_version() {
+        let hello = |protocol_version| {
+            let message = ControlMessage::Hello {
+                protocol_version,
+                capabilities: ControlMessage::CAP_AUDIO,
+                audio_codecs: vec![AudioCodec::Opus, AudioCodec::Pcm],
+            };
+            message.to_frame(0, 0).unwrap().encode()
+        };
+        let run = |stream: Vec<u8>| async move {
+            let (result, frames, _) = run_byte_stream(&stream).await;
+            let sequences: Vec<_> = frames.iter().map(|frame| frame.sequence).collect();
+            (result, sequences)
+        };
+
+        // The hello itself isn't handed on
+        let mut stream = hello(PROTOCOL_VERSION).to_vec();
+        stream.extend_from_slice(&raw_frame(1));
+        let (result, sequences) = run(stream).await;
+        result.unwrap();
+        assert_eq!(sequences, [1]);
+
+        // Senders without a hello still work
+        let (result, sequences) = run(raw_frame(2).to_vec()).await;
+        result.unwrap();
+        assert_eq!(sequences, [2]);
+
+        let (result, sequences) = run(hello(PROTOCOL_VERSION + 1).to_vec()).await;
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<VersionMismatch>(),
+            Some(&VersionMismatch(PROTOCOL_VERSION + 1))
+        );
+        let msg = err.to_string();
+        assert!(msg.contains("update this receiver"), "got {}", msg);
+        assert!(sequences.is_empty());
+
+        // A header version we can't parse is reported before anything is skipped
+        let mut stream = raw_frame(3).to_vec();
+        stream[0] = 0;
+        let (result, _) = run(stream).await;
+        let msg = result.unwrap_err().to_string();
+        assert!(msg.contains("update the Mac app"), "got {}", msg);
     }
 }
@@ -0,0 +1,92 @@
+//! Minimal HTTP endpoint serving receiver stats for Prometheus
+//!
+//! Only `GET /metrics` is answered; everything else gets a 404. This is meant
+//! for a scraper on a trusted network, not as a general-purpose HTTP server.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use thunder_shared::stats::Stats;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Serve `/metrics` on `addr` until the runtime shuts down
+pub async fn serve(addr: SocketAddr, stats: Arc<Stats>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Metrics endpoint listening on http://{}/metrics", addr);
+
+    loop {
+        // A failed accept (e.g. the peer reset first) only loses that client.
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Metrics accept failed: {}", e);
+                continue;
+            }
+        };
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_request(stream, &stats).await {
+                debug!("Metrics request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_request(mut stream: TcpStream, stats: &Stats) -> anyhow::Result<()> {
+    // The request line is all we need; ignore headers and any body.
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+
+    let response = match request_path(&request) {
+        Some("/metrics") => http_response(
+            "200 OK",
+            "text/plain; version=0.0.4",
+            &stats.snapshot().to_prometheus(),
+        ),
+        _ => http_response("404 Not Found", "text/plain", "Not Found\n"),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Path of a `GET` request line, without any query string
+fn request_path(request: &str) -> Option<&str> {
+    let mut parts = request.lines().next()?.split_whitespace();
+    if parts.next()? != "GET" {
+        return None;
+    }
+    parts.next()?.split('?').next()
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_path() {
+        assert_eq!(
+            request_path("GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n"),
+            Some("/metrics")
+        );
+        assert_eq!(
+            request_path("GET /metrics?x=1 HTTP/1.1\r\n"),
+            Some("/metrics")
+        );
+        assert_eq!(request_path("POST /metrics HTTP/1.1\r\n"), None);
+        assert_eq!(request_path(""), None);
+    }
+}
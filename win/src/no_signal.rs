@@ -0,0 +1,94 @@
+//! "No signal" card shown when the video stops
+//!
+//! Without frames the windows keep showing the last one, which looks the
+//! same as a frozen sender. Once no video has been shown for
+//! `--no-signal-after-secs`, the windows get `test_pattern::generate_no_signal`
+//! instead: dimmed color bars with slowly fading text. The frame buffer
+//! itself is left alone, so delta frames still apply to the last real frame
+//! and the card is gone with the first frame that is shown again.
+
+use std::time::Duration;
+
+use thunder_shared::test_pattern;
+
+/// Time between animation steps of the card
+const STEP: Duration = Duration::from_millis(50);
+
+/// The card, redrawn only when the animation moves on or the size changes
+pub struct NoSignal {
+    after: Duration,
+    step: Option<u64>,
+    size: (usize, usize),
+    frame: Vec<u32>,
+}
+
+impl NoSignal {
+    /// Show the card after `after` without video; zero never shows it
+    pub fn new(after: Duration) -> Self {
+        Self {
+            after,
+            step: None,
+            size: (0, 0),
+            frame: Vec::new(),
+        }
+    }
+
+    /// The card to show instead of the frame buffer, once `idle` (time
+    /// since the last video frame) reaches the timeout
+    pub fn frame(&mut self, idle: Duration, width: usize, height: usize) -> Option<&mut [u32]> {
+        if self.after.is_zero() || idle < self.after {
+            self.step = None;
+            return None;
+        }
+        let (Ok(w), Ok(h)) = (u16::try_from(width), u16::try_from(height)) else {
+            return None;
+        };
+        let step = ((idle - self.after).as_millis() / STEP.as_millis()) as u64;
+        if self.step != Some(step) || self.size != (width, height) {
+            let rgba = test_pattern::generate_no_signal(w, h, step);
+            self.frame.clear();
+            self.frame.extend(
+                rgba.chunks_exact(4).map(|px| {
+                    (u32::from(px[0]) << 16) | (u32::from(px[1]) << 8) | u32::from(px[2])
+                }),
+            );
+            self.step = Some(step);
+            self.size = (width, height);
+        }
+        Some(&mut self.frame)
+    }
+
+    /// Whether the card was shown by the last call to `frame`
+    pub fn showing(&self) -> bool {
+        self.step.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_card_after_timeout_only() {
+        let mut card = NoSignal::new(Duration::from_secs(3));
+        assert!(card.frame(Duration::from_secs(2), 320, 240).is_none());
+        assert!(!card.showing());
+
+        let frame = card.frame(Duration::from_secs(3), 320, 240).unwrap();
+        assert_eq!(frame.len(), 320 * 240);
+        let first = frame.to_vec();
+        assert!(card.showing());
+
+        // The text fades, so the card changes over time
+        let later = Duration::from_secs(3) + STEP * 30;
+        assert_ne!(card.frame(later, 320, 240).unwrap(), &first[..]);
+        assert_eq!(card.frame(later, 160, 120).unwrap().len(), 160 * 120);
+
+        // Video is back
+        assert!(card.frame(Duration::ZERO, 160, 120).is_none());
+        assert!(!card.showing());
+
+        let mut never = NoSignal::new(Duration::ZERO);
+        assert!(never.frame(Duration::from_secs(3600), 320, 240).is_none());
+    }
+}
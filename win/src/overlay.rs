@@ -0,0 +1,254 @@
+//! Status text drawn over the video in the top-left corner
+//!
+//! The overlay is blitted into the frame buffer right before it is shown
+//! and the pixels underneath are put back right after, so delta frames and
+//! screenshots never see it. Text uses a built-in 5x7 bitmap font; lowercase
+//! letters are drawn as capitals.
+
+use std::time::Duration;
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+
+/// Each font pixel is drawn as a `SCALE`x`SCALE` block
+const SCALE: usize = 2;
+
+/// Space between the text and the edge of its background box
+const PADDING: usize = 6;
+
+/// Space between lines of text
+const LINE_GAP: usize = 4;
+
+/// Offset of the box from the top-left corner of the frame
+const MARGIN: usize = 8;
+
+const TEXT_COLOR: u32 = 0x00FF_FFFF;
+
+/// No video for this long shows "No signal" instead of stale stats
+pub const NO_SIGNAL_AFTER: Duration = Duration::from_secs(2);
+
+/// Overlay text given how long ago the last video frame was shown
+pub fn status_text(since_last_video: Option<Duration>, live: &str) -> &str {
+    match since_last_video {
+        None => "Connecting...",
+        Some(elapsed) if elapsed > NO_SIGNAL_AFTER => "No signal",
+        Some(_) => live,
+    }
+}
+
+/// Pixels covered by the overlay, to put back once the frame was shown
+pub struct Patch {
+    x: usize,
+    y: usize,
+    width: usize,
+    pixels: Vec<u32>,
+}
+
+impl Patch {
+    /// Save the `width` x `height` pixels at (`x`, `y`) of `buffer`
+    /// (`frame_width` pixels wide) before drawing over them
+    pub fn save(
+        buffer: &[u32],
+        frame_width: usize,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Self {
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in y..y + height {
+            let start = row * frame_width + x;
+            pixels.extend_from_slice(&buffer[start..start + width]);
+        }
+        Self {
+            x,
+            y,
+            width,
+            pixels,
+        }
+    }
+
+    /// Put the covered pixels back into `buffer` (`frame_width` pixels wide)
+    pub fn restore(self, buffer: &mut [u32], frame_width: usize) {
+        if self.width == 0 {
+            return;
+        }
+        for (row, saved) in self.pixels.chunks_exact(self.width).enumerate() {
+            let start = (self.y + row) * frame_width + self.x;
+            buffer[start..start + self.width].copy_from_slice(saved);
+        }
+    }
+}
+
+/// Draw `text` on a darkened box in the top-left corner of the frame
+///
+/// Each `\n` starts a new line. The box is clipped to the frame. Returns
+/// `None` if nothing was drawn.
+pub fn draw_text(buffer: &mut [u32], width: usize, height: usize, text: &str) -> Option<Patch> {
+    if buffer.len() < width * height {
+        return None;
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let chars = lines.iter().map(|line| line.chars().count()).max()?;
+    if chars == 0 {
+        return None;
+    }
+
+    let line_height = GLYPH_HEIGHT * SCALE + LINE_GAP;
+    let text_width = chars * (GLYPH_WIDTH + 1) * SCALE - SCALE;
+    let text_height = lines.len() * line_height - LINE_GAP;
+    let box_width = (text_width + 2 * PADDING).min(width.saturating_sub(MARGIN));
+    let box_height = (text_height + 2 * PADDING).min(height.saturating_sub(MARGIN));
+    if box_width == 0 || box_height == 0 {
+        return None;
+    }
+
+    let patch = Patch::save(buffer, width, MARGIN, MARGIN, box_width, box_height);
+    for row in 0..box_height {
+        let start = (MARGIN + row) * width + MARGIN;
+        for pixel in &mut buffer[start..start + box_width] {
+            // Half brightness keeps the video visible behind the text.
+            *pixel = (*pixel >> 1) & 0x007F_7F7F;
+        }
+    }
+
+    for (line_index, line) in lines.iter().enumerate() {
+        let top = PADDING + line_index * line_height;
+        for (i, c) in line.chars().enumerate() {
+            let left = PADDING + i * (GLYPH_WIDTH + 1) * SCALE;
+            for (glyph_row, bits) in glyph(c).iter().enumerate() {
+                for glyph_col in 0..GLYPH_WIDTH {
+                    if bits & (0x10 >> glyph_col) == 0 {
+                        continue;
+                    }
+                    for dy in 0..SCALE {
+                        for dx in 0..SCALE {
+                            let x = left + glyph_col * SCALE + dx;
+                            let y = top + glyph_row * SCALE + dy;
+                            if x < box_width && y < box_height {
+                                buffer[(MARGIN + y) * width + MARGIN + x] = TEXT_COLOR;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Some(patch)
+}
+
+/// 5x7 bitmap for `c`, one byte per row with bit 4 as the leftmost column
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '@' => [0x0E, 0x11, 0x17, 0x15, 0x17, 0x10, 0x0F],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '[' => [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E],
+        ']' => [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_text() {
+        let live = "60 FPS";
+        assert_eq!(status_text(None, live), "Connecting...");
+        assert_eq!(status_text(Some(Duration::from_millis(16)), live), live);
+        assert_eq!(status_text(Some(Duration::from_secs(3)), live), "No signal");
+    }
+
+    #[test]
+    fn test_draw_and_restore() {
+        let (width, height) = (200, 40);
+        let original: Vec<u32> = (0..width * height).map(|i| i as u32 * 7).collect();
+        let mut buffer = original.clone();
+
+        let patch = draw_text(&mut buffer, width, height, "No signal").unwrap();
+        assert_ne!(buffer, original);
+        assert!(buffer.contains(&TEXT_COLOR));
+        // Nothing outside the box is touched
+        assert_eq!(buffer[0], original[0]);
+        assert_eq!(buffer[width * height - 1], original[width * height - 1]);
+
+        patch.restore(&mut buffer, width);
+        assert_eq!(buffer, original);
+    }
+
+    #[test]
+    fn test_lines_stack() {
+        let (width, height) = (200, 80);
+        let lit_rows = |buffer: &[u32]| {
+            (0..height)
+                .filter(|y| buffer[y * width..][..width].contains(&TEXT_COLOR))
+                .count()
+        };
+        let mut one = vec![0; width * height];
+        let mut two = vec![0; width * height];
+        draw_text(&mut one, width, height, "60 FPS").unwrap();
+        let patch = draw_text(&mut two, width, height, "60 FPS\n55 FPS").unwrap();
+        assert_eq!(lit_rows(&two), 2 * lit_rows(&one));
+
+        patch.restore(&mut two, width);
+        assert!(two.iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn test_clips_to_small_frames() {
+        let mut buffer = vec![0x00FF_0000; 30 * 12];
+        let patch = draw_text(&mut buffer, 30, 12, "1920x1080 @ 60 FPS").unwrap();
+        patch.restore(&mut buffer, 30);
+        assert!(buffer.iter().all(|&p| p == 0x00FF_0000));
+
+        let mut tiny = vec![0; 4 * 4];
+        assert!(draw_text(&mut tiny, 4, 4, "x").is_none());
+        assert!(draw_text(&mut buffer, 30, 12, "").is_none());
+    }
+}
@@ -0,0 +1,140 @@
+//! Reorder buffer for video frames that arrive out of sequence
+//!
+//! Frames are released as soon as they are next in sequence. A frame after a
+//! gap is held until the missing one shows up or until something has waited
+//! for the configured delay, at which point the gap is skipped. Frames older
+//! than the last one released are late and get dropped.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// A sequence this far behind the next expected one means the sender
+/// restarted its numbering rather than a frame arriving late
+const RESTART_DISTANCE: u64 = 300;
+
+/// Holds frames for up to `delay` to put them back in sequence order
+pub struct ReorderBuffer<T> {
+    delay: Duration,
+    next_sequence: Option<u64>,
+    pending: BTreeMap<u64, (Instant, T)>,
+}
+
+impl<T> ReorderBuffer<T> {
+    /// Create a buffer that waits at most `delay` for a missing frame
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            next_sequence: None,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Queue a frame that arrived at `now`
+    ///
+    /// Returns `false` if the frame was dropped as late or duplicate.
+    pub fn push(&mut self, sequence: u64, item: T, now: Instant) -> bool {
+        if let Some(next) = self.next_sequence {
+            if sequence < next {
+                if next - sequence <= RESTART_DISTANCE {
+                    return false;
+                }
+                // New stream: anything still pending belongs to the old one.
+                self.pending.clear();
+                self.next_sequence = None;
+            }
+        }
+        if self.pending.contains_key(&sequence) {
+            return false;
+        }
+        self.pending.insert(sequence, (now, item));
+        true
+    }
+
+    /// Next frame to display, if one is ready at `now`
+    pub fn pop_ready(&mut self, now: Instant) -> Option<T> {
+        let (&sequence, _) = self.pending.first_key_value()?;
+        let in_order = self.next_sequence == Some(sequence);
+        let waited_out = self
+            .pending
+            .values()
+            .any(|(arrived, _)| now.duration_since(*arrived) >= self.delay);
+        if !in_order && !waited_out {
+            return None;
+        }
+
+        let (sequence, (_, item)) = self.pending.pop_first()?;
+        self.next_sequence = Some(sequence + 1);
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELAY: Duration = Duration::from_millis(20);
+
+    fn drain(buffer: &mut ReorderBuffer<u64>, now: Instant) -> Vec<u64> {
+        std::iter::from_fn(|| buffer.pop_ready(now)).collect()
+    }
+
+    #[test]
+    fn test_releases_in_order_frames_immediately() {
+        let mut buffer = ReorderBuffer::new(DELAY);
+        let t0 = Instant::now();
+
+        // The first frame waits since nothing before it is known yet.
+        buffer.push(10, 10, t0);
+        assert!(drain(&mut buffer, t0).is_empty());
+        assert_eq!(drain(&mut buffer, t0 + DELAY), [10]);
+
+        buffer.push(11, 11, t0 + DELAY);
+        assert_eq!(drain(&mut buffer, t0 + DELAY), [11]);
+    }
+
+    #[test]
+    fn test_reorders_swapped_frames() {
+        let mut buffer = ReorderBuffer::new(DELAY);
+        let t0 = Instant::now();
+        buffer.push(0, 0, t0);
+        assert_eq!(drain(&mut buffer, t0 + DELAY), [0]);
+
+        let t1 = t0 + DELAY;
+        buffer.push(2, 2, t1);
+        assert!(drain(&mut buffer, t1).is_empty(), "waiting for 1");
+        buffer.push(1, 1, t1 + Duration::from_millis(3));
+        assert_eq!(drain(&mut buffer, t1 + Duration::from_millis(3)), [1, 2]);
+    }
+
+    #[test]
+    fn test_skips_gap_after_delay_and_drops_late_frames() {
+        let mut buffer = ReorderBuffer::new(DELAY);
+        let t0 = Instant::now();
+        buffer.push(0, 0, t0);
+        assert_eq!(drain(&mut buffer, t0 + DELAY), [0]);
+
+        let t1 = t0 + DELAY;
+        buffer.push(3, 3, t1);
+        buffer.push(2, 2, t1 + Duration::from_millis(15));
+        assert!(drain(&mut buffer, t1 + Duration::from_millis(19)).is_empty());
+        // Frame 3 has waited long enough; 1 is given up on.
+        assert_eq!(drain(&mut buffer, t1 + DELAY), [2, 3]);
+
+        assert!(!buffer.push(1, 1, t1 + DELAY), "late");
+        buffer.push(4, 4, t1 + DELAY);
+        assert!(!buffer.push(4, 4, t1 + DELAY), "duplicate");
+        assert_eq!(drain(&mut buffer, t1 + DELAY), [4]);
+    }
+
+    #[test]
+    fn test_resets_when_sender_restarts() {
+        let mut buffer = ReorderBuffer::new(DELAY);
+        let t0 = Instant::now();
+        buffer.push(5000, 5000, t0);
+        assert_eq!(drain(&mut buffer, t0 + DELAY), [5000]);
+
+        let t1 = t0 + DELAY;
+        assert!(buffer.push(0, 0, t1));
+        assert_eq!(drain(&mut buffer, t1 + DELAY), [0]);
+    }
+}
@@ -0,0 +1,128 @@
+//! Downscaling frames to the window size
+//!
+//! minifb stretches the buffer to the window by picking the nearest pixel,
+//! which turns text into a jagged mess when a 4K Mac is shown in a 1080p
+//! window. With `--scaling-quality bilinear` each window gets a copy of the
+//! frame resampled to its own size before `update_with_buffer`, so minifb
+//! has nothing left to stretch. Only frames larger than the window are
+//! resampled; upscaling stays with minifb either way.
+
+use clap::ValueEnum;
+
+/// How frames larger than the window are shrunk to fit it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ScalingQuality {
+    /// Let minifb pick the nearest pixel
+    Fast,
+    /// Resample with bilinear filtering first
+    Bilinear,
+}
+
+/// The two source pixels an output pixel falls between, and how far towards
+/// the second it is in 1/256ths
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tap {
+    first: usize,
+    second: usize,
+    weight: u32,
+}
+
+/// Taps for resampling a line of `src` pixels to `dst` pixels
+///
+/// Pixel centres are lined up, so halving the size averages each pair of
+/// source pixels equally.
+fn taps(src: usize, dst: usize) -> impl Iterator<Item = Tap> {
+    let ratio = src as f64 / dst as f64;
+    (0..dst).map(move |i| {
+        let pos = ((i as f64 + 0.5) * ratio - 0.5).max(0.0);
+        let first = (pos as usize).min(src - 1);
+        let weight = ((pos - first as f64) * 256.0).round().min(256.0) as u32;
+        Tap {
+            first,
+            second: (first + 1).min(src - 1),
+            weight,
+        }
+    })
+}
+
+/// Blend two `0RGB` pixels, `weight`/256 of the way from `a` to `b`
+fn lerp(a: u32, b: u32, weight: u32) -> u32 {
+    // Red and blue are 16 bits apart, so they can be blended in one go.
+    let rb = (((a & 0xFF00FF) * (256 - weight) + (b & 0xFF00FF) * weight) >> 8) & 0xFF00FF;
+    let g = (((a & 0x00FF00) * (256 - weight) + (b & 0x00FF00) * weight) >> 8) & 0x00FF00;
+    rb | g
+}
+
+/// Resample a `width`x`height` `0RGB` buffer to `dst_width`x`dst_height`
+/// with bilinear filtering, into `out`
+pub fn bilinear(
+    src: &[u32],
+    width: usize,
+    height: usize,
+    dst_width: usize,
+    dst_height: usize,
+    out: &mut Vec<u32>,
+) {
+    out.clear();
+    if width == 0 || height == 0 || src.len() < width * height {
+        return;
+    }
+    let columns: Vec<Tap> = taps(width, dst_width).collect();
+    out.reserve(dst_width * dst_height);
+    for row in taps(height, dst_height) {
+        let top = &src[row.first * width..][..width];
+        let bottom = &src[row.second * width..][..width];
+        out.extend(columns.iter().map(|column| {
+            let upper = lerp(top[column.first], top[column.second], column.weight);
+            let lower = lerp(bottom[column.first], bottom[column.second], column.weight);
+            lerp(upper, lower, row.weight)
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lerp_blends_each_channel() {
+        assert_eq!(lerp(0x000000, 0xFFFFFF, 0), 0x000000);
+        assert_eq!(lerp(0x000000, 0xFFFFFF, 256), 0xFFFFFF);
+        assert_eq!(lerp(0x00FF0000, 0x000000FF, 128), 0x007F007F);
+        assert_eq!(lerp(0x00204060, 0x00204060, 77), 0x00204060);
+    }
+
+    #[test]
+    fn test_halving_averages_pixel_pairs() {
+        // 4x2 to 2x1: each output pixel is the average of a 2x2 block
+        let src = [
+            0x000000, 0x0000FF, 0xFF0000, 0xFF0000, //
+            0x0000FF, 0x000000, 0xFF0000, 0xFF0000,
+        ];
+        let mut out = Vec::new();
+        bilinear(&src, 4, 2, 2, 1, &mut out);
+        assert_eq!(out, [0x00007F, 0xFF0000]);
+    }
+
+    #[test]
+    fn test_output_size() {
+        let src = vec![0x00123456; 3840 * 2160];
+        let mut out = Vec::new();
+        bilinear(&src, 3840, 2160, 1280, 720, &mut out);
+        assert_eq!(out.len(), 1280 * 720);
+        assert!(out.iter().all(|&pixel| pixel == 0x00123456));
+
+        bilinear(&src, 3840, 2160, 0, 0, &mut out);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_taps_stay_in_bounds() {
+        for (src, dst) in [(1, 1), (3, 2), (1920, 1283), (7, 1)] {
+            for tap in taps(src, dst) {
+                assert!(tap.first < src && tap.second < src);
+                assert!(tap.weight <= 256);
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Save the displayed frame as a PNG
+//!
+//! Screenshots are taken from the converted `0RGB` display buffer at the
+//! stream's resolution, so they show exactly what the decoder produced before
+//! any window scaling.
+
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+/// Write `buffer` to a timestamped PNG in `dir` and return its path
+pub fn save_png(
+    buffer: &[u32],
+    width: usize,
+    height: usize,
+    dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    if buffer.len() != width * height {
+        anyhow::bail!(
+            "Buffer holds {} pixels, expected {}x{}",
+            buffer.len(),
+            width,
+            height
+        );
+    }
+
+    let name = format!(
+        "thunder_screenshot_{}.png",
+        Local::now().format("%Y%m%d_%H%M%S_%3f")
+    );
+    let path = dir.join(name);
+    image::save_buffer(
+        &path,
+        &to_rgb8(buffer),
+        width as u32,
+        height as u32,
+        image::ColorType::Rgb8,
+    )?;
+    Ok(path)
+}
+
+/// Unpack `0RGB` display pixels into tightly packed RGB bytes
+fn to_rgb8(buffer: &[u32]) -> Vec<u8> {
+    buffer
+        .iter()
+        .flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_png_round_trip() {
+        let dir = std::env::temp_dir().join(format!("thunder_shot_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let buffer = [
+            0x00ff0000, 0x0000ff00, 0x000000ff, 0x00123456, 0x00ffffff, 0,
+        ];
+        let path = save_png(&buffer, 3, 2, &dir).unwrap();
+        let image = image::open(&path).unwrap().to_rgb8();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(0, 1).0, [0x12, 0x34, 0x56]);
+        assert_eq!(image.get_pixel(2, 1).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_save_png_rejects_mismatched_size() {
+        assert!(save_png(&[0; 5], 3, 2, &std::env::temp_dir()).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! HDR to SDR tone mapping for 10-bit video
+//!
+//! HDR pixels are linearized with their transfer function and scaled so SDR
+//! reference white (203 nits, per BT.2408) lands at 1.0. BT.2020 colors are
+//! then moved into BT.709 primaries, and an extended Reinhard curve rolls
+//! highlights off so everything up to `PEAK_NITS` stays distinguishable
+//! instead of clipping. The result is gamma encoded for an SDR monitor.
+
+use thunder_shared::protocol::{ColorSpace, TransferFunction};
+
+/// Luminance shown as SDR white
+const REFERENCE_WHITE_NITS: f32 = 203.0;
+
+/// Brightest highlight kept below white; brighter ones clip
+const PEAK_NITS: f32 = 1000.0;
+
+/// Gamma of the SDR output, and of SDR input that still needs gamut mapping
+const SDR_GAMMA: f32 = 2.2;
+
+/// Linear BT.2020 to BT.709 primaries (BT.2087)
+const BT2020_TO_BT709: [[f32; 3]; 3] = [
+    [1.6605, -0.5876, -0.0728],
+    [-0.1246, 1.1329, -0.0083],
+    [-0.0182, -0.1006, 1.1187],
+];
+
+/// Map a non-linear R'G'B' pixel (each 0.0-1.0) to 8-bit SDR RGB
+pub fn tone_map(rgb: [f32; 3], space: ColorSpace, transfer: TransferFunction) -> (u8, u8, u8) {
+    let mut linear = match transfer {
+        TransferFunction::Sdr => rgb.map(|c| c.powf(SDR_GAMMA)),
+        TransferFunction::Pq => rgb.map(|c| pq_eotf(c) / REFERENCE_WHITE_NITS),
+        TransferFunction::Hlg => hlg_eotf(rgb).map(|c| c / REFERENCE_WHITE_NITS),
+    };
+
+    if space == ColorSpace::Bt2020 {
+        linear = BT2020_TO_BT709
+            .map(|row| (row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2]).max(0.0));
+    }
+
+    if transfer.is_hdr() {
+        // Scaling all channels by the brightest one's curve keeps the hue.
+        let max = linear[0].max(linear[1]).max(linear[2]);
+        if max > 0.0 {
+            let scale = reinhard(max) / max;
+            linear = linear.map(|c| c * scale);
+        }
+    }
+
+    let [r, g, b] = linear.map(|c| (c.clamp(0.0, 1.0).powf(1.0 / SDR_GAMMA) * 255.0).round() as u8);
+    (r, g, b)
+}
+
+/// Extended Reinhard curve reaching 1.0 at `PEAK_NITS`
+fn reinhard(luminance: f32) -> f32 {
+    let white = PEAK_NITS / REFERENCE_WHITE_NITS;
+    luminance * (1.0 + luminance / (white * white)) / (1.0 + luminance)
+}
+
+/// SMPTE ST 2084 signal to display luminance in nits
+fn pq_eotf(signal: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+    let p = signal.powf(1.0 / M2);
+    let linear = ((p - C1).max(0.0) / (C2 - C3 * p)).powf(1.0 / M1);
+    linear * 10_000.0
+}
+
+/// ARIB STD-B67 signal to display luminance in nits, on a `PEAK_NITS` display
+fn hlg_eotf(rgb: [f32; 3]) -> [f32; 3] {
+    const A: f32 = 0.178_832_77;
+    const B: f32 = 1.0 - 4.0 * A;
+    const C: f32 = 0.559_910_7;
+    // System gamma for a 1000 nit display
+    const GAMMA: f32 = 1.2;
+
+    let scene = rgb.map(|e| {
+        if e <= 0.5 {
+            e * e / 3.0
+        } else {
+            (((e - C) / A).exp() + B) / 12.0
+        }
+    });
+    // The OOTF works on scene luminance, with BT.2020 weights
+    let luminance = 0.2627 * scene[0] + 0.6780 * scene[1] + 0.0593 * scene[2];
+    let gain = PEAK_NITS * luminance.powf(GAMMA - 1.0);
+    scene.map(|c| c * gain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_functions() {
+        assert_eq!(pq_eotf(0.0), 0.0);
+        assert!((pq_eotf(1.0) - 10_000.0).abs() < 1.0);
+        assert!((pq_eotf(0.5807) - 203.0).abs() < 1.0);
+
+        // Full-scale HLG white is the display peak
+        let [r, g, b] = hlg_eotf([1.0; 3]);
+        assert!((r - PEAK_NITS).abs() < 1.0 && r == g && g == b);
+        // Both halves of the HLG curve meet at 0.5
+        let below = hlg_eotf([0.5 - 1e-4; 3])[0];
+        let above = hlg_eotf([0.5 + 1e-4; 3])[0];
+        assert!((above - below).abs() < 0.5, "{} vs {}", below, above);
+    }
+
+    #[test]
+    fn test_tone_map_rolls_off_highlights() {
+        assert!((reinhard(PEAK_NITS / REFERENCE_WHITE_NITS) - 1.0).abs() < 1e-5);
+        assert_eq!(
+            tone_map([0.0; 3], ColorSpace::Bt2020, TransferFunction::Pq),
+            (0, 0, 0)
+        );
+
+        // Brighter input never comes out darker
+        let mut last = 0;
+        for step in 0..=20 {
+            let signal = step as f32 / 20.0;
+            let (r, _, _) = tone_map([signal; 3], ColorSpace::Bt2020, TransferFunction::Pq);
+            assert!(r >= last, "{} dropped to {}", last, r);
+            last = r;
+        }
+        assert_eq!(last, 255);
+    }
+
+    #[test]
+    fn test_sdr_round_trips() {
+        for value in [0u8, 1, 64, 128, 200, 255] {
+            let c = value as f32 / 255.0;
+            assert_eq!(
+                tone_map([c; 3], ColorSpace::Bt709, TransferFunction::Sdr),
+                (value, value, value)
+            );
+        }
+    }
+}
@@ -1,18 +1,21 @@
 #[cfg(windows)]
+mod settings;
+#[cfg(windows)]
 mod win32_shell;
 
 /// Runs the Windows UI shell for the receiver.
 ///
 /// On Windows, this shows a small native Win32 window with Start/Stop buttons and
-/// launches/stops the existing `thunder_receiver` CLI as a child process.
+/// launches/stops the existing `thunder_receiver` CLI as a child process. With
+/// `autostart` the receiver is started immediately, as if Start was clicked.
 #[cfg(windows)]
-pub fn run() -> anyhow::Result<()> {
-    win32_shell::run()
+pub fn run(autostart: bool) -> anyhow::Result<()> {
+    win32_shell::run(autostart)
 }
 
 /// Non-Windows stub so `cargo build` on macOS/Linux still works.
 #[cfg(not(windows))]
-pub fn run() -> anyhow::Result<()> {
+pub fn run(_autostart: bool) -> anyhow::Result<()> {
     eprintln!("thunder_receiver_ui is only supported on Windows.");
     Ok(())
 }
@@ -0,0 +1,110 @@
+//! Settings the UI remembers between launches
+//!
+//! Stored as JSON in `%APPDATA%\ThunderMirror\ui.json`. A missing or
+//! unreadable file just means the defaults are used.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use thunder_shared::Config;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiSettings {
+    /// Port the receiver was last started on
+    pub port: u16,
+
+    /// Color scheme of the window
+    pub theme: ThemeKind,
+
+    /// Where the window was when it was last closed
+    pub window: Option<WindowRect>,
+
+    /// Whether the receiver is started fullscreen
+    pub fullscreen: bool,
+
+    /// Start the receiver as soon as the UI opens (like `--autostart`)
+    pub autostart: bool,
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        Self {
+            port: Config::win_receiver().port,
+            theme: ThemeKind::default(),
+            window: None,
+            fullscreen: false,
+            autostart: false,
+        }
+    }
+}
+
+/// Outer bounds of a window in screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl WindowRect {
+    /// Move the rect inside `screen`, shrinking it if it doesn't fit
+    ///
+    /// Keeps a saved position usable after a monitor was unplugged or
+    /// the resolution changed.
+    pub fn clamped_to(self, screen: WindowRect) -> WindowRect {
+        let width = self.width.min(screen.width);
+        let height = self.height.min(screen.height);
+        WindowRect {
+            x: self.x.min(screen.x + screen.width - width).max(screen.x),
+            y: self.y.min(screen.y + screen.height - height).max(screen.y),
+            width,
+            height,
+        }
+    }
+}
+
+/// Which set of window colors to use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeKind {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl ThemeKind {
+    /// The other theme
+    pub fn toggled(self) -> Self {
+        match self {
+            ThemeKind::Dark => ThemeKind::Light,
+            ThemeKind::Light => ThemeKind::Dark,
+        }
+    }
+}
+
+impl UiSettings {
+    /// Load the saved settings, falling back to defaults
+    pub fn load() -> Self {
+        settings_path()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save the settings for the next launch
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = settings_path().ok_or_else(|| anyhow::anyhow!("APPDATA is not set"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("APPDATA")?;
+    Some(PathBuf::from(dir).join("ThunderMirror").join("ui.json"))
+}
@@ -1,5 +1,6 @@
 #![cfg(windows)]
 
+use std::collections::VecDeque;
 use std::io::{BufRead, BufReader};
 use std::os::windows::process::CommandExt;
 use std::process::Stdio;
@@ -7,21 +8,37 @@ use std::process::{Child, Command};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
-use windows::core::w;
-use windows::Win32::Foundation::{GetLastError, HWND, LPARAM, LRESULT, RECT, WPARAM, COLORREF};
+use thunder_shared::stats::StatusEvent;
+
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{GetLastError, HWND, LPARAM, LRESULT, POINT, RECT, WPARAM, COLORREF};
 use windows::Win32::Graphics::Gdi::{
     BeginPaint, CreateFontW, CreatePen, CreateSolidBrush, DeleteObject, EndPaint, FillRect,
-    GetDeviceCaps, GetStockObject, InvalidateRect, LineTo, MoveToEx, RoundRect, SelectObject, 
+    GetDeviceCaps, GetStockObject, InvalidateRect, LineTo, MoveToEx, Polyline, RoundRect, SelectObject, 
     SetBkMode, SetTextColor, TextOutW, HBRUSH, HGDIOBJ, LOGPIXELSY, PAINTSTRUCT, PS_SOLID, 
-    TRANSPARENT, DrawTextW, DT_CENTER, DT_VCENTER, DT_SINGLELINE,
+    TRANSPARENT, DrawTextW, DT_CENTER, DT_VCENTER, DT_SINGLELINE, SetBkColor, HDC,
 };
 use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::Controls::{EM_LIMITTEXT, WM_MOUSELEAVE};
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    EnableWindow, TrackMouseEvent, TME_LEAVE, TRACKMOUSEEVENT,
+};
+use windows::Win32::UI::Shell::{
+    Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NIM_MODIFY,
+    NOTIFYICONDATAW,
+};
 use windows::Win32::UI::WindowsAndMessaging::{
-    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetClientRect, GetMessageW,
-    LoadCursorW, PostMessageW, PostQuitMessage, RegisterClassW, ShowWindow,
-    TranslateMessage, CS_HREDRAW, CS_VREDRAW, CW_USEDEFAULT, IDC_ARROW,
-    MSG, SW_SHOW, WM_APP, WM_CLOSE, WM_CREATE, WM_DESTROY, WM_ERASEBKGND, WM_PAINT,
-    WM_LBUTTONDOWN, WM_LBUTTONUP, WNDCLASSW, WINDOW_EX_STYLE, WS_OVERLAPPEDWINDOW,
+    AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu, DestroyWindow,
+    DispatchMessageW, GetClientRect, GetCursorPos, GetMessageW, GetSystemMetrics, GetWindowRect,
+    GetWindowTextW, IsIconic, LoadCursorW, LoadIconW, PostMessageW, PostQuitMessage,
+    RegisterClassW, SendMessageW, SetForegroundWindow, SetWindowPos, ShowWindow, TrackPopupMenu,
+    TranslateMessage, CS_HREDRAW, CS_VREDRAW, CW_USEDEFAULT, EN_CHANGE, ES_NUMBER, HMENU,
+    IDC_ARROW, IDI_APPLICATION, MF_GRAYED, MF_SEPARATOR, MF_STRING, MSG, SM_CXVIRTUALSCREEN,
+    SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN, SWP_NOACTIVATE, SWP_NOZORDER,
+    SW_HIDE, SW_SHOW, TPM_RETURNCMD, TPM_RIGHTBUTTON, WINDOW_EX_STYLE, WINDOW_STYLE, WM_APP,
+    WM_CLOSE, WM_COMMAND, WM_CONTEXTMENU, WM_CREATE, WM_CTLCOLOREDIT, WM_DESTROY, WM_ERASEBKGND,
+    WM_LBUTTONDBLCLK, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MOUSEMOVE, WM_NULL, WM_PAINT,
+    WM_RBUTTONUP, WM_SETFONT, WNDCLASSW, WS_CHILD, WS_OVERLAPPEDWINDOW, WS_TABSTOP, WS_VISIBLE,
 };
 
 // Flag to hide console window when spawning child process
@@ -30,23 +47,94 @@ const CREATE_NO_WINDOW: u32 = 0x08000000;
 const ID_BTN_START: usize = 1001;
 const ID_BTN_STOP: usize = 1002;
 const ID_BTN_FULLSCREEN: usize = 1003;
+const ID_EDIT_PORT: usize = 1004;
+const ID_BTN_THEME: usize = 1005;
+const ID_CHECK_AUTOSTART: usize = 1006;
 
 const WM_UI_UPDATE: u32 = WM_APP + 1;
+// Tray icon callback; the mouse message is in the low word of lParam
+const WM_TRAY: u32 = WM_APP + 2;
+
+const TRAY_ICON_ID: u32 = 1;
+// Resource ID of the app icon embedded by build.rs
+const APP_ICON_RESOURCE: usize = 1;
+const ID_MENU_SHOW: usize = 2001;
+const ID_MENU_EXIT: usize = 2002;
 
 static UI_CLASS_REGISTERED: AtomicBool = AtomicBool::new(false);
+// `--autostart` from the command line, read when the window is created
+static AUTOSTART: AtomicBool = AtomicBool::new(false);
+
+// Window colors, as 0xRRGGBB
+struct Theme {
+    background: u32,
+    card: u32,
+    // Status badge behind the connection state
+    badge: u32,
+    border: u32,
+    text: u32,
+    text_muted: u32,
+    accent: u32,
+    accent_dark: u32,
+    green: u32,
+    green_dark: u32,
+    red: u32,
+    red_dark: u32,
+    // Label on filled buttons, and the fill of disabled or switched-off ones
+    button_text: u32,
+    button_off: u32,
+}
+
+impl Theme {
+    // Matches the Swift app's dark theme
+    const DARK: Theme = Theme {
+        background: 0x0D1117,
+        card: 0x161B22,
+        badge: 0x21262D,
+        border: 0x30363D,
+        text: 0xFFFFFF,
+        text_muted: 0x8B949E,
+        accent: 0x58A6FF,
+        accent_dark: 0x1F6FEB,
+        green: 0x3FB950,
+        green_dark: 0x238636,
+        red: 0xF85149,
+        red_dark: 0xDA3633,
+        button_text: 0xFFFFFF,
+        button_off: 0x30363D,
+    };
+
+    // For bright displays, where the dark window is hard to read
+    const LIGHT: Theme = Theme {
+        background: 0xFFFFFF,
+        card: 0xF6F8FA,
+        badge: 0xEAEEF2,
+        border: 0xD0D7DE,
+        text: 0x1F2328,
+        text_muted: 0x656D76,
+        accent: 0x0969DA,
+        accent_dark: 0x0550AE,
+        green: 0x1A7F37,
+        green_dark: 0x116329,
+        red: 0xCF222E,
+        red_dark: 0xA40E26,
+        button_text: 0xFFFFFF,
+        button_off: 0x8C959F,
+    };
+
+    fn of(kind: ThemeKind) -> &'static Theme {
+        match kind {
+            ThemeKind::Dark => &Theme::DARK,
+            ThemeKind::Light => &Theme::LIGHT,
+        }
+    }
+}
 
-// Colors matching Swift app's dark theme
-const COLOR_BG_DARK: u32 = 0x0D1117;      // Main background
-const COLOR_BG_MEDIUM: u32 = 0x161B22;    // Card background
-const COLOR_ACCENT_BLUE: u32 = 0x58A6FF;  // Accent blue
-const COLOR_ACCENT_DARK_BLUE: u32 = 0x1F6FEB;
-const COLOR_GREEN: u32 = 0x3FB950;        // Start button
-const COLOR_GREEN_DARK: u32 = 0x238636;
-const COLOR_RED: u32 = 0xF85149;          // Stop button
-const COLOR_RED_DARK: u32 = 0xDA3633;
-const COLOR_TEXT_PRIMARY: u32 = 0xFFFFFF; // White text
-const COLOR_TEXT_SECONDARY: u32 = 0x8B949E; // Muted text
-const COLOR_BORDER: u32 = 0x30363D;       // Card borders
+// Seconds of stats kept and shown in the graph (the receiver logs stats once a second)
+const STATS_HISTORY_LEN: usize = 60;
+
+// How far a hovered button's color is blended toward white (out of 255)
+const HOVER_LIGHTEN: u32 = 40;
 
 // Convert RGB to Windows COLORREF (BGR format)
 fn rgb_to_colorref(rgb: u32) -> COLORREF {
@@ -56,12 +144,34 @@ fn rgb_to_colorref(rgb: u32) -> COLORREF {
     COLORREF((b as u32) << 16 | (g as u32) << 8 | r as u32)
 }
 
+// Blend an RGB color toward white by `amount`/255
+fn lighten(rgb: u32, amount: u32) -> u32 {
+    let channel = |shift: u32| {
+        let c = (rgb >> shift) & 0xFF;
+        (c + (0xFF - c) * amount / 0xFF) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
+
+use super::settings::{ThemeKind, UiSettings, WindowRect};
+
 #[derive(Debug, Default)]
 struct UiModel {
     process_status: String,
     connection_status: String,
     stats_line: String,
+    // Sender-side FPS and loss from its stats frames
+    sender_line: String,
+    // Recent (FPS, Mbps) readings, oldest first, at most STATS_HISTORY_LEN
+    stats_history: VecDeque<(f32, f32)>,
     fullscreen: bool,
+    // The receiver has no H.264 decoder and only shows raw frames
+    h264_unavailable: bool,
+    // Address the running receiver reported it's bound to
+    listen_address: Option<String>,
+    // Bumped for every receiver started, so a stopped child's late EOF
+    // can't reset the state of the one that replaced it
+    child_generation: u64,
 }
 
 struct ButtonRect {
@@ -76,6 +186,17 @@ struct AppState {
     child: Option<Child>,
     model: Arc<Mutex<UiModel>>,
     buttons: Vec<ButtonRect>,
+    settings: UiSettings,
+    theme: &'static Theme,
+    port_edit: HWND,
+    // Entered port, or None while the text isn't a valid port
+    port: Option<u16>,
+    edit_brush: HBRUSH,
+    // Whether WM_MOUSELEAVE has been requested for the current hover
+    tracking_mouse: bool,
+    // Closing only hides the window while the tray icon is there to restore it
+    tray_added: bool,
+    tray_tip: String,
     font_title: HGDIOBJ,
     font_normal: HGDIOBJ,
     font_mono: HGDIOBJ,
@@ -110,6 +231,29 @@ impl AppState {
                 w!("Consolas"),
             );
 
+            // Port field inside the Connection card
+            let settings = UiSettings::load();
+            let theme = Theme::of(settings.theme);
+            let port_text: Vec<u16> = settings
+                .port
+                .to_string()
+                .encode_utf16()
+                .chain(std::iter::once(0))
+                .collect();
+            let port_edit = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("EDIT"),
+                PCWSTR(port_text.as_ptr()),
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(ES_NUMBER as u32),
+                150, 123, 70, 22,
+                hwnd,
+                HMENU(ID_EDIT_PORT as isize),
+                None,
+                None,
+            );
+            SendMessageW(port_edit, WM_SETFONT, WPARAM(font_mono.0 as usize), LPARAM(1));
+            SendMessageW(port_edit, EM_LIMITTEXT, WPARAM(5), LPARAM(0));
+
             Self {
                 hwnd,
                 child: None,
@@ -117,7 +261,12 @@ impl AppState {
                     process_status: "Stopped".to_string(),
                     connection_status: "Disconnected".to_string(),
                     stats_line: "—".to_string(),
-                    fullscreen: false,
+                    sender_line: "—".to_string(),
+                    stats_history: VecDeque::with_capacity(STATS_HISTORY_LEN),
+                    fullscreen: settings.fullscreen,
+                    h264_unavailable: false,
+                    listen_address: None,
+                    child_generation: 0,
                 })),
                 buttons: vec![
                     ButtonRect {
@@ -138,7 +287,27 @@ impl AppState {
                         hover: false,
                         pressed: false,
                     },
+                    ButtonRect {
+                        rect: RECT { left: 260, top: 50, right: 355, bottom: 70 },
+                        id: ID_BTN_THEME,
+                        hover: false,
+                        pressed: false,
+                    },
+                    ButtonRect {
+                        rect: RECT { left: 24, top: 452, right: 348, bottom: 474 },
+                        id: ID_CHECK_AUTOSTART,
+                        hover: false,
+                        pressed: false,
+                    },
                 ],
+                port: Some(settings.port),
+                theme,
+                settings,
+                port_edit,
+                edit_brush: CreateSolidBrush(rgb_to_colorref(theme.card)),
+                tracking_mouse: false,
+                tray_added: false,
+                tray_tip: String::new(),
                 font_title: HGDIOBJ(font_title.0),
                 font_normal: HGDIOBJ(font_normal.0),
                 font_mono: HGDIOBJ(font_mono.0),
@@ -147,7 +316,8 @@ impl AppState {
     }
 }
 
-pub fn run() -> anyhow::Result<()> {
+pub fn run(autostart: bool) -> anyhow::Result<()> {
+    AUTOSTART.store(autostart, Ordering::SeqCst);
     unsafe {
         // Enable DPI awareness for crisp rendering on high-DPI displays
         let _ = windows::Win32::UI::HiDpi::SetProcessDpiAwarenessContext(
@@ -184,7 +354,7 @@ pub fn run() -> anyhow::Result<()> {
             CW_USEDEFAULT,
             CW_USEDEFAULT,
             390,
-            500,
+            530,
             None,
             None,
             hinstance,
@@ -198,7 +368,11 @@ pub fn run() -> anyhow::Result<()> {
             ));
         }
 
-        let _ = ShowWindow(hwnd, SW_SHOW);
+        // An auto-started receiver can sit quietly in the tray
+        let started_in_tray = get_state(hwnd).is_some_and(|s| s.child.is_some() && s.tray_added);
+        if !started_in_tray {
+            let _ = ShowWindow(hwnd, SW_SHOW);
+        }
 
         let mut msg = MSG::default();
         while GetMessageW(&mut msg, None, 0, 0).into() {
@@ -222,6 +396,28 @@ unsafe extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam:
                 windows::Win32::UI::WindowsAndMessaging::GWLP_USERDATA,
                 Box::into_raw(state) as isize,
             );
+            if let Some(state) = get_state(hwnd) {
+                state.tray_added = add_tray_icon(hwnd);
+                update_tray_tip(state);
+                if let Some(rect) = state.settings.window {
+                    restore_window_rect(hwnd, rect);
+                }
+                if AUTOSTART.load(Ordering::SeqCst) || state.settings.autostart {
+                    handle_button_click(hwnd, state, ID_BTN_START);
+                }
+            }
+            LRESULT(0)
+        }
+        WM_TRAY => {
+            match (lparam.0 & 0xFFFF) as u32 {
+                WM_LBUTTONDBLCLK => show_window(hwnd),
+                WM_RBUTTONUP | WM_CONTEXTMENU => {
+                    if let Some(state) = get_state(hwnd) {
+                        show_tray_menu(hwnd, state);
+                    }
+                }
+                _ => {}
+            }
             LRESULT(0)
         }
         WM_ERASEBKGND => {
@@ -232,6 +428,43 @@ unsafe extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam:
             paint_window(hwnd);
             LRESULT(0)
         }
+        WM_MOUSEMOVE => {
+            let x = (lparam.0 & 0xFFFF) as i16 as i32;
+            let y = ((lparam.0 >> 16) & 0xFFFF) as i16 as i32;
+
+            if let Some(state) = get_state(hwnd) {
+                if !state.tracking_mouse {
+                    // Ask for WM_MOUSELEAVE so hover clears when the cursor leaves the window
+                    let mut tme = TRACKMOUSEEVENT {
+                        cbSize: std::mem::size_of::<TRACKMOUSEEVENT>() as u32,
+                        dwFlags: TME_LEAVE,
+                        hwndTrack: hwnd,
+                        dwHoverTime: 0,
+                    };
+                    state.tracking_mouse = TrackMouseEvent(&mut tme).is_ok();
+                }
+                for btn in &mut state.buttons {
+                    let hover = point_in_rect(x, y, &btn.rect);
+                    if hover != btn.hover {
+                        btn.hover = hover;
+                        invalidate_button(hwnd, btn);
+                    }
+                }
+            }
+            LRESULT(0)
+        }
+        WM_MOUSELEAVE => {
+            if let Some(state) = get_state(hwnd) {
+                state.tracking_mouse = false;
+                for btn in &mut state.buttons {
+                    if btn.hover {
+                        btn.hover = false;
+                        invalidate_button(hwnd, btn);
+                    }
+                }
+            }
+            LRESULT(0)
+        }
         WM_LBUTTONDOWN => {
             let x = (lparam.0 & 0xFFFF) as i32;
             let y = ((lparam.0 >> 16) & 0xFFFF) as i32;
@@ -267,17 +500,55 @@ unsafe extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam:
             }
             LRESULT(0)
         }
+        WM_COMMAND => {
+            let id = wparam.0 & 0xFFFF;
+            let code = ((wparam.0 >> 16) & 0xFFFF) as u32;
+            if id == ID_EDIT_PORT && code == EN_CHANGE {
+                if let Some(state) = get_state(hwnd) {
+                    state.port = read_port(state.port_edit);
+                    // Start is only enabled with a valid port
+                    invalidate_button(hwnd, &state.buttons[0]);
+                }
+            }
+            LRESULT(0)
+        }
+        WM_CTLCOLOREDIT => match get_state(hwnd) {
+            Some(state) => {
+                // Match the edit control to the card it sits on
+                let hdc = HDC(wparam.0 as isize);
+                SetTextColor(hdc, rgb_to_colorref(state.theme.text));
+                SetBkColor(hdc, rgb_to_colorref(state.theme.card));
+                LRESULT(state.edit_brush.0)
+            }
+            None => DefWindowProcW(hwnd, msg, wparam, lparam),
+        },
         WM_UI_UPDATE => {
+            if let Some(state) = get_state(hwnd) {
+                update_tray_tip(state);
+            }
             let _ = InvalidateRect(hwnd, None, false);
             LRESULT(0)
         }
         WM_CLOSE => {
-            let _ = DestroyWindow(hwnd);
+            // Keep receiving in the background; Exit in the tray menu quits
+            match get_state(hwnd) {
+                Some(state) if state.tray_added => {
+                    let _ = ShowWindow(hwnd, SW_HIDE);
+                }
+                _ => {
+                    let _ = DestroyWindow(hwnd);
+                }
+            }
             LRESULT(0)
         }
         WM_DESTROY => {
             if let Some(state) = get_state(hwnd) {
                 stop_child(state);
+                save_window_rect(hwnd, state);
+                let _ = DeleteObject(state.edit_brush);
+                if state.tray_added {
+                    let _ = Shell_NotifyIconW(NIM_DELETE, &tray_icon_data(hwnd));
+                }
             }
 
             let ptr = windows::Win32::UI::WindowsAndMessaging::GetWindowLongPtrW(
@@ -300,20 +571,91 @@ unsafe extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam:
     }
 }
 
+// Put the window back where it was last closed, kept on the current screens
+unsafe fn restore_window_rect(hwnd: HWND, saved: WindowRect) {
+    let screen = WindowRect {
+        x: GetSystemMetrics(SM_XVIRTUALSCREEN),
+        y: GetSystemMetrics(SM_YVIRTUALSCREEN),
+        width: GetSystemMetrics(SM_CXVIRTUALSCREEN),
+        height: GetSystemMetrics(SM_CYVIRTUALSCREEN),
+    };
+    if screen.width <= 0 || screen.height <= 0 {
+        return;
+    }
+    let rect = saved.clamped_to(screen);
+    let _ = SetWindowPos(
+        hwnd,
+        HWND(0),
+        rect.x,
+        rect.y,
+        rect.width,
+        rect.height,
+        SWP_NOZORDER | SWP_NOACTIVATE,
+    );
+}
+
+// Remember the window rect for the next launch
+unsafe fn save_window_rect(hwnd: HWND, state: &mut AppState) {
+    // A minimized window sits off-screen; keep the last saved rect instead
+    if IsIconic(hwnd).as_bool() {
+        return;
+    }
+    let mut rect = RECT::default();
+    if GetWindowRect(hwnd, &mut rect).is_err() {
+        return;
+    }
+    state.settings.window = Some(WindowRect {
+        x: rect.left,
+        y: rect.top,
+        width: rect.right - rect.left,
+        height: rect.bottom - rect.top,
+    });
+    let _ = state.settings.save();
+}
+
+// Port typed into the edit control, if it's a usable one
+unsafe fn read_port(edit: HWND) -> Option<u16> {
+    let mut buf = [0u16; 8];
+    let len = GetWindowTextW(edit, &mut buf).max(0) as usize;
+    parse_port(&String::from_utf16_lossy(&buf[..len]))
+}
+
+fn parse_port(text: &str) -> Option<u16> {
+    text.trim().parse::<u16>().ok().filter(|&port| port != 0)
+}
+
 fn point_in_rect(x: i32, y: i32, rect: &RECT) -> bool {
     x >= rect.left && x < rect.right && y >= rect.top && y < rect.bottom
 }
 
+// Repaint just one button, including the 1px offset used when it's pressed
+unsafe fn invalidate_button(hwnd: HWND, btn: &ButtonRect) {
+    let rect = RECT {
+        right: btn.rect.right + 1,
+        bottom: btn.rect.bottom + 1,
+        ..btn.rect
+    };
+    let _ = InvalidateRect(hwnd, Some(&rect), false);
+}
+
 unsafe fn handle_button_click(hwnd: HWND, state: &mut AppState, button_id: usize) {
     match button_id {
         ID_BTN_START => {
             if state.child.is_some() {
                 return;
             }
+            let Some(port) = state.port else {
+                return;
+            };
+            state.settings.port = port;
+            // Not being able to remember the port shouldn't stop the receiver
+            let _ = state.settings.save();
+
             let fullscreen = state.model.lock().map(|m| m.fullscreen).unwrap_or(false);
-            match spawn_receiver_child(hwnd, fullscreen, state.model.clone()) {
+            match spawn_receiver_child(hwnd, fullscreen, port, state.model.clone()) {
                 Ok(child) => {
                     state.child = Some(child);
+                    let _ = EnableWindow(state.port_edit, false);
                     if let Ok(mut m) = state.model.lock() {
                         m.process_status = "Running".to_string();
                     }
@@ -328,6 +670,7 @@ unsafe fn handle_button_click(hwnd: HWND, state: &mut AppState, button_id: usize
         }
         ID_BTN_STOP => {
             stop_child(state);
+            let _ = EnableWindow(state.port_edit, true);
             if let Ok(mut m) = state.model.lock() {
                 m.process_status = "Stopped".to_string();
                 m.connection_status = "Disconnected".to_string();
@@ -337,7 +680,9 @@ unsafe fn handle_button_click(hwnd: HWND, state: &mut AppState, button_id: usize
         ID_BTN_FULLSCREEN => {
             if let Ok(mut m) = state.model.lock() {
                 m.fullscreen = !m.fullscreen;
+                state.settings.fullscreen = m.fullscreen;
             }
+            let _ = state.settings.save();
             
             // Restart if running
             if state.child.is_some() {
@@ -349,7 +694,7 @@ unsafe fn handle_button_click(hwnd: HWND, state: &mut AppState, button_id: usize
                 let _ = InvalidateRect(hwnd, None, false);
 
                 let fullscreen = state.model.lock().map(|m| m.fullscreen).unwrap_or(false);
-                match spawn_receiver_child(hwnd, fullscreen, state.model.clone()) {
+                match spawn_receiver_child(hwnd, fullscreen, state.settings.port, state.model.clone()) {
                     Ok(child) => {
                         state.child = Some(child);
                         if let Ok(mut m) = state.model.lock() {
@@ -365,8 +710,132 @@ unsafe fn handle_button_click(hwnd: HWND, state: &mut AppState, button_id: usize
             }
             let _ = InvalidateRect(hwnd, None, false);
         }
+        ID_BTN_THEME => {
+            state.settings.theme = state.settings.theme.toggled();
+            state.theme = Theme::of(state.settings.theme);
+            // Only cosmetic, so a failed save just means dark again next time
+            let _ = state.settings.save();
+
+            let _ = DeleteObject(state.edit_brush);
+            state.edit_brush = CreateSolidBrush(rgb_to_colorref(state.theme.card));
+            let _ = InvalidateRect(state.port_edit, None, true);
+            let _ = InvalidateRect(hwnd, None, false);
+        }
+        ID_CHECK_AUTOSTART => {
+            state.settings.autostart = !state.settings.autostart;
+            let _ = state.settings.save();
+            invalidate_button(hwnd, &state.buttons[4]);
+        }
         _ => {}
     }
+    update_tray_tip(state);
+}
+
+fn tray_icon_data(hwnd: HWND) -> NOTIFYICONDATAW {
+    NOTIFYICONDATAW {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: hwnd,
+        uID: TRAY_ICON_ID,
+        ..Default::default()
+    }
+}
+
+unsafe fn add_tray_icon(hwnd: HWND) -> bool {
+    // MAKEINTRESOURCE: the ID goes in the pointer itself
+    let icon = GetModuleHandleW(None)
+        .and_then(|module| LoadIconW(module, PCWSTR(APP_ICON_RESOURCE as *const u16)))
+        .or_else(|_| LoadIconW(None, IDI_APPLICATION));
+
+    let mut data = tray_icon_data(hwnd);
+    data.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP;
+    data.uCallbackMessage = WM_TRAY;
+    data.hIcon = icon.unwrap_or_default();
+    copy_to_wide(&mut data.szTip, "ThunderMirror");
+    Shell_NotifyIconW(NIM_ADD, &data).as_bool()
+}
+
+// Show the connection status in the tray tooltip, only touching the shell when it changes
+unsafe fn update_tray_tip(state: &mut AppState) {
+    if !state.tray_added {
+        return;
+    }
+    let status = state
+        .model
+        .lock()
+        .map(|m| m.connection_status.clone())
+        .unwrap_or_default();
+    let tip = format!("ThunderMirror - {}", status);
+    if tip == state.tray_tip {
+        return;
+    }
+
+    let mut data = tray_icon_data(state.hwnd);
+    data.uFlags = NIF_TIP;
+    copy_to_wide(&mut data.szTip, &tip);
+    let _ = Shell_NotifyIconW(NIM_MODIFY, &data);
+    state.tray_tip = tip;
+}
+
+unsafe fn show_tray_menu(hwnd: HWND, state: &mut AppState) {
+    let Ok(menu) = CreatePopupMenu() else {
+        return;
+    };
+    let is_running = state.child.is_some();
+    let start_flags = if is_running || state.port.is_none() { MF_STRING | MF_GRAYED } else { MF_STRING };
+    let stop_flags = if is_running { MF_STRING } else { MF_STRING | MF_GRAYED };
+    let _ = AppendMenuW(menu, MF_STRING, ID_MENU_SHOW, w!("Show ThunderMirror"));
+    let _ = AppendMenuW(menu, MF_SEPARATOR, 0, None);
+    let _ = AppendMenuW(menu, start_flags, ID_BTN_START, w!("Start"));
+    let _ = AppendMenuW(menu, stop_flags, ID_BTN_STOP, w!("Stop"));
+    let _ = AppendMenuW(menu, MF_SEPARATOR, 0, None);
+    let theme_label = match state.settings.theme {
+        ThemeKind::Dark => w!("Light theme"),
+        ThemeKind::Light => w!("Dark theme"),
+    };
+    let _ = AppendMenuW(menu, MF_STRING, ID_BTN_THEME, theme_label);
+    let _ = AppendMenuW(menu, MF_SEPARATOR, 0, None);
+    let _ = AppendMenuW(menu, MF_STRING, ID_MENU_EXIT, w!("Exit"));
+
+    let mut cursor = POINT::default();
+    let _ = GetCursorPos(&mut cursor);
+    // Without this the menu doesn't close when clicking elsewhere
+    let _ = SetForegroundWindow(hwnd);
+    let command = TrackPopupMenu(
+        menu,
+        TPM_RETURNCMD | TPM_RIGHTBUTTON,
+        cursor.x,
+        cursor.y,
+        0,
+        hwnd,
+        None,
+    );
+    let _ = PostMessageW(hwnd, WM_NULL, WPARAM(0), LPARAM(0));
+    let _ = DestroyMenu(menu);
+
+    match command.0 as usize {
+        ID_MENU_SHOW => show_window(hwnd),
+        ID_MENU_EXIT => {
+            let _ = DestroyWindow(hwnd);
+        }
+        id @ (ID_BTN_START | ID_BTN_STOP | ID_BTN_THEME) => handle_button_click(hwnd, state, id),
+        _ => {}
+    }
+}
+
+unsafe fn show_window(hwnd: HWND) {
+    let _ = ShowWindow(hwnd, SW_SHOW);
+    let _ = SetForegroundWindow(hwnd);
+}
+
+// Copy `text` into a fixed-size, NUL-terminated UTF-16 buffer, truncating if needed
+fn copy_to_wide(dst: &mut [u16], text: &str) {
+    let len = dst.len().saturating_sub(1);
+    let mut written = 0;
+    for (slot, unit) in dst[..len].iter_mut().zip(text.encode_utf16()) {
+        *slot = unit;
+        written += 1;
+    }
+    dst[written] = 0;
 }
 
 unsafe fn paint_window(hwnd: HWND) {
@@ -376,12 +845,15 @@ unsafe fn paint_window(hwnd: HWND) {
     let mut client_rect = RECT::default();
     let _ = GetClientRect(hwnd, &mut client_rect);
     
-    // Fill background with dark gradient color
-    let bg_brush = CreateSolidBrush(rgb_to_colorref(COLOR_BG_DARK));
+    let state = get_state(hwnd);
+    let theme = state.as_ref().map_or(&Theme::DARK, |s| s.theme);
+
+    // Fill background
+    let bg_brush = CreateSolidBrush(rgb_to_colorref(theme.background));
     FillRect(hdc, &client_rect, bg_brush);
     let _ = DeleteObject(bg_brush);
     
-    let state = match get_state(hwnd) {
+    let state = match state {
         Some(s) => s,
         None => {
             let _ = EndPaint(hwnd, &ps);
@@ -393,28 +865,28 @@ unsafe fn paint_window(hwnd: HWND) {
     
     // Draw header
     let old_font = SelectObject(hdc, state.font_title);
-    SetTextColor(hdc, rgb_to_colorref(COLOR_TEXT_PRIMARY));
+    SetTextColor(hdc, rgb_to_colorref(theme.text));
     draw_text_utf16(hdc, "⚡ ThunderMirror", 24, 24);
     
     SelectObject(hdc, state.font_mono);
-    SetTextColor(hdc, rgb_to_colorref(COLOR_TEXT_SECONDARY));
+    SetTextColor(hdc, rgb_to_colorref(theme.text_muted));
     draw_text_utf16(hdc, "v0.3.0", 24, 52);
     
     // Draw status badge
     let (status_text, status_color) = {
         let model = state.model.lock().unwrap();
         let color = match model.connection_status.as_str() {
-            "Connected" => COLOR_GREEN,
-            "Listening" => COLOR_ACCENT_BLUE,
-            "Error" => COLOR_RED,
-            _ => COLOR_TEXT_SECONDARY,
+            "Connected" => theme.green,
+            "Listening" => theme.accent,
+            "Error" => theme.red,
+            _ => theme.text_muted,
         };
         (model.connection_status.clone(), color)
     };
     
     // Status badge background
     let badge_rect = RECT { left: 260, top: 24, right: 355, bottom: 45 };
-    let badge_brush = CreateSolidBrush(rgb_to_colorref(0x21262D));
+    let badge_brush = CreateSolidBrush(rgb_to_colorref(theme.badge));
     fill_rounded_rect(hdc, &badge_rect, badge_brush, 10);
     let _ = DeleteObject(badge_brush);
     
@@ -424,11 +896,11 @@ unsafe fn paint_window(hwnd: HWND) {
     fill_rounded_rect(hdc, &dot_rect, dot_brush, 4);
     let _ = DeleteObject(dot_brush);
     
-    SetTextColor(hdc, rgb_to_colorref(COLOR_TEXT_SECONDARY));
+    SetTextColor(hdc, rgb_to_colorref(theme.text_muted));
     draw_text_utf16(hdc, &status_text, 284, 29);
     
     // Draw separator line
-    let pen = CreatePen(PS_SOLID, 1, rgb_to_colorref(COLOR_BORDER));
+    let pen = CreatePen(PS_SOLID, 1, rgb_to_colorref(theme.border));
     let old_pen = SelectObject(hdc, pen);
     MoveToEx(hdc, 24, 75, None);
     LineTo(hdc, client_rect.right - 24, 75);
@@ -438,65 +910,101 @@ unsafe fn paint_window(hwnd: HWND) {
     // Connection Card
     draw_card(hdc, state, "CONNECTION", 24, 90, 342, 80);
     SelectObject(hdc, state.font_normal);
-    SetTextColor(hdc, rgb_to_colorref(COLOR_TEXT_SECONDARY));
-    draw_text_utf16(hdc, "Listening on", 40, 125);
-    SetTextColor(hdc, rgb_to_colorref(COLOR_TEXT_PRIMARY));
+    SetTextColor(hdc, rgb_to_colorref(theme.text_muted));
+    draw_text_utf16(hdc, "Port", 40, 125);
+    draw_text_utf16(hdc, "Listening on", 40, 147);
+    let listen_address = state.model.lock().ok().and_then(|m| m.listen_address.clone());
+    SetTextColor(hdc, rgb_to_colorref(theme.text));
     SelectObject(hdc, state.font_mono);
-    draw_text_utf16(hdc, "0.0.0.0:9999", 150, 125);
+    draw_text_utf16(hdc, listen_address.as_deref().unwrap_or("—"), 150, 147);
     
     // Status Card
     draw_card(hdc, state, "STATUS", 24, 180, 342, 80);
     SelectObject(hdc, state.font_normal);
-    SetTextColor(hdc, rgb_to_colorref(COLOR_TEXT_SECONDARY));
+    SetTextColor(hdc, rgb_to_colorref(theme.text_muted));
     draw_text_utf16(hdc, "Process", 40, 215);
     let process_status = state.model.lock().map(|m| m.process_status.clone()).unwrap_or_default();
-    let process_color = if process_status == "Running" { COLOR_GREEN } else { COLOR_TEXT_PRIMARY };
+    let process_color = if process_status == "Running" { theme.green } else { theme.text };
     SetTextColor(hdc, rgb_to_colorref(process_color));
     draw_text_utf16(hdc, &process_status, 150, 215);
+    SetTextColor(hdc, rgb_to_colorref(theme.text_muted));
+    draw_text_utf16(hdc, "Sender", 40, 237);
+    let sender_line = state.model.lock().map(|m| m.sender_line.clone()).unwrap_or_default();
+    SetTextColor(hdc, rgb_to_colorref(theme.text));
+    draw_text_utf16(hdc, &sender_line, 150, 237);
     
     // Stats Card
     draw_card(hdc, state, "STATISTICS", 24, 270, 342, 55);
     SelectObject(hdc, state.font_mono);
-    SetTextColor(hdc, rgb_to_colorref(COLOR_TEXT_SECONDARY));
+    SetTextColor(hdc, rgb_to_colorref(theme.text_muted));
     let stats = state.model.lock().map(|m| m.stats_line.clone()).unwrap_or_else(|_| "—".to_string());
     draw_text_utf16(hdc, &stats, 40, 300);
+    if let Ok(model) = state.model.lock() {
+        let graph = RECT { left: 190, top: 278, right: 350, bottom: 296 };
+        draw_fps_graph(hdc, &graph, &model.stats_history, theme.accent);
+    }
     
     // Draw buttons
     let is_running = state.child.is_some();
     let is_fullscreen = state.model.lock().map(|m| m.fullscreen).unwrap_or(false);
     
-    // Start button
+    // Start button, greyed out while running or without a valid port
+    let can_start = !is_running && state.port.is_some();
     draw_button(
         hdc, 
-        &state.buttons[0].rect, 
+        &state.buttons[0], 
         "▶  Start", 
-        if is_running { COLOR_BORDER } else { COLOR_GREEN },
-        if is_running { COLOR_BORDER } else { COLOR_GREEN_DARK },
-        state.buttons[0].pressed,
+        if can_start { theme.green } else { theme.button_off },
+        if can_start { theme.green_dark } else { theme.button_off },
+        theme.button_text,
         state.font_normal,
     );
     
     // Stop button
     draw_button(
         hdc, 
-        &state.buttons[1].rect, 
+        &state.buttons[1], 
         "■  Stop", 
-        if !is_running { COLOR_BORDER } else { COLOR_RED },
-        if !is_running { COLOR_BORDER } else { COLOR_RED_DARK },
-        state.buttons[1].pressed,
+        if !is_running { theme.button_off } else { theme.red },
+        if !is_running { theme.button_off } else { theme.red_dark },
+        theme.button_text,
         state.font_normal,
     );
     
     // Fullscreen toggle
     let fs_text = if is_fullscreen { "Fullscreen: ON" } else { "Fullscreen: OFF" };
-    let fs_color = if is_fullscreen { COLOR_ACCENT_BLUE } else { COLOR_BORDER };
+    let fs_color = if is_fullscreen { theme.accent } else { theme.button_off };
     draw_button(
         hdc,
-        &state.buttons[2].rect,
+        &state.buttons[2],
         fs_text,
         fs_color,
-        if is_fullscreen { COLOR_ACCENT_DARK_BLUE } else { 0x21262D },
-        state.buttons[2].pressed,
+        if is_fullscreen { theme.accent_dark } else { theme.badge },
+        theme.button_text,
+        state.font_normal,
+    );
+
+    // Theme toggle, labelled with the theme it switches to
+    let theme_text = match state.settings.theme {
+        ThemeKind::Dark => "Light mode",
+        ThemeKind::Light => "Dark mode",
+    };
+    draw_button(
+        hdc,
+        &state.buttons[3],
+        theme_text,
+        theme.badge,
+        theme.badge,
+        theme.text_muted,
+        state.font_mono,
+    );
+
+    draw_checkbox(
+        hdc,
+        &state.buttons[4],
+        "Start receiver when the app opens",
+        state.settings.autostart,
+        theme,
         state.font_normal,
     );
     
@@ -508,12 +1016,12 @@ unsafe fn draw_card(hdc: windows::Win32::Graphics::Gdi::HDC, state: &AppState, t
     let rect = RECT { left: x, top: y, right: x + w, bottom: y + h };
     
     // Card background
-    let bg_brush = CreateSolidBrush(rgb_to_colorref(COLOR_BG_MEDIUM));
+    let bg_brush = CreateSolidBrush(rgb_to_colorref(state.theme.card));
     fill_rounded_rect(hdc, &rect, bg_brush, 12);
     let _ = DeleteObject(bg_brush);
     
     // Card border
-    let border_pen = CreatePen(PS_SOLID, 1, rgb_to_colorref(COLOR_BORDER));
+    let border_pen = CreatePen(PS_SOLID, 1, rgb_to_colorref(state.theme.border));
     let old_pen = SelectObject(hdc, border_pen);
     let null_brush = GetStockObject(windows::Win32::Graphics::Gdi::NULL_BRUSH);
     let old_brush = SelectObject(hdc, null_brush);
@@ -524,20 +1032,48 @@ unsafe fn draw_card(hdc: windows::Win32::Graphics::Gdi::HDC, state: &AppState, t
     
     // Card title
     SelectObject(hdc, state.font_mono);
-    SetTextColor(hdc, rgb_to_colorref(COLOR_TEXT_SECONDARY));
+    SetTextColor(hdc, rgb_to_colorref(state.theme.text_muted));
     draw_text_utf16(hdc, title, x + 16, y + 10);
 }
 
+// Sparkline of recent FPS, scaled so 60 FPS (or the peak, if higher) fills the height
+unsafe fn draw_fps_graph(hdc: windows::Win32::Graphics::Gdi::HDC, rect: &RECT, history: &VecDeque<(f32, f32)>, color: u32) {
+    if history.len() < 2 {
+        return;
+    }
+    let peak = history.iter().map(|&(fps, _)| fps).fold(60.0f32, f32::max);
+    let width = (rect.right - rect.left) as f32;
+    let height = (rect.bottom - rect.top) as f32;
+    let step = width / (STATS_HISTORY_LEN - 1) as f32;
+    // Newest reading on the right edge
+    let start = rect.right as f32 - step * (history.len() - 1) as f32;
+    let points: Vec<POINT> = history
+        .iter()
+        .enumerate()
+        .map(|(i, &(fps, _))| POINT {
+            x: (start + step * i as f32) as i32,
+            y: rect.bottom - (fps.max(0.0) / peak * height) as i32,
+        })
+        .collect();
+
+    let pen = CreatePen(PS_SOLID, 2, rgb_to_colorref(color));
+    let old_pen = SelectObject(hdc, pen);
+    let _ = Polyline(hdc, &points);
+    SelectObject(hdc, old_pen);
+    let _ = DeleteObject(pen);
+}
+
 unsafe fn draw_button(
     hdc: windows::Win32::Graphics::Gdi::HDC,
-    rect: &RECT,
+    btn: &ButtonRect,
     text: &str,
     color: u32,
     _color_dark: u32,
-    pressed: bool,
+    text_color: u32,
     font: HGDIOBJ,
 ) {
-    let adj_rect = if pressed {
+    let rect = &btn.rect;
+    let adj_rect = if btn.pressed {
         RECT {
             left: rect.left + 1,
             top: rect.top + 1,
@@ -548,20 +1084,61 @@ unsafe fn draw_button(
         *rect
     };
     
-    // Button background
+    // Button background, lighter while hovered
+    let color = if btn.hover { lighten(color, HOVER_LIGHTEN) } else { color };
     let bg_brush = CreateSolidBrush(rgb_to_colorref(color));
     fill_rounded_rect(hdc, &adj_rect, bg_brush, 10);
     let _ = DeleteObject(bg_brush);
     
     // Button text
     SelectObject(hdc, font);
-    SetTextColor(hdc, rgb_to_colorref(COLOR_TEXT_PRIMARY));
+    SetTextColor(hdc, rgb_to_colorref(text_color));
     
     let mut text_rect = adj_rect;
     let wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
     DrawTextW(hdc, &mut wide[..wide.len()-1].to_vec(), &mut text_rect, DT_CENTER | DT_VCENTER | DT_SINGLELINE);
 }
 
+// Square box with a check mark when `checked`, followed by `label`
+unsafe fn draw_checkbox(
+    hdc: windows::Win32::Graphics::Gdi::HDC,
+    btn: &ButtonRect,
+    label: &str,
+    checked: bool,
+    theme: &Theme,
+    font: HGDIOBJ,
+) {
+    let size = 16;
+    let top = btn.rect.top + (btn.rect.bottom - btn.rect.top - size) / 2;
+    let square = RECT { left: btn.rect.left, top, right: btn.rect.left + size, bottom: top + size };
+    let fill = if checked { theme.accent } else { theme.card };
+    let fill = if btn.hover { lighten(fill, HOVER_LIGHTEN) } else { fill };
+    let brush = CreateSolidBrush(rgb_to_colorref(fill));
+    fill_rounded_rect(hdc, &square, brush, 4);
+    let _ = DeleteObject(brush);
+
+    let border_pen = CreatePen(PS_SOLID, 1, rgb_to_colorref(theme.border));
+    let old_pen = SelectObject(hdc, border_pen);
+    let null_brush = GetStockObject(windows::Win32::Graphics::Gdi::NULL_BRUSH);
+    let old_brush = SelectObject(hdc, null_brush);
+    let _ = RoundRect(hdc, square.left, square.top, square.right, square.bottom, 4, 4);
+    SelectObject(hdc, old_brush);
+    SelectObject(hdc, old_pen);
+    let _ = DeleteObject(border_pen);
+
+    SelectObject(hdc, font);
+    if checked {
+        SetTextColor(hdc, rgb_to_colorref(theme.button_text));
+        let mut mark_rect = square;
+        let mut mark: Vec<u16> = "✓".encode_utf16().collect();
+        DrawTextW(hdc, &mut mark, &mut mark_rect, DT_CENTER | DT_VCENTER | DT_SINGLELINE);
+    }
+    SetTextColor(hdc, rgb_to_colorref(theme.text_muted));
+    let mut text_rect = RECT { left: square.right + 10, ..btn.rect };
+    let mut text: Vec<u16> = label.encode_utf16().collect();
+    DrawTextW(hdc, &mut text, &mut text_rect, DT_VCENTER | DT_SINGLELINE);
+}
+
 unsafe fn fill_rounded_rect(hdc: windows::Win32::Graphics::Gdi::HDC, rect: &RECT, brush: HBRUSH, radius: i32) {
     let old_brush = SelectObject(hdc, brush);
     let null_pen = CreatePen(PS_SOLID, 0, rgb_to_colorref(0));
@@ -601,6 +1178,7 @@ fn stop_child(state: &mut AppState) {
 fn spawn_receiver_child(
     hwnd: HWND,
     fullscreen: bool,
+    port: u16,
     model: Arc<Mutex<UiModel>>,
 ) -> anyhow::Result<Child> {
     let ui_exe = std::env::current_exe()?;
@@ -616,8 +1194,20 @@ fn spawn_receiver_child(
         ));
     }
 
+    // The graph starts over with each receiver run
+    let mut generation = 0;
+    if let Ok(mut m) = model.lock() {
+        m.child_generation += 1;
+        generation = m.child_generation;
+        m.stats_history.clear();
+        m.h264_unavailable = false;
+        m.listen_address = None;
+    }
+
     let mut cmd = Command::new(receiver_exe);
     cmd.arg("--log-level").arg("info");
+    cmd.arg("--port").arg(port.to_string());
+    cmd.arg("--status-json");
     if fullscreen {
         cmd.arg("--fullscreen");
     }
@@ -632,13 +1222,17 @@ fn spawn_receiver_child(
         std::thread::spawn(move || {
             let reader = BufReader::new(stdout);
             for line in reader.lines().flatten() {
-                handle_child_log_line(hwnd, &model, &line);
+                handle_child_status_line(hwnd, &model, &line);
             }
             if let Ok(mut m) = model.lock() {
+                if m.child_generation != generation {
+                    return;
+                }
                 if m.process_status != "Stopped" {
                     m.process_status = "Stopped".to_string();
                     m.connection_status = "Disconnected".to_string();
                 }
+                m.listen_address = None;
             }
             unsafe {
                 let _ = PostMessageW(hwnd, WM_UI_UPDATE, WPARAM(0), LPARAM(0));
@@ -646,43 +1240,61 @@ fn spawn_receiver_child(
         });
     }
 
+    // Logs go to stderr in --status-json mode; drain them so the child
+    // never blocks on a full pipe.
     if let Some(stderr) = child.stderr.take() {
-        let model = model.clone();
         std::thread::spawn(move || {
             let reader = BufReader::new(stderr);
-            for line in reader.lines().flatten() {
-                handle_child_log_line(hwnd, &model, &line);
-            }
+            for _ in reader.lines().flatten() {}
         });
     }
 
     Ok(child)
 }
 
-fn handle_child_log_line(hwnd: HWND, model: &Arc<Mutex<UiModel>>, line: &str) {
-    let mut changed = false;
+// Apply one `StatusEvent` line from the receiver's stdout
+fn handle_child_status_line(hwnd: HWND, model: &Arc<Mutex<UiModel>>, line: &str) {
+    let event = match StatusEvent::from_json(line) {
+        Ok(event) => event,
+        Err(_) => return,
+    };
+
     if let Ok(mut m) = model.lock() {
-        if line.contains("Connection accepted from") {
-            m.connection_status = "Connected".to_string();
-            changed = true;
-        } else if line.contains("Connection closed") {
-            m.connection_status = "Disconnected".to_string();
-            changed = true;
-        } else if let Some(rest) = line.split("Stats: ").nth(1) {
-            m.stats_line = rest.trim().to_string();
-            changed = true;
-        } else if line.contains("QUIC server listening") {
-            m.connection_status = "Listening".to_string();
-            changed = true;
-        } else if line.contains("QUIC server error") || line.contains("Connection error") {
-            m.connection_status = "Error".to_string();
-            changed = true;
+        match event {
+            StatusEvent::Listening { address, .. } => {
+                m.connection_status = "Listening".to_string();
+                m.listen_address = address;
+            }
+            StatusEvent::Connected { .. } => m.connection_status = "Connected".to_string(),
+            StatusEvent::Disconnected { .. } => {
+                m.connection_status = "Disconnected".to_string();
+                m.sender_line = "—".to_string();
+            }
+            StatusEvent::Error { .. } => m.connection_status = "Error".to_string(),
+            StatusEvent::Stats { fps, mbps, width, height, codec, smoothed_fps, smoothed_mbps, .. } => {
+                // The line shows the steadier smoothed rates; the graph keeps the raw ones.
+                let (shown_fps, shown_mbps) = (smoothed_fps.unwrap_or(fps), smoothed_mbps.unwrap_or(mbps));
+                m.stats_line =
+                    format!("{:.1} FPS, {:.1} Mbps, {}x{} {}", shown_fps, shown_mbps, width, height, codec);
+                if m.h264_unavailable {
+                    m.stats_line.push_str(" (no H.264 decoder)");
+                }
+                if m.stats_history.len() == STATS_HISTORY_LEN {
+                    m.stats_history.pop_front();
+                }
+                m.stats_history.push_back((fps as f32, mbps as f32));
+            }
+            StatusEvent::SenderStats { fps, loss_percent } => {
+                m.sender_line = format!("{:.1} FPS, {:.1}% loss", fps, loss_percent);
+            }
+            StatusEvent::DecoderUnavailable { .. } => {
+                m.h264_unavailable = true;
+                m.stats_line = "No H.264 decoder, raw frames only".to_string();
+            }
         }
     }
 
-    if changed {
-        unsafe {
-            let _ = PostMessageW(hwnd, WM_UI_UPDATE, WPARAM(0), LPARAM(0));
-        }
+    unsafe {
+        let _ = PostMessageW(hwnd, WM_UI_UPDATE, WPARAM(0), LPARAM(0));
     }
 }